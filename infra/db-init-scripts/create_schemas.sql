@@ -1,2 +1,3 @@
 CREATE SCHEMA users;
 CREATE SCHEMA news;
+CREATE SCHEMA broker;
@@ -1,20 +1,31 @@
+use utils::pipeline::backend::BrokerBackend;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub logs_path: String,
-    pub kafka_url: String,
+    pub broker: BrokerBackend,
+    pub otlp_endpoint: String,
+    pub shutdown_timeout: u64,
 }
 
 impl Config {
     pub fn init() -> Config {
         let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
         let logs_path = std::env::var("LOGS_PATH").unwrap_or_else(|_| String::from(""));
-        let kafka_url = std::env::var("KAFKA_URL").expect("KAFKA_URL must be set");
+        let broker = BrokerBackend::from_env("news-scrapper")
+            .unwrap_or_else(|err| panic!("invalid broker config: {}", err.message));
+        let otlp_endpoint =
+            std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").unwrap_or_else(|_| String::from(""));
+        let shutdown_timeout =
+            std::env::var("SHUTDOWN_TIMEOUT").unwrap_or_else(|_| String::from("30"));
 
         Config {
             database_url,
             logs_path,
-            kafka_url,
+            broker,
+            otlp_endpoint,
+            shutdown_timeout: shutdown_timeout.parse::<u64>().unwrap(),
         }
     }
 }
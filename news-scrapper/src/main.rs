@@ -1,26 +1,30 @@
+use log::{error, info, warn};
 use news_scrapper::{
     config::Config,
     http_fetcher::HttpFetcher,
     news_ingestor::NewsIngestor,
     scrapper::{RssFetcher, RssScrapper},
 };
-use std::{error::Error, sync::Arc, thread};
+use std::{error::Error, sync::Arc, time::Duration};
 use tokio_cron_scheduler::{Job, JobScheduler};
 use utils::{
-    broker,
     db::connect_db,
     logger::init_logger,
     news::{
+        events::NEWS_CREATED_EVENT,
         repositories::{
             feed_repository::{FeedDieselRepository, FeedRepository},
             news_repository::{NewsDieselRepository, NewsRepository},
             subscription_repository::{SubscriptionRepository, SubscriptionsDieselRepository},
         },
-        services::{
-            events_service::{EventService, KafkaEventService},
-            news_service::{NewsService, Service},
-        },
+        services::news_service::{NewsService, Service},
+    },
+    outbox::{
+        relay::OutboxRelay,
+        repository::{OutboxDieselRepository, OutboxRepository},
     },
+    shutdown::shutdown_token,
+    telemetry::{init_tracing, shutdown_tracing},
 };
 
 #[tokio::main]
@@ -28,8 +32,18 @@ async fn main() {
     let config = Config::init();
 
     init_logger(config.logs_path.clone());
+    init_tracing("news-scrapper", &config.otlp_endpoint);
+
+    config
+        .broker
+        .ensure_topics(&[NEWS_CREATED_EVENT])
+        .await
+        .unwrap_or_else(|err| panic!("{}", err.message));
 
-    let kafka_producer = broker::create_producer(config.kafka_url.clone());
+    let producer = config
+        .broker
+        .create_producer()
+        .unwrap_or_else(|err| panic!("{}", err.message));
 
     let db_pool = connect_db(config.database_url.clone());
 
@@ -40,29 +54,60 @@ async fn main() {
     let subscription_repository: Arc<dyn SubscriptionRepository> = Arc::new(
         SubscriptionsDieselRepository::new(Arc::new(db_pool.clone())),
     );
-    let events_service: Arc<dyn EventService> = Arc::new(KafkaEventService::new(kafka_producer));
+    let outbox_repository: Arc<dyn OutboxRepository> =
+        Arc::new(OutboxDieselRepository::new(Arc::new(db_pool.clone())));
 
     let service: Arc<dyn NewsService> = Arc::new(Service::new(
         feed_repository.clone(),
         news_repository.clone(),
         subscription_repository.clone(),
-        events_service.clone(),
     ));
 
     let rss_fetcher: Arc<dyn RssFetcher> = Arc::new(HttpFetcher::default());
 
     let feeds_scrapper = Arc::new(RssScrapper::new(rss_fetcher.clone()));
 
-    let ingestor = NewsIngestor::new(service, feeds_scrapper);
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout);
+    let shutdown = shutdown_token();
 
-    if let Err(err) = setup_cronjobs(&ingestor).await {
-        panic!("failed setup cronjobs: {}", err);
+    let ingestor = NewsIngestor::new(service, feeds_scrapper).with_shutdown(shutdown.clone());
+
+    let relay = OutboxRelay::new(outbox_repository, producer.clone());
+    let relay_shutdown = shutdown.clone();
+    let relay_task = tokio::spawn(async move { relay.start(relay_shutdown).await });
+
+    let mut sched = match setup_cronjobs(&ingestor).await {
+        Ok(sched) => sched,
+        Err(err) => panic!("failed setup cronjobs: {}", err),
     };
 
-    thread::park();
+    shutdown.cancelled().await;
+
+    info!("Shutting down news scrapper");
+
+    if let Err(err) = sched.shutdown().await {
+        error!("failed stopping cronjobs: {}", err);
+    }
+
+    if tokio::time::timeout(shutdown_timeout, ingestor.wait())
+        .await
+        .is_err()
+    {
+        warn!("timed out waiting for news ingestion to finish");
+    }
+
+    if let Err(err) = relay_task.await {
+        error!("failed joining outbox relay: {}", err);
+    }
+
+    if let Err(err) = producer.flush(shutdown_timeout) {
+        error!("{}", err.message);
+    }
+
+    shutdown_tracing();
 }
 
-async fn setup_cronjobs(ingestor: &NewsIngestor) -> Result<(), Box<dyn Error>> {
+async fn setup_cronjobs(ingestor: &NewsIngestor) -> Result<JobScheduler, Box<dyn Error>> {
     let ingestor = ingestor.clone();
 
     let sched = JobScheduler::new().await?;
@@ -77,5 +122,5 @@ async fn setup_cronjobs(ingestor: &NewsIngestor) -> Result<(), Box<dyn Error>> {
 
     sched.start().await?;
 
-    Ok(())
+    Ok(sched)
 }
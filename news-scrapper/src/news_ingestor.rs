@@ -1,7 +1,11 @@
 use std::sync::Arc;
 
-use log::{debug, error};
-use tokio::{sync::mpsc, task};
+use log::{debug, error, info};
+use tokio::{
+    sync::{mpsc, Mutex},
+    task,
+};
+use tokio_util::sync::CancellationToken;
 use utils::news::{models::news::News, services::news_service::NewsService};
 
 use crate::scrapper::FeedsScrapper;
@@ -10,6 +14,8 @@ use crate::scrapper::FeedsScrapper;
 pub struct NewsIngestor {
     pub news_service: Arc<dyn NewsService>,
     pub feeds_scrapper: Arc<dyn FeedsScrapper>,
+    shutdown: CancellationToken,
+    running: Arc<Mutex<()>>,
 }
 
 impl NewsIngestor {
@@ -20,10 +26,29 @@ impl NewsIngestor {
         NewsIngestor {
             news_service,
             feeds_scrapper,
+            shutdown: CancellationToken::new(),
+            running: Arc::new(Mutex::new(())),
         }
     }
 
+    /// Stops ingesting news once the token is cancelled.
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> NewsIngestor {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Waits until the ingestion in progress, if any, finishes.
+    pub async fn wait(&self) {
+        let _running = self.running.lock().await;
+    }
+
     pub async fn ingest(&self) {
+        if self.shutdown.is_cancelled() {
+            return;
+        }
+
+        let _running = self.running.lock().await;
+
         debug!("start scrapping feeds");
         let result = self.news_service.list_feeds().await;
 
@@ -47,7 +72,19 @@ impl NewsIngestor {
             }
         });
 
-        while let Some(news) = rx.recv().await {
+        loop {
+            let news = tokio::select! {
+                biased;
+                _ = self.shutdown.cancelled() => {
+                    info!("stopped scrapping feeds on shutdown");
+                    break;
+                }
+                news = rx.recv() => match news {
+                    Some(news) => news,
+                    None => break,
+                },
+            };
+
             for news_item in news {
                 if let Err(err) = self.news_service.insert_news(&news_item).await {
                     error!("failed inserting news: {}", err);
@@ -109,4 +146,17 @@ mod tests {
         let news_ingestor = NewsIngestor::new(news_service, feeds_scrapper);
         news_ingestor.ingest().await;
     }
+
+    #[tokio::test]
+    async fn test_news_ingestor_ingest_after_shutdown() {
+        let news_service = Arc::new(MockNewsService::new());
+        let feeds_scrapper = Arc::new(MockFeedsScrapper::new());
+
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+
+        let news_ingestor = NewsIngestor::new(news_service, feeds_scrapper).with_shutdown(shutdown);
+        news_ingestor.ingest().await;
+        news_ingestor.wait().await;
+    }
 }
@@ -0,0 +1,8 @@
+CREATE TABLE processed_messages (
+    group_id TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    processed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (group_id, message_id)
+);
+
+CREATE INDEX processed_messages_processed_at_idx ON processed_messages (group_id, processed_at);
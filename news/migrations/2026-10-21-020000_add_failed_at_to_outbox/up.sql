@@ -0,0 +1,6 @@
+ALTER TABLE outbox ADD COLUMN failed_at TIMESTAMPTZ;
+
+DROP INDEX outbox_pending_idx;
+CREATE INDEX outbox_pending_idx ON outbox (id) WHERE sent_at IS NULL AND failed_at IS NULL;
+CREATE INDEX outbox_pending_key_idx ON outbox (topic, key, id)
+    WHERE sent_at IS NULL AND failed_at IS NULL;
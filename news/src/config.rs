@@ -1,3 +1,5 @@
+use utils::pipeline::backend::BrokerBackend;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub cors_origin: String,
@@ -5,7 +7,21 @@ pub struct Config {
     pub logs_path: String,
     pub server_port: String,
     pub jwt_secret: String,
-    pub kafka_url: String,
+    /// Database of the users service, where the revoked tokens are stored.
+    pub revocation_database_url: Option<String>,
+    pub revocation_cache_ttl: u64,
+    pub revocation_cache_size: usize,
+    pub broker: BrokerBackend,
+    pub otlp_endpoint: String,
+    pub shutdown_timeout: u64,
+    pub pipeline_workers: usize,
+    pub pipeline_max_in_flight: usize,
+    pub processor_timeout: u64,
+    /// Attempts at forgetting a deleted user, its event is skipped after the last one.
+    pub user_deleted_attempts: usize,
+    pub idempotency_store: String,
+    pub idempotency_ttl: u64,
+    pub idempotency_cache_size: usize,
 }
 
 impl Config {
@@ -15,7 +31,31 @@ impl Config {
         let logs_path = std::env::var("LOGS_PATH").unwrap_or_else(|_| String::from(""));
         let server_port = std::env::var("PORT").unwrap_or_else(|_| String::from("8000"));
         let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-        let kafka_url = std::env::var("KAFKA_URL").expect("KAFKA_URL must be set");
+        let revocation_database_url = std::env::var("REVOCATION_DATABASE_URL").ok();
+        let revocation_cache_ttl =
+            std::env::var("REVOCATION_CACHE_TTL").unwrap_or_else(|_| String::from("5"));
+        let revocation_cache_size =
+            std::env::var("REVOCATION_CACHE_SIZE").unwrap_or_else(|_| String::from("10000"));
+        let broker = BrokerBackend::from_env("news")
+            .unwrap_or_else(|err| panic!("invalid broker config: {}", err.message));
+        let otlp_endpoint =
+            std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").unwrap_or_else(|_| String::from(""));
+        let shutdown_timeout =
+            std::env::var("SHUTDOWN_TIMEOUT").unwrap_or_else(|_| String::from("30"));
+        let pipeline_workers =
+            std::env::var("PIPELINE_WORKERS").unwrap_or_else(|_| String::from("4"));
+        let pipeline_max_in_flight =
+            std::env::var("PIPELINE_MAX_IN_FLIGHT").unwrap_or_else(|_| String::from("100"));
+        let processor_timeout =
+            std::env::var("PROCESSOR_TIMEOUT").unwrap_or_else(|_| String::from("30"));
+        let user_deleted_attempts =
+            std::env::var("USER_DELETED_ATTEMPTS").unwrap_or_else(|_| String::from("5"));
+        let idempotency_store =
+            std::env::var("IDEMPOTENCY_STORE").unwrap_or_else(|_| String::from("postgres"));
+        let idempotency_ttl =
+            std::env::var("IDEMPOTENCY_TTL").unwrap_or_else(|_| String::from("604800"));
+        let idempotency_cache_size =
+            std::env::var("IDEMPOTENCY_CACHE_SIZE").unwrap_or_else(|_| String::from("10000"));
 
         Config {
             cors_origin,
@@ -23,7 +63,19 @@ impl Config {
             logs_path,
             server_port,
             jwt_secret,
-            kafka_url,
+            revocation_database_url,
+            revocation_cache_ttl: revocation_cache_ttl.parse::<u64>().unwrap(),
+            revocation_cache_size: revocation_cache_size.parse::<usize>().unwrap(),
+            broker,
+            otlp_endpoint,
+            shutdown_timeout: shutdown_timeout.parse::<u64>().unwrap(),
+            pipeline_workers: pipeline_workers.parse::<usize>().unwrap(),
+            pipeline_max_in_flight: pipeline_max_in_flight.parse::<usize>().unwrap(),
+            processor_timeout: processor_timeout.parse::<u64>().unwrap(),
+            user_deleted_attempts: user_deleted_attempts.parse::<usize>().unwrap(),
+            idempotency_store,
+            idempotency_ttl: idempotency_ttl.parse::<u64>().unwrap(),
+            idempotency_cache_size: idempotency_cache_size.parse::<usize>().unwrap(),
         }
     }
 }
@@ -3,6 +3,7 @@ use actix_web::{post, HttpMessage};
 use log::error;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use utils::http::middlewares::authorization::Verified;
 use utils::{error::CommonError, http::middlewares::jwt_auth::JwtMiddleware};
 use uuid::Uuid;
 use validator::Validate;
@@ -45,7 +46,7 @@ async fn create_subscription(
     r: HttpRequest,
     subscription_repo: web::Data<dyn SubscriptionRepository>,
     payload: Option<web::Json<CreateSubscriptionPayload>>,
-    _: JwtMiddleware,
+    _: Verified,
 ) -> HttpResponse {
     let user_id = *r.extensions().get::<uuid::Uuid>().unwrap();
 
@@ -312,7 +313,7 @@ mod tests {
         let mut uri = "/subscriptions".to_owned();
 
         if let Some(feed_id) = case.feed_id {
-            uri = format!("{}?feed_id={}", uri.to_string(), feed_id.to_string());
+            uri = format!("{}?feed_id={}", uri, feed_id);
         }
 
         let req = test::TestRequest::delete().uri(&uri);
@@ -1,3 +1,23 @@
+use utils::news::events::NEWS_CREATED_EVENT;
+
+use crate::user_deleted_processor::USER_DELETED_EVENT;
+
 pub mod config;
 pub mod handlers;
 pub mod news_websocket_processor;
+pub mod user_deleted_processor;
+
+/// Topics the news service consumes, ensured on startup.
+pub const NEWS_TOPICS: &[&str] = &[NEWS_CREATED_EVENT, USER_DELETED_EVENT];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_news_topics_are_registered() {
+        assert!(utils::broker::topics::registry()
+            .topics(NEWS_TOPICS)
+            .is_ok());
+    }
+}
@@ -1,29 +1,48 @@
 extern crate log;
 
 use actix::{Actor, Addr};
+use actix_rt::task::JoinHandle;
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
 use actix_web::{error::Error as ActixError, web, App as ActixApp, HttpServer};
-use log::info;
+use log::{error, info, warn};
 use news::handlers::subscriptions::{create_subscription, delete_subscription, get_subscriptions};
-use news::news_websocket_processor::NewsWebsocketProcessor;
-use rdkafka::consumer::{Consumer, StreamConsumer};
+use news::news_websocket_processor::{NewsCreatedMessage, NewsWebsocketProcessor};
+use news::user_deleted_processor::{UserDeletedProcessor, USER_DELETED_EVENT};
+use news::NEWS_TOPICS;
 use std::sync::Arc;
-use std::thread;
-use utils::broker;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use utils::db::PgPool;
 use utils::http::services::auth_service::{AuthService, JwtAuthService};
+use utils::http::services::personal_token_store::cache::CachedPersonalTokenStore;
+use utils::http::services::personal_token_store::postgres::PostgresPersonalTokenStore;
+use utils::http::services::revocation_store::cache::CachedRevocationStore;
+use utils::http::services::revocation_store::postgres::PostgresRevocationStore;
 use utils::http::websockets::ws_handler::get_ws;
 use utils::http::websockets::ws_sender::WsSenderWrapper;
-use utils::http::websockets::ws_server::WebsocketServer;
+use utils::http::websockets::ws_server::{CloseAll, WebsocketServer};
 use utils::news::events::NEWS_CREATED_EVENT;
 use utils::news::repositories::feed_repository::{FeedDieselRepository, FeedRepository};
 use utils::news::repositories::news_repository::{NewsDieselRepository, NewsRepository};
 use utils::news::repositories::subscription_repository::{
     SubscriptionRepository, SubscriptionsDieselRepository,
 };
-use utils::pipeline::consumer::KafkaConsumer;
+use utils::pipeline::consumer::Consumer;
 use utils::pipeline::data_pipeline::DataPipeline;
-use utils::{db::connect_db, http::utils::build_server, logger::init_logger};
+use utils::pipeline::idempotency::{
+    memory::MemoryIdempotencyStore, postgres::PostgresIdempotencyStore, IdempotencyStore,
+};
+use utils::pipeline::metrics::{PipelineMetrics, ProcessorMetrics};
+use utils::pipeline::middleware::ProcessorExt;
+use utils::pipeline::router::TopicRouter;
+use utils::{
+    db::connect_db,
+    http::utils::build_server,
+    logger::init_logger,
+    shutdown::shutdown_token,
+    telemetry::{init_tracing, shutdown_tracing},
+};
 
 use news::{config::Config, handlers::feeds::get_feeds, handlers::news::get_news};
 
@@ -32,6 +51,7 @@ async fn main() {
     let config = Config::init();
 
     init_logger(config.logs_path.clone());
+    init_tracing("news", &config.otlp_endpoint);
 
     let db_pool = connect_db(config.database_url.clone());
 
@@ -44,41 +64,82 @@ async fn main() {
     );
 
     let server_port = config.server_port.clone();
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout);
 
     info!("Starting API server in port {}", server_port.clone());
 
+    let shutdown = shutdown_token();
     let ws_server = WebsocketServer::new().start();
-    let consumer = broker::create_consumer(config.kafka_url.to_string());
+    config
+        .broker
+        .ensure_topics(NEWS_TOPICS)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err.message));
+    let consumer = config
+        .broker
+        .create_consumer()
+        .await
+        .unwrap_or_else(|err| panic!("{}", err.message));
 
-    setup_news_created_pipeline(consumer, &ws_server, subscription_repository.clone());
+    let idempotency_store = setup_idempotency_store(&config, Arc::new(db_pool.clone()));
+    let pipeline = setup_events_pipeline(
+        consumer,
+        idempotency_store,
+        &ws_server,
+        subscription_repository.clone(),
+        &config,
+        shutdown.clone(),
+    );
 
+    let auth_service = setup_auth_service(&config);
+    let http_ws_server = ws_server.clone();
     let server_result = HttpServer::new(move || {
         setup_http_server(
             &config,
+            auth_service.clone(),
             feed_repository.clone(),
             news_repository.clone(),
             subscription_repository.clone(),
-            ws_server.clone(),
+            http_ws_server.clone(),
         )
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs())
     .bind(format!("0.0.0.0:{}", server_port.clone()));
 
-    match server_result {
-        Ok(server) => {
-            if let Err(err) = server.run().await {
-                panic!("failed running server: {}", err)
-            }
-        }
+    let server = match server_result {
+        Ok(server) => server.run(),
         Err(err) => {
             panic!("failed building server: {}", err)
         }
+    };
+    let server_handle = server.handle();
+    let server_task = actix_rt::spawn(server);
+
+    shutdown.cancelled().await;
+
+    info!("Shutting down API server");
+
+    // close websocket sessions first so the server does not wait for them to time out
+    ws_server.do_send(CloseAll);
+    server_handle.stop(true).await;
+
+    match server_task.await {
+        Ok(Err(err)) => error!("failed running server: {}", err),
+        Err(err) => error!("failed joining server task: {}", err),
+        Ok(Ok(_)) => {}
+    }
+
+    if let Err(err) = pipeline.await {
+        error!("failed joining events pipeline: {}", err);
     }
 
-    thread::park();
+    shutdown_tracing();
 }
 
 fn setup_http_server(
     config: &Config,
+    auth_service: Arc<dyn AuthService>,
     feed_repo: Arc<dyn FeedRepository>,
     news_repo: Arc<dyn NewsRepository>,
     subscription_repo: Arc<dyn SubscriptionRepository>,
@@ -92,9 +153,6 @@ fn setup_http_server(
         Error = ActixError,
     >,
 > {
-    let auth_service: Arc<dyn AuthService> =
-        Arc::new(JwtAuthService::new(config.jwt_secret.clone()));
-
     build_server(config.cors_origin.clone())
         .app_data(web::Data::from(feed_repo.clone()))
         .app_data(web::Data::from(news_repo.clone()))
@@ -110,21 +168,96 @@ fn setup_http_server(
         .service(get_ws)
 }
 
-fn setup_news_created_pipeline(
-    consumer: StreamConsumer,
+/// Checks the tokens against the revocations made by the users service, when its
+/// database is configured. Personal tokens live there too, they are rejected without it.
+fn setup_auth_service(config: &Config) -> Arc<dyn AuthService> {
+    let auth_service = JwtAuthService::new(config.jwt_secret.clone());
+
+    match &config.revocation_database_url {
+        Some(database_url) => {
+            let pool = Arc::new(connect_db(database_url.clone()));
+            let store = PostgresRevocationStore::new(pool.clone());
+            let personal_token_store = PostgresPersonalTokenStore::new(pool);
+            Arc::new(
+                auth_service
+                    .with_revocation_store(Arc::new(CachedRevocationStore::new(
+                        Arc::new(store),
+                        config.revocation_cache_size,
+                        Duration::from_secs(config.revocation_cache_ttl),
+                    )))
+                    .with_personal_token_store(Arc::new(CachedPersonalTokenStore::new(
+                        Arc::new(personal_token_store),
+                        config.revocation_cache_size,
+                        Duration::from_secs(config.revocation_cache_ttl),
+                    ))),
+            )
+        }
+        None => {
+            warn!("REVOCATION_DATABASE_URL not set, revoked tokens are accepted until they expire");
+            Arc::new(auth_service)
+        }
+    }
+}
+
+/// Processed messages are remembered so redelivered ones don't notify the users twice.
+fn setup_idempotency_store(config: &Config, db_pool: Arc<PgPool>) -> Box<dyn IdempotencyStore> {
+    match config.idempotency_store.as_str() {
+        "postgres" => Box::new(
+            PostgresIdempotencyStore::new(db_pool, config.broker.group_id())
+                .with_ttl(Duration::from_secs(config.idempotency_ttl)),
+        ),
+        "memory" => Box::new(MemoryIdempotencyStore::new(config.idempotency_cache_size)),
+        store => panic!("unsupported idempotency store: {}", store),
+    }
+}
+
+fn setup_events_pipeline(
+    consumer: Box<dyn Consumer>,
+    idempotency_store: Box<dyn IdempotencyStore>,
     ws_server: &Addr<WebsocketServer>,
     subscription_repo: Arc<dyn SubscriptionRepository>,
-) {
+    config: &Config,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
     let ws_sender = WsSenderWrapper::new(ws_server.clone());
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout);
+    let workers = config.pipeline_workers;
+    let max_in_flight = config.pipeline_max_in_flight;
+    let processor_timeout = Duration::from_secs(config.processor_timeout);
+    let user_deleted_attempts = config.user_deleted_attempts;
 
     actix_rt::spawn(async move {
-        consumer
-            .subscribe(&[NEWS_CREATED_EVENT])
-            .expect("Error subscribing to topic");
-        let processor = NewsWebsocketProcessor::new(&ws_sender, subscription_repo);
-        let consumer = KafkaConsumer::new(consumer);
-        let pipeline = DataPipeline::new(&consumer, &processor);
-
-        pipeline.start().await;
-    });
+        let processor_metrics = ProcessorMetrics::new(prometheus::default_registry())
+            .expect("failed registering processor metrics");
+        let news_processor = NewsWebsocketProcessor::new(&ws_sender, subscription_repo.clone())
+            .catch_panic()
+            .with_timeout(processor_timeout)
+            .with_metrics("news_websocket", &processor_metrics);
+        // the offset is committed even when it fails, so transient failures are retried
+        let user_deleted_processor = UserDeletedProcessor::new(&ws_sender, subscription_repo)
+            .catch_panic()
+            .with_timeout(processor_timeout)
+            .with_retry(user_deleted_attempts)
+            .with_metrics("user_deleted", &processor_metrics);
+        let router = TopicRouter::new()
+            .route_with(
+                NEWS_CREATED_EVENT,
+                &news_processor,
+                NewsCreatedMessage::decode,
+            )
+            .route(USER_DELETED_EVENT, &user_deleted_processor);
+        let metrics = PipelineMetrics::new(prometheus::default_registry())
+            .expect("failed registering pipeline metrics");
+        let pipeline = DataPipeline::new(consumer.as_ref(), &router)
+            .with_shutdown_timeout(shutdown_timeout)
+            .with_workers(workers)
+            .with_max_in_flight(max_in_flight)
+            .with_idempotency_store(idempotency_store.as_ref())
+            .with_metrics(&metrics);
+
+        pipeline
+            .start(shutdown)
+            .await
+            .expect("failed starting events pipeline");
+    })
 }
@@ -1,12 +1,61 @@
 use async_trait::async_trait;
+use futures::future::join_all;
 use log::{debug, info};
+use serde::Deserialize;
 use std::sync::Arc;
+use tracing::instrument;
 use utils::{
     error::{CommonError, DatabaseError, SerializationError},
+    events::Event,
     http::websockets::{ws_sender::WebsocketServerSender, ws_server::SessionMessage},
-    news::{models::news::News, repositories::subscription_repository::SubscriptionRepository},
-    pipeline::processor::Processor,
+    news::{
+        events::{NEWS_CREATED_EVENT, NEWS_CREATED_VERSION, NEWS_EVENTS_SOURCE},
+        models::news::News,
+        repositories::subscription_repository::SubscriptionRepository,
+    },
+    pipeline::{
+        consumer::ConsumedMessage, idempotency::message_id, processor::Processor,
+        router::decode_json,
+    },
 };
+use uuid::Uuid;
+
+/// Version of the bare news the scrappers published before the event envelope.
+pub const LEGACY_NEWS_CREATED_VERSION: u32 = 0;
+
+/// `news_created` message, either an event or a bare news of a scrapper not upgraded yet.
+/// The bare news are read as events of the legacy version during the migration, routed
+/// with `NewsCreatedMessage::decode`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct NewsCreatedMessage(pub Event);
+
+impl NewsCreatedMessage {
+    /// The id of the events made up for the bare news derives from the position of the
+    /// message, like `message_id`, so it's the same every time the message is read.
+    pub fn decode(message: &ConsumedMessage) -> Result<Self, SerializationError> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Payload {
+            Event(Event),
+            News(News),
+        }
+
+        match decode_json::<Payload>(message)? {
+            Payload::Event(event) => Ok(NewsCreatedMessage(event)),
+            Payload::News(news) => {
+                let mut event = Event::new(
+                    NEWS_CREATED_EVENT,
+                    NEWS_EVENTS_SOURCE,
+                    LEGACY_NEWS_CREATED_VERSION,
+                    &news,
+                )?;
+                event.id = Uuid::new_v5(&Uuid::NAMESPACE_OID, message_id(message).as_bytes());
+                Ok(NewsCreatedMessage(event))
+            }
+        }
+    }
+}
 
 pub struct NewsWebsocketProcessor<'a> {
     websocket_server: &'a dyn WebsocketServerSender,
@@ -23,28 +72,46 @@ impl<'a> NewsWebsocketProcessor<'a> {
             subscription_repo,
         }
     }
+
+    fn decode(&self, event: &Event) -> Result<News, SerializationError> {
+        match (event.event_type.as_str(), event.dataversion) {
+            (NEWS_CREATED_EVENT, LEGACY_NEWS_CREATED_VERSION | NEWS_CREATED_VERSION) => {
+                event.data::<News>()
+            }
+            (event_type, version) => Err(SerializationError::new(
+                format!("unsupported event {} v{}", event_type, version).as_str(),
+            )),
+        }
+    }
 }
 
 #[async_trait]
-impl<'a> Processor for NewsWebsocketProcessor<'a> {
-    async fn process(&self, payload: &str) -> Result<(), CommonError> {
-        info!("Received message: {}", payload);
-        let news: News = serde_json::from_str(payload).map_err(|err| {
-            SerializationError::new(format!("failed to convert JSON string: {}", err).as_str())
+impl<'a> Processor<NewsCreatedMessage> for NewsWebsocketProcessor<'a> {
+    #[instrument(skip_all, name = "news_websocket.process", fields(event = %message.0.id))]
+    async fn process(&self, message: &NewsCreatedMessage) -> Result<(), CommonError> {
+        let event = &message.0;
+        info!("Received event: {}", event.id);
+        let news = self.decode(event)?;
+        // websocket clients receive the news itself, not the envelope
+        let message = serde_json::to_string(&news).map_err(|err| {
+            SerializationError::new(format!("failed to serialize news: {}", err).as_str())
         })?;
 
         match self.subscription_repo.list_by_feed(news.feed_id) {
             Ok(subscriptions) => {
-                for s in subscriptions {
+                let sends = subscriptions.into_iter().map(|s| {
                     debug!("sending message to socket {}", s.user_id);
-                    // TODO: send messages concurrently
-                    self.websocket_server
-                        .do_send(SessionMessage {
-                            id: s.user_id.to_string(),
-                            message: payload.to_string(),
-                        })
-                        .await?;
-                }
+                    self.websocket_server.do_send(SessionMessage {
+                        id: s.user_id.to_string(),
+                        message: message.clone(),
+                    })
+                });
+
+                // every subscriber is sent the news even when sending to another failed
+                join_all(sends)
+                    .await
+                    .into_iter()
+                    .collect::<Result<(), _>>()?;
 
                 Ok(())
             }
@@ -71,6 +138,27 @@ mod tests {
         news::repositories::subscription_repository::MockSubscriptionRepository,
     };
 
+    fn consumed(payload: &str, offset: i64) -> ConsumedMessage {
+        ConsumedMessage {
+            topic: NEWS_CREATED_EVENT.to_string(),
+            partition: 0,
+            offset,
+            key: None,
+            payload: payload.to_string(),
+            headers: std::collections::HashMap::new(),
+        }
+    }
+
+    const NEWS: &str = r#"{"id":"f130494f-711e-4bb3-940a-3d50bb65e521","author":"author1","url":"","title":"news test","feed_id":"63a0ae94-1ad8-45fd-acc6-9c68f58e28af","publish_date":"2023-05-13"}"#;
+
+    fn news_created_event(version: u32) -> NewsCreatedMessage {
+        let news: News = serde_json::from_str(NEWS).unwrap();
+
+        NewsCreatedMessage(
+            Event::new(NEWS_CREATED_EVENT, NEWS_EVENTS_SOURCE, version, &news).unwrap(),
+        )
+    }
+
     #[tokio::test]
     async fn test_process_success() {
         // Create mock objects
@@ -78,7 +166,7 @@ mod tests {
         let mut mock_subscription_repo = MockSubscriptionRepository::new();
 
         // Define expected inputs and outputs
-        let payload = r#"{"id":"f130494f-711e-4bb3-940a-3d50bb65e521","author":"author1","url":"","title":"news test","feed_id":"63a0ae94-1ad8-45fd-acc6-9c68f58e28af","publish_date":"2023-05-13"}"#;
+        let event = news_created_event(NEWS_CREATED_VERSION);
         let subscriptions = vec![
             Subscription {
                 feed_id: uuid::Uuid::from_str("63a0ae94-1ad8-45fd-acc6-9c68f58e28af").unwrap(),
@@ -112,7 +200,7 @@ mod tests {
             NewsWebsocketProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo));
 
         // Perform the test
-        let result = processor.process(payload).await;
+        let result = processor.process(&event).await;
 
         // Check the result
         assert!(result.is_ok());
@@ -125,20 +213,22 @@ mod tests {
         let mock_subscription_repo = MockSubscriptionRepository::new();
 
         // Define expected input
-        let payload = "invalid json";
+        let event = NewsCreatedMessage(
+            Event::new(NEWS_CREATED_EVENT, NEWS_EVENTS_SOURCE, 1, &"invalid news").unwrap(),
+        );
 
         // Create the processor instance
         let processor =
             NewsWebsocketProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo));
 
         // Perform the test
-        let result = processor.process(payload).await;
+        let result = processor.process(&event).await;
 
         // Check the result
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().to_string(),
-            "Error: failed to convert JSON string: expected value at line 1 column 1, Code: 6"
+            "Error: failed to deserialize news_created v1 data: invalid type: string \"invalid news\", expected struct News, Code: 6"
         );
     }
 
@@ -149,7 +239,7 @@ mod tests {
         let mut mock_subscription_repo = MockSubscriptionRepository::new();
 
         // Define expected inputs and outputs
-        let payload = r#"{"id":"f130494f-711e-4bb3-940a-3d50bb65e521","author":"author1","url":"","title":"news test","feed_id":"63a0ae94-1ad8-45fd-acc6-9c68f58e28af","publish_date":"2023-05-13"}"#;
+        let event = news_created_event(NEWS_CREATED_VERSION);
 
         let error_message = "Failed to fetch subscriptions";
 
@@ -168,7 +258,7 @@ mod tests {
             NewsWebsocketProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo));
 
         // Perform the test
-        let result = processor.process(payload).await;
+        let result = processor.process(&event).await;
 
         // Check the result
         assert!(result.is_err());
@@ -180,4 +270,71 @@ mod tests {
             ),
         );
     }
+
+    #[tokio::test]
+    async fn test_process_bare_news() {
+        let mut mock_websocket_server = MockWebsocketServerSender::new();
+        let mut mock_subscription_repo = MockSubscriptionRepository::new();
+
+        // scrappers published the news without the envelope before
+        let message = NewsCreatedMessage::decode(&consumed(NEWS, 3)).unwrap();
+        assert_eq!(message.0.dataversion, LEGACY_NEWS_CREATED_VERSION);
+        assert_eq!(message.0.source, NEWS_EVENTS_SOURCE);
+        // the id is kept when the message is read again, not when another one is read
+        let redelivered = NewsCreatedMessage::decode(&consumed(NEWS, 3)).unwrap();
+        assert_eq!(redelivered.0.id, message.0.id);
+        let other = NewsCreatedMessage::decode(&consumed(NEWS, 4)).unwrap();
+        assert_ne!(other.0.id, message.0.id);
+        let news: News = serde_json::from_str(NEWS).unwrap();
+        let sent = serde_json::to_string(&news).unwrap();
+
+        mock_websocket_server
+            .expect_do_send()
+            .times(1)
+            .withf(move |message| message.message == sent)
+            .return_const(Ok(()));
+        mock_subscription_repo
+            .expect_list_by_feed()
+            .return_const(Ok(vec![Subscription {
+                feed_id: uuid::Uuid::from_str("63a0ae94-1ad8-45fd-acc6-9c68f58e28af").unwrap(),
+                user_id: uuid::Uuid::from_str("9454decf-b36d-436e-96e1-f31a9a2f3d68").unwrap(),
+            }]));
+        let processor =
+            NewsWebsocketProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo));
+
+        assert!(processor.process(&message).await.is_ok());
+
+        // events are read as they are
+        let event = news_created_event(NEWS_CREATED_VERSION);
+        let decoded =
+            NewsCreatedMessage::decode(&consumed(&event.0.to_json().unwrap(), 5)).unwrap();
+        assert_eq!(decoded, event);
+    }
+
+    #[tokio::test]
+    async fn test_process_unsupported_version() {
+        // Create mock objects
+        let mut mock_websocket_server = MockWebsocketServerSender::new();
+        let mut mock_subscription_repo = MockSubscriptionRepository::new();
+
+        // Define expected input
+        let event = news_created_event(NEWS_CREATED_VERSION + 1);
+
+        // Set up expectations
+        mock_websocket_server.expect_do_send().times(0);
+        mock_subscription_repo.expect_list_by_feed().times(0);
+
+        // Create the processor instance
+        let processor =
+            NewsWebsocketProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo));
+
+        // Perform the test
+        let result = processor.process(&event).await;
+
+        // Check the result
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: unsupported event news_created v2, Code: 6"
+        );
+    }
 }
@@ -0,0 +1,201 @@
+use async_trait::async_trait;
+use log::info;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::instrument;
+use utils::{
+    error::{CommonError, DatabaseError, SerializationError},
+    events::Event,
+    http::websockets::{ws_sender::WebsocketServerSender, ws_server::CloseSession},
+    news::repositories::subscription_repository::SubscriptionRepository,
+    pipeline::processor::Processor,
+};
+use uuid::Uuid;
+
+/// Published by the users service once a user is deleted.
+pub const USER_DELETED_EVENT: &str = "user_deleted";
+pub const USER_DELETED_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct UserDeleted {
+    user_id: Uuid,
+}
+
+/// Forgets the deleted users, their subscriptions are deleted and their websocket
+/// session is closed. Processing the same event twice is harmless.
+pub struct UserDeletedProcessor<'a> {
+    websocket_server: &'a dyn WebsocketServerSender,
+    subscription_repo: Arc<dyn SubscriptionRepository>,
+}
+
+impl<'a> UserDeletedProcessor<'a> {
+    pub fn new(
+        websocket_server: &'a dyn WebsocketServerSender,
+        subscription_repo: Arc<dyn SubscriptionRepository>,
+    ) -> Self {
+        UserDeletedProcessor {
+            websocket_server,
+            subscription_repo,
+        }
+    }
+
+    fn decode(&self, event: &Event) -> Result<UserDeleted, SerializationError> {
+        match (event.event_type.as_str(), event.dataversion) {
+            (USER_DELETED_EVENT, USER_DELETED_VERSION) => event.data::<UserDeleted>(),
+            (event_type, version) => Err(SerializationError::new(
+                format!("unsupported event {} v{}", event_type, version).as_str(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> Processor<Event> for UserDeletedProcessor<'a> {
+    #[instrument(skip_all, name = "user_deleted.process", fields(event = %event.id))]
+    async fn process(&self, event: &Event) -> Result<(), CommonError> {
+        let deleted = self.decode(event)?;
+
+        let subscriptions = self
+            .subscription_repo
+            .delete_by_user(deleted.user_id)
+            .map_err(|err| {
+                DatabaseError::new(
+                    format!("failed deleting subscriptions: {}", err.message).as_str(),
+                )
+            })?;
+        info!(
+            "deleted {} subscriptions of deleted user {}",
+            subscriptions, deleted.user_id
+        );
+
+        self.websocket_server
+            .close(CloseSession {
+                id: deleted.user_id.to_string(),
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::{predicate::*, Sequence};
+    use std::time::Duration;
+    use utils::{
+        http::websockets::ws_sender::MockWebsocketServerSender,
+        news::repositories::subscription_repository::MockSubscriptionRepository,
+        pipeline::middleware::ProcessorExt,
+    };
+
+    fn user_deleted_event(user_id: Uuid, version: u32) -> Event {
+        Event::new(
+            USER_DELETED_EVENT,
+            "users",
+            version,
+            &serde_json::json!({ "user_id": user_id }),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_process_forgets_user() {
+        let user_id = Uuid::new_v4();
+        let mut mock_websocket_server = MockWebsocketServerSender::new();
+        let mut mock_subscription_repo = MockSubscriptionRepository::new();
+        mock_subscription_repo
+            .expect_delete_by_user()
+            .with(eq(user_id))
+            .times(1)
+            .return_const(Ok(2));
+        mock_websocket_server
+            .expect_close()
+            .withf(move |message| message.id == user_id.to_string())
+            .times(1)
+            .return_const(Ok(()));
+        let processor =
+            UserDeletedProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo));
+
+        let result = processor
+            .process(&user_deleted_event(user_id, USER_DELETED_VERSION))
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_process_failed_database() {
+        let mut mock_websocket_server = MockWebsocketServerSender::new();
+        let mut mock_subscription_repo = MockSubscriptionRepository::new();
+        mock_subscription_repo
+            .expect_delete_by_user()
+            .return_const(Err(DatabaseError::new("db is down")));
+        // the session is only closed once the subscriptions are gone
+        mock_websocket_server.expect_close().times(0);
+        let processor =
+            UserDeletedProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo));
+
+        let result = processor
+            .process(&user_deleted_event(Uuid::new_v4(), USER_DELETED_VERSION))
+            .await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: failed deleting subscriptions: db is down, Code: 1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_retried_after_failed_database() {
+        let user_id = Uuid::new_v4();
+        let mut mock_websocket_server = MockWebsocketServerSender::new();
+        let mut mock_subscription_repo = MockSubscriptionRepository::new();
+        let mut sequence = Sequence::new();
+        mock_subscription_repo
+            .expect_delete_by_user()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_const(Err(DatabaseError::new("db is down")));
+        mock_subscription_repo
+            .expect_delete_by_user()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_const(Ok(2));
+        mock_websocket_server
+            .expect_close()
+            .times(1)
+            .return_const(Ok(()));
+        let processor =
+            UserDeletedProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo))
+                .with_retry(2)
+                .with_backoff(Duration::ZERO);
+
+        let result = processor
+            .process(&user_deleted_event(user_id, USER_DELETED_VERSION))
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_process_unsupported_version() {
+        let mock_websocket_server = MockWebsocketServerSender::new();
+        let mut mock_subscription_repo = MockSubscriptionRepository::new();
+        mock_subscription_repo.expect_delete_by_user().times(0);
+        let processor =
+            UserDeletedProcessor::new(&mock_websocket_server, Arc::new(mock_subscription_repo));
+
+        let result = processor
+            .process(&user_deleted_event(
+                Uuid::new_v4(),
+                USER_DELETED_VERSION + 1,
+            ))
+            .await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: unsupported event user_deleted v2, Code: 6"
+        );
+    }
+}
@@ -0,0 +1,2 @@
+mod news_created_test;
+mod user_deleted_test;
@@ -0,0 +1,134 @@
+use std::sync::{Arc, Mutex};
+
+use actix::MailboxError;
+use async_trait::async_trait;
+use chrono::Utc;
+use news::news_websocket_processor::{NewsCreatedMessage, NewsWebsocketProcessor};
+use tokio_util::sync::CancellationToken;
+use utils::{
+    http::websockets::{
+        ws_sender::WebsocketServerSender,
+        ws_server::{CloseSession, SessionMessage},
+    },
+    news::{
+        events::NEWS_CREATED_EVENT,
+        models::{news::News, subscription::Subscription},
+        repositories::{
+            feed_repository::MockFeedRepository, news_repository::MockNewsRepository,
+            subscription_repository::MockSubscriptionRepository,
+        },
+        services::news_service::{NewsService, Service},
+    },
+    outbox::{models::OutboxMessage, relay::OutboxRelay, repository::MockOutboxRepository},
+    pipeline::{data_pipeline::DataPipeline, memory_broker::MemoryBroker, router::TopicRouter},
+};
+use uuid::Uuid;
+
+/// Records the messages sent to websocket sessions and stops the pipeline on the first one.
+struct RecordingWebsocketServer {
+    shutdown: CancellationToken,
+    messages: Mutex<Vec<(String, String)>>,
+}
+
+#[async_trait]
+impl WebsocketServerSender for RecordingWebsocketServer {
+    async fn do_send(&self, message: SessionMessage) -> Result<(), MailboxError> {
+        self.messages
+            .lock()
+            .unwrap()
+            .push((message.id, message.message));
+        self.shutdown.cancel();
+        Ok(())
+    }
+
+    async fn close(&self, _: CloseSession) -> Result<(), MailboxError> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_news_created_is_sent_to_subscribed_sessions() {
+    let broker = MemoryBroker::new();
+    let feed_id = Uuid::new_v4();
+    let user_id = Uuid::new_v4();
+    let news = News {
+        id: Uuid::new_v4(),
+        author: "author".to_string(),
+        url: "https://news.com/1".to_string(),
+        title: "news title".to_string(),
+        publish_date: None,
+        feed_id,
+    };
+
+    // news-scrapper side: inserting news stores the news_created event in the outbox
+    let outbox = Arc::new(Mutex::new(vec![]));
+    let stored = outbox.clone();
+    let mut news_repo = MockNewsRepository::new();
+    news_repo.expect_find_by_fields().returning(|_, _| Ok(None));
+    news_repo.expect_create().returning(move |news, events| {
+        stored.lock().unwrap().extend(events.iter().cloned());
+        Ok(news.clone())
+    });
+    let news_service = Service::new(
+        Arc::new(MockFeedRepository::new()),
+        Arc::new(news_repo),
+        Arc::new(MockSubscriptionRepository::new()),
+    );
+    news_service.insert_news(&news).await.unwrap();
+
+    // and the outbox relay publishes it
+    let mut outbox_repo = MockOutboxRepository::new();
+    outbox_repo.expect_claim().returning(move |_, _| {
+        Ok(outbox
+            .lock()
+            .unwrap()
+            .drain(..)
+            .enumerate()
+            .map(|(id, message)| OutboxMessage {
+                id: id as i64,
+                topic: message.topic,
+                key: message.key,
+                payload: message.payload,
+                headers: message.headers,
+                created_at: Utc::now(),
+                sent_at: None,
+                attempts: 0,
+                locked_until: None,
+                last_error: None,
+                failed_at: None,
+            })
+            .collect())
+    });
+    outbox_repo
+        .expect_mark_sent()
+        .returning(|ids| Ok(ids.len()));
+    let relay = OutboxRelay::new(Arc::new(outbox_repo), Arc::new(broker.producer()));
+    assert_eq!(relay.relay_batch().await, 1);
+
+    // news side: the pipeline forwards the event to the subscribed users
+    let mut subscription_repo = MockSubscriptionRepository::new();
+    subscription_repo
+        .expect_list_by_feed()
+        .returning(move |feed_id| Ok(vec![Subscription { feed_id, user_id }]));
+    let shutdown = CancellationToken::new();
+    let websocket_server = RecordingWebsocketServer {
+        shutdown: shutdown.clone(),
+        messages: Mutex::new(vec![]),
+    };
+    let consumer = broker.consumer("news");
+    let processor = NewsWebsocketProcessor::new(&websocket_server, Arc::new(subscription_repo));
+    let router =
+        TopicRouter::new().route_with(NEWS_CREATED_EVENT, &processor, NewsCreatedMessage::decode);
+
+    DataPipeline::new(&consumer, &router)
+        .start(shutdown)
+        .await
+        .unwrap();
+
+    let messages = websocket_server.messages.lock().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].0, user_id.to_string());
+    let sent_news: News = serde_json::from_str(&messages[0].1).unwrap();
+    assert_eq!(sent_news, news);
+    assert_eq!(broker.committed_offset("news", NEWS_CREATED_EVENT), Some(1));
+}
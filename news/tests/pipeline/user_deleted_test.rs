@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex};
+
+use actix::MailboxError;
+use async_trait::async_trait;
+use news::user_deleted_processor::{UserDeletedProcessor, USER_DELETED_EVENT};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use utils::{
+    events::Event,
+    http::websockets::{
+        ws_sender::WebsocketServerSender,
+        ws_server::{CloseSession, SessionMessage},
+    },
+    news::repositories::subscription_repository::MockSubscriptionRepository,
+    pipeline::{
+        data_pipeline::DataPipeline,
+        memory_broker::MemoryBroker,
+        producer::{Producer, ProducerMessage},
+        router::TopicRouter,
+    },
+};
+use uuid::Uuid;
+
+/// Records the closed websocket sessions and stops the pipeline on the first one.
+struct RecordingWebsocketServer {
+    shutdown: CancellationToken,
+    closed: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl WebsocketServerSender for RecordingWebsocketServer {
+    async fn do_send(&self, _: SessionMessage) -> Result<(), MailboxError> {
+        Ok(())
+    }
+
+    async fn close(&self, message: CloseSession) -> Result<(), MailboxError> {
+        self.closed.lock().unwrap().push(message.id);
+        self.shutdown.cancel();
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_user_deleted_forgets_the_user() {
+    let broker = MemoryBroker::new();
+    let user_id = Uuid::new_v4();
+
+    // users side: the outbox relay publishes the user_deleted event keyed by user
+    let event = Event::new(
+        USER_DELETED_EVENT,
+        "users",
+        1,
+        &json!({ "user_id": user_id }),
+    )
+    .unwrap();
+    broker
+        .producer()
+        .send(ProducerMessage::new(
+            USER_DELETED_EVENT,
+            &user_id.to_string(),
+            &event.to_json().unwrap(),
+        ))
+        .await
+        .unwrap();
+
+    // news side: the subscriptions are deleted and the session closed
+    let mut subscription_repo = MockSubscriptionRepository::new();
+    subscription_repo
+        .expect_delete_by_user()
+        .withf(move |id| *id == user_id)
+        .times(1)
+        .returning(|_| Ok(3));
+    let shutdown = CancellationToken::new();
+    let websocket_server = RecordingWebsocketServer {
+        shutdown: shutdown.clone(),
+        closed: Mutex::new(vec![]),
+    };
+    let consumer = broker.consumer("news");
+    let processor = UserDeletedProcessor::new(&websocket_server, Arc::new(subscription_repo));
+    let router = TopicRouter::new().route(USER_DELETED_EVENT, &processor);
+
+    DataPipeline::new(&consumer, &router)
+        .start(shutdown)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *websocket_server.closed.lock().unwrap(),
+        vec![user_id.to_string()]
+    );
+    assert_eq!(broker.committed_offset("news", USER_DELETED_EVENT), Some(1));
+}
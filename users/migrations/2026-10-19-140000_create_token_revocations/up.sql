@@ -0,0 +1,12 @@
+CREATE TABLE revoked_tokens (
+    jti TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX revoked_tokens_expires_at_idx ON revoked_tokens (expires_at);
+
+CREATE TABLE revoked_users (
+    user_id TEXT PRIMARY KEY,
+    revoked_before TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,19 @@
+-- failed logins by account name or client ip, names are tracked even when unknown
+CREATE TABLE login_throttles (
+    key TEXT PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    last_failure_at TIMESTAMPTZ NOT NULL,
+    locked_until TIMESTAMPTZ
+);
+
+CREATE TABLE lockout_events (
+    id UUID PRIMARY KEY,
+    key TEXT NOT NULL,
+    action TEXT NOT NULL CHECK (action IN ('locked', 'unlocked')),
+    ip TEXT,
+    actor_id UUID,
+    locked_until TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX lockout_events_key_idx ON lockout_events (key, created_at);
@@ -0,0 +1,12 @@
+-- users without a row have the default preferences
+CREATE TABLE user_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    display_name TEXT,
+    timezone TEXT NOT NULL DEFAULT 'UTC',
+    locale TEXT NOT NULL DEFAULT 'en',
+    notifications TEXT NOT NULL DEFAULT 'realtime'
+        CHECK (notifications IN ('realtime', 'digest', 'off')),
+    -- local hour of the timezone the digest is sent at
+    digest_hour SMALLINT NOT NULL DEFAULT 8 CHECK (digest_hour BETWEEN 0 AND 23),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -5,23 +5,72 @@ use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
 use actix_web::{error::Error, web, App};
 use utils::http::services::auth_service::{AuthService, JwtAuthService};
+use utils::http::services::personal_token_store::cache::CachedPersonalTokenStore;
+use utils::http::services::personal_token_store::postgres::PostgresPersonalTokenStore;
+use utils::http::services::personal_token_store::PersonalTokenStore;
+use utils::http::services::revocation_store::cache::CachedRevocationStore;
+use utils::http::services::revocation_store::postgres::PostgresRevocationStore;
+use utils::http::services::revocation_store::RevocationStore;
 use utils::http::websockets::ws_handler::get_ws;
 use utils::http::websockets::ws_server::WebsocketServer;
-use utils::{broker, db, http::utils::build_server};
+use utils::mail::Mailer;
+use utils::{db, http::utils::build_server};
 
 use crate::config::Config;
-use crate::handlers::auth::{login_handler, logout_handler, me_handler};
+use crate::handlers::auth::{
+    change_password_handler, confirm_mfa_handler, enroll_mfa_handler, forgot_password_handler,
+    login_handler, logout_handler, me_handler, oidc_callback_handler, oidc_login_handler,
+    refresh_handler, resend_verification_handler, reset_password_handler, revoke_all_handler,
+    revoke_handler, verify_email_handler, verify_mfa_handler,
+};
 use crate::handlers::health::get_health;
 use crate::handlers::index::get_index;
 use crate::handlers::message::create_message;
-use crate::handlers::user::{create_user, delete_user, get_user_by_id, get_users, update_user};
+use crate::handlers::personal_token::{
+    create_personal_token, get_personal_tokens, revoke_personal_token,
+};
+use crate::handlers::preferences::{get_preferences, update_preferences};
+use crate::handlers::user::{
+    create_user, delete_user, get_user_by_id, get_users, unlock_user, update_user,
+};
+use crate::oidc::{HttpOidcClient, OidcSettings};
+use crate::repositories::email_verification_repository::{
+    EmailVerificationDieselRepository, EmailVerificationRepository,
+};
+use crate::repositories::login_throttle_repository::{
+    LoginThrottleDieselRepository, LoginThrottleRepository,
+};
+use crate::repositories::mfa_repository::{MfaDieselRepository, MfaRepository};
+use crate::repositories::oidc_repository::{OidcDieselRepository, OidcRepository};
+use crate::repositories::password_reset_repository::{
+    PasswordResetDieselRepository, PasswordResetRepository,
+};
+use crate::repositories::personal_token_repository::{
+    PersonalTokenDieselRepository, PersonalTokenRepository,
+};
+use crate::repositories::preferences_repository::{
+    PreferencesDieselRepository, PreferencesRepository,
+};
+use crate::repositories::refresh_token_repository::{
+    RefreshTokenDieselRepository, RefreshTokenRepository,
+};
 use crate::repositories::user_repository::{UserDieselRepository, UserRepository};
-use crate::services::event_service::KafkaEventService;
+use crate::services::email_verification_service::{
+    EmailVerificationService, EmailVerificationServiceImpl,
+};
+use crate::services::login_service::{LoginPolicy, LoginService, LoginServiceImpl};
+use crate::services::mfa_service::{MfaService, MfaServiceImpl};
+use crate::services::oidc_service::{OidcService, OidcServiceImpl};
+use crate::services::password_reset_service::{PasswordResetService, PasswordResetServiceImpl};
+use crate::services::personal_token_service::{PersonalTokenService, PersonalTokenServiceImpl};
+use crate::services::preferences_service::{PreferencesService, PreferencesServiceImpl};
+use crate::services::token_service::{TokenService, TokenServiceImpl};
 use crate::services::user_service::{UserService, UserServiceImpl};
 
 pub fn setup_app(
     config: &Config,
     ws_server: Addr<WebsocketServer>,
+    mailer: Arc<dyn Mailer>,
 ) -> App<
     impl ServiceFactory<
         ServiceRequest,
@@ -32,28 +81,133 @@ pub fn setup_app(
     >,
 > {
     let db_connection = db::connect_db(config.database_url.clone());
-    let kafka_producer = broker::create_producer(config.kafka_url.clone());
 
     // repositories
     let user_repo: Arc<dyn UserRepository> =
         Arc::new(UserDieselRepository::new(Arc::new(db_connection.clone())));
+    let refresh_token_repo: Arc<dyn RefreshTokenRepository> = Arc::new(
+        RefreshTokenDieselRepository::new(Arc::new(db_connection.clone())),
+    );
+    let email_verification_repo: Arc<dyn EmailVerificationRepository> = Arc::new(
+        EmailVerificationDieselRepository::new(Arc::new(db_connection.clone())),
+    );
+    let password_reset_repo: Arc<dyn PasswordResetRepository> = Arc::new(
+        PasswordResetDieselRepository::new(Arc::new(db_connection.clone())),
+    );
+    let login_throttle_repo: Arc<dyn LoginThrottleRepository> = Arc::new(
+        LoginThrottleDieselRepository::new(Arc::new(db_connection.clone())),
+    );
+    let mfa_repo: Arc<dyn MfaRepository> =
+        Arc::new(MfaDieselRepository::new(Arc::new(db_connection.clone())));
+    let preferences_repo: Arc<dyn PreferencesRepository> = Arc::new(
+        PreferencesDieselRepository::new(Arc::new(db_connection.clone())),
+    );
+    let personal_token_repo: Arc<dyn PersonalTokenRepository> = Arc::new(
+        PersonalTokenDieselRepository::new(Arc::new(db_connection.clone())),
+    );
 
     // services
-    let events_service = Arc::new(KafkaEventService::new(kafka_producer.clone()));
-    let user_service: Arc<dyn UserService> =
-        Arc::new(UserServiceImpl::new(user_repo, events_service));
-    let auth_service: Arc<dyn AuthService> =
-        Arc::new(JwtAuthService::new(config.jwt_secret.clone()));
+    let user_service: Arc<dyn UserService> = Arc::new(UserServiceImpl::new(user_repo.clone()));
+    let revocation_store: Arc<dyn RevocationStore> = Arc::new(CachedRevocationStore::new(
+        Arc::new(PostgresRevocationStore::new(Arc::new(
+            db_connection.clone(),
+        ))),
+        config.revocation_cache_size,
+        config.revocation_cache_ttl,
+    ));
+    // revoked personal tokens go unnoticed as long as revocations do
+    let personal_token_store: Arc<dyn PersonalTokenStore> =
+        Arc::new(CachedPersonalTokenStore::new(
+            Arc::new(PostgresPersonalTokenStore::new(Arc::new(
+                db_connection.clone(),
+            ))),
+            config.revocation_cache_size,
+            config.revocation_cache_ttl,
+        ));
+    let auth_service: Arc<dyn AuthService> = Arc::new(
+        JwtAuthService::new(config.jwt_secret.clone())
+            .with_revocation_store(revocation_store)
+            .with_personal_token_store(personal_token_store),
+    );
+    let token_service: Arc<dyn TokenService> = Arc::new(TokenServiceImpl::new(
+        refresh_token_repo,
+        user_repo.clone(),
+        auth_service.clone(),
+        config.jwt_expires_in,
+        config.refresh_token_expires_in,
+    ));
+    let mfa_service: Arc<dyn MfaService> = Arc::new(MfaServiceImpl::new(
+        mfa_repo,
+        &config.mfa_issuer,
+        config.mfa_skew,
+    ));
+    let login_service: Arc<dyn LoginService> = Arc::new(LoginServiceImpl::new(
+        login_throttle_repo,
+        user_repo.clone(),
+        mfa_service.clone(),
+        LoginPolicy {
+            max_account_failures: config.login_max_failures,
+            max_ip_failures: config.login_ip_max_failures,
+            delay: chrono::Duration::from_std(config.login_delay).unwrap(),
+            max_delay: chrono::Duration::from_std(config.login_max_delay).unwrap(),
+            lockout: chrono::Duration::from_std(config.login_lockout).unwrap(),
+        },
+    ));
+    let personal_token_service: Arc<dyn PersonalTokenService> =
+        Arc::new(PersonalTokenServiceImpl::new(personal_token_repo));
+    let preferences_service: Arc<dyn PreferencesService> =
+        Arc::new(PreferencesServiceImpl::new(preferences_repo));
+    let email_verification_service: Arc<dyn EmailVerificationService> =
+        Arc::new(EmailVerificationServiceImpl::new(
+            email_verification_repo,
+            mailer.clone(),
+            &config.email_verification_url,
+            config.email_verification_expires_in,
+        ));
+    let oidc_service: Option<Arc<dyn OidcService>> = if config.oidc_issuer.is_empty() {
+        None
+    } else {
+        let oidc_repo: Arc<dyn OidcRepository> =
+            Arc::new(OidcDieselRepository::new(Arc::new(db_connection.clone())));
+        let client = HttpOidcClient::new(OidcSettings {
+            issuer: config.oidc_issuer.clone(),
+            client_id: config.oidc_client_id.clone(),
+            client_secret: Some(config.oidc_client_secret.clone()).filter(|s| !s.is_empty()),
+            redirect_url: config.oidc_redirect_url.clone(),
+            scopes: config.oidc_scopes.clone(),
+        });
+        Some(Arc::new(OidcServiceImpl::new(
+            Arc::new(client),
+            oidc_repo,
+            user_repo.clone(),
+            config.oidc_login_expires_in,
+        )))
+    };
+    let password_reset_service: Arc<dyn PasswordResetService> =
+        Arc::new(PasswordResetServiceImpl::new(
+            password_reset_repo,
+            user_repo,
+            mailer,
+            &config.password_reset_url,
+            config.password_reset_expires_in,
+        ));
 
     let jwt_config = Arc::new(config.clone());
 
-    build_server(config.cors_origin.clone())
+    let app = build_server(config.cors_origin.clone())
         .app_data(web::Data::new(db_connection.clone()))
         .app_data(web::Data::from(user_service.clone()))
         .app_data(web::Data::new(ws_server.clone()))
         .app_data(web::Data::new(config.clone()))
         .app_data(web::Data::from(jwt_config.clone()))
         .app_data(web::Data::from(auth_service.clone()))
+        .app_data(web::Data::from(token_service.clone()))
+        .app_data(web::Data::from(email_verification_service.clone()))
+        .app_data(web::Data::from(password_reset_service.clone()))
+        .app_data(web::Data::from(login_service.clone()))
+        .app_data(web::Data::from(mfa_service.clone()))
+        .app_data(web::Data::from(personal_token_service.clone()))
+        .app_data(web::Data::from(preferences_service.clone()))
         .service(get_index)
         .service(get_health)
         .service(get_ws)
@@ -62,8 +216,34 @@ pub fn setup_app(
         .service(create_user)
         .service(update_user)
         .service(delete_user)
+        .service(unlock_user)
         .service(login_handler)
         .service(logout_handler)
+        .service(refresh_handler)
+        .service(revoke_handler)
+        .service(revoke_all_handler)
+        .service(verify_email_handler)
+        .service(resend_verification_handler)
+        .service(forgot_password_handler)
+        .service(reset_password_handler)
+        .service(change_password_handler)
+        .service(verify_mfa_handler)
+        .service(enroll_mfa_handler)
+        .service(confirm_mfa_handler)
+        .service(create_personal_token)
+        .service(get_personal_tokens)
+        .service(revoke_personal_token)
+        .service(get_preferences)
+        .service(update_preferences)
         .service(me_handler)
-        .service(create_message)
+        .service(create_message);
+
+    // the logins with the identity provider only exist when one is configured
+    match oidc_service {
+        Some(oidc_service) => app
+            .app_data(web::Data::from(oidc_service))
+            .service(oidc_login_handler)
+            .service(oidc_callback_handler),
+        None => app,
+    }
 }
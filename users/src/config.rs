@@ -1,13 +1,63 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use utils::pipeline::backend::BrokerBackend;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub jwt_expires_in: i64,
     pub jwt_max_age: i64,
     pub jwt_secret: String,
-    pub kafka_url: String,
+    /// Minutes a refresh token can be exchanged for new tokens.
+    pub refresh_token_expires_in: i64,
+    /// How long other instances' revocations can go unnoticed.
+    pub revocation_cache_ttl: Duration,
+    pub revocation_cache_size: usize,
+    /// Page the verification links point to, with the token appended.
+    pub email_verification_url: String,
+    /// Minutes a verification link can be used.
+    pub email_verification_expires_in: i64,
+    /// Page the password reset links point to, with the token appended.
+    pub password_reset_url: String,
+    /// Minutes a password reset link can be used.
+    pub password_reset_expires_in: i64,
+    /// Failed logins of an account before it is locked.
+    pub login_max_failures: i32,
+    /// Failed logins from a client before it is locked.
+    pub login_ip_max_failures: i32,
+    /// Wait after a failed login, doubled after every other failure up to the max.
+    pub login_delay: Duration,
+    pub login_max_delay: Duration,
+    /// How long lockouts last.
+    pub login_lockout: Duration,
+    /// Proxies whose forwarded headers tell the address of the clients, the address of
+    /// the connection is used for the others.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Name of the service in the authenticator apps.
+    pub mfa_issuer: String,
+    /// Minutes to enter the second factor after the password.
+    pub mfa_token_expires_in: i64,
+    /// Steps of 30 seconds a code is accepted before and after the current one.
+    pub mfa_skew: i64,
+    /// Identity provider users can log in with, none when empty.
+    pub oidc_issuer: String,
+    pub oidc_client_id: String,
+    /// Empty for public clients, which only rely on PKCE.
+    pub oidc_client_secret: String,
+    /// Callback registered with the provider.
+    pub oidc_redirect_url: String,
+    pub oidc_scopes: String,
+    /// Minutes to log in at the provider.
+    pub oidc_login_expires_in: i64,
+    /// Emails of the users made admins at startup, once they verified them.
+    pub admin_emails: Vec<String>,
+    pub broker: BrokerBackend,
     pub server_port: String,
     pub cors_origin: String,
     pub logs_path: String,
+    pub otlp_endpoint: String,
+    pub shutdown_timeout: u64,
 }
 
 impl Config {
@@ -17,9 +67,52 @@ impl Config {
         let jwt_max_age = std::env::var("JWT_MAX_AGE").expect("JWT_MAX_AGE must be set");
         let jwt_expires_in = std::env::var("JWT_EXPIRED_IN").expect("JWT_EXPIRED_IN must be set");
         let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-        let kafka_url = std::env::var("KAFKA_URL").expect("KAFKA_URL must be set");
+        let refresh_token_expires_in =
+            std::env::var("REFRESH_TOKEN_EXPIRED_IN").unwrap_or_else(|_| String::from("43200"));
+        let revocation_cache_ttl =
+            std::env::var("REVOCATION_CACHE_TTL").unwrap_or_else(|_| String::from("5"));
+        let revocation_cache_size =
+            std::env::var("REVOCATION_CACHE_SIZE").unwrap_or_else(|_| String::from("10000"));
+        let email_verification_url = std::env::var("EMAIL_VERIFICATION_URL")
+            .unwrap_or_else(|_| String::from("http://localhost:3000/verify-email"));
+        let email_verification_expires_in =
+            std::env::var("EMAIL_VERIFICATION_EXPIRED_IN").unwrap_or_else(|_| String::from("1440"));
+        let password_reset_url = std::env::var("PASSWORD_RESET_URL")
+            .unwrap_or_else(|_| String::from("http://localhost:3000/reset-password"));
+        let password_reset_expires_in =
+            std::env::var("PASSWORD_RESET_EXPIRED_IN").unwrap_or_else(|_| String::from("60"));
+        let login_max_failures =
+            std::env::var("LOGIN_MAX_FAILURES").unwrap_or_else(|_| String::from("5"));
+        let login_ip_max_failures =
+            std::env::var("LOGIN_IP_MAX_FAILURES").unwrap_or_else(|_| String::from("50"));
+        let login_delay = std::env::var("LOGIN_DELAY").unwrap_or_else(|_| String::from("1"));
+        let login_max_delay =
+            std::env::var("LOGIN_MAX_DELAY").unwrap_or_else(|_| String::from("60"));
+        let login_lockout = std::env::var("LOGIN_LOCKOUT").unwrap_or_else(|_| String::from("15"));
+        let trusted_proxies = std::env::var("TRUSTED_PROXIES").unwrap_or_else(|_| String::from(""));
+        let mfa_issuer = std::env::var("MFA_ISSUER").unwrap_or_else(|_| String::from("rust-xp"));
+        let mfa_token_expires_in =
+            std::env::var("MFA_TOKEN_EXPIRED_IN").unwrap_or_else(|_| String::from("5"));
+        let mfa_skew = std::env::var("MFA_SKEW").unwrap_or_else(|_| String::from("1"));
+        let oidc_issuer = std::env::var("OIDC_ISSUER").unwrap_or_else(|_| String::from(""));
+        let oidc_client_id = std::env::var("OIDC_CLIENT_ID").unwrap_or_else(|_| String::from(""));
+        let oidc_client_secret =
+            std::env::var("OIDC_CLIENT_SECRET").unwrap_or_else(|_| String::from(""));
+        let oidc_redirect_url = std::env::var("OIDC_REDIRECT_URL")
+            .unwrap_or_else(|_| String::from("http://localhost:8000/auth/oidc/callback"));
+        let oidc_scopes =
+            std::env::var("OIDC_SCOPES").unwrap_or_else(|_| String::from("openid email profile"));
+        let oidc_login_expires_in =
+            std::env::var("OIDC_LOGIN_EXPIRED_IN").unwrap_or_else(|_| String::from("10"));
+        let admin_emails = std::env::var("ADMIN_EMAILS").unwrap_or_else(|_| String::from(""));
+        let broker = BrokerBackend::from_env("users")
+            .unwrap_or_else(|err| panic!("invalid broker config: {}", err.message));
         let logs_path = std::env::var("LOGS_PATH").unwrap_or_else(|_| String::from(""));
         let server_port = std::env::var("PORT").unwrap_or_else(|_| String::from("8000"));
+        let otlp_endpoint =
+            std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").unwrap_or_else(|_| String::from(""));
+        let shutdown_timeout =
+            std::env::var("SHUTDOWN_TIMEOUT").unwrap_or_else(|_| String::from("30"));
 
         Config {
             cors_origin,
@@ -27,9 +120,44 @@ impl Config {
             jwt_secret,
             jwt_expires_in: jwt_expires_in.parse::<i64>().unwrap(),
             jwt_max_age: jwt_max_age.parse::<i64>().unwrap(),
-            kafka_url,
+            refresh_token_expires_in: refresh_token_expires_in.parse::<i64>().unwrap(),
+            revocation_cache_ttl: Duration::from_secs(revocation_cache_ttl.parse::<u64>().unwrap()),
+            revocation_cache_size: revocation_cache_size.parse::<usize>().unwrap(),
+            email_verification_url,
+            email_verification_expires_in: email_verification_expires_in.parse::<i64>().unwrap(),
+            password_reset_url,
+            password_reset_expires_in: password_reset_expires_in.parse::<i64>().unwrap(),
+            login_max_failures: login_max_failures.parse::<i32>().unwrap(),
+            login_ip_max_failures: login_ip_max_failures.parse::<i32>().unwrap(),
+            login_delay: Duration::from_secs(login_delay.parse::<u64>().unwrap()),
+            login_max_delay: Duration::from_secs(login_max_delay.parse::<u64>().unwrap()),
+            login_lockout: Duration::from_secs(login_lockout.parse::<u64>().unwrap() * 60),
+            trusted_proxies: trusted_proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(|proxy| proxy.parse::<IpAddr>().unwrap())
+                .collect(),
+            mfa_issuer,
+            mfa_token_expires_in: mfa_token_expires_in.parse::<i64>().unwrap(),
+            mfa_skew: mfa_skew.parse::<i64>().unwrap(),
+            oidc_issuer,
+            oidc_client_id,
+            oidc_client_secret,
+            oidc_redirect_url,
+            oidc_scopes,
+            oidc_login_expires_in: oidc_login_expires_in.parse::<i64>().unwrap(),
+            admin_emails: admin_emails
+                .split(',')
+                .map(str::trim)
+                .filter(|email| !email.is_empty())
+                .map(String::from)
+                .collect(),
+            broker,
             logs_path,
             server_port,
+            otlp_endpoint,
+            shutdown_timeout: shutdown_timeout.parse::<u64>().unwrap(),
         }
     }
 }
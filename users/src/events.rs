@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use utils::{error::SerializationError, events::Event, outbox::models::NewOutboxMessage};
+use uuid::Uuid;
+
+use crate::models::preferences::UserPreferences;
+use crate::models::user::User;
+
+pub const USER_CREATED_EVENT: &str = "user_created";
+pub const USER_CREATED_VERSION: u32 = 1;
+pub const USER_UPDATED_EVENT: &str = "user_updated";
+pub const USER_UPDATED_VERSION: u32 = 1;
+pub const USER_DELETED_EVENT: &str = "user_deleted";
+pub const USER_DELETED_VERSION: u32 = 1;
+pub const USER_PASSWORD_CHANGED_EVENT: &str = "user_password_changed";
+pub const USER_PASSWORD_CHANGED_VERSION: u32 = 1;
+pub const USER_PREFERENCES_UPDATED_EVENT: &str = "user_preferences_updated";
+pub const USER_PREFERENCES_UPDATED_VERSION: u32 = 1;
+const USER_EVENTS_SOURCE: &str = "users";
+
+/// Topics the users service publishes to, ensured on startup.
+pub const USER_TOPICS: &[&str] = &[
+    USER_CREATED_EVENT,
+    USER_UPDATED_EVENT,
+    USER_DELETED_EVENT,
+    USER_PASSWORD_CHANGED_EVENT,
+    USER_PREFERENCES_UPDATED_EVENT,
+];
+
+/// Data of the `user_password_changed` event, the tokens issued to the user before it are
+/// revoked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserPasswordChanged {
+    pub user_id: Uuid,
+}
+
+/// Builds the `user_created` outbox message, keyed by user so the events of a user are
+/// consumed in order.
+pub fn user_created(user: &User) -> Result<NewOutboxMessage, SerializationError> {
+    let event = Event::new(
+        USER_CREATED_EVENT,
+        USER_EVENTS_SOURCE,
+        USER_CREATED_VERSION,
+        user,
+    )?;
+
+    NewOutboxMessage::from_event(USER_CREATED_EVENT, user.id.to_string().as_str(), &event)
+}
+
+/// Data of the `user_deleted` event, the other services forget everything about the user.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserDeleted {
+    pub user_id: Uuid,
+}
+
+/// Builds the `user_updated` outbox message with the user as updated, like
+/// `user_created`.
+pub fn user_updated(user: &User) -> Result<NewOutboxMessage, SerializationError> {
+    let event = Event::new(
+        USER_UPDATED_EVENT,
+        USER_EVENTS_SOURCE,
+        USER_UPDATED_VERSION,
+        user,
+    )?;
+
+    NewOutboxMessage::from_event(USER_UPDATED_EVENT, user.id.to_string().as_str(), &event)
+}
+
+/// Builds the `user_deleted` outbox message, keyed by user so it is consumed after the
+/// other events of the user.
+pub fn user_deleted(user_id: Uuid) -> Result<NewOutboxMessage, SerializationError> {
+    let event = Event::new(
+        USER_DELETED_EVENT,
+        USER_EVENTS_SOURCE,
+        USER_DELETED_VERSION,
+        &UserDeleted { user_id },
+    )?;
+
+    NewOutboxMessage::from_event(USER_DELETED_EVENT, user_id.to_string().as_str(), &event)
+}
+
+/// Builds the `user_password_changed` outbox message, keyed by user like the other user
+/// events.
+pub fn user_password_changed(user_id: Uuid) -> Result<NewOutboxMessage, SerializationError> {
+    let event = Event::new(
+        USER_PASSWORD_CHANGED_EVENT,
+        USER_EVENTS_SOURCE,
+        USER_PASSWORD_CHANGED_VERSION,
+        &UserPasswordChanged { user_id },
+    )?;
+
+    NewOutboxMessage::from_event(
+        USER_PASSWORD_CHANGED_EVENT,
+        user_id.to_string().as_str(),
+        &event,
+    )
+}
+
+/// Builds the `user_preferences_updated` outbox message with every preference, so the
+/// consumers only need the latest event of a user.
+pub fn user_preferences_updated(
+    preferences: &UserPreferences,
+) -> Result<NewOutboxMessage, SerializationError> {
+    let event = Event::new(
+        USER_PREFERENCES_UPDATED_EVENT,
+        USER_EVENTS_SOURCE,
+        USER_PREFERENCES_UPDATED_VERSION,
+        preferences,
+    )?;
+
+    NewOutboxMessage::from_event(
+        USER_PREFERENCES_UPDATED_EVENT,
+        preferences.user_id.to_string().as_str(),
+        &event,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::http::middlewares::jwt_auth::Role;
+
+    #[test]
+    fn test_user_created_is_keyed_by_user_in_envelope() {
+        let user = User {
+            id: uuid::Uuid::new_v4(),
+            name: "user".to_string(),
+            password: "password".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
+        };
+
+        let message = user_created(&user).unwrap();
+        let event = Event::from_json(&message.payload).unwrap();
+
+        assert_eq!(message.topic, USER_CREATED_EVENT);
+        assert_eq!(message.key, user.id.to_string());
+        assert_eq!(event.event_type, USER_CREATED_EVENT);
+        assert_eq!(event.dataversion, USER_CREATED_VERSION);
+        // the password is never published
+        assert!(event.data.get("password").is_none());
+    }
+
+    #[test]
+    fn test_user_password_changed_is_keyed_by_user() {
+        let user_id = Uuid::new_v4();
+
+        let message = user_password_changed(user_id).unwrap();
+        let event = Event::from_json(&message.payload).unwrap();
+
+        assert_eq!(message.topic, USER_PASSWORD_CHANGED_EVENT);
+        assert_eq!(message.key, user_id.to_string());
+        assert_eq!(event.event_type, USER_PASSWORD_CHANGED_EVENT);
+        assert_eq!(
+            event.data::<UserPasswordChanged>().unwrap(),
+            UserPasswordChanged { user_id }
+        );
+    }
+
+    #[test]
+    fn test_user_topics_are_registered() {
+        assert!(utils::broker::topics::registry()
+            .topics(USER_TOPICS)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_user_preferences_updated_carries_every_preference() {
+        let preferences = UserPreferences::defaults(Uuid::new_v4());
+
+        let message = user_preferences_updated(&preferences).unwrap();
+        let event = Event::from_json(&message.payload).unwrap();
+
+        assert_eq!(message.topic, USER_PREFERENCES_UPDATED_EVENT);
+        assert_eq!(message.key, preferences.user_id.to_string());
+        assert_eq!(event.dataversion, USER_PREFERENCES_UPDATED_VERSION);
+        assert_eq!(event.data::<UserPreferences>().unwrap(), preferences);
+    }
+
+    #[test]
+    fn test_user_updated_and_deleted_are_keyed_by_user() {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: "user".to_string(),
+            password: "password".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
+        };
+
+        let updated = user_updated(&user).unwrap();
+        let event = Event::from_json(&updated.payload).unwrap();
+        assert_eq!(updated.topic, USER_UPDATED_EVENT);
+        assert_eq!(updated.key, user.id.to_string());
+        assert!(event.data.get("password").is_none());
+
+        let deleted = user_deleted(user.id).unwrap();
+        let event = Event::from_json(&deleted.payload).unwrap();
+        assert_eq!(deleted.topic, USER_DELETED_EVENT);
+        assert_eq!(deleted.key, user.id.to_string());
+        assert_eq!(
+            event.data::<UserDeleted>().unwrap(),
+            UserDeleted { user_id: user.id }
+        );
+    }
+}
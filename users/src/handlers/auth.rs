@@ -1,42 +1,289 @@
 use actix_web::{
     cookie::{time::Duration as ActixWebDuration, Cookie},
-    get, post, web, HttpMessage, HttpRequest, HttpResponse, Responder,
+    get,
+    http::header,
+    post, put, web, HttpMessage, HttpRequest, HttpResponse, Responder,
 };
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use log::error;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use utils::http::{middlewares::jwt_auth::JwtMiddleware, services::auth_service::AuthService};
+use std::net::{IpAddr, SocketAddr};
+use utils::error::{
+    AUTH_TOKEN_ENCODING_CODE, DATABASE_ERROR_CODE, HTTP_ERROR_CODE, MAIL_ERROR_CODE,
+};
+use utils::http::middlewares::authorization::{Authorization, LoggedIn};
+use utils::http::middlewares::jwt_auth::JwtMiddleware;
+use utils::http::services::auth_service::AuthService;
+use uuid::Uuid;
 use validator::Validate;
 
-use crate::{config::Config, services::user_service::UserService};
+use crate::{
+    config::Config,
+    services::email_verification_service::EmailVerificationService,
+    services::login_service::{Authenticated, LoginError, LoginService},
+    services::mfa_service::MfaService,
+    services::oidc_service::OidcService,
+    services::password_reset_service::PasswordResetService,
+    services::token_service::{TokenPair, TokenService},
+    services::user_service::UserService,
+};
+
+const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+/// Refresh tokens are only sent to the auth endpoints.
+const REFRESH_TOKEN_PATH: &str = "/auth";
+/// Binds the OpenID Connect logins to the browser they started in.
+const OIDC_STATE_COOKIE: &str = "oidc_state";
+const OIDC_PATH: &str = "/auth/oidc";
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct LoginPayload {
     #[validate(required, length(min = 2))]
-    name: Option<String>,
+    pub name: Option<String>,
     #[validate(required, length(min = 6))]
-    password: Option<String>,
+    pub password: Option<String>,
 }
 
-#[get("/auth/logout")]
-pub async fn logout_handler() -> impl Responder {
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct VerifyEmailPayload {
+    #[validate(required, length(min = 1))]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ForgotPasswordPayload {
+    #[validate(required, email)]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResetPasswordPayload {
+    #[validate(required, length(min = 1))]
+    pub token: Option<String>,
+    #[validate(required, length(min = 6))]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ChangePasswordPayload {
+    #[validate(required, length(min = 1))]
+    pub current_password: Option<String>,
+    #[validate(required, length(min = 6))]
+    pub new_password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct MfaCodePayload {
+    #[validate(required, length(min = 1))]
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct VerifyMfaPayload {
+    #[validate(required, length(min = 1))]
+    pub mfa_token: Option<String>,
+    #[validate(required, length(min = 1))]
+    pub code: Option<String>,
+}
+
+/// Sent by the identity provider, with an error instead of the code when the login
+/// failed there.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct RefreshPayload {
+    /// Read from the refresh token cookie when missing.
+    pub refresh_token: Option<String>,
+}
+
+/// Address of the client. Behind trusted proxies it's the nearest X-Forwarded-For hop that
+/// isn't one of them: every proxy appends the address it got the request from, the hops
+/// further left are written by the client, who could pick the address they are throttled by.
+fn client_ip(r: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<String> {
+    let mut client = r.peer_addr()?.ip();
+    if !trusted_proxies.contains(&client) {
+        return Some(client.to_string());
+    }
+
+    let hops: Vec<&str> = r
+        .headers()
+        .get_all(header::X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    for hop in hops.into_iter().rev() {
+        let hop = hop.trim();
+        let ip = match hop.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            // some proxies forward the port too
+            Err(_) => match hop.parse::<SocketAddr>() {
+                Ok(addr) => addr.ip(),
+                Err(_) => break,
+            },
+        };
+        client = ip;
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+
+    Some(client.to_string())
+}
+
+/// Responds with the tokens in the body and in http only cookies.
+fn tokens_response(tokens: TokenPair, config: &Config) -> HttpResponse {
+    let token_cookie = Cookie::build("token", tokens.access_token.to_owned())
+        .path("/")
+        .max_age(ActixWebDuration::new(config.jwt_max_age, 0))
+        .http_only(true)
+        .finish();
+    let refresh_cookie = Cookie::build(REFRESH_TOKEN_COOKIE, tokens.refresh_token.to_owned())
+        .path(REFRESH_TOKEN_PATH)
+        .max_age(ActixWebDuration::minutes(config.refresh_token_expires_in))
+        .http_only(true)
+        .finish();
+
+    HttpResponse::Ok()
+        .cookie(token_cookie)
+        .cookie(refresh_cookie)
+        .json(json!({
+            "status": "success",
+            "token": tokens.access_token,
+            "refresh_token": tokens.refresh_token,
+        }))
+}
+
+/// Responds clearing the token cookies.
+fn logged_out_response() -> HttpResponse {
     let cookie = Cookie::build("token", "")
         .path("/")
         .max_age(ActixWebDuration::new(-1, 0))
         .http_only(true)
         .finish();
+    let refresh_cookie = Cookie::build(REFRESH_TOKEN_COOKIE, "")
+        .path(REFRESH_TOKEN_PATH)
+        .max_age(ActixWebDuration::new(-1, 0))
+        .http_only(true)
+        .finish();
 
     HttpResponse::Ok()
         .cookie(cookie)
+        .cookie(refresh_cookie)
         .json(json!({"status": "success"}))
 }
 
+/// Responds to a failed login, whether the password or the second factor failed.
+fn login_error_response(err: LoginError) -> HttpResponse {
+    match err {
+        LoginError::InvalidCredentials => HttpResponse::Unauthorized()
+            .json(json!({"status": "fail", "message": "Invalid name or password"})),
+        LoginError::Throttled(retry_after) => {
+            // rounded up, retrying earlier would fail again
+            let seconds = retry_after.as_secs() + (retry_after.subsec_nanos() > 0) as u64;
+            HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, seconds.to_string()))
+                .json(json!({
+                    "status": "fail",
+                    "message": "Too many failed login attempts, try again later",
+                }))
+        }
+        LoginError::Internal(err) => {
+            error!("failed authenticating user: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/auth/logout")]
+pub async fn logout_handler(
+    auth_service: web::Data<dyn AuthService>,
+    token_service: web::Data<dyn TokenService>,
+    r: HttpRequest,
+    auth: Option<JwtMiddleware>,
+) -> impl Responder {
+    if let Some(auth) = auth {
+        if let Err(err) = auth_service.revoke_token(&auth.claims) {
+            error!("failed revoking access token: {}", err);
+        }
+    }
+
+    if let Some(refresh_token) = r.cookie(REFRESH_TOKEN_COOKIE) {
+        if let Err(err) = token_service
+            .revoke(refresh_token.value().to_string())
+            .await
+        {
+            error!("failed revoking refresh token: {}", err);
+        }
+    }
+
+    logged_out_response()
+}
+
+/// Revokes the access token of the request and the refresh token of the same login.
+#[post("/auth/revoke")]
+pub async fn revoke_handler(
+    auth_service: web::Data<dyn AuthService>,
+    token_service: web::Data<dyn TokenService>,
+    r: HttpRequest,
+    payload: Option<web::Json<RefreshPayload>>,
+    auth: JwtMiddleware,
+) -> HttpResponse {
+    if let Err(err) = auth_service.revoke_token(&auth.claims) {
+        error!("failed revoking access token of {}: {}", auth.user_id, err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let refresh_token = payload
+        .and_then(|payload| payload.into_inner().refresh_token)
+        .or_else(|| {
+            r.cookie(REFRESH_TOKEN_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+        });
+    if let Some(refresh_token) = refresh_token {
+        match token_service.revoke(refresh_token).await {
+            Ok(()) => {}
+            Err(err) if err.code == DATABASE_ERROR_CODE => {
+                error!("failed revoking refresh token of {}: {}", auth.user_id, err);
+                return HttpResponse::InternalServerError().finish();
+            }
+            // an unknown refresh token can't be used anyway
+            Err(_) => {}
+        }
+    }
+
+    logged_out_response()
+}
+
+/// Revokes every token issued to the user, logging out all of their sessions.
+#[post("/auth/revoke-all")]
+pub async fn revoke_all_handler(
+    token_service: web::Data<dyn TokenService>,
+    LoggedIn(auth): LoggedIn,
+) -> HttpResponse {
+    match token_service.revoke_all(auth.user_id).await {
+        Ok(()) => logged_out_response(),
+        Err(err) => {
+            error!("failed revoking tokens of {}: {}", auth.user_id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Logs in with a name and password. Failures look the same whether the name is
+/// registered or not, and slow down the next attempts of the account and the client.
+/// Users with two-factor authentication get a short-lived token to send along with a
+/// code to `/auth/mfa/verify` instead.
 #[post("/auth/login")]
 pub async fn login_handler(
-    user_service: web::Data<dyn UserService>,
+    login_service: web::Data<dyn LoginService>,
+    token_service: web::Data<dyn TokenService>,
     auth_service: web::Data<dyn AuthService>,
     config: web::Data<Config>,
+    r: HttpRequest,
     payload: Option<web::Json<LoginPayload>>,
 ) -> HttpResponse {
     if payload.is_none() {
@@ -50,43 +297,70 @@ pub async fn login_handler(
     }
 
     let LoginPayload { name, password } = payload;
-    let name = name.unwrap();
+    let ip = client_ip(&r, &config.trusted_proxies);
 
-    let user = match user_service.get_by_name(name.clone()).await {
-        Ok(user) => user,
-        Err(err) => {
-            error!("failed getting user by name {}: {}", name, err);
-            return HttpResponse::InternalServerError()
-                .json(json!({"status": "fail", "message": "Invalid user"}));
+    let user = match login_service
+        .authenticate(name.unwrap(), password.unwrap(), ip)
+        .await
+    {
+        Ok(Authenticated {
+            user,
+            mfa_required: false,
+        }) => user,
+        Ok(Authenticated { user, .. }) => {
+            return match auth_service
+                .encode_mfa_token(user.id.to_string(), config.mfa_token_expires_in)
+            {
+                Ok(mfa_token) => HttpResponse::Ok()
+                    .json(json!({"status": "mfa_required", "mfa_token": mfa_token})),
+                Err(err) => {
+                    error!("failed encoding mfa token: {}", err);
+                    HttpResponse::InternalServerError().finish()
+                }
+            }
         }
+        Err(err) => return login_error_response(err),
     };
 
-    let parsed_hash = PasswordHash::new(&user.password).unwrap();
-    let password_is_valid = Argon2::default()
-        .verify_password(password.unwrap().as_bytes(), &parsed_hash)
-        .map_or(false, |_| true);
-
-    if !password_is_valid {
-        return HttpResponse::BadRequest()
-            .json(json!({"status": "fail", "message": "Invalid password"}));
+    match token_service.issue(user).await {
+        Ok(tokens) => tokens_response(tokens, &config),
+        Err(err) => {
+            error!("failed issuing auth tokens: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
     }
+}
 
-    match auth_service.encode_token(user.id.to_string(), config.jwt_expires_in) {
-        Ok(token) => {
-            let cookie = Cookie::build("token", token.to_owned())
-                .path("/")
-                .max_age(ActixWebDuration::new(config.jwt_max_age, 0))
-                .http_only(true)
-                .finish();
+#[post("/auth/refresh")]
+pub async fn refresh_handler(
+    token_service: web::Data<dyn TokenService>,
+    config: web::Data<Config>,
+    r: HttpRequest,
+    payload: Option<web::Json<RefreshPayload>>,
+) -> HttpResponse {
+    let refresh_token = payload
+        .and_then(|payload| payload.into_inner().refresh_token)
+        .or_else(|| {
+            r.cookie(REFRESH_TOKEN_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+        });
 
-            HttpResponse::Ok()
-                .cookie(cookie)
-                .json(json!({"status": "success", "token": token}))
+    let refresh_token = match refresh_token {
+        Some(refresh_token) => refresh_token,
+        None => {
+            return HttpResponse::BadRequest()
+                .json(json!({"status": "fail", "message": "Missing refresh token"}))
         }
-        Err(err) => {
-            error!("failed encoding auth token: {}", err);
+    };
+
+    match token_service.refresh(refresh_token).await {
+        Ok(tokens) => tokens_response(tokens, &config),
+        Err(err) if err.code == DATABASE_ERROR_CODE => {
+            error!("failed refreshing auth tokens: {}", err);
             HttpResponse::InternalServerError().finish()
         }
+        Err(_) => HttpResponse::Unauthorized()
+            .json(json!({"status": "fail", "message": "Invalid refresh token"})),
     }
 }
 
@@ -106,3 +380,463 @@ pub async fn me_handler(
         }
     }
 }
+
+/// Verifies the email the token was sent to. Tokens issued before carry the previous
+/// state until they are refreshed.
+#[post("/auth/verify-email")]
+pub async fn verify_email_handler(
+    email_verification_service: web::Data<dyn EmailVerificationService>,
+    payload: Option<web::Json<VerifyEmailPayload>>,
+) -> HttpResponse {
+    if payload.is_none() {
+        return HttpResponse::BadRequest().body("empty body");
+    }
+
+    let payload = payload.unwrap().into_inner();
+    if let Err(err) = payload.validate() {
+        return HttpResponse::BadRequest().json(err);
+    }
+
+    match email_verification_service
+        .verify(payload.token.unwrap())
+        .await
+    {
+        Ok(()) => HttpResponse::Ok().json(json!({"status": "success"})),
+        Err(err) if err.code == DATABASE_ERROR_CODE => {
+            error!("failed verifying email: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+        Err(_) => HttpResponse::BadRequest()
+            .json(json!({"status": "fail", "message": "Invalid verification token"})),
+    }
+}
+
+/// Sends the verification email again, to the current email of the user.
+#[post("/auth/verify-email/resend")]
+pub async fn resend_verification_handler(
+    user_service: web::Data<dyn UserService>,
+    email_verification_service: web::Data<dyn EmailVerificationService>,
+    auth: Authorization,
+) -> HttpResponse {
+    let user = match user_service.get_by_id(auth.user_id).await {
+        Ok(user) => user,
+        Err(err) => {
+            error!("failed getting user {}: {}", auth.user_id, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if user.email.is_none() || user.email_verified() {
+        return HttpResponse::BadRequest()
+            .json(json!({"status": "fail", "message": "No email to verify"}));
+    }
+
+    match email_verification_service.send(user).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"status": "success"})),
+        Err(err) if err.code == MAIL_ERROR_CODE => {
+            error!("failed mailing verification to {}: {}", auth.user_id, err);
+            HttpResponse::ServiceUnavailable().finish()
+        }
+        Err(err) => {
+            error!("failed sending verification to {}: {}", auth.user_id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Revokes the tokens issued before the password changed, logging out every session.
+async fn password_changed_response(
+    token_service: &dyn TokenService,
+    user_id: Uuid,
+) -> HttpResponse {
+    match token_service.revoke_all(user_id).await {
+        Ok(()) => logged_out_response(),
+        Err(err) => {
+            error!(
+                "failed revoking tokens of {} after a password change: {}",
+                user_id, err
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Mails a password reset link, responding the same whether the email is registered or
+/// not.
+#[post("/auth/password/forgot")]
+pub async fn forgot_password_handler(
+    password_reset_service: web::Data<dyn PasswordResetService>,
+    payload: Option<web::Json<ForgotPasswordPayload>>,
+) -> HttpResponse {
+    if payload.is_none() {
+        return HttpResponse::BadRequest().body("empty body");
+    }
+
+    let payload = payload.unwrap().into_inner();
+    if let Err(err) = payload.validate() {
+        return HttpResponse::BadRequest().json(err);
+    }
+
+    // the reset is mailed in the background, failures there don't reach the response
+    if let Err(err) = password_reset_service.forgot(payload.email.unwrap()).await {
+        error!("failed requesting password reset: {}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(json!({"status": "success"}))
+}
+
+/// Replaces the password of the user the reset token was mailed to.
+#[post("/auth/password/reset")]
+pub async fn reset_password_handler(
+    password_reset_service: web::Data<dyn PasswordResetService>,
+    token_service: web::Data<dyn TokenService>,
+    payload: Option<web::Json<ResetPasswordPayload>>,
+) -> HttpResponse {
+    if payload.is_none() {
+        return HttpResponse::BadRequest().body("empty body");
+    }
+
+    let payload = payload.unwrap().into_inner();
+    if let Err(err) = payload.validate() {
+        return HttpResponse::BadRequest().json(err);
+    }
+
+    let ResetPasswordPayload { token, password } = payload;
+    match password_reset_service
+        .reset(token.unwrap(), password.unwrap())
+        .await
+    {
+        Ok(user) => password_changed_response(token_service.as_ref(), user.id).await,
+        Err(err) if err.code == AUTH_TOKEN_ENCODING_CODE => HttpResponse::BadRequest()
+            .json(json!({"status": "fail", "message": "Invalid password reset token"})),
+        Err(err) => {
+            error!("failed resetting password: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Changes the password of the signed in user, who has to log in again.
+#[put("/auth/password")]
+pub async fn change_password_handler(
+    user_service: web::Data<dyn UserService>,
+    token_service: web::Data<dyn TokenService>,
+    payload: Option<web::Json<ChangePasswordPayload>>,
+    LoggedIn(auth): LoggedIn,
+) -> HttpResponse {
+    if payload.is_none() {
+        return HttpResponse::BadRequest().body("empty body");
+    }
+
+    let payload = payload.unwrap().into_inner();
+    if let Err(err) = payload.validate() {
+        return HttpResponse::BadRequest().json(err);
+    }
+
+    let ChangePasswordPayload {
+        current_password,
+        new_password,
+    } = payload;
+    match user_service
+        .change_password(
+            auth.user_id,
+            current_password.unwrap(),
+            new_password.unwrap(),
+        )
+        .await
+    {
+        Ok(user) => password_changed_response(token_service.as_ref(), user.id).await,
+        Err(err) if err.code == AUTH_TOKEN_ENCODING_CODE => HttpResponse::BadRequest()
+            .json(json!({"status": "fail", "message": "Invalid password"})),
+        Err(err) => {
+            error!("failed changing password of {}: {}", auth.user_id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Completes the login of a user with two-factor authentication, exchanging the token of
+/// `/auth/login` and a code of the authenticator app, or a recovery code, for the tokens.
+#[post("/auth/mfa/verify")]
+pub async fn verify_mfa_handler(
+    login_service: web::Data<dyn LoginService>,
+    token_service: web::Data<dyn TokenService>,
+    auth_service: web::Data<dyn AuthService>,
+    config: web::Data<Config>,
+    r: HttpRequest,
+    payload: Option<web::Json<VerifyMfaPayload>>,
+) -> HttpResponse {
+    if payload.is_none() {
+        return HttpResponse::BadRequest().body("empty body");
+    }
+
+    let payload = payload.unwrap().into_inner();
+    if let Err(err) = payload.validate() {
+        return HttpResponse::BadRequest().json(err);
+    }
+
+    let VerifyMfaPayload { mfa_token, code } = payload;
+    let user_id = match auth_service
+        .decode_mfa_token(mfa_token.unwrap())
+        .ok()
+        .and_then(|claims| Uuid::parse_str(&claims.sub).ok())
+    {
+        Some(user_id) => user_id,
+        None => {
+            return HttpResponse::Unauthorized()
+                .json(json!({"status": "fail", "message": "Invalid mfa token"}))
+        }
+    };
+    let ip = client_ip(&r, &config.trusted_proxies);
+
+    let user = match login_service.verify_mfa(user_id, code.unwrap(), ip).await {
+        Ok(user) => user,
+        Err(err) => return login_error_response(err),
+    };
+
+    match token_service.issue(user).await {
+        Ok(tokens) => tokens_response(tokens, &config),
+        Err(err) => {
+            error!("failed issuing auth tokens: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Starts enabling two-factor authentication, which only takes effect once confirmed
+/// with a code.
+#[post("/auth/mfa/enroll")]
+pub async fn enroll_mfa_handler(
+    user_service: web::Data<dyn UserService>,
+    mfa_service: web::Data<dyn MfaService>,
+    LoggedIn(auth): LoggedIn,
+) -> HttpResponse {
+    let user = match user_service.get_by_id(auth.user_id).await {
+        Ok(user) => user,
+        Err(err) => {
+            error!("failed getting user {}: {}", auth.user_id, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match mfa_service.enroll(user).await {
+        Ok(Some(enrollment)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "secret": enrollment.secret,
+            "otpauth_uri": enrollment.otpauth_uri,
+        })),
+        Ok(None) => HttpResponse::Conflict().json(
+            json!({"status": "fail", "message": "Two-factor authentication is already enabled"}),
+        ),
+        Err(err) => {
+            error!("failed enrolling mfa of {}: {}", auth.user_id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Enables two-factor authentication with the first code of the app. The recovery codes
+/// are only shown in this response.
+#[post("/auth/mfa/confirm")]
+pub async fn confirm_mfa_handler(
+    mfa_service: web::Data<dyn MfaService>,
+    payload: Option<web::Json<MfaCodePayload>>,
+    LoggedIn(auth): LoggedIn,
+) -> HttpResponse {
+    if payload.is_none() {
+        return HttpResponse::BadRequest().body("empty body");
+    }
+
+    let payload = payload.unwrap().into_inner();
+    if let Err(err) = payload.validate() {
+        return HttpResponse::BadRequest().json(err);
+    }
+
+    match mfa_service
+        .confirm(auth.user_id, payload.code.unwrap())
+        .await
+    {
+        Ok(Some(recovery_codes)) => {
+            HttpResponse::Ok().json(json!({"status": "success", "recovery_codes": recovery_codes}))
+        }
+        Ok(None) => {
+            HttpResponse::BadRequest().json(json!({"status": "fail", "message": "Invalid code"}))
+        }
+        Err(err) => {
+            error!("failed confirming mfa of {}: {}", auth.user_id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Redirects to the identity provider to log in there.
+#[get("/auth/oidc/login")]
+pub async fn oidc_login_handler(
+    oidc_service: web::Data<dyn OidcService>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    match oidc_service.start().await {
+        Ok(redirect) => {
+            let state_cookie = Cookie::build(OIDC_STATE_COOKIE, redirect.state)
+                .path(OIDC_PATH)
+                .max_age(ActixWebDuration::minutes(config.oidc_login_expires_in))
+                .http_only(true)
+                .finish();
+
+            HttpResponse::Found()
+                .insert_header((header::LOCATION, redirect.authorization_url))
+                .cookie(state_cookie)
+                .finish()
+        }
+        Err(err) if err.code == HTTP_ERROR_CODE => {
+            error!("failed discovering the identity provider: {}", err);
+            HttpResponse::BadGateway().finish()
+        }
+        Err(err) => {
+            error!("failed starting oidc login: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Completes the login at the identity provider, logging in the user linked to the
+/// provider's account, or provisioning one. The provider is trusted to have checked the
+/// second factor.
+#[get("/auth/oidc/callback")]
+pub async fn oidc_callback_handler(
+    oidc_service: web::Data<dyn OidcService>,
+    token_service: web::Data<dyn TokenService>,
+    config: web::Data<Config>,
+    r: HttpRequest,
+    query: web::Query<OidcCallbackQuery>,
+) -> HttpResponse {
+    let OidcCallbackQuery { code, state, error } = query.into_inner();
+    if let Some(error) = error {
+        return HttpResponse::Unauthorized().json(json!({
+            "status": "fail",
+            "message": format!("Login failed at the identity provider: {}", error),
+        }));
+    }
+    let (code, state) = match (code, state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(json!({"status": "fail", "message": "Missing code or state"}))
+        }
+    };
+    // else anyone could log a victim's browser into the attacker's account
+    let browser_state = r.cookie(OIDC_STATE_COOKIE);
+    if browser_state.as_ref().map(|cookie| cookie.value()) != Some(state.as_str()) {
+        return HttpResponse::Unauthorized()
+            .json(json!({"status": "fail", "message": "Invalid OpenID Connect login"}));
+    }
+
+    let user = match oidc_service.complete(state, code).await {
+        Ok(user) => user,
+        Err(err) if err.code == AUTH_TOKEN_ENCODING_CODE => {
+            return HttpResponse::Unauthorized()
+                .json(json!({"status": "fail", "message": "Invalid OpenID Connect login"}))
+        }
+        Err(err) if err.code == HTTP_ERROR_CODE => {
+            error!("failed reaching the identity provider: {}", err);
+            return HttpResponse::BadGateway().finish();
+        }
+        Err(err) => {
+            error!("failed completing oidc login: {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match token_service.issue(user).await {
+        Ok(tokens) => {
+            let mut response = tokens_response(tokens, &config);
+            let mut state_cookie = Cookie::named(OIDC_STATE_COOKIE);
+            state_cookie.set_path(OIDC_PATH);
+            // only fails for invalid cookies
+            let _ = response.add_removal_cookie(&state_cookie);
+            response
+        }
+        Err(err) => {
+            error!("failed issuing auth tokens: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let inner_proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let cases = vec![
+            ("10.0.0.1:80", vec![proxy], "203.0.113.7", "203.0.113.7"),
+            ("10.0.0.2:80", vec![proxy], "203.0.113.7", "10.0.0.2"),
+            ("10.0.0.1:80", vec![], "203.0.113.7", "10.0.0.1"),
+            // the client made up the leftmost hops
+            (
+                "10.0.0.1:80",
+                vec![proxy],
+                "198.51.100.9, 192.0.2.1,203.0.113.7",
+                "203.0.113.7",
+            ),
+            (
+                "10.0.0.1:80",
+                vec![proxy, inner_proxy],
+                "198.51.100.9, 203.0.113.7, 10.0.0.2",
+                "203.0.113.7",
+            ),
+            (
+                "10.0.0.1:80",
+                vec![proxy],
+                "203.0.113.7:4321",
+                "203.0.113.7",
+            ),
+            (
+                "10.0.0.1:80",
+                vec![proxy, inner_proxy],
+                "10.0.0.2",
+                "10.0.0.2",
+            ),
+            ("10.0.0.1:80", vec![proxy], "unknown", "10.0.0.1"),
+            ("10.0.0.1:80", vec![proxy], "", "10.0.0.1"),
+        ];
+
+        for (peer, trusted_proxies, forwarded_for, expected) in cases {
+            let r = TestRequest::default()
+                .peer_addr(peer.parse().unwrap())
+                .insert_header((header::X_FORWARDED_FOR, forwarded_for))
+                .to_http_request();
+
+            assert_eq!(
+                client_ip(&r, &trusted_proxies).as_deref(),
+                Some(expected),
+                "{} forwarding {:?} trusting {:?}",
+                peer,
+                forwarded_for,
+                trusted_proxies
+            );
+        }
+        assert_eq!(
+            client_ip(&TestRequest::default().to_http_request(), &[proxy]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_client_ip_reads_every_forwarded_for_header() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let r = TestRequest::default()
+            .peer_addr("10.0.0.1:80".parse().unwrap())
+            .append_header((header::X_FORWARDED_FOR, "198.51.100.9"))
+            .append_header((header::X_FORWARDED_FOR, "203.0.113.7"))
+            .to_http_request();
+
+        assert_eq!(client_ip(&r, &[proxy]).as_deref(), Some("203.0.113.7"));
+    }
+}
@@ -5,21 +5,27 @@ use actix_web::{
     HttpResponse,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utils::http::middlewares::authorization::Verified;
 use utils::http::websockets::ws_server::{SessionMessage, WebsocketServer};
+use uuid::Uuid;
 use validator::Validate;
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
-struct MessagePayload {
+pub struct MessagePayload {
     #[validate(required, length(min = 1))]
-    message: Option<String>,
+    pub message: Option<String>,
     #[validate(required, length(min = 1))]
-    user_id: Option<String>,
+    pub user_id: Option<String>,
 }
 
+/// Sends a message to the websocket sessions of a user, users only message themselves
+/// once they verified their email address.
 #[post("/messages")]
 async fn create_message(
     ws_server: Data<Addr<WebsocketServer>>,
     payload: Option<Json<MessagePayload>>,
+    Verified(auth): Verified,
 ) -> HttpResponse {
     if payload.is_none() {
         return HttpResponse::BadRequest().body("empty body");
@@ -31,11 +37,108 @@ async fn create_message(
     }
 
     let MessagePayload { message, user_id } = payload;
+    let user_id = user_id.unwrap();
+
+    // sessions are identified by the id of the user who logged in
+    let recipient = match Uuid::parse_str(&user_id) {
+        Ok(recipient) => recipient,
+        Err(_) => {
+            return HttpResponse::BadRequest()
+                .json(json!({"status": "fail", "message": "Invalid user id"}))
+        }
+    };
+    if let Err(err) = auth.authorize(&recipient) {
+        return err.error_response();
+    }
 
     ws_server.do_send(SessionMessage {
-        id: user_id.unwrap(),
+        id: user_id,
         message: message.unwrap(),
     });
 
     HttpResponse::Ok().finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::Actor;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use rstest::*;
+    use std::sync::Arc;
+    use utils::http::middlewares::jwt_auth::{Role, TokenSubject};
+    use utils::http::services::auth_service::{AuthService, JwtAuthService};
+    use utils::http::test_utils::get_subject_authorization_header;
+
+    const ALICE: &str = "b73ccd26-1832-4d10-9251-271ce453cee3";
+    const BOB: &str = "fdd0a6f3-af61-4760-a789-5b6dd16eb7dc";
+
+    struct CreateMessageTestCase {
+        user: Option<(&'static str, Role, bool)>,
+        recipient: &'static str,
+        expected_status: StatusCode,
+    }
+
+    #[rstest]
+    #[case::own_sessions(CreateMessageTestCase {
+        user: Some((ALICE, Role::User, true)),
+        recipient: ALICE,
+        expected_status: StatusCode::OK,
+    })]
+    #[case::other_user(CreateMessageTestCase {
+        user: Some((BOB, Role::User, true)),
+        recipient: ALICE,
+        expected_status: StatusCode::FORBIDDEN,
+    })]
+    #[case::unverified(CreateMessageTestCase {
+        user: Some((ALICE, Role::User, false)),
+        recipient: ALICE,
+        expected_status: StatusCode::FORBIDDEN,
+    })]
+    #[case::admin(CreateMessageTestCase {
+        user: Some((BOB, Role::Admin, true)),
+        recipient: ALICE,
+        expected_status: StatusCode::OK,
+    })]
+    #[case::invalid_recipient(CreateMessageTestCase {
+        user: Some((BOB, Role::Admin, true)),
+        recipient: "alice",
+        expected_status: StatusCode::BAD_REQUEST,
+    })]
+    #[case::not_logged_in(CreateMessageTestCase {
+        user: None,
+        recipient: ALICE,
+        expected_status: StatusCode::UNAUTHORIZED,
+    })]
+    #[actix_rt::test]
+    async fn test_create_message(#[case] case: CreateMessageTestCase) {
+        let auth_service: Arc<dyn AuthService> =
+            Arc::new(JwtAuthService::new("secret123".to_owned()));
+        let ws_server = WebsocketServer::new().start();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(auth_service.clone()))
+                .app_data(web::Data::new(ws_server))
+                .service(create_message),
+        )
+        .await;
+
+        let mut req = test::TestRequest::post()
+            .uri("/messages")
+            .set_json(MessagePayload {
+                message: Some("hello".to_owned()),
+                user_id: Some(case.recipient.to_owned()),
+            });
+        if let Some((user_id, role, email_verified)) = case.user {
+            req = req.append_header(get_subject_authorization_header(
+                auth_service,
+                TokenSubject::new(user_id, role, email_verified),
+            ));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+
+        assert_eq!(resp.status(), case.expected_status);
+    }
+}
@@ -2,4 +2,6 @@ pub mod auth;
 pub mod health;
 pub mod index;
 pub mod message;
+pub mod personal_token;
+pub mod preferences;
 pub mod user;
@@ -0,0 +1,103 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utils::http::middlewares::authorization::LoggedIn;
+use utils::http::middlewares::jwt_auth::Scope;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::services::personal_token_service::PersonalTokenService;
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreatePersonalTokenPayload {
+    #[validate(required, length(min = 1, max = 100))]
+    pub name: Option<String>,
+    /// The token can do whatever the user can without scopes.
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+    /// Days until the token expires, it never does without.
+    #[validate(range(min = 1, max = 3650))]
+    pub expires_in: Option<i64>,
+}
+
+/// Creates a personal token, the token is only shown in this response.
+#[post("/users/me/tokens")]
+pub async fn create_personal_token(
+    personal_token_service: web::Data<dyn PersonalTokenService>,
+    payload: Option<web::Json<CreatePersonalTokenPayload>>,
+    LoggedIn(auth): LoggedIn,
+) -> HttpResponse {
+    if payload.is_none() {
+        return HttpResponse::BadRequest().body("empty body");
+    }
+
+    let payload = payload.unwrap().into_inner();
+    if let Err(err) = payload.validate() {
+        return HttpResponse::BadRequest().json(err);
+    }
+
+    let result = personal_token_service
+        .create(
+            auth.user_id,
+            payload.name.unwrap(),
+            payload.scopes,
+            payload.expires_in,
+        )
+        .await;
+
+    match result {
+        Ok(created) => HttpResponse::Created().json(json!({
+            "status": "success",
+            "token": created.token,
+            "details": created.details,
+        })),
+        Err(err) => {
+            error!(
+                "failed creating personal token of {}: {}",
+                auth.user_id, err
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/users/me/tokens")]
+pub async fn get_personal_tokens(
+    personal_token_service: web::Data<dyn PersonalTokenService>,
+    LoggedIn(auth): LoggedIn,
+) -> HttpResponse {
+    match personal_token_service.list(auth.user_id).await {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(err) => {
+            error!(
+                "failed listing personal tokens of {}: {}",
+                auth.user_id, err
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Revokes the token, other instances notice it once their cache expires.
+#[delete("/users/me/tokens/{id}")]
+pub async fn revoke_personal_token(
+    personal_token_service: web::Data<dyn PersonalTokenService>,
+    id: web::Path<Uuid>,
+    LoggedIn(auth): LoggedIn,
+) -> HttpResponse {
+    match personal_token_service
+        .revoke(auth.user_id, id.into_inner())
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            error!(
+                "failed revoking personal token of {}: {}",
+                auth.user_id, err
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use actix_web::{get, patch, web, HttpResponse};
+use log::error;
+use serde::{Deserialize, Serialize};
+use utils::http::middlewares::jwt_auth::JwtMiddleware;
+use validator::{Validate, ValidationError};
+
+use crate::models::preferences::{is_locale, is_timezone, Notifications, PreferencesUpdate};
+use crate::services::preferences_service::PreferencesService;
+
+#[derive(Debug, Default, Serialize, Deserialize, Validate)]
+pub struct UpdatePreferencesPayload {
+    /// An empty display name removes it.
+    #[validate(length(max = 100))]
+    pub display_name: Option<String>,
+    #[validate(custom = "validate_timezone")]
+    pub timezone: Option<String>,
+    #[validate(custom = "validate_locale")]
+    pub locale: Option<String>,
+    pub notifications: Option<Notifications>,
+    #[validate(range(min = 0, max = 23))]
+    pub digest_hour: Option<i16>,
+}
+
+fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    if is_timezone(timezone) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("timezone"))
+    }
+}
+
+fn validate_locale(locale: &str) -> Result<(), ValidationError> {
+    if is_locale(locale) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("locale"))
+    }
+}
+
+#[get("/users/me/preferences")]
+pub async fn get_preferences(
+    preferences_service: web::Data<dyn PreferencesService>,
+    auth: JwtMiddleware,
+) -> HttpResponse {
+    match preferences_service.get(auth.user_id).await {
+        Ok(preferences) => HttpResponse::Ok().json(preferences),
+        Err(err) => {
+            error!("failed getting preferences of {}: {}", auth.user_id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Changes the preferences in the body, the others are kept.
+#[patch("/users/me/preferences")]
+pub async fn update_preferences(
+    preferences_service: web::Data<dyn PreferencesService>,
+    payload: Option<web::Json<UpdatePreferencesPayload>>,
+    auth: JwtMiddleware,
+) -> HttpResponse {
+    if payload.is_none() {
+        return HttpResponse::BadRequest().body("empty body");
+    }
+
+    let payload = payload.unwrap().into_inner();
+    if let Err(err) = payload.validate() {
+        return HttpResponse::BadRequest().json(err);
+    }
+
+    let update = PreferencesUpdate {
+        display_name: payload.display_name,
+        timezone: payload.timezone,
+        locale: payload.locale,
+        notifications: payload.notifications,
+        digest_hour: payload.digest_hour,
+    };
+    match preferences_service.update(auth.user_id, update).await {
+        Ok(preferences) => HttpResponse::Ok().json(preferences),
+        Err(err) => {
+            error!("failed updating preferences of {}: {}", auth.user_id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,15 +1,23 @@
 use actix_web::{delete, get, post, put, web, HttpResponse};
 use log::error;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utils::http::middlewares::authorization::{AdminOnly, Authorization, LoggedIn};
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::models::user::User;
+use crate::services::email_verification_service::EmailVerificationService;
+use crate::services::login_service::LoginService;
+use crate::services::token_service::TokenService;
 use crate::services::user_service::UserService;
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct CreateUserPayload {
     #[validate(required, length(min = 2))]
     pub name: Option<String>,
+    #[validate(required, email)]
+    pub email: Option<String>,
     #[validate(required, length(min = 6))]
     pub password: Option<String>,
 }
@@ -18,10 +26,23 @@ pub struct CreateUserPayload {
 pub struct UpdateUserPayload {
     #[validate(required, length(min = 2))]
     pub name: Option<String>,
+    /// A new email has to be verified again.
+    #[validate(email)]
+    pub email: Option<String>,
+}
+
+/// The user can ask for the verification email again if sending it fails.
+async fn send_verification(email_verification_service: &dyn EmailVerificationService, user: &User) {
+    if let Err(err) = email_verification_service.send(user.clone()).await {
+        error!(
+            "failed sending verification email to user {}: {}",
+            user.id, err
+        );
+    }
 }
 
 #[get("/users")]
-async fn get_users(user_service: web::Data<dyn UserService>) -> HttpResponse {
+async fn get_users(user_service: web::Data<dyn UserService>, _: AdminOnly) -> HttpResponse {
     let result = user_service.list().await;
 
     match result {
@@ -37,7 +58,12 @@ async fn get_users(user_service: web::Data<dyn UserService>) -> HttpResponse {
 async fn get_user_by_id(
     user_service: web::Data<dyn UserService>,
     id: web::Path<Uuid>,
+    auth: Authorization,
 ) -> HttpResponse {
+    if let Err(err) = auth.authorize(&id) {
+        return err.error_response();
+    }
+
     let result = user_service.get_by_id(id.into_inner()).await;
 
     match result {
@@ -52,6 +78,7 @@ async fn get_user_by_id(
 #[post("/users")]
 async fn create_user(
     user_service: web::Data<dyn UserService>,
+    email_verification_service: web::Data<dyn EmailVerificationService>,
     payload: Option<web::Json<CreateUserPayload>>,
 ) -> HttpResponse {
     if payload.is_none() {
@@ -63,23 +90,39 @@ async fn create_user(
         return HttpResponse::BadRequest().json(err);
     }
 
-    let CreateUserPayload { name, password } = payload.into_inner();
+    let CreateUserPayload {
+        name,
+        email,
+        password,
+    } = payload.into_inner();
 
-    match user_service.create(name.unwrap(), password.unwrap()).await {
+    match user_service
+        .create(name.unwrap(), email.unwrap(), password.unwrap())
+        .await
+    {
         Err(err) => {
             error!("failed creating user: {}", err);
             HttpResponse::InternalServerError().finish()
         }
-        Ok(new_user) => HttpResponse::Ok().json(new_user),
+        Ok(new_user) => {
+            send_verification(email_verification_service.as_ref(), &new_user).await;
+            HttpResponse::Ok().json(new_user)
+        }
     }
 }
 
 #[put("/users/{id}")]
 async fn update_user(
     user_service: web::Data<dyn UserService>,
+    email_verification_service: web::Data<dyn EmailVerificationService>,
     payload: Option<web::Json<UpdateUserPayload>>,
     id: web::Path<Uuid>,
+    LoggedIn(auth): LoggedIn,
 ) -> HttpResponse {
+    if let Err(err) = auth.authorize(&id) {
+        return err.error_response();
+    }
+
     if payload.is_none() {
         return HttpResponse::BadRequest().body("empty body");
     }
@@ -89,28 +132,75 @@ async fn update_user(
         return HttpResponse::BadRequest().json(err);
     }
 
-    let name = payload.into_inner().name;
+    let UpdateUserPayload { name, email } = payload.into_inner();
+    let id = id.into_inner();
 
-    match user_service.update(id.into_inner(), name.unwrap()).await {
+    let updated_user = match user_service.update(id, name.unwrap()).await {
         Err(err) => {
             error!("failed updating user: {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+        Ok(updated_user) => updated_user,
+    };
+
+    let email = match email {
+        Some(email) if updated_user.email.as_ref() != Some(&email) => email,
+        _ => return HttpResponse::Ok().json(updated_user),
+    };
+
+    match user_service.update_email(id, email).await {
+        Err(err) => {
+            error!("failed updating email of user {}: {}", id, err);
             HttpResponse::InternalServerError().finish()
         }
-        Ok(updated_user) => HttpResponse::Ok().json(updated_user),
+        Ok(updated_user) => {
+            send_verification(email_verification_service.as_ref(), &updated_user).await;
+            HttpResponse::Ok().json(updated_user)
+        }
     }
 }
 
+/// Deletes the user, the tokens issued to them stop working right away.
 #[delete("/users/{id}")]
 async fn delete_user(
     user_service: web::Data<dyn UserService>,
+    token_service: web::Data<dyn TokenService>,
     id: web::Path<Uuid>,
+    LoggedIn(auth): LoggedIn,
 ) -> HttpResponse {
-    match user_service.delete(id.into_inner()).await {
+    if let Err(err) = auth.authorize(&id) {
+        return err.error_response();
+    }
+
+    let id = id.into_inner();
+    // revoked first, a failure leaves the user in place for the client to retry
+    if let Err(err) = token_service.revoke_all(id).await {
+        error!("failed revoking tokens of user {}: {}", id, err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match user_service.delete(id).await {
+        Ok(_) => HttpResponse::Ok().finish(),
         Err(err) => {
             error!("failed deleting user: {}", err);
             HttpResponse::InternalServerError().finish()
         }
-        Ok(_) => HttpResponse::Ok().finish(),
+    }
+}
+
+/// Lifts the lockout of the account after failed logins.
+#[post("/users/{id}/unlock")]
+async fn unlock_user(
+    login_service: web::Data<dyn LoginService>,
+    id: web::Path<Uuid>,
+    AdminOnly(admin): AdminOnly,
+) -> HttpResponse {
+    match login_service.unlock(id.into_inner(), admin.user_id).await {
+        Err(err) => {
+            error!("failed unlocking user: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+        Ok(unlocked) => HttpResponse::Ok().json(json!({"status": "success", "unlocked": unlocked})),
     }
 }
 
@@ -125,60 +215,100 @@ mod tests {
     use std::str::FromStr;
     use std::sync::Arc;
     use utils::error::CommonError;
+    use utils::http::middlewares::jwt_auth::Role;
+    use utils::http::services::auth_service::{AuthService, JwtAuthService};
+    use utils::http::test_utils::get_user_authorization_header;
     use uuid::Uuid;
 
     use crate::models::user::User;
+    use crate::services::email_verification_service::MockEmailVerificationService;
+    use crate::services::login_service::MockLoginService;
+    use crate::services::token_service::MockTokenService;
     use crate::services::user_service::MockUserService;
 
+    const ALICE: &str = "b73ccd26-1832-4d10-9251-271ce453cee3";
+    const BOB: &str = "fdd0a6f3-af61-4760-a789-5b6dd16eb7dc";
+
+    fn auth_service() -> Arc<dyn AuthService> {
+        Arc::new(JwtAuthService::new("secret123".to_owned()))
+    }
+
     struct ListUsersTestCase {
+        role: Role,
         expected_status: StatusCode,
         expected_body: &'static str,
-        service_result: Result<Vec<User>, CommonError>,
+        service_result: Option<Result<Vec<User>, CommonError>>,
     }
 
     #[rstest]
     #[case(ListUsersTestCase {
+        role: Role::Admin,
         expected_status: StatusCode::OK,
-        expected_body: r#"[{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice"},{"id":"fdd0a6f3-af61-4760-a789-5b6dd16eb7dc","name":"Bob"}]"#,
-        service_result: Ok(vec![
+        expected_body: r#"[{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice","role":"user","email":null,"email_verified_at":null},{"id":"fdd0a6f3-af61-4760-a789-5b6dd16eb7dc","name":"Bob","role":"user","email":null,"email_verified_at":null}]"#,
+        service_result: Some(Ok(vec![
             User {
                 id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
                 name: "Alice".to_owned(),
                 password: "1234".to_owned(),
+                role: Role::User,
+                email: None,
+                email_verified_at: None,
             },
             User {
                 id: Uuid::from_str("fdd0a6f3-af61-4760-a789-5b6dd16eb7dc").unwrap(),
                 name: "Bob".to_owned(),
                 password: "1234".to_owned(),
+                role: Role::User,
+                email: None,
+                email_verified_at: None,
             },
-        ]),
+        ])),
     })]
     #[case(ListUsersTestCase {
+        role: Role::Admin,
         expected_status: StatusCode::INTERNAL_SERVER_ERROR,
         expected_body: "",
-        service_result: Err(CommonError {
+        service_result: Some(Err(CommonError {
             message: "db is down".to_owned(),
             code: 1,
-        }),
+        })),
+    })]
+    #[case::not_admin(ListUsersTestCase {
+        role: Role::User,
+        expected_status: StatusCode::FORBIDDEN,
+        expected_body: r#"{"status":"fail","message":"You are not allowed to perform this action"}"#,
+        service_result: None,
     })]
     #[actix_rt::test]
     async fn test_get_users(#[case] case: ListUsersTestCase) {
         let mut user_service = MockUserService::new();
 
-        user_service
-            .expect_list()
-            .returning(move || case.service_result.clone());
+        if let Some(result) = case.service_result {
+            user_service
+                .expect_list()
+                .times(1)
+                .returning(move || result.clone());
+        }
 
         let user_service: Arc<dyn UserService> = Arc::new(user_service);
+        let auth_service = auth_service();
 
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::from(user_service.clone()))
+                .app_data(web::Data::from(auth_service.clone()))
                 .service(get_users),
         )
         .await;
 
-        let req = test::TestRequest::get().uri("/users").to_request();
+        let req = test::TestRequest::get()
+            .uri("/users")
+            .append_header(get_user_authorization_header(
+                auth_service,
+                ALICE,
+                case.role,
+            ))
+            .to_request();
         let resp = test::call_service(&app, req).await;
 
         assert_eq!(resp.status(), case.expected_status);
@@ -189,54 +319,83 @@ mod tests {
 
     struct GetUserTestCase {
         id: Uuid,
+        user: (&'static str, Role),
         expected_status: StatusCode,
         expected_body: &'static str,
-        service_result: Result<User, CommonError>,
+        service_result: Option<Result<User, CommonError>>,
     }
 
     #[rstest]
     #[case(GetUserTestCase {
         id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (ALICE, Role::User),
         expected_status: StatusCode::OK,
-        expected_body: r#"{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice"}"#,
-        service_result: Ok(
+        expected_body: r#"{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice","role":"user","email":null,"email_verified_at":null}"#,
+        service_result: Some(Ok(
             User {
                 id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
                 name: "Alice".to_owned(),
                 password: "1234".to_owned(),
+                role: Role::User,
+                email: None,
+                email_verified_at: None,
             },
-        ),
+        )),
     })]
     #[case(GetUserTestCase {
         id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (ALICE, Role::User),
         expected_status: StatusCode::INTERNAL_SERVER_ERROR,
         expected_body: "",
-        service_result: Err(CommonError {
+        service_result: Some(Err(CommonError {
             message: "db is down".to_owned(),
             code: 1,
-        }),
+        })),
+    })]
+    #[case::other_user(GetUserTestCase {
+        id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (BOB, Role::User),
+        expected_status: StatusCode::FORBIDDEN,
+        expected_body: r#"{"status":"fail","message":"You are not allowed to perform this action"}"#,
+        service_result: None,
+    })]
+    #[case::admin(GetUserTestCase {
+        id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (BOB, Role::Admin),
+        expected_status: StatusCode::INTERNAL_SERVER_ERROR,
+        expected_body: "",
+        service_result: Some(Err(CommonError {
+            message: "db is down".to_owned(),
+            code: 1,
+        })),
     })]
     #[actix_rt::test]
     async fn test_get_user_by_id(#[case] case: GetUserTestCase) {
         let mut user_service = MockUserService::new();
 
-        user_service
-            .expect_get_by_id()
-            .with(eq(case.id))
-            .times(1)
-            .returning(move |_| case.service_result.clone());
+        if let Some(result) = case.service_result {
+            user_service
+                .expect_get_by_id()
+                .with(eq(case.id))
+                .times(1)
+                .returning(move |_| result.clone());
+        }
 
         let user_service: Arc<dyn UserService> = Arc::new(user_service);
+        let auth_service = auth_service();
 
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::from(user_service.clone()))
+                .app_data(web::Data::from(auth_service.clone()))
                 .service(get_user_by_id),
         )
         .await;
 
+        let (user_id, role) = case.user;
         let req = test::TestRequest::get()
             .uri(format!("/users/{}", case.id).as_str())
+            .append_header(get_user_authorization_header(auth_service, user_id, role))
             .to_request();
         let resp = test::call_service(&app, req).await;
 
@@ -255,19 +414,22 @@ mod tests {
 
     #[rstest]
     #[case::success(CreateUserTestCase {
-        payload: Some(CreateUserPayload { name: Some("Alice".to_owned()), password: Some("1234567".to_owned()) }),
+        payload: Some(CreateUserPayload { name: Some("Alice".to_owned()), email: Some("alice@example.com".to_owned()), password: Some("1234567".to_owned()) }),
         expected_status: StatusCode::OK,
-        expected_body: r#"{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice"}"#,
+        expected_body: r#"{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice","role":"user","email":null,"email_verified_at":null}"#,
         service_result: Some(Ok(
             User {
                 id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
                 name: "Alice".to_owned(),
                 password: "1234".to_owned(),
+                role: Role::User,
+                email: None,
+                email_verified_at: None,
             },
         )),
     })]
     #[case::error_db(CreateUserTestCase {
-        payload: Some(CreateUserPayload { name: Some("Alice".to_owned()), password: Some("1234567".to_owned()) }),
+        payload: Some(CreateUserPayload { name: Some("Alice".to_owned()), email: Some("alice@example.com".to_owned()), password: Some("1234567".to_owned()) }),
         expected_status: StatusCode::INTERNAL_SERVER_ERROR,
         expected_body: r#""#,
         service_result: Some(Err(CommonError{
@@ -276,17 +438,23 @@ mod tests {
     })),
     })]
     #[case::no_name(CreateUserTestCase {
-        payload: Some(CreateUserPayload { name: None, password: Some("1234567".to_owned()) }),
+        payload: Some(CreateUserPayload { name: None, email: Some("alice@example.com".to_owned()), password: Some("1234567".to_owned()) }),
         expected_status: StatusCode::BAD_REQUEST,
         expected_body: r#"{"name":[{"code":"required","message":null,"params":{"value":null}}]}"#,
         service_result: None,
     })]
     #[case::no_password(CreateUserTestCase {
-        payload: Some(CreateUserPayload { name: Some("Bob".to_owned()), password: None }),
+        payload: Some(CreateUserPayload { name: Some("Bob".to_owned()), email: Some("bob@example.com".to_owned()), password: None }),
         expected_status: StatusCode::BAD_REQUEST,
         expected_body: r#"{"password":[{"code":"required","message":null,"params":{"value":null}}]}"#,
         service_result: None,
     })]
+    #[case::invalid_email(CreateUserTestCase {
+        payload: Some(CreateUserPayload { name: Some("Bob".to_owned()), email: Some("bob".to_owned()), password: Some("1234567".to_owned()) }),
+        expected_status: StatusCode::BAD_REQUEST,
+        expected_body: r#"{"email":[{"code":"email","message":null,"params":{"value":"bob"}}]}"#,
+        service_result: None,
+    })]
     #[case::no_payload(CreateUserTestCase {
         payload: None,
         expected_status: StatusCode::BAD_REQUEST,
@@ -296,19 +464,28 @@ mod tests {
     #[actix_rt::test]
     async fn test_create_user(#[case] case: CreateUserTestCase) {
         let mut user_service = MockUserService::new();
+        let mut email_verification_service = MockEmailVerificationService::new();
 
         if let Some(result) = case.service_result {
+            // the new user is asked to verify their email
+            email_verification_service
+                .expect_send()
+                .times(result.is_ok() as usize)
+                .returning(|_| Ok(()));
             user_service
                 .expect_create()
                 .times(1)
-                .returning(move |_, _| result.clone());
+                .returning(move |_, _, _| result.clone());
         }
 
         let user_service: Arc<dyn UserService> = Arc::new(user_service);
+        let email_verification_service: Arc<dyn EmailVerificationService> =
+            Arc::new(email_verification_service);
 
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::from(user_service.clone()))
+                .app_data(web::Data::from(email_verification_service))
                 .service(create_user),
         )
         .await;
@@ -328,6 +505,7 @@ mod tests {
 
     struct UpdateUserTestCase {
         id: Uuid,
+        user: (&'static str, Role),
         payload: Option<UpdateUserPayload>,
         expected_status: StatusCode,
         expected_body: &'static str,
@@ -337,20 +515,25 @@ mod tests {
     #[rstest]
     #[case::success(UpdateUserTestCase {
         id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
-        payload: Some(UpdateUserPayload { name: Some("Alice".to_owned()) }),
+        user: (ALICE, Role::User),
+        payload: Some(UpdateUserPayload { name: Some("Alice".to_owned()), email: None }),
         expected_status: StatusCode::OK,
-        expected_body: r#"{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice"}"#,
+        expected_body: r#"{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice","role":"user","email":null,"email_verified_at":null}"#,
         service_result: Some(Ok(
             User {
                 id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
                 name: "Alice".to_owned(),
                 password: "1234".to_owned(),
+                role: Role::User,
+                email: None,
+                email_verified_at: None,
             },
         )),
     })]
     #[case::error_db(UpdateUserTestCase {
         id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
-        payload: Some(UpdateUserPayload { name: Some("Alice".to_owned()) }),
+        user: (ALICE, Role::User),
+        payload: Some(UpdateUserPayload { name: Some("Alice".to_owned()), email: None }),
         expected_status: StatusCode::INTERNAL_SERVER_ERROR,
         expected_body: r#""#,
         service_result: Some(Err(CommonError{
@@ -360,18 +543,28 @@ mod tests {
     })]
     #[case::no_name(UpdateUserTestCase {
         id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
-        payload: Some(UpdateUserPayload { name: None }),
+        user: (ALICE, Role::User),
+        payload: Some(UpdateUserPayload { name: None, email: None }),
         expected_status: StatusCode::BAD_REQUEST,
         expected_body: r#"{"name":[{"code":"required","message":null,"params":{"value":null}}]}"#,
         service_result: None,
     })]
     #[case::no_content(UpdateUserTestCase {
         id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (ALICE, Role::User),
         payload: None,
         expected_status: StatusCode::BAD_REQUEST,
         expected_body: r#"empty body"#,
         service_result: None,
     })]
+    #[case::other_user(UpdateUserTestCase {
+        id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (BOB, Role::User),
+        payload: Some(UpdateUserPayload { name: Some("Alice".to_owned()), email: None }),
+        expected_status: StatusCode::FORBIDDEN,
+        expected_body: r#"{"status":"fail","message":"You are not allowed to perform this action"}"#,
+        service_result: None,
+    })]
     #[actix_rt::test]
     async fn test_update_user(#[case] case: UpdateUserTestCase) {
         let mut user_service = MockUserService::new();
@@ -384,16 +577,23 @@ mod tests {
         }
 
         let user_service: Arc<dyn UserService> = Arc::new(user_service);
+        let email_verification_service: Arc<dyn EmailVerificationService> =
+            Arc::new(MockEmailVerificationService::new());
+        let auth_service = auth_service();
 
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::from(user_service.clone()))
+                .app_data(web::Data::from(email_verification_service))
+                .app_data(web::Data::from(auth_service.clone()))
                 .service(update_user),
         )
         .await;
 
+        let (user_id, role) = case.user;
         let req = test::TestRequest::put()
             .uri(format!("/users/{}", case.id).as_str())
+            .append_header(get_user_authorization_header(auth_service, user_id, role))
             .insert_header(("content-type", "application/json"))
             .set_payload(serde_json::to_vec(&case.payload).unwrap())
             .to_request();
@@ -405,50 +605,166 @@ mod tests {
         assert_eq!(body, case.expected_body);
     }
 
+    #[actix_rt::test]
+    async fn test_update_user_email() {
+        let id = Uuid::from_str(ALICE).unwrap();
+        let user = |email: Option<&str>| User {
+            id,
+            name: "Alice".to_owned(),
+            password: "1234".to_owned(),
+            role: Role::User,
+            email: email.map(str::to_owned),
+            email_verified_at: None,
+        };
+        let updated = user(None);
+        let with_email = user(Some("alice@example.com"));
+
+        let mut user_service = MockUserService::new();
+        user_service
+            .expect_update()
+            .times(1)
+            .returning(move |_, _| Ok(updated.clone()));
+        user_service
+            .expect_update_email()
+            .with(eq(id), eq("alice@example.com".to_owned()))
+            .times(1)
+            .returning(move |_, _| Ok(with_email.clone()));
+        let mut email_verification_service = MockEmailVerificationService::new();
+        email_verification_service
+            .expect_send()
+            .withf(|user| user.email == Some("alice@example.com".to_owned()))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let user_service: Arc<dyn UserService> = Arc::new(user_service);
+        let email_verification_service: Arc<dyn EmailVerificationService> =
+            Arc::new(email_verification_service);
+        let auth_service = auth_service();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(user_service))
+                .app_data(web::Data::from(email_verification_service))
+                .app_data(web::Data::from(auth_service.clone()))
+                .service(update_user),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri(format!("/users/{}", id).as_str())
+            .append_header(get_user_authorization_header(
+                auth_service,
+                ALICE,
+                Role::User,
+            ))
+            .set_json(UpdateUserPayload {
+                name: Some("Alice".to_owned()),
+                email: Some("alice@example.com".to_owned()),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            r#"{"id":"b73ccd26-1832-4d10-9251-271ce453cee3","name":"Alice","role":"user","email":"alice@example.com","email_verified_at":null}"#
+        );
+    }
+
     struct DeleteUserTestCase {
         id: Uuid,
+        user: (&'static str, Role),
         expected_status: StatusCode,
         expected_body: &'static str,
-        service_result: Result<usize, CommonError>,
+        service_result: Option<Result<usize, CommonError>>,
+        revoke_result: Option<Result<(), CommonError>>,
     }
 
     #[rstest]
     #[case(DeleteUserTestCase {
         id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (ALICE, Role::User),
         expected_status: StatusCode::OK,
         expected_body: r#""#,
-        service_result: Ok(1),
+        service_result: Some(Ok(1)),
+        revoke_result: Some(Ok(())),
     })]
     #[case(DeleteUserTestCase {
         id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (ALICE, Role::User),
         expected_status: StatusCode::INTERNAL_SERVER_ERROR,
         expected_body: "",
-        service_result: Err(CommonError {
+        service_result: Some(Err(CommonError {
             message: "db is down".to_owned(),
             code: 1,
-        }),
+        })),
+        revoke_result: Some(Ok(())),
+    })]
+    #[case::other_user(DeleteUserTestCase {
+        id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (BOB, Role::User),
+        expected_status: StatusCode::FORBIDDEN,
+        expected_body: r#"{"status":"fail","message":"You are not allowed to perform this action"}"#,
+        service_result: None,
+        revoke_result: None,
+    })]
+    #[case::admin(DeleteUserTestCase {
+        id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (BOB, Role::Admin),
+        expected_status: StatusCode::OK,
+        expected_body: r#""#,
+        service_result: Some(Ok(1)),
+        revoke_result: Some(Ok(())),
+    })]
+    #[case::failed_revoke(DeleteUserTestCase {
+        id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
+        user: (ALICE, Role::User),
+        expected_status: StatusCode::INTERNAL_SERVER_ERROR,
+        expected_body: "",
+        service_result: None,
+        revoke_result: Some(Err(CommonError {
+            message: "db is down".to_owned(),
+            code: 1,
+        })),
     })]
     #[actix_rt::test]
     async fn test_delete_user_by_id(#[case] case: DeleteUserTestCase) {
         let mut user_service = MockUserService::new();
 
-        user_service
-            .expect_delete()
-            .with(eq(case.id))
-            .times(1)
-            .returning(move |_| case.service_result.clone());
+        if let Some(result) = case.service_result {
+            user_service
+                .expect_delete()
+                .with(eq(case.id))
+                .times(1)
+                .returning(move |_| result.clone());
+        }
+        let mut token_service = MockTokenService::new();
+        if let Some(result) = case.revoke_result {
+            token_service
+                .expect_revoke_all()
+                .with(eq(case.id))
+                .times(1)
+                .returning(move |_| result.clone());
+        }
 
         let user_service: Arc<dyn UserService> = Arc::new(user_service);
+        let token_service: Arc<dyn TokenService> = Arc::new(token_service);
+        let auth_service = auth_service();
 
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::from(user_service.clone()))
+                .app_data(web::Data::from(token_service))
+                .app_data(web::Data::from(auth_service.clone()))
                 .service(delete_user),
         )
         .await;
 
+        let (user_id, role) = case.user;
         let req = test::TestRequest::delete()
             .uri(format!("/users/{}", case.id).as_str())
+            .append_header(get_user_authorization_header(auth_service, user_id, role))
             .to_request();
         let resp = test::call_service(&app, req).await;
 
@@ -457,4 +773,70 @@ mod tests {
         let body = test::read_body(resp).await;
         assert_eq!(body, case.expected_body);
     }
+
+    struct UnlockUserTestCase {
+        role: Role,
+        expected_status: StatusCode,
+        expected_body: &'static str,
+        service_result: Option<Result<bool, CommonError>>,
+    }
+
+    #[rstest]
+    #[case::unlocked(UnlockUserTestCase {
+        role: Role::Admin,
+        expected_status: StatusCode::OK,
+        expected_body: r#"{"status":"success","unlocked":true}"#,
+        service_result: Some(Ok(true)),
+    })]
+    #[case::error_db(UnlockUserTestCase {
+        role: Role::Admin,
+        expected_status: StatusCode::INTERNAL_SERVER_ERROR,
+        expected_body: "",
+        service_result: Some(Err(CommonError {
+            message: "db is down".to_owned(),
+            code: 1,
+        })),
+    })]
+    #[case::not_admin(UnlockUserTestCase {
+        role: Role::User,
+        expected_status: StatusCode::FORBIDDEN,
+        expected_body: r#"{"status":"fail","message":"You are not allowed to perform this action"}"#,
+        service_result: None,
+    })]
+    #[actix_rt::test]
+    async fn test_unlock_user(#[case] case: UnlockUserTestCase) {
+        let mut login_service = MockLoginService::new();
+
+        if let Some(result) = case.service_result {
+            login_service
+                .expect_unlock()
+                .with(
+                    eq(Uuid::from_str(ALICE).unwrap()),
+                    eq(Uuid::from_str(BOB).unwrap()),
+                )
+                .times(1)
+                .returning(move |_, _| result.clone());
+        }
+
+        let login_service: Arc<dyn LoginService> = Arc::new(login_service);
+        let auth_service = auth_service();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(login_service))
+                .app_data(web::Data::from(auth_service.clone()))
+                .service(unlock_user),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri(format!("/users/{}/unlock", ALICE).as_str())
+            .append_header(get_user_authorization_header(auth_service, BOB, case.role))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), case.expected_status);
+        let body = test::read_body(resp).await;
+        assert_eq!(body, case.expected_body);
+    }
 }
@@ -1,8 +1,11 @@
 pub mod app;
 pub mod config;
 pub mod error;
+pub mod events;
 pub mod handlers;
 pub mod models;
+pub mod oidc;
 pub mod repositories;
 pub mod schema;
 pub mod services;
+pub mod totp;
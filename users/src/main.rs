@@ -1,28 +1,97 @@
 #[macro_use]
 extern crate log;
 
+use std::sync::Arc;
+use std::time::Duration;
+
 use actix::Actor;
 use actix_web::HttpServer;
 use users::app;
 use users::config::Config;
-use utils::http::websockets::ws_server::WebsocketServer;
+use users::events::USER_TOPICS;
+use users::repositories::user_repository::UserDieselRepository;
+use users::services::user_service::{UserService, UserServiceImpl};
+use utils::db;
+use utils::http::websockets::ws_server::{CloseAll, WebsocketServer};
 use utils::logger::init_logger;
+use utils::mail;
+use utils::outbox::{relay::OutboxRelay, repository::OutboxDieselRepository};
+use utils::shutdown::shutdown_token;
+use utils::telemetry::{init_tracing, shutdown_tracing};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let config = Config::init();
 
     init_logger(config.logs_path.clone());
+    init_tracing("users", &config.otlp_endpoint);
 
     let server_port = config.server_port.clone();
+    let shutdown_timeout = config.shutdown_timeout;
 
     // http server
     info!("Starting API server in port {}", config.server_port.clone());
 
+    let shutdown = shutdown_token();
     let ws_server = WebsocketServer::new().start();
 
-    HttpServer::new(move || app::setup_app(&config, ws_server.clone()))
-        .bind(format!("0.0.0.0:{}", server_port))?
-        .run()
+    config
+        .broker
+        .ensure_topics(USER_TOPICS)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err.message));
+
+    let pool = Arc::new(db::connect_db(config.database_url.clone()));
+
+    // the API can't make admins, they are named in the config
+    let promoted = UserServiceImpl::new(Arc::new(UserDieselRepository::new(pool.clone())))
+        .promote_admins(config.admin_emails.clone())
         .await
+        .unwrap_or_else(|err| panic!("failed promoting admins: {}", err.message));
+    if promoted > 0 {
+        info!("Promoted {} users to admin", promoted);
+    }
+
+    // events are stored in the outbox while handling requests and published from here
+    let producer = config
+        .broker
+        .create_producer()
+        .unwrap_or_else(|err| panic!("{}", err.message));
+    let relay = OutboxRelay::new(
+        Arc::new(OutboxDieselRepository::new(pool)),
+        producer.clone(),
+    );
+    let relay_shutdown = shutdown.clone();
+    let relay_task = actix_rt::spawn(async move { relay.start(relay_shutdown).await });
+
+    let mailer =
+        mail::from_env().unwrap_or_else(|err| panic!("invalid mailer config: {}", err.message));
+
+    let http_ws_server = ws_server.clone();
+    let server =
+        HttpServer::new(move || app::setup_app(&config, http_ws_server.clone(), mailer.clone()))
+            .disable_signals()
+            .shutdown_timeout(shutdown_timeout)
+            .bind(format!("0.0.0.0:{}", server_port))?
+            .run();
+    let server_handle = server.handle();
+    let server_task = actix_rt::spawn(server);
+
+    shutdown.cancelled().await;
+
+    info!("Shutting down API server");
+
+    ws_server.do_send(CloseAll);
+    server_handle.stop(true).await;
+
+    if let Err(err) = relay_task.await {
+        error!("failed joining outbox relay: {}", err);
+    }
+    if let Err(err) = producer.flush(Duration::from_secs(shutdown_timeout)) {
+        error!("{}", err.message);
+    }
+
+    shutdown_tracing();
+
+    server_task.await?
 }
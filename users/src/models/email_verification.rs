@@ -0,0 +1,18 @@
+use crate::schema::email_verifications;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+/// A token sent to an email address to prove the user owns it, only the hash of the
+/// token is stored.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = email_verifications)]
+pub struct EmailVerification {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    /// The address the token was sent to, the token is useless once the user changes it.
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
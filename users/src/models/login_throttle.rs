@@ -0,0 +1,42 @@
+use crate::schema::{lockout_events, login_throttles};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+pub const LOCKED_ACTION: &str = "locked";
+pub const UNLOCKED_ACTION: &str = "unlocked";
+
+/// Failed logins of an account name or a client ip, see [`account_key`] and [`ip_key`].
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = login_throttles)]
+pub struct LoginThrottle {
+    pub key: String,
+    /// Failures since the count last restarted.
+    pub failures: i32,
+    pub last_failure_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+/// Audit record of a key being locked out, or unlocked by an admin.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = lockout_events)]
+pub struct LockoutEvent {
+    pub id: uuid::Uuid,
+    pub key: String,
+    pub action: String,
+    /// Client of the attempt that locked the key.
+    pub ip: Option<String>,
+    /// Admin who unlocked the key.
+    pub actor_id: Option<uuid::Uuid>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Key of the attempts on an account, names are compared case insensitively.
+pub fn account_key(name: &str) -> String {
+    format!("account:{}", name.to_lowercase())
+}
+
+/// Key of the attempts from a client.
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
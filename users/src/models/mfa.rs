@@ -0,0 +1,31 @@
+use crate::schema::{mfa_recovery_codes, user_mfa};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+/// TOTP secret of a user, the second factor is only required once enabled.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = user_mfa)]
+pub struct UserMfa {
+    pub user_id: uuid::Uuid,
+    pub secret: String,
+    pub enabled_at: Option<DateTime<Utc>>,
+    /// Step of the last code used, codes can't be used twice.
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UserMfa {
+    pub fn enabled(&self) -> bool {
+        self.enabled_at.is_some()
+    }
+}
+
+/// Code to log in without the authenticator app, only the hash of the code is stored.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = mfa_recovery_codes)]
+pub struct RecoveryCode {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub code_hash: String,
+    pub used_at: Option<DateTime<Utc>>,
+}
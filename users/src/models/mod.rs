@@ -1 +1,9 @@
+pub mod email_verification;
+pub mod login_throttle;
+pub mod mfa;
+pub mod oidc;
+pub mod password_reset;
+pub mod personal_token;
+pub mod preferences;
+pub mod refresh_token;
 pub mod user;
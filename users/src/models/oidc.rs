@@ -0,0 +1,27 @@
+use crate::schema::{oidc_logins, user_identities};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+/// Account of a user at an identity provider, the subject never changes unlike the
+/// email.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = user_identities)]
+pub struct UserIdentity {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub issuer: String,
+    pub subject: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Login sent to the identity provider, only the hash of the state is stored since the
+/// state alone completes the login.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = oidc_logins)]
+pub struct OidcLogin {
+    pub state_hash: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
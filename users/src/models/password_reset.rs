@@ -0,0 +1,16 @@
+use crate::schema::password_resets;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+/// A token sent to the email of a user who forgot their password, only the hash of the
+/// token is stored.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = password_resets)]
+pub struct PasswordReset {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
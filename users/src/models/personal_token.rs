@@ -0,0 +1,22 @@
+use crate::schema::personal_access_tokens;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+
+/// Token a user created for their scripts, only the hash of the token is stored.
+#[derive(Debug, Clone, Queryable, Insertable, Serialize, PartialEq)]
+#[diesel(table_name = personal_access_tokens)]
+pub struct PersonalAccessToken {
+    pub id: uuid::Uuid,
+    #[serde(skip)]
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    /// The token can do whatever its user can when empty.
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,244 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::schema::user_preferences;
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+pub const DEFAULT_LOCALE: &str = "en";
+pub const DEFAULT_DIGEST_HOUR: i16 = 8;
+
+/// How the user is told about the news of their subscriptions.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    diesel::AsExpression,
+    diesel::FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum Notifications {
+    /// As soon as the news is published.
+    #[default]
+    Realtime,
+    /// Once a day at the digest hour.
+    Digest,
+    Off,
+}
+
+impl Notifications {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Notifications::Realtime => "realtime",
+            Notifications::Digest => "digest",
+            Notifications::Off => "off",
+        }
+    }
+}
+
+impl fmt::Display for Notifications {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Notifications {
+    type Err = String;
+
+    fn from_str(notifications: &str) -> Result<Self, Self::Err> {
+        match notifications {
+            "realtime" => Ok(Notifications::Realtime),
+            "digest" => Ok(Notifications::Digest),
+            "off" => Ok(Notifications::Off),
+            notifications => Err(format!("unknown notifications {}", notifications)),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for Notifications {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for Notifications {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let notifications = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+        Ok(notifications.parse()?)
+    }
+}
+
+/// Profile and preferences of a user, the news service reads them from the
+/// `user_preferences_updated` event.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = user_preferences)]
+pub struct UserPreferences {
+    pub user_id: uuid::Uuid,
+    /// Shown instead of the name when set.
+    pub display_name: Option<String>,
+    /// IANA name of the timezone, like Europe/Paris.
+    pub timezone: String,
+    pub locale: String,
+    pub notifications: Notifications,
+    /// Local hour the digest is sent at.
+    pub digest_hour: i16,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UserPreferences {
+    /// Preferences of the users who never changed them.
+    pub fn defaults(user_id: uuid::Uuid) -> Self {
+        UserPreferences {
+            user_id,
+            display_name: None,
+            timezone: DEFAULT_TIMEZONE.to_string(),
+            locale: DEFAULT_LOCALE.to_string(),
+            notifications: Notifications::default(),
+            digest_hour: DEFAULT_DIGEST_HOUR,
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+/// Changes to the preferences, the missing fields are kept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreferencesUpdate {
+    /// An empty display name removes it.
+    pub display_name: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+    pub notifications: Option<Notifications>,
+    pub digest_hour: Option<i16>,
+}
+
+impl PreferencesUpdate {
+    /// The preferences with the changes applied, none when nothing changes.
+    pub fn apply(self, preferences: &UserPreferences) -> Option<UserPreferences> {
+        let updated = UserPreferences {
+            user_id: preferences.user_id,
+            display_name: match self.display_name {
+                Some(name) if name.trim().is_empty() => None,
+                Some(name) => Some(name.trim().to_string()),
+                None => preferences.display_name.clone(),
+            },
+            timezone: self
+                .timezone
+                .unwrap_or_else(|| preferences.timezone.clone()),
+            locale: self.locale.unwrap_or_else(|| preferences.locale.clone()),
+            notifications: self.notifications.unwrap_or(preferences.notifications),
+            digest_hour: self.digest_hour.unwrap_or(preferences.digest_hour),
+            updated_at: Utc::now(),
+        };
+
+        let unchanged = UserPreferences {
+            updated_at: preferences.updated_at,
+            ..updated.clone()
+        } == *preferences;
+        (!unchanged).then_some(updated)
+    }
+}
+
+/// Whether the timezone is in the IANA database.
+pub fn is_timezone(timezone: &str) -> bool {
+    timezone.parse::<chrono_tz::Tz>().is_ok()
+}
+
+/// Whether the locale is a language, optionally followed by a region, like en or pt-BR.
+pub fn is_locale(locale: &str) -> bool {
+    let mut parts = locale.split('-');
+    let language = parts.next().unwrap_or_default();
+    let region = parts.next();
+
+    let valid_region = match region {
+        Some(region) => {
+            (region.len() == 2 && region.chars().all(|c| c.is_ascii_uppercase()))
+                || (region.len() == 3 && region.chars().all(|c| c.is_ascii_digit()))
+        }
+        None => true,
+    };
+
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_lowercase())
+        && valid_region
+        && parts.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let preferences = UserPreferences::defaults(uuid::Uuid::new_v4());
+
+        assert_eq!(PreferencesUpdate::default().apply(&preferences), None);
+        assert_eq!(
+            PreferencesUpdate {
+                timezone: Some(DEFAULT_TIMEZONE.to_string()),
+                ..Default::default()
+            }
+            .apply(&preferences),
+            None
+        );
+
+        let updated = PreferencesUpdate {
+            display_name: Some(" Alice ".to_string()),
+            notifications: Some(Notifications::Digest),
+            ..Default::default()
+        }
+        .apply(&preferences)
+        .unwrap();
+        assert_eq!(updated.display_name.as_deref(), Some("Alice"));
+        assert_eq!(updated.notifications, Notifications::Digest);
+        assert_eq!(updated.timezone, preferences.timezone);
+        assert_eq!(updated.digest_hour, preferences.digest_hour);
+
+        let cleared = PreferencesUpdate {
+            display_name: Some("".to_string()),
+            ..Default::default()
+        }
+        .apply(&updated)
+        .unwrap();
+        assert_eq!(cleared.display_name, None);
+    }
+
+    #[test]
+    fn test_validation() {
+        let timezones = vec![
+            ("Europe/Paris", true),
+            ("UTC", true),
+            ("America/Argentina/Buenos_Aires", true),
+            ("Mars/Olympus", false),
+            ("", false),
+        ];
+        for (timezone, expected) in timezones {
+            assert_eq!(is_timezone(timezone), expected, "{}", timezone);
+        }
+
+        let locales = vec![
+            ("en", true),
+            ("pt-BR", true),
+            ("es-419", true),
+            ("fil", true),
+            ("EN", false),
+            ("pt_BR", false),
+            ("pt-br", false),
+            ("en-US-x", false),
+            ("", false),
+        ];
+        for (locale, expected) in locales {
+            assert_eq!(is_locale(locale), expected, "{}", locale);
+        }
+    }
+}
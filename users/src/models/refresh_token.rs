@@ -0,0 +1,18 @@
+use crate::schema::refresh_tokens;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+/// A refresh token issued to a user, only the hash of the token is stored.
+#[derive(Debug, Clone, Queryable, Insertable, PartialEq)]
+#[diesel(table_name = refresh_tokens)]
+pub struct RefreshToken {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub family_id: uuid::Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// Set once the token is exchanged, using it again means it was stolen.
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
@@ -1,6 +1,10 @@
 use crate::schema::users;
+use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
+use utils::http::middlewares::jwt_auth::{Role, TokenSubject};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, PartialEq)]
 #[diesel(table_name = users)]
@@ -9,4 +13,43 @@ pub struct User {
     pub name: String,
     #[serde(skip_serializing)]
     pub password: String,
+    pub role: Role,
+    /// Missing for the users registered before emails were required.
+    pub email: Option<String>,
+    /// Reset whenever the email changes.
+    pub email_verified_at: Option<DateTime<Utc>>,
+}
+
+impl User {
+    pub fn email_verified(&self) -> bool {
+        self.email.is_some() && self.email_verified_at.is_some()
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        verify_password(&self.password, password)
+    }
+
+    /// What the access tokens of the user carry.
+    pub fn token_subject(&self) -> TokenSubject {
+        TokenSubject::new(&self.id.to_string(), self.role, self.email_verified())
+    }
+}
+
+/// Salted hash stored instead of the password.
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Error while hashing password")
+        .to_string()
+}
+
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
 }
@@ -0,0 +1,417 @@
+//! OpenID Connect relying party: the authorization code flow with PKCE against a single
+//! issuer, whose endpoints and signing keys are discovered.
+
+use async_trait::async_trait;
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::info;
+use mockall::automock;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use utils::error::{CommonError, HttpError, AUTH_TOKEN_ENCODING_CODE};
+
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+/// Everything but the unreserved characters of RFC 3986.
+const QUERY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// How the service is registered with the provider.
+#[derive(Debug, Clone)]
+pub struct OidcSettings {
+    pub issuer: String,
+    pub client_id: String,
+    /// Public clients only rely on PKCE.
+    pub client_secret: Option<String>,
+    /// Callback the provider redirects the browser to with the code.
+    pub redirect_url: String,
+    pub scopes: String,
+}
+
+/// The endpoints of the discovery document the flow needs.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// Claims of a validated ID token.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    /// Identifies the user at the issuer, unlike the email it never changes.
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// Email the provider vouches for.
+    pub fn verified_email(&self) -> Option<&str> {
+        self.email.as_deref().filter(|_| self.email_verified)
+    }
+}
+
+/// Challenge sent in the authorization request for the verifier sent with the code, so
+/// an intercepted code is useless without the verifier.
+pub fn code_challenge(code_verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn invalid(message: String) -> CommonError {
+    CommonError {
+        message,
+        code: AUTH_TOKEN_ENCODING_CODE,
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait OidcClient: Send + Sync {
+    /// Url of the provider the browser is sent to, to log in.
+    async fn authorization_url(
+        &self,
+        state: String,
+        nonce: String,
+        code_challenge: String,
+    ) -> Result<String, CommonError>;
+    /// Exchanges the code for an ID token and validates it: signature, issuer, audience,
+    /// expiry and nonce. Invalid codes and tokens fail with the auth token error code,
+    /// unreachable providers with the http error code.
+    async fn authenticate(
+        &self,
+        code: String,
+        code_verifier: String,
+        nonce: String,
+    ) -> Result<IdTokenClaims, CommonError>;
+}
+
+pub struct HttpOidcClient {
+    http: reqwest::Client,
+    settings: OidcSettings,
+    /// Discovered on first use.
+    metadata: RwLock<Option<ProviderMetadata>>,
+    /// Fetched again when a token is signed with an unknown key, after a rotation.
+    keys: RwLock<Option<JwkSet>>,
+}
+
+impl HttpOidcClient {
+    pub fn new(settings: OidcSettings) -> Self {
+        HttpOidcClient {
+            http: reqwest::Client::new(),
+            settings,
+            metadata: RwLock::new(None),
+            keys: RwLock::new(None),
+        }
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, HttpError> {
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .map_err(|v| HttpError::new(&format!("failed to send request: {}", v)))?;
+        if !response.status().is_success() {
+            return Err(HttpError::new(&format!(
+                "Request to {} was not successful: {}",
+                url,
+                response.status().as_str()
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|v| HttpError::new(&format!("failed to read response body: {}", v)))
+    }
+
+    async fn metadata(&self) -> Result<ProviderMetadata, CommonError> {
+        if let Some(metadata) = self.metadata.read().await.as_ref() {
+            return Ok(metadata.clone());
+        }
+
+        let issuer = self.settings.issuer.trim_end_matches('/');
+        let metadata: ProviderMetadata = self
+            .get_json(&format!("{}{}", issuer, DISCOVERY_PATH))
+            .await?;
+        // the tokens are only accepted from the issuer the discovery was for
+        if metadata.issuer != self.settings.issuer {
+            return Err(HttpError::new(&format!(
+                "discovered issuer {} instead of {}",
+                metadata.issuer, self.settings.issuer
+            ))
+            .into());
+        }
+        *self.metadata.write().await = Some(metadata.clone());
+
+        Ok(metadata)
+    }
+
+    async fn decoding_key(
+        &self,
+        metadata: &ProviderMetadata,
+        kid: Option<&str>,
+    ) -> Result<DecodingKey, CommonError> {
+        let find = |keys: &JwkSet| match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            // without a key id the provider must only have one key
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        };
+
+        let cached = self.keys.read().await.as_ref().and_then(find);
+        let jwk = match cached {
+            Some(jwk) => jwk,
+            None => {
+                info!("fetching the signing keys of {}", metadata.issuer);
+                let keys: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+                let jwk = find(&keys);
+                *self.keys.write().await = Some(keys);
+                jwk.ok_or_else(|| invalid(format!("unknown signing key {:?}", kid)))?
+            }
+        };
+
+        DecodingKey::from_jwk(&jwk).map_err(|err| invalid(format!("invalid signing key: {}", err)))
+    }
+
+    async fn exchange_code(
+        &self,
+        metadata: &ProviderMetadata,
+        code: String,
+        code_verifier: String,
+    ) -> Result<String, CommonError> {
+        let mut request = self.http.post(&metadata.token_endpoint).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", self.settings.redirect_url.as_str()),
+            ("client_id", self.settings.client_id.as_str()),
+            ("code_verifier", code_verifier.as_str()),
+        ]);
+        if let Some(client_secret) = &self.settings.client_secret {
+            request = request.basic_auth(&self.settings.client_id, Some(client_secret));
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|v| HttpError::new(&format!("failed to send request: {}", v)))?;
+        // the provider rejects codes that are unknown, used or sent with another verifier
+        if response.status().is_client_error() {
+            return Err(invalid(format!(
+                "code rejected by the provider: {}",
+                response.status().as_str()
+            )));
+        }
+        if !response.status().is_success() {
+            return Err(HttpError::new(&format!(
+                "Request to {} was not successful: {}",
+                metadata.token_endpoint,
+                response.status().as_str()
+            ))
+            .into());
+        }
+
+        let tokens: TokenResponse = response
+            .json()
+            .await
+            .map_err(|v| HttpError::new(&format!("failed to read response body: {}", v)))?;
+
+        tokens
+            .id_token
+            .ok_or_else(|| HttpError::new("no id token in the token response").into())
+    }
+
+    async fn validate_id_token(
+        &self,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, CommonError> {
+        let header = decode_header(id_token)
+            .map_err(|err| invalid(format!("invalid id token header: {}", err)))?;
+        // the keys are public, tokens signed with them as a shared secret are forged
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(invalid(format!("unsupported algorithm {:?}", header.alg)));
+        }
+        let key = self.decoding_key(metadata, header.kid.as_deref()).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.settings.client_id]);
+        let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|err| invalid(format!("invalid id token: {}", err)))?
+            .claims;
+
+        // replayed tokens carry the nonce of another login
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("id token nonce mismatch".to_string()));
+        }
+
+        Ok(claims)
+    }
+}
+
+#[async_trait]
+impl OidcClient for HttpOidcClient {
+    async fn authorization_url(
+        &self,
+        state: String,
+        nonce: String,
+        code_challenge: String,
+    ) -> Result<String, CommonError> {
+        let metadata = self.metadata().await?;
+        let params = [
+            ("response_type", "code"),
+            ("client_id", &self.settings.client_id),
+            ("redirect_uri", &self.settings.redirect_url),
+            ("scope", &self.settings.scopes),
+            ("state", &state),
+            ("nonce", &nonce),
+            ("code_challenge", &code_challenge),
+            ("code_challenge_method", "S256"),
+        ];
+        let query = params
+            .iter()
+            .map(|(name, value)| {
+                format!("{}={}", name, utf8_percent_encode(value, QUERY_ENCODE_SET))
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        let separator = if metadata.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+
+        Ok(format!(
+            "{}{}{}",
+            metadata.authorization_endpoint, separator, query
+        ))
+    }
+
+    async fn authenticate(
+        &self,
+        code: String,
+        code_verifier: String,
+        nonce: String,
+    ) -> Result<IdTokenClaims, CommonError> {
+        let metadata = self.metadata().await?;
+        let id_token = self.exchange_code(&metadata, code, code_verifier).await?;
+
+        self.validate_id_token(&metadata, &id_token, &nonce).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::error::HTTP_ERROR_CODE;
+
+    fn settings(issuer: &str) -> OidcSettings {
+        OidcSettings {
+            issuer: issuer.to_string(),
+            client_id: "users".to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost:8000/auth/oidc/callback".to_string(),
+            scopes: "openid email".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_code_challenge() {
+        // example of RFC 7636
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authorization_url() {
+        let mut server = mockito::Server::new_async().await;
+        let discovery = server
+            .mock("GET", DISCOVERY_PATH)
+            .with_body(
+                serde_json::json!({
+                    "issuer": server.url(),
+                    "authorization_endpoint": format!("{}/authorize", server.url()),
+                    "token_endpoint": format!("{}/token", server.url()),
+                    "jwks_uri": format!("{}/jwks", server.url()),
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let client = HttpOidcClient::new(settings(&server.url()));
+
+        for _ in 0..2 {
+            let url = client
+                .authorization_url(
+                    "state".to_string(),
+                    "nonce".to_string(),
+                    "a-b_c".to_string(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(
+                url,
+                format!(
+                    "{}/authorize?response_type=code&client_id=users\
+                     &redirect_uri=http%3A%2F%2Flocalhost%3A8000%2Fauth%2Foidc%2Fcallback\
+                     &scope=openid%20email&state=state&nonce=nonce&code_challenge=a-b_c\
+                     &code_challenge_method=S256",
+                    server.url()
+                )
+            );
+        }
+        // discovered once
+        discovery.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_discovery_rejects_other_issuer() {
+        let mut server = mockito::Server::new_async().await;
+        let _discovery = server
+            .mock("GET", DISCOVERY_PATH)
+            .with_body(
+                serde_json::json!({
+                    "issuer": "https://evil.example.com",
+                    "authorization_endpoint": "https://evil.example.com/authorize",
+                    "token_endpoint": "https://evil.example.com/token",
+                    "jwks_uri": "https://evil.example.com/jwks",
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = HttpOidcClient::new(settings(&server.url()));
+
+        let result = client
+            .authorization_url("state".to_string(), "nonce".to_string(), "abc".to_string())
+            .await;
+
+        assert_eq!(result.unwrap_err().code, HTTP_ERROR_CODE);
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use crate::error::DieselRepositoryError;
+use crate::models::email_verification::EmailVerification;
+use crate::schema::{email_verifications, users};
+use actix_threadpool::run;
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::prelude::*;
+use mockall::automock;
+use tracing::instrument;
+use utils::db::PgPool;
+use utils::error::DatabaseError;
+use uuid::Uuid;
+
+#[automock]
+#[async_trait]
+pub trait EmailVerificationRepository: Send + Sync {
+    async fn create(
+        &self,
+        verification: EmailVerification,
+    ) -> Result<EmailVerification, DatabaseError>;
+    async fn find_by_hash(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<EmailVerification>, DatabaseError>;
+    /// Uses the token and marks the email of the user as verified in the same transaction.
+    /// Returns false when the token was used or expired meanwhile, or the user changed
+    /// their email since it was sent.
+    async fn verify(&self, verification_id: Uuid) -> Result<bool, DatabaseError>;
+}
+
+pub struct EmailVerificationDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl EmailVerificationDieselRepository {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        EmailVerificationDieselRepository { pool: db }
+    }
+}
+
+#[async_trait]
+impl EmailVerificationRepository for EmailVerificationDieselRepository {
+    #[instrument(skip_all, name = "email_verification_repository.create")]
+    async fn create(
+        &self,
+        verification: EmailVerification,
+    ) -> Result<EmailVerification, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let verification = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::insert_into(email_verifications::table)
+                .values(verification)
+                .get_result(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(verification)
+    }
+
+    #[instrument(skip_all, name = "email_verification_repository.find_by_hash")]
+    async fn find_by_hash(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<EmailVerification>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let verification = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            email_verifications::table
+                .filter(email_verifications::token_hash.eq(token_hash))
+                .first(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(verification)
+    }
+
+    #[instrument(skip_all, name = "email_verification_repository.verify")]
+    async fn verify(&self, verification_id: Uuid) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let verified = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let now = Utc::now();
+                let verification: Option<EmailVerification> = diesel::update(
+                    email_verifications::table
+                        .find(verification_id)
+                        .filter(email_verifications::used_at.is_null())
+                        .filter(email_verifications::expires_at.gt(now)),
+                )
+                .set(email_verifications::used_at.eq(now))
+                .get_result(conn)
+                .optional()?;
+                let verification = match verification {
+                    Some(verification) => verification,
+                    None => return Ok(false),
+                };
+
+                let verified = diesel::update(
+                    users::table
+                        .find(verification.user_id)
+                        .filter(users::email.eq(verification.email)),
+                )
+                .set(users::email_verified_at.eq(now))
+                .execute(conn)?;
+                Ok::<bool, diesel::result::Error>(verified == 1)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(verified)
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use crate::error::DieselRepositoryError;
+use crate::models::login_throttle::{LockoutEvent, LoginThrottle};
+use crate::schema::{lockout_events, login_throttles};
+use actix_threadpool::run;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use mockall::automock;
+use tracing::instrument;
+use utils::db::PgPool;
+use utils::error::DatabaseError;
+
+#[automock]
+#[async_trait]
+pub trait LoginThrottleRepository: Send + Sync {
+    async fn find(&self, keys: Vec<String>) -> Result<Vec<LoginThrottle>, DatabaseError>;
+    /// Counts a failed attempt at `now`, the count restarts when the last failure is
+    /// older than `since`.
+    async fn record_failure(
+        &self,
+        key: String,
+        now: DateTime<Utc>,
+        since: DateTime<Utc>,
+    ) -> Result<LoginThrottle, DatabaseError>;
+    /// Locks the key and records the lockout in the same transaction.
+    async fn lock(&self, event: LockoutEvent) -> Result<(), DatabaseError>;
+    /// Forgets the failures of the key.
+    async fn clear(&self, key: String) -> Result<(), DatabaseError>;
+    /// Forgets the failures of the key and records the unlock in the same transaction,
+    /// unless there was nothing to unlock. Returns whether there was.
+    async fn unlock(&self, event: LockoutEvent) -> Result<bool, DatabaseError>;
+}
+
+pub struct LoginThrottleDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl LoginThrottleDieselRepository {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        LoginThrottleDieselRepository { pool: db }
+    }
+}
+
+#[async_trait]
+impl LoginThrottleRepository for LoginThrottleDieselRepository {
+    #[instrument(skip_all, name = "login_throttle_repository.find")]
+    async fn find(&self, keys: Vec<String>) -> Result<Vec<LoginThrottle>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let throttles = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            login_throttles::table
+                .filter(login_throttles::key.eq_any(keys))
+                .load::<LoginThrottle>(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(throttles)
+    }
+
+    #[instrument(skip_all, name = "login_throttle_repository.record_failure")]
+    async fn record_failure(
+        &self,
+        key: String,
+        now: DateTime<Utc>,
+        since: DateTime<Utc>,
+    ) -> Result<LoginThrottle, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let throttle = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let inserted = diesel::insert_into(login_throttles::table)
+                    .values(LoginThrottle {
+                        key: key.clone(),
+                        failures: 1,
+                        last_failure_at: now,
+                        locked_until: None,
+                    })
+                    .on_conflict_do_nothing()
+                    .get_result::<LoginThrottle>(conn)
+                    .optional()?;
+                if let Some(throttle) = inserted {
+                    return Ok(throttle);
+                }
+
+                let throttle: LoginThrottle =
+                    login_throttles::table.find(&key).for_update().first(conn)?;
+                let failures = if throttle.last_failure_at < since {
+                    1
+                } else {
+                    throttle.failures + 1
+                };
+                let locked_until = throttle.locked_until.filter(|until| *until > now);
+
+                diesel::update(login_throttles::table.find(&key))
+                    .set((
+                        login_throttles::failures.eq(failures),
+                        login_throttles::last_failure_at.eq(now),
+                        login_throttles::locked_until.eq(locked_until),
+                    ))
+                    .get_result(conn)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(throttle)
+    }
+
+    #[instrument(skip_all, name = "login_throttle_repository.lock")]
+    async fn lock(&self, event: LockoutEvent) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+
+        run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                diesel::update(login_throttles::table.find(&event.key))
+                    .set(login_throttles::locked_until.eq(event.locked_until))
+                    .execute(conn)?;
+                diesel::insert_into(lockout_events::table)
+                    .values(event)
+                    .execute(conn)?;
+                Ok::<(), diesel::result::Error>(())
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(())
+    }
+
+    #[instrument(skip_all, name = "login_throttle_repository.clear")]
+    async fn clear(&self, key: String) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+
+        run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::delete(login_throttles::table.find(key)).execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(())
+    }
+
+    #[instrument(skip_all, name = "login_throttle_repository.unlock")]
+    async fn unlock(&self, event: LockoutEvent) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let unlocked = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let deleted =
+                    diesel::delete(login_throttles::table.find(&event.key)).execute(conn)?;
+                if deleted == 0 {
+                    return Ok(false);
+                }
+
+                diesel::insert_into(lockout_events::table)
+                    .values(event)
+                    .execute(conn)?;
+                Ok::<bool, diesel::result::Error>(true)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(unlocked)
+    }
+}
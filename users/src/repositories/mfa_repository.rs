@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use crate::error::DieselRepositoryError;
+use crate::models::mfa::{RecoveryCode, UserMfa};
+use crate::schema::{mfa_recovery_codes, user_mfa};
+use actix_threadpool::run;
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::prelude::*;
+use mockall::automock;
+use tracing::instrument;
+use utils::db::PgPool;
+use utils::error::DatabaseError;
+use uuid::Uuid;
+
+#[automock]
+#[async_trait]
+pub trait MfaRepository: Send + Sync {
+    async fn find(&self, user_id: Uuid) -> Result<Option<UserMfa>, DatabaseError>;
+    /// Replaces the pending enrollment of the user, none when the user already enabled it.
+    async fn save_pending(&self, mfa: UserMfa) -> Result<Option<UserMfa>, DatabaseError>;
+    /// Enables the pending enrollment with the recovery codes, in the same transaction.
+    /// Returns false when there was no pending enrollment.
+    async fn enable(
+        &self,
+        user_id: Uuid,
+        step: i64,
+        codes: Vec<RecoveryCode>,
+    ) -> Result<bool, DatabaseError>;
+    /// Records the step of a code, unless a code of this step or a later one was already
+    /// used. Returns whether it was recorded.
+    async fn use_step(&self, user_id: Uuid, step: i64) -> Result<bool, DatabaseError>;
+    /// Marks the recovery code used. Returns false when it is unknown or already used.
+    async fn use_recovery_code(
+        &self,
+        user_id: Uuid,
+        code_hash: String,
+    ) -> Result<bool, DatabaseError>;
+}
+
+pub struct MfaDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl MfaDieselRepository {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        MfaDieselRepository { pool: db }
+    }
+}
+
+#[async_trait]
+impl MfaRepository for MfaDieselRepository {
+    #[instrument(skip_all, name = "mfa_repository.find")]
+    async fn find(&self, user_id: Uuid) -> Result<Option<UserMfa>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let mfa = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            user_mfa::table
+                .find(user_id)
+                .first::<UserMfa>(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(mfa)
+    }
+
+    #[instrument(skip_all, name = "mfa_repository.save_pending")]
+    async fn save_pending(&self, mfa: UserMfa) -> Result<Option<UserMfa>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let mfa = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let enabled = user_mfa::table
+                    .find(mfa.user_id)
+                    .filter(user_mfa::enabled_at.is_not_null())
+                    .for_update()
+                    .first::<UserMfa>(conn)
+                    .optional()?;
+                if enabled.is_some() {
+                    return Ok(None);
+                }
+
+                diesel::insert_into(user_mfa::table)
+                    .values(&mfa)
+                    .on_conflict(user_mfa::user_id)
+                    .do_update()
+                    .set((
+                        user_mfa::secret.eq(&mfa.secret),
+                        user_mfa::created_at.eq(mfa.created_at),
+                    ))
+                    .get_result::<UserMfa>(conn)
+                    .map(Some)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(mfa)
+    }
+
+    #[instrument(skip_all, name = "mfa_repository.enable")]
+    async fn enable(
+        &self,
+        user_id: Uuid,
+        step: i64,
+        codes: Vec<RecoveryCode>,
+    ) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let enabled = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let updated = diesel::update(
+                    user_mfa::table
+                        .find(user_id)
+                        .filter(user_mfa::enabled_at.is_null()),
+                )
+                .set((
+                    user_mfa::enabled_at.eq(Utc::now()),
+                    user_mfa::last_used_step.eq(step),
+                ))
+                .execute(conn)?;
+                if updated == 0 {
+                    return Ok(false);
+                }
+
+                diesel::delete(
+                    mfa_recovery_codes::table.filter(mfa_recovery_codes::user_id.eq(user_id)),
+                )
+                .execute(conn)?;
+                diesel::insert_into(mfa_recovery_codes::table)
+                    .values(codes)
+                    .execute(conn)?;
+                Ok::<bool, diesel::result::Error>(true)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(enabled)
+    }
+
+    #[instrument(skip_all, name = "mfa_repository.use_step")]
+    async fn use_step(&self, user_id: Uuid, step: i64) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let updated = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::update(
+                user_mfa::table.find(user_id).filter(
+                    user_mfa::last_used_step
+                        .is_null()
+                        .or(user_mfa::last_used_step.lt(step)),
+                ),
+            )
+            .set(user_mfa::last_used_step.eq(step))
+            .execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(updated > 0)
+    }
+
+    #[instrument(skip_all, name = "mfa_repository.use_recovery_code")]
+    async fn use_recovery_code(
+        &self,
+        user_id: Uuid,
+        code_hash: String,
+    ) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let updated = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::update(
+                mfa_recovery_codes::table
+                    .filter(mfa_recovery_codes::user_id.eq(user_id))
+                    .filter(mfa_recovery_codes::code_hash.eq(code_hash))
+                    .filter(mfa_recovery_codes::used_at.is_null()),
+            )
+            .set(mfa_recovery_codes::used_at.eq(Utc::now()))
+            .execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(updated > 0)
+    }
+}
@@ -1 +1,9 @@
+pub mod email_verification_repository;
+pub mod login_throttle_repository;
+pub mod mfa_repository;
+pub mod oidc_repository;
+pub mod password_reset_repository;
+pub mod personal_token_repository;
+pub mod preferences_repository;
+pub mod refresh_token_repository;
 pub mod user_repository;
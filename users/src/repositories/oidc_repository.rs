@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use crate::error::DieselRepositoryError;
+use crate::models::oidc::{OidcLogin, UserIdentity};
+use crate::models::user::User;
+use crate::schema::{oidc_logins, user_identities, users};
+use actix_threadpool::run;
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::prelude::*;
+use mockall::automock;
+use tracing::instrument;
+use utils::db::PgPool;
+use utils::error::DatabaseError;
+use utils::outbox::{models::NewOutboxMessage, repository::enqueue};
+
+#[automock]
+#[async_trait]
+pub trait OidcRepository: Send + Sync {
+    /// Stores the login, forgetting the expired ones.
+    async fn create_login(&self, login: OidcLogin) -> Result<(), DatabaseError>;
+    /// Removes the login of the state, so it completes at most once.
+    async fn take_login(&self, state_hash: String) -> Result<Option<OidcLogin>, DatabaseError>;
+    /// User linked to the account at the issuer.
+    async fn find_user(
+        &self,
+        issuer: String,
+        subject: String,
+    ) -> Result<Option<User>, DatabaseError>;
+    async fn link(&self, identity: UserIdentity) -> Result<(), DatabaseError>;
+    /// Inserts the user linked to the identity and stores the events in the outbox in the
+    /// same transaction.
+    async fn provision(
+        &self,
+        user: User,
+        identity: UserIdentity,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError>;
+}
+
+pub struct OidcDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl OidcDieselRepository {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        OidcDieselRepository { pool: db }
+    }
+}
+
+#[async_trait]
+impl OidcRepository for OidcDieselRepository {
+    #[instrument(skip_all, name = "oidc_repository.create_login")]
+    async fn create_login(&self, login: OidcLogin) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+
+        run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::delete(oidc_logins::table.filter(oidc_logins::expires_at.lt(Utc::now())))
+                .execute(&mut conn)?;
+            diesel::insert_into(oidc_logins::table)
+                .values(login)
+                .execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(())
+    }
+
+    #[instrument(skip_all, name = "oidc_repository.take_login")]
+    async fn take_login(&self, state_hash: String) -> Result<Option<OidcLogin>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let login = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::delete(oidc_logins::table.find(state_hash))
+                .get_result::<OidcLogin>(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(login)
+    }
+
+    #[instrument(skip_all, name = "oidc_repository.find_user")]
+    async fn find_user(
+        &self,
+        issuer: String,
+        subject: String,
+    ) -> Result<Option<User>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let user = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            user_identities::table
+                .inner_join(users::table)
+                .filter(user_identities::issuer.eq(issuer))
+                .filter(user_identities::subject.eq(subject))
+                .select(users::all_columns)
+                .first::<User>(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(user)
+    }
+
+    #[instrument(skip_all, name = "oidc_repository.link")]
+    async fn link(&self, identity: UserIdentity) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+
+        run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::insert_into(user_identities::table)
+                .values(identity)
+                .execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(())
+    }
+
+    #[instrument(skip_all, name = "oidc_repository.provision")]
+    async fn provision(
+        &self,
+        user: User,
+        identity: UserIdentity,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let user = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let user = diesel::insert_into(users::table)
+                    .values(user)
+                    .get_result(conn)?;
+                diesel::insert_into(user_identities::table)
+                    .values(identity)
+                    .execute(conn)?;
+                enqueue(conn, &events)?;
+                Ok::<User, diesel::result::Error>(user)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(user)
+    }
+}
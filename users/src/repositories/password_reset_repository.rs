@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use crate::error::DieselRepositoryError;
+use crate::models::password_reset::PasswordReset;
+use crate::models::user::User;
+use crate::repositories::user_repository::set_password;
+use crate::schema::password_resets;
+use actix_threadpool::run;
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::prelude::*;
+use mockall::automock;
+use tracing::instrument;
+use utils::db::PgPool;
+use utils::error::DatabaseError;
+use utils::outbox::models::NewOutboxMessage;
+use uuid::Uuid;
+
+#[automock]
+#[async_trait]
+pub trait PasswordResetRepository: Send + Sync {
+    async fn create(&self, reset: PasswordReset) -> Result<PasswordReset, DatabaseError>;
+    async fn find_by_hash(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<PasswordReset>, DatabaseError>;
+    /// Uses the token and replaces the password of the user in the same transaction, the
+    /// events are stored in the outbox along. Returns none when the token was used or
+    /// expired meanwhile.
+    async fn reset(
+        &self,
+        reset_id: Uuid,
+        password: String,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<Option<User>, DatabaseError>;
+}
+
+pub struct PasswordResetDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PasswordResetDieselRepository {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        PasswordResetDieselRepository { pool: db }
+    }
+}
+
+#[async_trait]
+impl PasswordResetRepository for PasswordResetDieselRepository {
+    #[instrument(skip_all, name = "password_reset_repository.create")]
+    async fn create(&self, reset: PasswordReset) -> Result<PasswordReset, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let reset = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::insert_into(password_resets::table)
+                .values(reset)
+                .get_result(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(reset)
+    }
+
+    #[instrument(skip_all, name = "password_reset_repository.find_by_hash")]
+    async fn find_by_hash(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<PasswordReset>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let reset = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            password_resets::table
+                .filter(password_resets::token_hash.eq(token_hash))
+                .first(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(reset)
+    }
+
+    #[instrument(skip_all, name = "password_reset_repository.reset")]
+    async fn reset(
+        &self,
+        reset_id: Uuid,
+        password: String,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<Option<User>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let user = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let now = Utc::now();
+                let reset: Option<PasswordReset> = diesel::update(
+                    password_resets::table
+                        .find(reset_id)
+                        .filter(password_resets::used_at.is_null())
+                        .filter(password_resets::expires_at.gt(now)),
+                )
+                .set(password_resets::used_at.eq(now))
+                .get_result(conn)
+                .optional()?;
+                let reset = match reset {
+                    Some(reset) => reset,
+                    None => return Ok(None),
+                };
+
+                set_password(conn, reset.user_id, password, &events).map(Some)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(user)
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use crate::error::DieselRepositoryError;
+use crate::models::personal_token::PersonalAccessToken;
+use crate::schema::personal_access_tokens;
+use actix_threadpool::run;
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::prelude::*;
+use mockall::automock;
+use tracing::instrument;
+use utils::db::PgPool;
+use utils::error::DatabaseError;
+use uuid::Uuid;
+
+#[automock]
+#[async_trait]
+pub trait PersonalTokenRepository: Send + Sync {
+    async fn create(
+        &self,
+        token: PersonalAccessToken,
+    ) -> Result<PersonalAccessToken, DatabaseError>;
+    /// Tokens of the user which aren't revoked, expired ones included.
+    async fn list(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>, DatabaseError>;
+    /// Returns false when the user has no such token or it was already revoked.
+    async fn revoke(&self, user_id: Uuid, id: Uuid) -> Result<bool, DatabaseError>;
+}
+
+pub struct PersonalTokenDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PersonalTokenDieselRepository {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        PersonalTokenDieselRepository { pool: db }
+    }
+}
+
+#[async_trait]
+impl PersonalTokenRepository for PersonalTokenDieselRepository {
+    #[instrument(skip_all, name = "personal_token_repository.create")]
+    async fn create(
+        &self,
+        token: PersonalAccessToken,
+    ) -> Result<PersonalAccessToken, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let token = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::insert_into(personal_access_tokens::table)
+                .values(token)
+                .get_result(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(token)
+    }
+
+    #[instrument(skip_all, name = "personal_token_repository.list")]
+    async fn list(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let tokens = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            personal_access_tokens::table
+                .filter(personal_access_tokens::user_id.eq(user_id))
+                .filter(personal_access_tokens::revoked_at.is_null())
+                .order(personal_access_tokens::created_at.desc())
+                .load(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(tokens)
+    }
+
+    #[instrument(skip_all, name = "personal_token_repository.revoke")]
+    async fn revoke(&self, user_id: Uuid, id: Uuid) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let revoked = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::update(
+                personal_access_tokens::table
+                    .find(id)
+                    .filter(personal_access_tokens::user_id.eq(user_id))
+                    .filter(personal_access_tokens::revoked_at.is_null()),
+            )
+            .set(personal_access_tokens::revoked_at.eq(Utc::now()))
+            .execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(revoked > 0)
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use crate::error::DieselRepositoryError;
+use crate::models::preferences::UserPreferences;
+use crate::schema::user_preferences;
+use actix_threadpool::run;
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use mockall::automock;
+use tracing::instrument;
+use utils::db::PgPool;
+use utils::error::DatabaseError;
+use utils::outbox::{models::NewOutboxMessage, repository::enqueue};
+use uuid::Uuid;
+
+#[automock]
+#[async_trait]
+pub trait PreferencesRepository: Send + Sync {
+    /// None when the user never changed their preferences.
+    async fn find(&self, user_id: Uuid) -> Result<Option<UserPreferences>, DatabaseError>;
+    /// Stores the preferences and the events in the outbox in the same transaction.
+    async fn save(
+        &self,
+        preferences: UserPreferences,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<UserPreferences, DatabaseError>;
+}
+
+pub struct PreferencesDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PreferencesDieselRepository {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        PreferencesDieselRepository { pool: db }
+    }
+}
+
+#[async_trait]
+impl PreferencesRepository for PreferencesDieselRepository {
+    #[instrument(skip_all, name = "preferences_repository.find")]
+    async fn find(&self, user_id: Uuid) -> Result<Option<UserPreferences>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let preferences = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            user_preferences::table
+                .find(user_id)
+                .first(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(preferences)
+    }
+
+    #[instrument(skip_all, name = "preferences_repository.save")]
+    async fn save(
+        &self,
+        preferences: UserPreferences,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<UserPreferences, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let preferences = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let preferences = diesel::insert_into(user_preferences::table)
+                    .values(preferences)
+                    .on_conflict(user_preferences::user_id)
+                    .do_update()
+                    .set((
+                        user_preferences::display_name.eq(excluded(user_preferences::display_name)),
+                        user_preferences::timezone.eq(excluded(user_preferences::timezone)),
+                        user_preferences::locale.eq(excluded(user_preferences::locale)),
+                        user_preferences::notifications
+                            .eq(excluded(user_preferences::notifications)),
+                        user_preferences::digest_hour.eq(excluded(user_preferences::digest_hour)),
+                        user_preferences::updated_at.eq(excluded(user_preferences::updated_at)),
+                    ))
+                    .get_result(conn)?;
+                enqueue(conn, &events)?;
+                Ok::<UserPreferences, diesel::result::Error>(preferences)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(preferences)
+    }
+}
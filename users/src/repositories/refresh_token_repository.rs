@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use crate::error::DieselRepositoryError;
+use crate::models::refresh_token::RefreshToken;
+use crate::schema::refresh_tokens;
+use actix_threadpool::run;
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::prelude::*;
+use mockall::automock;
+use tracing::instrument;
+use utils::db::PgPool;
+use utils::error::DatabaseError;
+use uuid::Uuid;
+
+#[automock]
+#[async_trait]
+pub trait RefreshTokenRepository: Send + Sync {
+    async fn create(&self, token: RefreshToken) -> Result<RefreshToken, DatabaseError>;
+    async fn find_by_hash(&self, token_hash: String)
+        -> Result<Option<RefreshToken>, DatabaseError>;
+    /// Marks the token as used and stores the one replacing it in the same transaction.
+    /// Returns false, storing nothing, when the token was used or revoked meanwhile.
+    async fn rotate(&self, used_id: Uuid, token: RefreshToken) -> Result<bool, DatabaseError>;
+    /// Revokes every token of the family, returns how many were still active.
+    async fn revoke_family(&self, family_id: Uuid) -> Result<usize, DatabaseError>;
+    /// Revokes every token of the user, returns how many were still active.
+    async fn revoke_user(&self, user_id: Uuid) -> Result<usize, DatabaseError>;
+}
+
+pub struct RefreshTokenDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl RefreshTokenDieselRepository {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        RefreshTokenDieselRepository { pool: db }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for RefreshTokenDieselRepository {
+    #[instrument(skip_all, name = "refresh_token_repository.create")]
+    async fn create(&self, token: RefreshToken) -> Result<RefreshToken, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let token = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::insert_into(refresh_tokens::table)
+                .values(token)
+                .get_result(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(token)
+    }
+
+    #[instrument(skip_all, name = "refresh_token_repository.find_by_hash")]
+    async fn find_by_hash(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<RefreshToken>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let token = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(token_hash))
+                .first(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(token)
+    }
+
+    #[instrument(skip_all, name = "refresh_token_repository.rotate")]
+    async fn rotate(&self, used_id: Uuid, token: RefreshToken) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let rotated = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let used = diesel::update(
+                    refresh_tokens::table
+                        .find(used_id)
+                        .filter(refresh_tokens::used_at.is_null())
+                        .filter(refresh_tokens::revoked_at.is_null()),
+                )
+                .set(refresh_tokens::used_at.eq(Utc::now()))
+                .execute(conn)?;
+                // another request exchanged the token first
+                if used == 0 {
+                    return Ok(false);
+                }
+
+                diesel::insert_into(refresh_tokens::table)
+                    .values(token)
+                    .execute(conn)?;
+                Ok::<bool, diesel::result::Error>(true)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(rotated)
+    }
+
+    #[instrument(skip_all, name = "refresh_token_repository.revoke_family")]
+    async fn revoke_family(&self, family_id: Uuid) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let revoked = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::update(
+                refresh_tokens::table
+                    .filter(refresh_tokens::family_id.eq(family_id))
+                    .filter(refresh_tokens::revoked_at.is_null()),
+            )
+            .set(refresh_tokens::revoked_at.eq(Utc::now()))
+            .execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(revoked)
+    }
+
+    #[instrument(skip_all, name = "refresh_token_repository.revoke_user")]
+    async fn revoke_user(&self, user_id: Uuid) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let revoked = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::update(
+                refresh_tokens::table
+                    .filter(refresh_tokens::user_id.eq(user_id))
+                    .filter(refresh_tokens::revoked_at.is_null()),
+            )
+            .set(refresh_tokens::revoked_at.eq(Utc::now()))
+            .execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(revoked)
+    }
+}
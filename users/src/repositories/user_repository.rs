@@ -2,24 +2,65 @@ use std::sync::Arc;
 
 use crate::error::DieselRepositoryError;
 use crate::models::user::User;
-use crate::schema::users;
+use crate::schema::{password_resets, users};
 use actix_threadpool::run;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use mockall::automock;
+use tracing::instrument;
 use utils::db::PgPool;
 use utils::error::DatabaseError;
+use utils::http::middlewares::jwt_auth::Role;
+use utils::outbox::{models::NewOutboxMessage, repository::enqueue};
 use uuid::Uuid;
 
 #[automock]
 #[async_trait]
 pub trait UserRepository: Send + Sync {
-    async fn create(&self, name: String, password: String) -> Result<User, DatabaseError>;
+    /// Inserts the user and stores the events in the outbox in the same transaction.
+    async fn create(
+        &self,
+        user: User,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError>;
     async fn list(&self) -> Result<Vec<User>, DatabaseError>;
     async fn get_by_id(&self, user_id: Uuid) -> Result<User, DatabaseError>;
     async fn get_by_name(&self, name: String) -> Result<User, DatabaseError>;
-    async fn update(&self, user_id: Uuid, name: String) -> Result<User, DatabaseError>;
-    async fn delete(&self, user_id: Uuid) -> Result<usize, DatabaseError>;
+    async fn find_by_name(&self, name: String) -> Result<Option<User>, DatabaseError>;
+    async fn find_by_email(&self, email: String) -> Result<Option<User>, DatabaseError>;
+    /// Renames the user and stores the events in the outbox in the same transaction.
+    async fn update(
+        &self,
+        user_id: Uuid,
+        name: String,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError>;
+    /// Changes the email of the user, which has to be verified again. Nothing changes and
+    /// the events are dropped when the email is the same.
+    async fn update_email(
+        &self,
+        user_id: Uuid,
+        email: String,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError>;
+    /// Replaces the password hash and stores the events in the outbox in the same
+    /// transaction. Pending password resets of the user can't be used anymore.
+    async fn update_password(
+        &self,
+        user_id: Uuid,
+        password: String,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError>;
+    /// Deletes the user, the events are stored in the outbox only if it existed.
+    async fn delete(
+        &self,
+        user_id: Uuid,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<usize, DatabaseError>;
+    /// Makes admins of the users who verified one of the emails, returns how many
+    /// weren't admins already.
+    async fn promote_admins(&self, emails: Vec<String>) -> Result<usize, DatabaseError>;
 }
 
 pub struct UserDieselRepository {
@@ -34,21 +75,24 @@ impl UserDieselRepository {
 
 #[async_trait]
 impl UserRepository for UserDieselRepository {
-    async fn create(&self, name: String, password: String) -> Result<User, DatabaseError> {
-        let new_user = User {
-            id: Uuid::new_v4(),
-            name,
-            password,
-        };
-
+    #[instrument(skip_all, name = "user_repository.create")]
+    async fn create(
+        &self,
+        user: User,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError> {
         let pool = self.pool.clone();
 
         let inserted_user = run(move || {
             let mut conn = pool.get().unwrap();
 
-            diesel::insert_into(users::table)
-                .values(new_user)
-                .get_result(&mut conn)
+            conn.transaction(|conn| {
+                let user = diesel::insert_into(users::table)
+                    .values(user)
+                    .get_result(conn)?;
+                enqueue(conn, &events)?;
+                Ok::<User, diesel::result::Error>(user)
+            })
         })
         .await
         .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
@@ -56,6 +100,7 @@ impl UserRepository for UserDieselRepository {
         Ok(inserted_user)
     }
 
+    #[instrument(skip_all, name = "user_repository.list")]
     async fn list(&self) -> Result<Vec<User>, DatabaseError> {
         let pool = self.pool.clone();
 
@@ -70,6 +115,7 @@ impl UserRepository for UserDieselRepository {
         Ok(users)
     }
 
+    #[instrument(skip_all, name = "user_repository.get_by_id")]
     async fn get_by_id(&self, user_id: Uuid) -> Result<User, DatabaseError> {
         let pool = self.pool.clone();
 
@@ -84,6 +130,7 @@ impl UserRepository for UserDieselRepository {
         Ok(user)
     }
 
+    #[instrument(skip_all, name = "user_repository.get_by_name")]
     async fn get_by_name(&self, name: String) -> Result<User, DatabaseError> {
         let pool = self.pool.clone();
 
@@ -98,15 +145,61 @@ impl UserRepository for UserDieselRepository {
         Ok(user)
     }
 
-    async fn update(&self, user_id: Uuid, name: String) -> Result<User, DatabaseError> {
+    #[instrument(skip_all, name = "user_repository.find_by_name")]
+    async fn find_by_name(&self, name: String) -> Result<Option<User>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let user = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            users::table
+                .filter(users::name.eq(name))
+                .first::<User>(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(user)
+    }
+
+    #[instrument(skip_all, name = "user_repository.find_by_email")]
+    async fn find_by_email(&self, email: String) -> Result<Option<User>, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let user = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            users::table
+                .filter(users::email.eq(email))
+                .first::<User>(&mut conn)
+                .optional()
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(user)
+    }
+
+    #[instrument(skip_all, name = "user_repository.update")]
+    async fn update(
+        &self,
+        user_id: Uuid,
+        name: String,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError> {
         let pool = self.pool.clone();
 
         let user = run(move || {
             let mut conn = pool.get().unwrap();
 
-            diesel::update(users::table.find(user_id))
-                .set(users::name.eq(name))
-                .get_result(&mut conn)
+            conn.transaction(|conn| {
+                let user = diesel::update(users::table.find(user_id))
+                    .set(users::name.eq(name))
+                    .get_result(conn)?;
+                enqueue(conn, &events)?;
+                Ok::<User, diesel::result::Error>(user)
+            })
         })
         .await
         .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
@@ -114,17 +207,127 @@ impl UserRepository for UserDieselRepository {
         Ok(user)
     }
 
-    async fn delete(&self, user_id: Uuid) -> Result<usize, DatabaseError> {
+    #[instrument(skip_all, name = "user_repository.update_email")]
+    async fn update_email(
+        &self,
+        user_id: Uuid,
+        email: String,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError> {
         let pool = self.pool.clone();
 
-        let user_id = run(move || {
+        let user = run(move || {
             let mut conn = pool.get().unwrap();
 
-            diesel::delete(users::table.find(user_id)).execute(&mut conn)
+            conn.transaction(|conn| {
+                let user: User = users::table.find(user_id).for_update().first(conn)?;
+                if user.email.as_deref() == Some(email.as_str()) {
+                    return Ok(user);
+                }
+
+                let user = diesel::update(users::table.find(user_id))
+                    .set((
+                        users::email.eq(Some(email)),
+                        users::email_verified_at.eq(None::<DateTime<Utc>>),
+                    ))
+                    .get_result(conn)?;
+                enqueue(conn, &events)?;
+                Ok::<User, diesel::result::Error>(user)
+            })
         })
         .await
         .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
 
-        Ok(user_id)
+        Ok(user)
     }
+
+    #[instrument(skip_all, name = "user_repository.update_password")]
+    async fn update_password(
+        &self,
+        user_id: Uuid,
+        password: String,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<User, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let user = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| set_password(conn, user_id, password, &events))
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(user)
+    }
+
+    #[instrument(skip_all, name = "user_repository.delete")]
+    async fn delete(
+        &self,
+        user_id: Uuid,
+        events: Vec<NewOutboxMessage>,
+    ) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let deleted = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            conn.transaction(|conn| {
+                let deleted = diesel::delete(users::table.find(user_id)).execute(conn)?;
+                if deleted > 0 {
+                    enqueue(conn, &events)?;
+                }
+                Ok::<usize, diesel::result::Error>(deleted)
+            })
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(deleted)
+    }
+
+    #[instrument(skip_all, name = "user_repository.promote_admins")]
+    async fn promote_admins(&self, emails: Vec<String>) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+
+        let promoted = run(move || {
+            let mut conn = pool.get().unwrap();
+
+            diesel::update(
+                users::table
+                    .filter(users::email.eq_any(emails))
+                    .filter(users::email_verified_at.is_not_null())
+                    .filter(users::role.ne(Role::Admin)),
+            )
+            .set(users::role.eq(Role::Admin))
+            .execute(&mut conn)
+        })
+        .await
+        .map_err(|v| DieselRepositoryError::from(v).into_inner())?;
+
+        Ok(promoted)
+    }
+}
+
+/// Replaces the password hash, uses up the pending password resets of the user and
+/// enqueues the events, meant to run in a transaction.
+pub(crate) fn set_password(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    password: String,
+    events: &[NewOutboxMessage],
+) -> QueryResult<User> {
+    let user = diesel::update(users::table.find(user_id))
+        .set(users::password.eq(password))
+        .get_result(conn)?;
+    diesel::update(
+        password_resets::table
+            .filter(password_resets::user_id.eq(user_id))
+            .filter(password_resets::used_at.is_null()),
+    )
+    .set(password_resets::used_at.eq(Utc::now()))
+    .execute(conn)?;
+    enqueue(conn, events)?;
+
+    Ok(user)
 }
@@ -5,5 +5,152 @@ table! {
       id -> Uuid,
       name -> Text,
       password -> Text,
+      role -> Text,
+      email -> Nullable<Text>,
+      email_verified_at -> Nullable<Timestamptz>,
   }
 }
+
+table! {
+  refresh_tokens (id) {
+      id -> Uuid,
+      user_id -> Uuid,
+      family_id -> Uuid,
+      token_hash -> Text,
+      expires_at -> Timestamptz,
+      created_at -> Timestamptz,
+      used_at -> Nullable<Timestamptz>,
+      revoked_at -> Nullable<Timestamptz>,
+  }
+}
+
+table! {
+  email_verifications (id) {
+      id -> Uuid,
+      user_id -> Uuid,
+      email -> Text,
+      token_hash -> Text,
+      expires_at -> Timestamptz,
+      created_at -> Timestamptz,
+      used_at -> Nullable<Timestamptz>,
+  }
+}
+
+table! {
+  password_resets (id) {
+      id -> Uuid,
+      user_id -> Uuid,
+      token_hash -> Text,
+      expires_at -> Timestamptz,
+      created_at -> Timestamptz,
+      used_at -> Nullable<Timestamptz>,
+  }
+}
+
+table! {
+  login_throttles (key) {
+      key -> Text,
+      failures -> Int4,
+      last_failure_at -> Timestamptz,
+      locked_until -> Nullable<Timestamptz>,
+  }
+}
+
+table! {
+  lockout_events (id) {
+      id -> Uuid,
+      key -> Text,
+      action -> Text,
+      ip -> Nullable<Text>,
+      actor_id -> Nullable<Uuid>,
+      locked_until -> Nullable<Timestamptz>,
+      created_at -> Timestamptz,
+  }
+}
+
+table! {
+  user_mfa (user_id) {
+      user_id -> Uuid,
+      secret -> Text,
+      enabled_at -> Nullable<Timestamptz>,
+      last_used_step -> Nullable<Int8>,
+      created_at -> Timestamptz,
+  }
+}
+
+table! {
+  mfa_recovery_codes (id) {
+      id -> Uuid,
+      user_id -> Uuid,
+      code_hash -> Text,
+      used_at -> Nullable<Timestamptz>,
+  }
+}
+
+table! {
+  user_identities (id) {
+      id -> Uuid,
+      user_id -> Uuid,
+      issuer -> Text,
+      subject -> Text,
+      created_at -> Timestamptz,
+  }
+}
+
+table! {
+  oidc_logins (state_hash) {
+      state_hash -> Text,
+      code_verifier -> Text,
+      nonce -> Text,
+      expires_at -> Timestamptz,
+      created_at -> Timestamptz,
+  }
+}
+
+table! {
+  personal_access_tokens (id) {
+      id -> Uuid,
+      user_id -> Uuid,
+      name -> Text,
+      token_hash -> Text,
+      scopes -> Array<Text>,
+      expires_at -> Nullable<Timestamptz>,
+      created_at -> Timestamptz,
+      revoked_at -> Nullable<Timestamptz>,
+  }
+}
+
+table! {
+  user_preferences (user_id) {
+      user_id -> Uuid,
+      display_name -> Nullable<Text>,
+      timezone -> Text,
+      locale -> Text,
+      notifications -> Text,
+      digest_hour -> Int2,
+      updated_at -> Timestamptz,
+  }
+}
+
+diesel::joinable!(refresh_tokens -> users (user_id));
+diesel::joinable!(email_verifications -> users (user_id));
+diesel::joinable!(password_resets -> users (user_id));
+diesel::joinable!(user_mfa -> users (user_id));
+diesel::joinable!(mfa_recovery_codes -> users (user_id));
+diesel::joinable!(user_identities -> users (user_id));
+diesel::joinable!(personal_access_tokens -> users (user_id));
+diesel::joinable!(user_preferences -> users (user_id));
+diesel::allow_tables_to_appear_in_same_query!(
+    refresh_tokens,
+    email_verifications,
+    password_resets,
+    login_throttles,
+    lockout_events,
+    user_mfa,
+    mfa_recovery_codes,
+    user_identities,
+    oidc_logins,
+    personal_access_tokens,
+    user_preferences,
+    users
+);
@@ -0,0 +1,221 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use mockall::automock;
+use std::sync::Arc;
+use utils::error::{CommonError, AUTH_TOKEN_ENCODING_CODE};
+use utils::http::services::auth_service::hash_token;
+use utils::mail::{Email, Mailer};
+use uuid::Uuid;
+
+use crate::models::email_verification::EmailVerification;
+use crate::models::user::User;
+use crate::repositories::email_verification_repository::EmailVerificationRepository;
+use crate::services::random_token;
+
+#[automock]
+#[async_trait]
+pub trait EmailVerificationService: Send + Sync {
+    /// Sends a link to verify the email of the user.
+    async fn send(&self, user: User) -> Result<(), CommonError>;
+    /// Marks the email the token was sent to as verified.
+    async fn verify(&self, token: String) -> Result<(), CommonError>;
+}
+
+pub struct EmailVerificationServiceImpl {
+    repo: Arc<dyn EmailVerificationRepository>,
+    mailer: Arc<dyn Mailer>,
+    verification_url: String,
+    expires_in: i64,
+}
+
+impl EmailVerificationServiceImpl {
+    /// The token is appended to the verification url, it expires after `expires_in`
+    /// minutes.
+    pub fn new(
+        repo: Arc<dyn EmailVerificationRepository>,
+        mailer: Arc<dyn Mailer>,
+        verification_url: &str,
+        expires_in: i64,
+    ) -> Self {
+        EmailVerificationServiceImpl {
+            repo,
+            mailer,
+            verification_url: verification_url.to_string(),
+            expires_in,
+        }
+    }
+}
+
+#[async_trait]
+impl EmailVerificationService for EmailVerificationServiceImpl {
+    async fn send(&self, user: User) -> Result<(), CommonError> {
+        let email = user
+            .email
+            .ok_or_else(|| invalid_verification("user has no email"))?;
+        let token = random_token();
+
+        let now = Utc::now();
+        self.repo
+            .create(EmailVerification {
+                id: Uuid::new_v4(),
+                user_id: user.id,
+                email: email.clone(),
+                token_hash: hash_token(&token),
+                expires_at: now + Duration::minutes(self.expires_in),
+                created_at: now,
+                used_at: None,
+            })
+            .await?;
+
+        let body = format!(
+            "Hi {},\n\nConfirm your email address by opening {}?token={}\n\nThe link expires in {} minutes.",
+            user.name, self.verification_url, token, self.expires_in
+        );
+        self.mailer
+            .send(Email::new(&email, "Verify your email address", &body))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn verify(&self, token: String) -> Result<(), CommonError> {
+        let verification = self
+            .repo
+            .find_by_hash(hash_token(&token))
+            .await?
+            .ok_or_else(|| invalid_verification("unknown verification token"))?;
+
+        if !self.repo.verify(verification.id).await? {
+            return Err(invalid_verification("verification token expired or used"));
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_verification(message: &str) -> CommonError {
+    CommonError {
+        message: message.to_string(),
+        code: AUTH_TOKEN_ENCODING_CODE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::email_verification_repository::MockEmailVerificationRepository;
+    use rstest::*;
+    use utils::http::middlewares::jwt_auth::Role;
+    use utils::mail::memory::MemoryMailer;
+
+    fn user(email: Option<&str>) -> User {
+        User {
+            id: Uuid::new_v4(),
+            name: "Alice".to_string(),
+            password: "1234".to_string(),
+            role: Role::User,
+            email: email.map(str::to_string),
+            email_verified_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_mails_the_stored_token() {
+        let mut repo_mock = MockEmailVerificationRepository::new();
+        repo_mock
+            .expect_create()
+            .withf(|verification| verification.email == "alice@example.com")
+            .times(1)
+            .returning(Ok);
+        let mailer = Arc::new(MemoryMailer::new());
+        let service = EmailVerificationServiceImpl::new(
+            Arc::new(repo_mock),
+            mailer.clone(),
+            "http://localhost/verify-email",
+            10,
+        );
+
+        let result = service.send(user(Some("alice@example.com"))).await;
+
+        assert!(result.is_ok());
+        let email = mailer.last_sent_to("alice@example.com").unwrap();
+        assert!(email.body.contains("http://localhost/verify-email?token="));
+    }
+
+    #[tokio::test]
+    async fn test_send_requires_email() {
+        let service = EmailVerificationServiceImpl::new(
+            Arc::new(MockEmailVerificationRepository::new()),
+            Arc::new(MemoryMailer::new()),
+            "http://localhost/verify-email",
+            10,
+        );
+
+        let result = service.send(user(None)).await;
+
+        assert_eq!(result.unwrap_err().code, AUTH_TOKEN_ENCODING_CODE);
+    }
+
+    struct VerifyTestCase {
+        found: bool,
+        verified: bool,
+        expect_verify: bool,
+        expected_result: Result<(), CommonError>,
+    }
+
+    #[rstest]
+    #[case::verified(VerifyTestCase {
+        found: true,
+        verified: true,
+        expect_verify: true,
+        expected_result: Ok(()),
+    })]
+    #[case::unknown(VerifyTestCase {
+        found: false,
+        verified: false,
+        expect_verify: false,
+        expected_result: Err(invalid_verification("unknown verification token")),
+    })]
+    #[case::expired_or_used(VerifyTestCase {
+        found: true,
+        verified: false,
+        expect_verify: true,
+        expected_result: Err(invalid_verification("verification token expired or used")),
+    })]
+    #[tokio::test]
+    async fn test_verify(#[case] case: VerifyTestCase) {
+        let verification = EmailVerification {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            email: "alice@example.com".to_string(),
+            token_hash: hash_token("token"),
+            expires_at: Utc::now() + Duration::minutes(10),
+            created_at: Utc::now(),
+            used_at: None,
+        };
+        let verification_id = verification.id;
+
+        let mut repo_mock = MockEmailVerificationRepository::new();
+        let found = case.found;
+        repo_mock
+            .expect_find_by_hash()
+            .with(mockall::predicate::eq(hash_token("token")))
+            .returning(move |_| Ok(found.then(|| verification.clone())));
+        let verified = case.verified;
+        repo_mock
+            .expect_verify()
+            .with(mockall::predicate::eq(verification_id))
+            .times(case.expect_verify as usize)
+            .returning(move |_| Ok(verified));
+        let service = EmailVerificationServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(MemoryMailer::new()),
+            "http://localhost/verify-email",
+            10,
+        );
+
+        let result = service.verify("token".to_string()).await;
+
+        assert_eq!(result, case.expected_result);
+    }
+}
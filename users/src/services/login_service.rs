@@ -0,0 +1,628 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+use mockall::automock;
+use std::sync::Arc;
+use utils::error::{CommonError, DatabaseError};
+use uuid::Uuid;
+
+use crate::models::login_throttle::{
+    account_key, ip_key, LockoutEvent, LoginThrottle, LOCKED_ACTION, UNLOCKED_ACTION,
+};
+use crate::models::user::{hash_password, verify_password, User};
+use crate::repositories::login_throttle_repository::LoginThrottleRepository;
+use crate::repositories::user_repository::UserRepository;
+use crate::services::mfa_service::MfaService;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginError {
+    /// Unknown name or wrong password, callers can't tell which.
+    InvalidCredentials,
+    /// Too many failed attempts, the login can be retried after the duration.
+    Throttled(std::time::Duration),
+    Internal(CommonError),
+}
+
+impl From<DatabaseError> for LoginError {
+    fn from(error: DatabaseError) -> Self {
+        LoginError::Internal(error.into())
+    }
+}
+
+/// User whose password was checked, the login is only complete once the second factor
+/// is verified too when it is required.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authenticated {
+    pub user: User,
+    pub mfa_required: bool,
+}
+
+/// How failed logins slow down the next attempts of the account and of the client.
+#[derive(Debug, Clone)]
+pub struct LoginPolicy {
+    /// Failures of an account before it is locked.
+    pub max_account_failures: i32,
+    /// Failures from a client before it is locked, higher since clients can be shared.
+    pub max_ip_failures: i32,
+    /// Wait after the first failure, doubled after every other failure.
+    pub delay: Duration,
+    pub max_delay: Duration,
+    /// How long the lockouts last, and failures are remembered.
+    pub lockout: Duration,
+}
+
+impl LoginPolicy {
+    fn delay(&self, failures: i32) -> Duration {
+        // far beyond any sensible max delay already
+        let doublings = failures.clamp(1, 21) - 1;
+
+        (self.delay * (1 << doublings)).min(self.max_delay)
+    }
+
+    /// When the key can be used again, none if it can be used now.
+    fn retry_at(&self, throttle: &LoginThrottle, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if let Some(locked_until) = throttle.locked_until.filter(|until| *until > now) {
+            return Some(locked_until);
+        }
+        if throttle.last_failure_at + self.lockout <= now {
+            return None;
+        }
+
+        Some(throttle.last_failure_at + self.delay(throttle.failures)).filter(|at| *at > now)
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait LoginService: Send + Sync {
+    /// Checks the credentials, unless the account or the client failed too many times.
+    async fn authenticate(
+        &self,
+        name: String,
+        password: String,
+        ip: Option<String>,
+    ) -> Result<Authenticated, LoginError>;
+    /// Checks the second factor of a user who authenticated with their password. Wrong
+    /// codes count as failed logins of the account.
+    async fn verify_mfa(
+        &self,
+        user_id: Uuid,
+        code: String,
+        ip: Option<String>,
+    ) -> Result<User, LoginError>;
+    /// Lifts the lockout of the account on behalf of an admin. Returns false when it
+    /// wasn't locked or throttled.
+    async fn unlock(&self, user_id: Uuid, actor_id: Uuid) -> Result<bool, CommonError>;
+}
+
+pub struct LoginServiceImpl {
+    repo: Arc<dyn LoginThrottleRepository>,
+    user_repo: Arc<dyn UserRepository>,
+    mfa_service: Arc<dyn MfaService>,
+    policy: LoginPolicy,
+    /// Verified against for unknown names, so they take as long as wrong passwords.
+    dummy_password: String,
+}
+
+impl LoginServiceImpl {
+    pub fn new(
+        repo: Arc<dyn LoginThrottleRepository>,
+        user_repo: Arc<dyn UserRepository>,
+        mfa_service: Arc<dyn MfaService>,
+        policy: LoginPolicy,
+    ) -> Self {
+        LoginServiceImpl {
+            repo,
+            user_repo,
+            mfa_service,
+            policy,
+            dummy_password: hash_password(&Uuid::new_v4().to_string()),
+        }
+    }
+
+    /// Keys of the attempt with the failures allowed for each.
+    fn keys(&self, name: &str, ip: Option<&str>) -> Vec<(String, i32)> {
+        let mut keys = vec![(account_key(name), self.policy.max_account_failures)];
+        if let Some(ip) = ip {
+            keys.push((ip_key(ip), self.policy.max_ip_failures));
+        }
+
+        keys
+    }
+
+    /// Fails while any of the keys is throttled.
+    async fn check_throttles(
+        &self,
+        keys: &[(String, i32)],
+        now: DateTime<Utc>,
+    ) -> Result<(), LoginError> {
+        let throttles = self
+            .repo
+            .find(keys.iter().map(|(key, _)| key.clone()).collect())
+            .await?;
+        if let Some(retry_at) = throttles
+            .iter()
+            .filter_map(|throttle| self.policy.retry_at(throttle, now))
+            .max()
+        {
+            return Err(LoginError::Throttled(
+                (retry_at - now).to_std().unwrap_or_default(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn record_failures(
+        &self,
+        keys: Vec<(String, i32)>,
+        ip: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Result<(), DatabaseError> {
+        for (key, max_failures) in keys {
+            self.record_failure(key, max_failures, ip.clone(), now)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn record_failure(
+        &self,
+        key: String,
+        max_failures: i32,
+        ip: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Result<(), DatabaseError> {
+        let throttle = self
+            .repo
+            .record_failure(key.clone(), now, now - self.policy.lockout)
+            .await?;
+        if throttle.failures < max_failures || throttle.locked_until.is_some() {
+            return Ok(());
+        }
+
+        let locked_until = now + self.policy.lockout;
+        warn!(
+            "locking {} until {} after {} failed logins",
+            key, locked_until, throttle.failures
+        );
+        self.repo
+            .lock(LockoutEvent {
+                id: Uuid::new_v4(),
+                key,
+                action: LOCKED_ACTION.to_string(),
+                ip,
+                actor_id: None,
+                locked_until: Some(locked_until),
+                created_at: now,
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl LoginService for LoginServiceImpl {
+    async fn authenticate(
+        &self,
+        name: String,
+        password: String,
+        ip: Option<String>,
+    ) -> Result<Authenticated, LoginError> {
+        let now = Utc::now();
+        let keys = self.keys(&name, ip.as_deref());
+        self.check_throttles(&keys, now).await?;
+
+        let user = self.user_repo.find_by_name(name).await?;
+        let user = match user {
+            Some(user) if user.verify_password(&password) => user,
+            user => {
+                if user.is_none() {
+                    verify_password(&self.dummy_password, &password);
+                }
+                self.record_failures(keys, ip, now).await?;
+                return Err(LoginError::InvalidCredentials);
+            }
+        };
+
+        let mfa_required = self
+            .mfa_service
+            .is_enabled(user.id)
+            .await
+            .map_err(LoginError::Internal)?;
+        // the failures are kept until the second factor is verified too, else guessing
+        // codes between correct passwords would never lock the account
+        if !mfa_required {
+            // the client keeps its failures, it may be guessing other accounts
+            self.repo.clear(keys[0].0.clone()).await?;
+        }
+
+        Ok(Authenticated { user, mfa_required })
+    }
+
+    async fn verify_mfa(
+        &self,
+        user_id: Uuid,
+        code: String,
+        ip: Option<String>,
+    ) -> Result<User, LoginError> {
+        let now = Utc::now();
+        let user = self.user_repo.get_by_id(user_id).await?;
+        let keys = self.keys(&user.name, ip.as_deref());
+        self.check_throttles(&keys, now).await?;
+
+        let verified = self
+            .mfa_service
+            .verify(user_id, code)
+            .await
+            .map_err(LoginError::Internal)?;
+        if !verified {
+            self.record_failures(keys, ip, now).await?;
+            return Err(LoginError::InvalidCredentials);
+        }
+
+        self.repo.clear(keys[0].0.clone()).await?;
+
+        Ok(user)
+    }
+
+    async fn unlock(&self, user_id: Uuid, actor_id: Uuid) -> Result<bool, CommonError> {
+        let user = self.user_repo.get_by_id(user_id).await?;
+
+        Ok(self
+            .repo
+            .unlock(LockoutEvent {
+                id: Uuid::new_v4(),
+                key: account_key(&user.name),
+                action: UNLOCKED_ACTION.to_string(),
+                ip: None,
+                actor_id: Some(actor_id),
+                locked_until: None,
+                created_at: Utc::now(),
+            })
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::login_throttle_repository::MockLoginThrottleRepository;
+    use crate::repositories::user_repository::MockUserRepository;
+    use crate::services::mfa_service::MockMfaService;
+    use mockall::predicate::eq;
+    use rstest::*;
+    use utils::http::middlewares::jwt_auth::Role;
+
+    fn policy() -> LoginPolicy {
+        LoginPolicy {
+            max_account_failures: 3,
+            max_ip_failures: 10,
+            delay: Duration::seconds(1),
+            max_delay: Duration::seconds(5),
+            lockout: Duration::minutes(15),
+        }
+    }
+
+    fn user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            name: "Alice".to_string(),
+            password: hash_password("1234567"),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
+        }
+    }
+
+    fn mfa_service(enabled: bool) -> Arc<MockMfaService> {
+        let mut mfa_service_mock = MockMfaService::new();
+        mfa_service_mock
+            .expect_is_enabled()
+            .returning(move |_| Ok(enabled));
+
+        Arc::new(mfa_service_mock)
+    }
+
+    fn throttle(failures: i32, last_failure_at: DateTime<Utc>) -> LoginThrottle {
+        LoginThrottle {
+            key: account_key("Alice"),
+            failures,
+            last_failure_at,
+            locked_until: None,
+        }
+    }
+
+    #[test]
+    fn test_retry_at() {
+        let now = Utc::now();
+        let locked = LoginThrottle {
+            locked_until: Some(now + Duration::minutes(1)),
+            ..throttle(3, now)
+        };
+        let cases = vec![
+            (
+                "delay passed",
+                throttle(1, now - Duration::seconds(2)),
+                None,
+            ),
+            (
+                "first delay",
+                throttle(1, now),
+                Some(now + Duration::seconds(1)),
+            ),
+            (
+                "doubled delay",
+                throttle(3, now),
+                Some(now + Duration::seconds(4)),
+            ),
+            (
+                "capped delay",
+                throttle(8, now),
+                Some(now + Duration::seconds(5)),
+            ),
+            ("locked", locked.clone(), Some(now + Duration::minutes(1))),
+            (
+                "lock expired",
+                LoginThrottle {
+                    locked_until: Some(now - Duration::seconds(1)),
+                    ..throttle(3, now - Duration::minutes(16))
+                },
+                None,
+            ),
+        ];
+
+        for (name, throttle, expected) in cases {
+            assert_eq!(policy().retry_at(&throttle, now), expected, "{}", name);
+        }
+    }
+
+    struct AuthenticateTestCase {
+        password: &'static str,
+        known: bool,
+        failures: i32,
+        expect_lock: bool,
+        expected_error: Option<LoginError>,
+    }
+
+    #[rstest]
+    #[case::valid(AuthenticateTestCase {
+        password: "1234567",
+        known: true,
+        failures: 0,
+        expect_lock: false,
+        expected_error: None,
+    })]
+    #[case::wrong_password(AuthenticateTestCase {
+        password: "7654321",
+        known: true,
+        failures: 1,
+        expect_lock: false,
+        expected_error: Some(LoginError::InvalidCredentials),
+    })]
+    #[case::unknown_user(AuthenticateTestCase {
+        password: "1234567",
+        known: false,
+        failures: 1,
+        expect_lock: false,
+        expected_error: Some(LoginError::InvalidCredentials),
+    })]
+    #[case::locks_account(AuthenticateTestCase {
+        password: "7654321",
+        known: true,
+        failures: 3,
+        expect_lock: true,
+        expected_error: Some(LoginError::InvalidCredentials),
+    })]
+    #[tokio::test]
+    async fn test_authenticate(#[case] case: AuthenticateTestCase) {
+        let user = user();
+        let mut user_repo_mock = MockUserRepository::new();
+        let found = case.known.then(|| user.clone());
+        user_repo_mock
+            .expect_find_by_name()
+            .with(eq("Alice".to_string()))
+            .times(1)
+            .returning(move |_| Ok(found.clone()));
+        let mut repo_mock = MockLoginThrottleRepository::new();
+        repo_mock
+            .expect_find()
+            .with(eq(vec![account_key("Alice"), ip_key("10.0.0.1")]))
+            .times(1)
+            .returning(|_| Ok(vec![]));
+        let failed = case.expected_error.is_some();
+        repo_mock
+            .expect_clear()
+            .with(eq(account_key("Alice")))
+            .times(!failed as usize)
+            .returning(|_| Ok(()));
+        let failures = case.failures;
+        repo_mock
+            .expect_record_failure()
+            .times(if failed { 2 } else { 0 })
+            .returning(move |key, now, _| {
+                Ok(LoginThrottle {
+                    key,
+                    failures,
+                    last_failure_at: now,
+                    locked_until: None,
+                })
+            });
+        repo_mock
+            .expect_lock()
+            .withf(|event| {
+                event.key == account_key("Alice")
+                    && event.action == LOCKED_ACTION
+                    && event.ip == Some("10.0.0.1".to_string())
+            })
+            .times(case.expect_lock as usize)
+            .returning(|_| Ok(()));
+        let service = LoginServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(user_repo_mock),
+            mfa_service(false),
+            policy(),
+        );
+
+        let result = service
+            .authenticate(
+                "Alice".to_string(),
+                case.password.to_string(),
+                Some("10.0.0.1".to_string()),
+            )
+            .await;
+
+        assert_eq!(result.err(), case.expected_error);
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_throttled() {
+        let mut repo_mock = MockLoginThrottleRepository::new();
+        repo_mock.expect_find().times(1).returning(|_| {
+            Ok(vec![LoginThrottle {
+                locked_until: Some(Utc::now() + Duration::minutes(10)),
+                ..throttle(3, Utc::now())
+            }])
+        });
+        // the password isn't even checked
+        let service = LoginServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(MockUserRepository::new()),
+            Arc::new(MockMfaService::new()),
+            policy(),
+        );
+
+        let result = service
+            .authenticate("Alice".to_string(), "1234567".to_string(), None)
+            .await;
+
+        match result {
+            Err(LoginError::Throttled(retry_after)) => {
+                assert!(retry_after > std::time::Duration::from_secs(9 * 60))
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_requires_mfa() {
+        let user = user();
+        let found = user.clone();
+        let mut user_repo_mock = MockUserRepository::new();
+        user_repo_mock
+            .expect_find_by_name()
+            .returning(move |_| Ok(Some(found.clone())));
+        let mut repo_mock = MockLoginThrottleRepository::new();
+        repo_mock.expect_find().returning(|_| Ok(vec![]));
+        // the failures are only cleared once the code is verified
+        repo_mock.expect_clear().times(0);
+        let service = LoginServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(user_repo_mock),
+            mfa_service(true),
+            policy(),
+        );
+
+        let result = service
+            .authenticate("Alice".to_string(), "1234567".to_string(), None)
+            .await;
+
+        assert_eq!(
+            result,
+            Ok(Authenticated {
+                user,
+                mfa_required: true
+            })
+        );
+    }
+
+    #[rstest]
+    #[case::valid(true)]
+    #[case::invalid(false)]
+    #[tokio::test]
+    async fn test_verify_mfa(#[case] valid: bool) {
+        let user = user();
+        let user_id = user.id;
+        let found = user.clone();
+        let mut user_repo_mock = MockUserRepository::new();
+        user_repo_mock
+            .expect_get_by_id()
+            .with(eq(user_id))
+            .returning(move |_| Ok(found.clone()));
+        let mut mfa_service_mock = MockMfaService::new();
+        mfa_service_mock
+            .expect_verify()
+            .with(eq(user_id), eq("123456".to_string()))
+            .times(1)
+            .returning(move |_, _| Ok(valid));
+        let mut repo_mock = MockLoginThrottleRepository::new();
+        repo_mock.expect_find().times(1).returning(|_| Ok(vec![]));
+        repo_mock
+            .expect_clear()
+            .with(eq(account_key("Alice")))
+            .times(valid as usize)
+            .returning(|_| Ok(()));
+        repo_mock
+            .expect_record_failure()
+            .with(
+                eq(account_key("Alice")),
+                mockall::predicate::always(),
+                mockall::predicate::always(),
+            )
+            .times(!valid as usize)
+            .returning(|key, now, _| {
+                Ok(LoginThrottle {
+                    key,
+                    failures: 1,
+                    last_failure_at: now,
+                    locked_until: None,
+                })
+            });
+        let service = LoginServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(user_repo_mock),
+            Arc::new(mfa_service_mock),
+            policy(),
+        );
+
+        let result = service
+            .verify_mfa(user_id, "123456".to_string(), None)
+            .await;
+
+        if valid {
+            assert_eq!(result, Ok(user));
+        } else {
+            assert_eq!(result, Err(LoginError::InvalidCredentials));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unlock() {
+        let user = user();
+        let user_id = user.id;
+        let admin_id = Uuid::new_v4();
+        let mut user_repo_mock = MockUserRepository::new();
+        user_repo_mock
+            .expect_get_by_id()
+            .with(eq(user_id))
+            .returning(move |_| Ok(user.clone()));
+        let mut repo_mock = MockLoginThrottleRepository::new();
+        repo_mock
+            .expect_unlock()
+            .withf(move |event| {
+                event.key == account_key("alice")
+                    && event.action == UNLOCKED_ACTION
+                    && event.actor_id == Some(admin_id)
+            })
+            .times(1)
+            .returning(|_| Ok(true));
+        let service = LoginServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(user_repo_mock),
+            mfa_service(false),
+            policy(),
+        );
+
+        assert_eq!(service.unlock(user_id, admin_id).await, Ok(true));
+    }
+}
@@ -0,0 +1,277 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use mockall::automock;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Serialize;
+use std::sync::Arc;
+use utils::error::CommonError;
+use utils::http::services::auth_service::hash_token;
+use uuid::Uuid;
+
+use crate::models::mfa::{RecoveryCode, UserMfa};
+use crate::models::user::User;
+use crate::repositories::mfa_repository::MfaRepository;
+use crate::totp;
+
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// Secret to add to the authenticator app, by hand or by scanning the uri as a QR code.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MfaEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[automock]
+#[async_trait]
+pub trait MfaService: Send + Sync {
+    /// Starts the enrollment of the user with a new secret, replacing any pending one.
+    /// None when the user already enabled it.
+    async fn enroll(&self, user: User) -> Result<Option<MfaEnrollment>, CommonError>;
+    /// Enables the pending enrollment once the app shows a valid code. Returns the
+    /// recovery codes, they can't be retrieved later. None when the code is invalid or
+    /// there is nothing to confirm.
+    async fn confirm(
+        &self,
+        user_id: Uuid,
+        code: String,
+    ) -> Result<Option<Vec<String>>, CommonError>;
+    async fn is_enabled(&self, user_id: Uuid) -> Result<bool, CommonError>;
+    /// Checks a code of the app or an unused recovery code, either can only be used once.
+    async fn verify(&self, user_id: Uuid, code: String) -> Result<bool, CommonError>;
+}
+
+pub struct MfaServiceImpl {
+    repo: Arc<dyn MfaRepository>,
+    issuer: String,
+    skew: i64,
+}
+
+impl MfaServiceImpl {
+    /// Codes are accepted `skew` steps before and after the current one.
+    pub fn new(repo: Arc<dyn MfaRepository>, issuer: &str, skew: i64) -> Self {
+        MfaServiceImpl {
+            repo,
+            issuer: issuer.to_string(),
+            skew,
+        }
+    }
+}
+
+fn recovery_code() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(RECOVERY_CODE_LENGTH)
+        .map(char::from)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Codes of the app are digits only, recovery codes never are.
+fn is_totp_code(code: &str) -> bool {
+    code.len() == 6 && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[async_trait]
+impl MfaService for MfaServiceImpl {
+    async fn enroll(&self, user: User) -> Result<Option<MfaEnrollment>, CommonError> {
+        let secret = totp::generate_secret();
+        let saved = self
+            .repo
+            .save_pending(UserMfa {
+                user_id: user.id,
+                secret: secret.clone(),
+                enabled_at: None,
+                last_used_step: None,
+                created_at: Utc::now(),
+            })
+            .await?;
+
+        Ok(saved.map(|_| MfaEnrollment {
+            otpauth_uri: totp::otpauth_uri(&self.issuer, &user.name, &secret),
+            secret,
+        }))
+    }
+
+    async fn confirm(
+        &self,
+        user_id: Uuid,
+        code: String,
+    ) -> Result<Option<Vec<String>>, CommonError> {
+        let mfa = match self.repo.find(user_id).await? {
+            Some(mfa) if !mfa.enabled() => mfa,
+            _ => return Ok(None),
+        };
+        let step = match totp::verify(&mfa.secret, &code, Utc::now(), self.skew) {
+            Some(step) => step,
+            None => return Ok(None),
+        };
+
+        let codes: Vec<String> = (0..RECOVERY_CODES).map(|_| recovery_code()).collect();
+        let recovery_codes = codes
+            .iter()
+            .map(|code| RecoveryCode {
+                id: Uuid::new_v4(),
+                user_id,
+                code_hash: hash_token(code),
+                used_at: None,
+            })
+            .collect();
+        if !self.repo.enable(user_id, step, recovery_codes).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(codes))
+    }
+
+    async fn is_enabled(&self, user_id: Uuid) -> Result<bool, CommonError> {
+        let mfa = self.repo.find(user_id).await?;
+
+        Ok(mfa.is_some_and(|mfa| mfa.enabled()))
+    }
+
+    async fn verify(&self, user_id: Uuid, code: String) -> Result<bool, CommonError> {
+        let mfa = match self.repo.find(user_id).await? {
+            Some(mfa) if mfa.enabled() => mfa,
+            _ => return Ok(false),
+        };
+
+        let code = code.trim().to_lowercase();
+        if !is_totp_code(&code) {
+            return Ok(self
+                .repo
+                .use_recovery_code(user_id, hash_token(&code))
+                .await?);
+        }
+
+        match totp::verify(&mfa.secret, &code, Utc::now(), self.skew) {
+            Some(step) => Ok(self.repo.use_step(user_id, step).await?),
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::mfa_repository::MockMfaRepository;
+    use mockall::predicate::eq;
+    use rstest::*;
+
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn mfa(user_id: Uuid, enabled: bool) -> UserMfa {
+        UserMfa {
+            user_id,
+            secret: SECRET.to_string(),
+            enabled_at: enabled.then(Utc::now),
+            last_used_step: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn current_code() -> String {
+        totp::code(SECRET, totp::step(Utc::now())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_confirm_returns_recovery_codes() {
+        let user_id = Uuid::new_v4();
+        let mut repo_mock = MockMfaRepository::new();
+        repo_mock
+            .expect_find()
+            .with(eq(user_id))
+            .returning(move |_| Ok(Some(mfa(user_id, false))));
+        repo_mock
+            .expect_enable()
+            .withf(move |id, step, codes| {
+                // the step may have moved on since the code was computed
+                *id == user_id
+                    && totp::step(Utc::now()) - *step <= 1
+                    && codes.len() == RECOVERY_CODES
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(true));
+        let service = MfaServiceImpl::new(Arc::new(repo_mock), "rust-xp", 1);
+
+        let codes = service.confirm(user_id, current_code()).await.unwrap();
+
+        let codes = codes.unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert!(codes.iter().all(|code| !is_totp_code(code)));
+    }
+
+    struct VerifyTestCase {
+        enabled: bool,
+        code: fn() -> String,
+        use_step: Option<bool>,
+        use_recovery_code: Option<bool>,
+        expected: bool,
+    }
+
+    #[rstest]
+    #[case::valid_code(VerifyTestCase {
+        enabled: true,
+        code: current_code,
+        use_step: Some(true),
+        use_recovery_code: None,
+        expected: true,
+    })]
+    #[case::replayed_code(VerifyTestCase {
+        enabled: true,
+        code: current_code,
+        use_step: Some(false),
+        use_recovery_code: None,
+        expected: false,
+    })]
+    #[case::wrong_code(VerifyTestCase {
+        enabled: true,
+        code: || {
+            let step = totp::step(Utc::now());
+            totp::code(SECRET, step - 5).unwrap()
+        },
+        use_step: None,
+        use_recovery_code: None,
+        expected: false,
+    })]
+    #[case::recovery_code(VerifyTestCase {
+        enabled: true,
+        code: || "Abcde12345".to_string(),
+        use_step: None,
+        use_recovery_code: Some(true),
+        expected: true,
+    })]
+    #[case::not_enabled(VerifyTestCase {
+        enabled: false,
+        code: current_code,
+        use_step: None,
+        use_recovery_code: None,
+        expected: false,
+    })]
+    #[tokio::test]
+    async fn test_verify(#[case] case: VerifyTestCase) {
+        let user_id = Uuid::new_v4();
+        let enabled = case.enabled;
+        let mut repo_mock = MockMfaRepository::new();
+        repo_mock
+            .expect_find()
+            .returning(move |_| Ok(Some(mfa(user_id, enabled))));
+        let use_step = case.use_step;
+        repo_mock
+            .expect_use_step()
+            .times(use_step.is_some() as usize)
+            .returning(move |_, _| Ok(use_step.unwrap()));
+        let use_recovery_code = case.use_recovery_code;
+        repo_mock
+            .expect_use_recovery_code()
+            .with(eq(user_id), eq(hash_token("abcde12345")))
+            .times(use_recovery_code.is_some() as usize)
+            .returning(move |_, _| Ok(use_recovery_code.unwrap()));
+        let service = MfaServiceImpl::new(Arc::new(repo_mock), "rust-xp", 1);
+
+        let result = service.verify(user_id, (case.code)()).await;
+
+        assert_eq!(result, Ok(case.expected));
+    }
+}
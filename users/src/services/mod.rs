@@ -1,2 +1,24 @@
-pub mod event_service;
+use rand::{distributions::Alphanumeric, Rng};
+
+pub mod email_verification_service;
+pub mod login_service;
+pub mod mfa_service;
+pub mod oidc_service;
+pub mod password_reset_service;
+pub mod personal_token_service;
+pub mod preferences_service;
+pub mod token_service;
 pub mod user_service;
+
+/// Length of the random tokens, about 256 bits of randomness.
+const TOKEN_LENGTH: usize = 43;
+
+/// Token for the links sent by email and the other secrets used once, unreserved
+/// characters only so it can be sent as is in urls.
+pub(crate) fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
@@ -0,0 +1,388 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use log::info;
+use mockall::automock;
+use rand::{distributions::Alphanumeric, Rng};
+use std::sync::Arc;
+use utils::error::{CommonError, AUTH_TOKEN_ENCODING_CODE};
+use utils::http::middlewares::jwt_auth::Role;
+use utils::http::services::auth_service::hash_token;
+use uuid::Uuid;
+
+use crate::events;
+use crate::models::oidc::{OidcLogin, UserIdentity};
+use crate::models::user::{hash_password, User};
+use crate::oidc::{code_challenge, IdTokenClaims, OidcClient};
+use crate::repositories::oidc_repository::OidcRepository;
+use crate::repositories::user_repository::UserRepository;
+use crate::services::random_token;
+
+/// Login started with the identity provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcRedirect {
+    /// Sent back by the provider with the code, the browser keeps it too so logins
+    /// can't be completed in another browser.
+    pub state: String,
+    pub authorization_url: String,
+}
+
+#[automock]
+#[async_trait]
+pub trait OidcService: Send + Sync {
+    async fn start(&self) -> Result<OidcRedirect, CommonError>;
+    /// Completes the login of the state with the code of the provider. The user is the
+    /// one linked to the provider's account, else the one with the same verified email,
+    /// else a new one.
+    async fn complete(&self, state: String, code: String) -> Result<User, CommonError>;
+}
+
+pub struct OidcServiceImpl {
+    client: Arc<dyn OidcClient>,
+    repo: Arc<dyn OidcRepository>,
+    user_repo: Arc<dyn UserRepository>,
+    expires_in: i64,
+}
+
+impl OidcServiceImpl {
+    /// Logins have to complete within `expires_in` minutes.
+    pub fn new(
+        client: Arc<dyn OidcClient>,
+        repo: Arc<dyn OidcRepository>,
+        user_repo: Arc<dyn UserRepository>,
+        expires_in: i64,
+    ) -> Self {
+        OidcServiceImpl {
+            client,
+            repo,
+            user_repo,
+            expires_in,
+        }
+    }
+
+    /// Name from the provider's account, made unique when it is taken.
+    async fn available_name(&self, claims: &IdTokenClaims) -> Result<String, CommonError> {
+        let name = claims
+            .preferred_username
+            .clone()
+            .or_else(|| {
+                claims
+                    .email
+                    .as_deref()
+                    .and_then(|email| email.split('@').next())
+                    .map(str::to_string)
+            })
+            .filter(|name| name.len() >= 2)
+            .unwrap_or_else(|| "user".to_string());
+
+        if self.user_repo.find_by_name(name.clone()).await?.is_none() {
+            return Ok(name);
+        }
+
+        let suffix: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(6)
+            .map(char::from)
+            .collect();
+
+        Ok(format!("{}-{}", name, suffix.to_lowercase()))
+    }
+}
+
+#[async_trait]
+impl OidcService for OidcServiceImpl {
+    async fn start(&self) -> Result<OidcRedirect, CommonError> {
+        let state = random_token();
+        let nonce = random_token();
+        let code_verifier = random_token();
+
+        let authorization_url = self
+            .client
+            .authorization_url(state.clone(), nonce.clone(), code_challenge(&code_verifier))
+            .await?;
+        let now = Utc::now();
+        self.repo
+            .create_login(OidcLogin {
+                state_hash: hash_token(&state),
+                code_verifier,
+                nonce,
+                expires_at: now + Duration::minutes(self.expires_in),
+                created_at: now,
+            })
+            .await?;
+
+        Ok(OidcRedirect {
+            state,
+            authorization_url,
+        })
+    }
+
+    async fn complete(&self, state: String, code: String) -> Result<User, CommonError> {
+        let login = match self.repo.take_login(hash_token(&state)).await? {
+            Some(login) if login.expires_at > Utc::now() => login,
+            _ => return Err(invalid_login()),
+        };
+
+        let claims = self
+            .client
+            .authenticate(code, login.code_verifier, login.nonce)
+            .await?;
+        if let Some(user) = self
+            .repo
+            .find_user(claims.iss.clone(), claims.sub.clone())
+            .await?
+        {
+            return Ok(user);
+        }
+
+        let identity = |user_id| UserIdentity {
+            id: Uuid::new_v4(),
+            user_id,
+            issuer: claims.iss.clone(),
+            subject: claims.sub.clone(),
+            created_at: Utc::now(),
+        };
+
+        let email = claims.verified_email().map(str::to_string);
+        let email_user = match &email {
+            Some(email) => self.user_repo.find_by_email(email.clone()).await?,
+            None => None,
+        };
+        // both sides vouch for the email, it is the same person
+        if let Some(user) = email_user.as_ref().filter(|user| user.email_verified()) {
+            info!(
+                "linking {} to the account {} of {}",
+                user.id, claims.sub, claims.iss
+            );
+            self.repo.link(identity(user.id)).await?;
+            return Ok(user.clone());
+        }
+        // whoever registered the unverified email doesn't get the account, and the new
+        // user can't have the email either
+        let email = email.filter(|_| email_user.is_none());
+
+        let user = User {
+            id: Uuid::new_v4(),
+            name: self.available_name(&claims).await?,
+            // only the provider logs the user in, until a password is set with a reset
+            password: hash_password(&random_token()),
+            role: Role::User,
+            email_verified_at: email.as_ref().map(|_| Utc::now()),
+            email,
+        };
+        info!(
+            "provisioning {} for the account {} of {}",
+            user.id, claims.sub, claims.iss
+        );
+        let user_created = events::user_created(&user)?;
+
+        Ok(self
+            .repo
+            .provision(user.clone(), identity(user.id), vec![user_created])
+            .await?)
+    }
+}
+
+fn invalid_login() -> CommonError {
+    CommonError {
+        message: "unknown or expired login".to_string(),
+        code: AUTH_TOKEN_ENCODING_CODE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oidc::MockOidcClient;
+    use crate::repositories::oidc_repository::MockOidcRepository;
+    use crate::repositories::user_repository::MockUserRepository;
+    use mockall::predicate::eq;
+    use rstest::*;
+
+    const ISSUER: &str = "https://idp.example.com";
+
+    fn claims(email: Option<&str>, email_verified: bool) -> IdTokenClaims {
+        IdTokenClaims {
+            iss: ISSUER.to_string(),
+            sub: "subject".to_string(),
+            nonce: Some("nonce".to_string()),
+            email: email.map(str::to_string),
+            email_verified,
+            preferred_username: Some("alice".to_string()),
+        }
+    }
+
+    fn user(email_verified: bool) -> User {
+        User {
+            id: Uuid::new_v4(),
+            name: "Alice".to_string(),
+            password: hash_password("1234567"),
+            role: Role::User,
+            email: Some("alice@example.com".to_string()),
+            email_verified_at: email_verified.then(Utc::now),
+        }
+    }
+
+    fn login(expires_in: i64) -> OidcLogin {
+        OidcLogin {
+            state_hash: hash_token("state"),
+            code_verifier: "verifier".to_string(),
+            nonce: "nonce".to_string(),
+            expires_at: Utc::now() + Duration::minutes(expires_in),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start() {
+        let mut client_mock = MockOidcClient::new();
+        client_mock
+            .expect_authorization_url()
+            .times(1)
+            .returning(|state, _, _| Ok(format!("{}/authorize?state={}", ISSUER, state)));
+        let mut repo_mock = MockOidcRepository::new();
+        repo_mock
+            .expect_create_login()
+            .withf(|login| login.state_hash != login.nonce && login.code_verifier.len() == 43)
+            .times(1)
+            .returning(|_| Ok(()));
+        let service = OidcServiceImpl::new(
+            Arc::new(client_mock),
+            Arc::new(repo_mock),
+            Arc::new(MockUserRepository::new()),
+            10,
+        );
+
+        let redirect = service.start().await.unwrap();
+
+        assert_eq!(
+            redirect.authorization_url,
+            format!("{}/authorize?state={}", ISSUER, redirect.state)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete_rejects_expired_login() {
+        let mut repo_mock = MockOidcRepository::new();
+        repo_mock
+            .expect_take_login()
+            .with(eq(hash_token("state")))
+            .returning(|_| Ok(Some(login(-1))));
+        // the code isn't even exchanged
+        let service = OidcServiceImpl::new(
+            Arc::new(MockOidcClient::new()),
+            Arc::new(repo_mock),
+            Arc::new(MockUserRepository::new()),
+            10,
+        );
+
+        let result = service
+            .complete("state".to_string(), "code".to_string())
+            .await;
+
+        assert_eq!(result.unwrap_err().code, AUTH_TOKEN_ENCODING_CODE);
+    }
+
+    struct CompleteTestCase {
+        claims: IdTokenClaims,
+        linked: bool,
+        email_user: Option<User>,
+        expect_link: bool,
+        /// Email of the provisioned user, none when no user is provisioned.
+        expected_provisioned: Option<Option<&'static str>>,
+    }
+
+    #[rstest]
+    #[case::linked(CompleteTestCase {
+        claims: claims(Some("alice@example.com"), true),
+        linked: true,
+        email_user: None,
+        expect_link: false,
+        expected_provisioned: None,
+    })]
+    #[case::same_verified_email(CompleteTestCase {
+        claims: claims(Some("alice@example.com"), true),
+        linked: false,
+        email_user: Some(user(true)),
+        expect_link: true,
+        expected_provisioned: None,
+    })]
+    #[case::same_unverified_email(CompleteTestCase {
+        claims: claims(Some("alice@example.com"), true),
+        linked: false,
+        email_user: Some(user(false)),
+        expect_link: false,
+        expected_provisioned: Some(None),
+    })]
+    #[case::email_not_verified_by_provider(CompleteTestCase {
+        claims: claims(Some("alice@example.com"), false),
+        linked: false,
+        email_user: None,
+        expect_link: false,
+        expected_provisioned: Some(None),
+    })]
+    #[case::new_user(CompleteTestCase {
+        claims: claims(Some("alice@example.com"), true),
+        linked: false,
+        email_user: None,
+        expect_link: false,
+        expected_provisioned: Some(Some("alice@example.com")),
+    })]
+    #[tokio::test]
+    async fn test_complete(#[case] case: CompleteTestCase) {
+        let mut client_mock = MockOidcClient::new();
+        let claims = case.claims.clone();
+        client_mock
+            .expect_authenticate()
+            .with(
+                eq("code".to_string()),
+                eq("verifier".to_string()),
+                eq("nonce".to_string()),
+            )
+            .times(1)
+            .returning(move |_, _, _| Ok(claims.clone()));
+        let mut repo_mock = MockOidcRepository::new();
+        repo_mock
+            .expect_take_login()
+            .returning(|_| Ok(Some(login(10))));
+        let linked = case.linked.then(|| user(true));
+        repo_mock
+            .expect_find_user()
+            .with(eq(ISSUER.to_string()), eq("subject".to_string()))
+            .returning(move |_, _| Ok(linked.clone()));
+        repo_mock
+            .expect_link()
+            .withf(|identity| identity.issuer == ISSUER && identity.subject == "subject")
+            .times(case.expect_link as usize)
+            .returning(|_| Ok(()));
+        let expected_email = case.expected_provisioned;
+        repo_mock
+            .expect_provision()
+            .withf(move |user, identity, events| {
+                identity.user_id == user.id
+                    && user.name == "alice"
+                    && Some(user.email.as_deref()) == expected_email
+                    && user.email_verified_at.is_some() == user.email.is_some()
+                    && events.len() == 1
+            })
+            .times(expected_email.is_some() as usize)
+            .returning(|user, _, _| Ok(user));
+        let mut user_repo_mock = MockUserRepository::new();
+        let email_user = case.email_user.clone();
+        user_repo_mock
+            .expect_find_by_email()
+            .returning(move |_| Ok(email_user.clone()));
+        user_repo_mock.expect_find_by_name().returning(|_| Ok(None));
+        let service = OidcServiceImpl::new(
+            Arc::new(client_mock),
+            Arc::new(repo_mock),
+            Arc::new(user_repo_mock),
+            10,
+        );
+
+        let result = service
+            .complete("state".to_string(), "code".to_string())
+            .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+    }
+}
@@ -0,0 +1,323 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use log::{error, info};
+use mockall::automock;
+use std::sync::Arc;
+use utils::error::{CommonError, AUTH_TOKEN_ENCODING_CODE};
+use utils::http::services::auth_service::hash_token;
+use utils::mail::{Email, Mailer};
+use uuid::Uuid;
+
+use crate::events;
+use crate::models::password_reset::PasswordReset;
+use crate::models::user::{hash_password, User};
+use crate::repositories::password_reset_repository::PasswordResetRepository;
+use crate::repositories::user_repository::UserRepository;
+use crate::services::random_token;
+
+#[automock]
+#[async_trait]
+pub trait PasswordResetService: Send + Sync {
+    /// Mails a link to reset the password to the user of the email, when it was verified.
+    /// The mail is sent in the background and unknown emails succeed too, so callers can't
+    /// tell which emails are registered, not even from the time taken.
+    async fn forgot(&self, email: String) -> Result<(), CommonError>;
+    /// Replaces the password of the user the token was sent to.
+    async fn reset(&self, token: String, password: String) -> Result<User, CommonError>;
+}
+
+#[derive(Clone)]
+pub struct PasswordResetServiceImpl {
+    repo: Arc<dyn PasswordResetRepository>,
+    user_repo: Arc<dyn UserRepository>,
+    mailer: Arc<dyn Mailer>,
+    reset_url: String,
+    expires_in: i64,
+}
+
+impl PasswordResetServiceImpl {
+    /// The token is appended to the reset url, it expires after `expires_in` minutes.
+    pub fn new(
+        repo: Arc<dyn PasswordResetRepository>,
+        user_repo: Arc<dyn UserRepository>,
+        mailer: Arc<dyn Mailer>,
+        reset_url: &str,
+        expires_in: i64,
+    ) -> Self {
+        PasswordResetServiceImpl {
+            repo,
+            user_repo,
+            mailer,
+            reset_url: reset_url.to_string(),
+            expires_in,
+        }
+    }
+
+    /// Mails the reset link when the user of the email verified it.
+    async fn send_reset(&self, email: String) -> Result<(), CommonError> {
+        let user = match self.user_repo.find_by_email(email.clone()).await? {
+            Some(user) if user.email_verified() => user,
+            // whoever owns an unverified address could take over the account
+            _ => {
+                info!("password reset requested for an unknown or unverified email");
+                return Ok(());
+            }
+        };
+
+        let token = random_token();
+        let now = Utc::now();
+        self.repo
+            .create(PasswordReset {
+                id: Uuid::new_v4(),
+                user_id: user.id,
+                token_hash: hash_token(&token),
+                expires_at: now + Duration::minutes(self.expires_in),
+                created_at: now,
+                used_at: None,
+            })
+            .await?;
+
+        let body = format!(
+            "Hi {},\n\nReset your password by opening {}?token={}\n\nThe link expires in {} minutes, ignore this email if you did not ask for it.",
+            user.name, self.reset_url, token, self.expires_in
+        );
+        self.mailer
+            .send(Email::new(&email, "Reset your password", &body))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PasswordResetService for PasswordResetServiceImpl {
+    async fn forgot(&self, email: String) -> Result<(), CommonError> {
+        let service = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = service.send_reset(email).await {
+                error!("failed sending password reset: {}", err);
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn reset(&self, token: String, password: String) -> Result<User, CommonError> {
+        let reset = self
+            .repo
+            .find_by_hash(hash_token(&token))
+            .await?
+            .ok_or_else(|| invalid_reset("unknown password reset token"))?;
+
+        let password_changed = events::user_password_changed(reset.user_id)?;
+        self.repo
+            .reset(reset.id, hash_password(&password), vec![password_changed])
+            .await?
+            .ok_or_else(|| invalid_reset("password reset token expired or used"))
+    }
+}
+
+fn invalid_reset(message: &str) -> CommonError {
+    CommonError {
+        message: message.to_string(),
+        code: AUTH_TOKEN_ENCODING_CODE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::password_reset_repository::MockPasswordResetRepository;
+    use crate::repositories::user_repository::MockUserRepository;
+    use mockall::predicate::eq;
+    use rstest::*;
+    use utils::http::middlewares::jwt_auth::Role;
+    use utils::mail::memory::MemoryMailer;
+
+    fn user(verified: bool) -> User {
+        User {
+            id: Uuid::new_v4(),
+            name: "Alice".to_string(),
+            password: hash_password("1234567"),
+            role: Role::User,
+            email: Some("alice@example.com".to_string()),
+            email_verified_at: verified.then(Utc::now),
+        }
+    }
+
+    struct SendResetTestCase {
+        user: Option<User>,
+        expect_mail: bool,
+    }
+
+    #[rstest]
+    #[case::verified(SendResetTestCase { user: Some(user(true)), expect_mail: true })]
+    #[case::unverified(SendResetTestCase { user: Some(user(false)), expect_mail: false })]
+    #[case::unknown(SendResetTestCase { user: None, expect_mail: false })]
+    #[tokio::test]
+    async fn test_send_reset(#[case] case: SendResetTestCase) {
+        let mut user_repo_mock = MockUserRepository::new();
+        let found = case.user.clone();
+        user_repo_mock
+            .expect_find_by_email()
+            .with(eq("alice@example.com".to_string()))
+            .times(1)
+            .returning(move |_| Ok(found.clone()));
+        let mut repo_mock = MockPasswordResetRepository::new();
+        let user_id = case.user.map(|user| user.id);
+        repo_mock
+            .expect_create()
+            .withf(move |reset| Some(reset.user_id) == user_id)
+            .times(case.expect_mail as usize)
+            .returning(Ok);
+        let mailer = Arc::new(MemoryMailer::new());
+        let service = PasswordResetServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(user_repo_mock),
+            mailer.clone(),
+            "http://localhost/reset-password",
+            10,
+        );
+
+        let result = service.send_reset("alice@example.com".to_string()).await;
+
+        assert!(result.is_ok());
+        let email = mailer.last_sent_to("alice@example.com");
+        assert_eq!(email.is_some(), case.expect_mail);
+        if let Some(email) = email {
+            assert!(email
+                .body
+                .contains("http://localhost/reset-password?token="));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forgot_mails_in_the_background() {
+        let mut user_repo_mock = MockUserRepository::new();
+        let found = user(true);
+        user_repo_mock
+            .expect_find_by_email()
+            .returning(move |_| Ok(Some(found.clone())));
+        let mut repo_mock = MockPasswordResetRepository::new();
+        repo_mock.expect_create().returning(Ok);
+        let mailer = Arc::new(MemoryMailer::new());
+        let service = PasswordResetServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(user_repo_mock),
+            mailer.clone(),
+            "http://localhost/reset-password",
+            10,
+        );
+
+        let result = service.forgot("alice@example.com".to_string()).await;
+
+        assert!(result.is_ok());
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while mailer.last_sent_to("alice@example.com").is_none() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_forgot_succeeds_whatever_the_lookup() {
+        let mut user_repo_mock = MockUserRepository::new();
+        user_repo_mock.expect_find_by_email().returning(|_| {
+            Err(utils::error::DatabaseError {
+                message: "db is down".to_string(),
+            })
+        });
+        let service = PasswordResetServiceImpl::new(
+            Arc::new(MockPasswordResetRepository::new()),
+            Arc::new(user_repo_mock),
+            Arc::new(MemoryMailer::new()),
+            "http://localhost/reset-password",
+            10,
+        );
+
+        let result = service.forgot("alice@example.com".to_string()).await;
+
+        assert!(result.is_ok());
+    }
+
+    struct ResetTestCase {
+        found: bool,
+        reset: bool,
+        expect_reset: bool,
+        expected_error: Option<CommonError>,
+    }
+
+    #[rstest]
+    #[case::reset(ResetTestCase {
+        found: true,
+        reset: true,
+        expect_reset: true,
+        expected_error: None,
+    })]
+    #[case::unknown(ResetTestCase {
+        found: false,
+        reset: false,
+        expect_reset: false,
+        expected_error: Some(invalid_reset("unknown password reset token")),
+    })]
+    #[case::expired_or_used(ResetTestCase {
+        found: true,
+        reset: false,
+        expect_reset: true,
+        expected_error: Some(invalid_reset("password reset token expired or used")),
+    })]
+    #[tokio::test]
+    async fn test_reset(#[case] case: ResetTestCase) {
+        let user = user(true);
+        let reset = PasswordReset {
+            id: Uuid::new_v4(),
+            user_id: user.id,
+            token_hash: hash_token("token"),
+            expires_at: Utc::now() + Duration::minutes(10),
+            created_at: Utc::now(),
+            used_at: None,
+        };
+        let reset_id = reset.id;
+
+        let mut repo_mock = MockPasswordResetRepository::new();
+        let found = case.found;
+        repo_mock
+            .expect_find_by_hash()
+            .with(eq(hash_token("token")))
+            .returning(move |_| Ok(found.then(|| reset.clone())));
+        let reset = case.reset;
+        repo_mock
+            .expect_reset()
+            .withf(move |id, password, events| {
+                let changed = User {
+                    password: password.clone(),
+                    ..user.clone()
+                };
+                *id == reset_id
+                    && changed.verify_password("new password")
+                    && events[0].topic == events::USER_PASSWORD_CHANGED_EVENT
+            })
+            .times(case.expect_reset as usize)
+            .returning(move |_, password, _| {
+                Ok(reset.then(|| User {
+                    password,
+                    ..self::user(true)
+                }))
+            });
+        let service = PasswordResetServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(MockUserRepository::new()),
+            Arc::new(MemoryMailer::new()),
+            "http://localhost/reset-password",
+            10,
+        );
+
+        let result = service
+            .reset("token".to_string(), "new password".to_string())
+            .await;
+
+        assert_eq!(result.err(), case.expected_error);
+    }
+}
@@ -0,0 +1,136 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use mockall::automock;
+use std::sync::Arc;
+use utils::error::CommonError;
+use utils::http::middlewares::jwt_auth::Scope;
+use utils::http::services::auth_service::{hash_token, PERSONAL_TOKEN_PREFIX};
+use uuid::Uuid;
+
+use crate::models::personal_token::PersonalAccessToken;
+use crate::repositories::personal_token_repository::PersonalTokenRepository;
+use crate::services::random_token;
+
+/// Token just created, the only time the token itself is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatedPersonalToken {
+    pub token: String,
+    pub details: PersonalAccessToken,
+}
+
+#[automock]
+#[async_trait]
+pub trait PersonalTokenService: Send + Sync {
+    /// Creates a token restricted to the scopes, or as capable as the user without any.
+    /// It expires after `expires_in` days, or never.
+    async fn create(
+        &self,
+        user_id: Uuid,
+        name: String,
+        scopes: Vec<Scope>,
+        expires_in: Option<i64>,
+    ) -> Result<CreatedPersonalToken, CommonError>;
+    async fn list(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>, CommonError>;
+    /// Returns false when the user has no such token.
+    async fn revoke(&self, user_id: Uuid, id: Uuid) -> Result<bool, CommonError>;
+}
+
+pub struct PersonalTokenServiceImpl {
+    repo: Arc<dyn PersonalTokenRepository>,
+}
+
+impl PersonalTokenServiceImpl {
+    pub fn new(repo: Arc<dyn PersonalTokenRepository>) -> Self {
+        PersonalTokenServiceImpl { repo }
+    }
+}
+
+#[async_trait]
+impl PersonalTokenService for PersonalTokenServiceImpl {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        name: String,
+        scopes: Vec<Scope>,
+        expires_in: Option<i64>,
+    ) -> Result<CreatedPersonalToken, CommonError> {
+        let token = format!("{}{}", PERSONAL_TOKEN_PREFIX, random_token());
+        let mut scopes: Vec<String> = scopes.iter().map(|scope| scope.to_string()).collect();
+        scopes.sort();
+        scopes.dedup();
+        let now = Utc::now();
+
+        let details = self
+            .repo
+            .create(PersonalAccessToken {
+                id: Uuid::new_v4(),
+                user_id,
+                name,
+                token_hash: hash_token(&token),
+                scopes,
+                expires_at: expires_in.map(|days| now + Duration::days(days)),
+                created_at: now,
+                revoked_at: None,
+            })
+            .await?;
+
+        Ok(CreatedPersonalToken { token, details })
+    }
+
+    async fn list(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>, CommonError> {
+        Ok(self.repo.list(user_id).await?)
+    }
+
+    async fn revoke(&self, user_id: Uuid, id: Uuid) -> Result<bool, CommonError> {
+        Ok(self.repo.revoke(user_id, id).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::personal_token_repository::MockPersonalTokenRepository;
+    use rstest::*;
+
+    struct CreateTestCase {
+        scopes: Vec<Scope>,
+        expires_in: Option<i64>,
+        expected_scopes: Vec<&'static str>,
+    }
+
+    #[rstest]
+    #[case::unrestricted(CreateTestCase {
+        scopes: vec![],
+        expires_in: None,
+        expected_scopes: vec![],
+    })]
+    #[case::scoped(CreateTestCase {
+        scopes: vec![Scope::SubscriptionsManage, Scope::NewsRead, Scope::NewsRead],
+        expires_in: Some(30),
+        expected_scopes: vec!["news:read", "subscriptions:manage"],
+    })]
+    #[tokio::test]
+    async fn test_create(#[case] case: CreateTestCase) {
+        let user_id = Uuid::new_v4();
+        let mut repo_mock = MockPersonalTokenRepository::new();
+        repo_mock.expect_create().times(1).returning(Ok);
+        let service = PersonalTokenServiceImpl::new(Arc::new(repo_mock));
+
+        let created = service
+            .create(user_id, "script".to_string(), case.scopes, case.expires_in)
+            .await
+            .unwrap();
+
+        assert!(created.token.starts_with(PERSONAL_TOKEN_PREFIX));
+        assert_eq!(created.details.token_hash, hash_token(&created.token));
+        assert_eq!(created.details.user_id, user_id);
+        assert_eq!(created.details.scopes, case.expected_scopes);
+        assert_eq!(
+            created
+                .details
+                .expires_at
+                .map(|expires_at| (expires_at - created.details.created_at).num_days()),
+            case.expires_in
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use mockall::automock;
+use std::sync::Arc;
+use utils::error::CommonError;
+use uuid::Uuid;
+
+use crate::events;
+use crate::models::preferences::{PreferencesUpdate, UserPreferences};
+use crate::repositories::preferences_repository::PreferencesRepository;
+
+#[automock]
+#[async_trait]
+pub trait PreferencesService: Send + Sync {
+    /// Preferences of the user, the defaults until they are changed.
+    async fn get(&self, user_id: Uuid) -> Result<UserPreferences, CommonError>;
+    /// Applies the changes, publishing the preferences when anything changed.
+    async fn update(
+        &self,
+        user_id: Uuid,
+        update: PreferencesUpdate,
+    ) -> Result<UserPreferences, CommonError>;
+}
+
+pub struct PreferencesServiceImpl {
+    repo: Arc<dyn PreferencesRepository>,
+}
+
+impl PreferencesServiceImpl {
+    pub fn new(repo: Arc<dyn PreferencesRepository>) -> Self {
+        PreferencesServiceImpl { repo }
+    }
+}
+
+#[async_trait]
+impl PreferencesService for PreferencesServiceImpl {
+    async fn get(&self, user_id: Uuid) -> Result<UserPreferences, CommonError> {
+        let preferences = self.repo.find(user_id).await?;
+
+        Ok(preferences.unwrap_or_else(|| UserPreferences::defaults(user_id)))
+    }
+
+    async fn update(
+        &self,
+        user_id: Uuid,
+        update: PreferencesUpdate,
+    ) -> Result<UserPreferences, CommonError> {
+        let preferences = self.get(user_id).await?;
+        let updated = match update.apply(&preferences) {
+            Some(updated) => updated,
+            None => return Ok(preferences),
+        };
+
+        let preferences_updated = events::user_preferences_updated(&updated)?;
+        Ok(self.repo.save(updated, vec![preferences_updated]).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::preferences::Notifications;
+    use crate::repositories::preferences_repository::MockPreferencesRepository;
+    use rstest::*;
+
+    struct UpdateTestCase {
+        update: PreferencesUpdate,
+        expect_save: bool,
+        expected_notifications: Notifications,
+    }
+
+    #[rstest]
+    #[case::changed(UpdateTestCase {
+        update: PreferencesUpdate {
+            notifications: Some(Notifications::Off),
+            ..Default::default()
+        },
+        expect_save: true,
+        expected_notifications: Notifications::Off,
+    })]
+    #[case::unchanged(UpdateTestCase {
+        update: PreferencesUpdate {
+            notifications: Some(Notifications::Realtime),
+            ..Default::default()
+        },
+        expect_save: false,
+        expected_notifications: Notifications::Realtime,
+    })]
+    #[tokio::test]
+    async fn test_update(#[case] case: UpdateTestCase) {
+        let user_id = Uuid::new_v4();
+        let mut repo_mock = MockPreferencesRepository::new();
+        repo_mock.expect_find().times(1).returning(|_| Ok(None));
+        repo_mock
+            .expect_save()
+            .withf(move |preferences, events| {
+                preferences.user_id == user_id
+                    && events[0].topic == events::USER_PREFERENCES_UPDATED_EVENT
+            })
+            .times(case.expect_save as usize)
+            .returning(|preferences, _| Ok(preferences));
+        let service = PreferencesServiceImpl::new(Arc::new(repo_mock));
+
+        let preferences = service.update(user_id, case.update).await.unwrap();
+
+        assert_eq!(preferences.user_id, user_id);
+        assert_eq!(preferences.notifications, case.expected_notifications);
+    }
+}
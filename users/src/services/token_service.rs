@@ -0,0 +1,395 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use log::warn;
+use mockall::automock;
+use std::sync::Arc;
+use utils::error::{CommonError, AUTH_TOKEN_ENCODING_CODE};
+use utils::http::services::auth_service::{hash_token, AuthService};
+use uuid::Uuid;
+
+use crate::models::refresh_token::RefreshToken;
+use crate::models::user::User;
+use crate::repositories::refresh_token_repository::RefreshTokenRepository;
+use crate::repositories::user_repository::UserRepository;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+#[automock]
+#[async_trait]
+pub trait TokenService: Send + Sync {
+    /// Issues the tokens of a new login, starting a new refresh token family.
+    async fn issue(&self, user: User) -> Result<TokenPair, CommonError>;
+    /// Exchanges a refresh token for new tokens carrying the current role and email
+    /// verification of the user.
+    /// The refresh token can't be used again, doing so revokes every token rotated from
+    /// the same login.
+    async fn refresh(&self, refresh_token: String) -> Result<TokenPair, CommonError>;
+    /// Revokes the refresh token and the tokens of its family.
+    async fn revoke(&self, refresh_token: String) -> Result<(), CommonError>;
+    /// Revokes every access and refresh token issued to the user so far.
+    async fn revoke_all(&self, user_id: Uuid) -> Result<(), CommonError>;
+}
+
+pub struct TokenServiceImpl {
+    repo: Arc<dyn RefreshTokenRepository>,
+    user_repo: Arc<dyn UserRepository>,
+    auth_service: Arc<dyn AuthService>,
+    access_expires_in: i64,
+    refresh_expires_in: i64,
+}
+
+impl TokenServiceImpl {
+    /// Expirations are in minutes.
+    pub fn new(
+        repo: Arc<dyn RefreshTokenRepository>,
+        user_repo: Arc<dyn UserRepository>,
+        auth_service: Arc<dyn AuthService>,
+        access_expires_in: i64,
+        refresh_expires_in: i64,
+    ) -> Self {
+        TokenServiceImpl {
+            repo,
+            user_repo,
+            auth_service,
+            access_expires_in,
+            refresh_expires_in,
+        }
+    }
+
+    /// Encodes the tokens and the refresh token to store.
+    fn tokens(
+        &self,
+        user: &User,
+        family_id: Uuid,
+    ) -> Result<(TokenPair, RefreshToken), CommonError> {
+        let user_id = user.id;
+        let access_token = self
+            .auth_service
+            .encode_token(user.token_subject(), self.access_expires_in)?;
+        let refresh_token = self.auth_service.encode_refresh_token(
+            user_id.to_string(),
+            family_id.to_string(),
+            self.refresh_expires_in,
+        )?;
+
+        let now = Utc::now();
+        let stored = RefreshToken {
+            id: Uuid::new_v4(),
+            user_id,
+            family_id,
+            token_hash: hash_token(&refresh_token),
+            expires_at: now + Duration::minutes(self.refresh_expires_in),
+            created_at: now,
+            used_at: None,
+            revoked_at: None,
+        };
+
+        Ok((
+            TokenPair {
+                access_token,
+                refresh_token,
+            },
+            stored,
+        ))
+    }
+
+    async fn find(&self, refresh_token: String) -> Result<RefreshToken, CommonError> {
+        let claims = self
+            .auth_service
+            .decode_refresh_token(refresh_token.clone())?;
+
+        match self.repo.find_by_hash(hash_token(&refresh_token)).await? {
+            Some(stored) if stored.user_id.to_string() == claims.sub => Ok(stored),
+            _ => Err(invalid_token("unknown refresh token")),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenService for TokenServiceImpl {
+    async fn issue(&self, user: User) -> Result<TokenPair, CommonError> {
+        let (tokens, stored) = self.tokens(&user, Uuid::new_v4())?;
+        self.repo.create(stored).await?;
+
+        Ok(tokens)
+    }
+
+    async fn refresh(&self, refresh_token: String) -> Result<TokenPair, CommonError> {
+        let used = self.find(refresh_token).await?;
+
+        if used.revoked_at.is_some() {
+            return Err(invalid_token("refresh token revoked"));
+        }
+
+        if used.used_at.is_none() {
+            // the role or the email verification may have changed since the login
+            let user = self.user_repo.get_by_id(used.user_id).await?;
+            let (tokens, stored) = self.tokens(&user, used.family_id)?;
+            if self.repo.rotate(used.id, stored).await? {
+                return Ok(tokens);
+            }
+        }
+
+        // the token was exchanged before, either the user or whoever stole it holds
+        // the tokens rotated from it
+        warn!(
+            "refresh token reused, revoking family {} of user {}",
+            used.family_id, used.user_id
+        );
+        self.repo.revoke_family(used.family_id).await?;
+
+        Err(invalid_token("refresh token reused"))
+    }
+
+    async fn revoke(&self, refresh_token: String) -> Result<(), CommonError> {
+        let stored = self.find(refresh_token).await?;
+        self.repo.revoke_family(stored.family_id).await?;
+
+        Ok(())
+    }
+
+    async fn revoke_all(&self, user_id: Uuid) -> Result<(), CommonError> {
+        self.repo.revoke_user(user_id).await?;
+        self.auth_service.revoke_user_tokens(&user_id.to_string())?;
+
+        Ok(())
+    }
+}
+
+fn invalid_token(message: &str) -> CommonError {
+    CommonError {
+        message: message.to_string(),
+        code: AUTH_TOKEN_ENCODING_CODE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::refresh_token_repository::MockRefreshTokenRepository;
+    use crate::repositories::user_repository::MockUserRepository;
+    use rstest::*;
+    use utils::http::middlewares::jwt_auth::{Role, TokenSubject};
+    use utils::http::services::auth_service::JwtAuthService;
+    use utils::http::services::revocation_store::MockRevocationStore;
+
+    const USER_ID: &str = "b73ccd26-1832-4d10-9251-271ce453cee3";
+
+    fn auth_service() -> Arc<dyn AuthService> {
+        Arc::new(JwtAuthService::new("secret".to_string()))
+    }
+
+    fn stored(token: &str, family_id: Uuid) -> RefreshToken {
+        RefreshToken {
+            id: Uuid::new_v4(),
+            user_id: Uuid::parse_str(USER_ID).unwrap(),
+            family_id,
+            token_hash: hash_token(token),
+            expires_at: Utc::now() + Duration::minutes(10),
+            created_at: Utc::now(),
+            used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_issue_stores_hashed_refresh_token() {
+        let mut repo_mock = MockRefreshTokenRepository::new();
+        repo_mock
+            .expect_create()
+            .withf(|token| token.user_id.to_string() == USER_ID && token.used_at.is_none())
+            .returning(Ok);
+        let service = TokenServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(MockUserRepository::new()),
+            auth_service(),
+            5,
+            10,
+        );
+
+        let tokens = service
+            .issue(User {
+                id: Uuid::parse_str(USER_ID).unwrap(),
+                name: "Alice".to_string(),
+                password: "1234".to_string(),
+                role: Role::Admin,
+                email: Some("alice@example.com".to_string()),
+                email_verified_at: Some(Utc::now()),
+            })
+            .await;
+
+        assert!(tokens.is_ok());
+        let tokens = tokens.unwrap();
+        let claims = auth_service()
+            .decode_token(tokens.access_token.clone())
+            .unwrap();
+        assert_eq!(claims.sub, USER_ID);
+        assert_eq!(claims.role, Role::Admin);
+        assert!(claims.email_verified);
+        assert!(auth_service()
+            .decode_refresh_token(tokens.refresh_token)
+            .is_ok());
+    }
+
+    struct RefreshTestCase {
+        used: bool,
+        revoked: bool,
+        rotated: bool,
+        expect_rotate: bool,
+        expect_revoke_family: bool,
+        expected_result: Result<(), CommonError>,
+    }
+
+    #[rstest]
+    #[case::rotated(RefreshTestCase {
+        used: false,
+        revoked: false,
+        rotated: true,
+        expect_rotate: true,
+        expect_revoke_family: false,
+        expected_result: Ok(()),
+    })]
+    #[case::reused(RefreshTestCase {
+        used: true,
+        revoked: false,
+        rotated: false,
+        expect_rotate: false,
+        expect_revoke_family: true,
+        expected_result: Err(invalid_token("refresh token reused")),
+    })]
+    #[case::reused_concurrently(RefreshTestCase {
+        used: false,
+        revoked: false,
+        rotated: false,
+        expect_rotate: true,
+        expect_revoke_family: true,
+        expected_result: Err(invalid_token("refresh token reused")),
+    })]
+    #[case::revoked(RefreshTestCase {
+        used: true,
+        revoked: true,
+        rotated: false,
+        expect_rotate: false,
+        expect_revoke_family: false,
+        expected_result: Err(invalid_token("refresh token revoked")),
+    })]
+    #[tokio::test]
+    async fn test_refresh(#[case] case: RefreshTestCase) {
+        let family_id = Uuid::new_v4();
+        let refresh_token = auth_service()
+            .encode_refresh_token(USER_ID.to_string(), family_id.to_string(), 10)
+            .unwrap();
+        let mut used = stored(&refresh_token, family_id);
+        if case.used {
+            used.used_at = Some(Utc::now());
+        }
+        if case.revoked {
+            used.revoked_at = Some(Utc::now());
+        }
+
+        let mut repo_mock = MockRefreshTokenRepository::new();
+        let found = used.clone();
+        repo_mock
+            .expect_find_by_hash()
+            .with(mockall::predicate::eq(hash_token(&refresh_token)))
+            .returning(move |_| Ok(Some(found.clone())));
+        let rotated = case.rotated;
+        let used_id = used.id;
+        repo_mock
+            .expect_rotate()
+            .withf(move |id, token| *id == used_id && token.family_id == family_id)
+            .times(case.expect_rotate as usize)
+            .returning(move |_, _| Ok(rotated));
+        repo_mock
+            .expect_revoke_family()
+            .with(mockall::predicate::eq(family_id))
+            .times(case.expect_revoke_family as usize)
+            .returning(|_| Ok(2));
+        let mut user_repo_mock = MockUserRepository::new();
+        user_repo_mock
+            .expect_get_by_id()
+            .times(case.expect_rotate as usize)
+            .returning(|id| {
+                Ok(User {
+                    id,
+                    name: "Alice".to_string(),
+                    password: "1234".to_string(),
+                    role: Role::Admin,
+                    email: None,
+                    email_verified_at: None,
+                })
+            });
+        let service = TokenServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(user_repo_mock),
+            auth_service(),
+            5,
+            10,
+        );
+
+        let result = service.refresh(refresh_token.clone()).await;
+
+        match case.expected_result {
+            Ok(_) => {
+                let tokens = result.unwrap();
+                assert_ne!(tokens.refresh_token, refresh_token);
+                // the role is read again instead of copied from the refresh token
+                let claims = auth_service().decode_token(tokens.access_token).unwrap();
+                assert_eq!(claims.role, Role::Admin);
+            }
+            Err(err) => assert_eq!(result.unwrap_err(), err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rejects_access_token() {
+        let repo_mock = MockRefreshTokenRepository::new();
+        let service = TokenServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(MockUserRepository::new()),
+            auth_service(),
+            5,
+            10,
+        );
+        let access_token = auth_service()
+            .encode_token(TokenSubject::new(USER_ID, Role::User, true), 10)
+            .unwrap();
+
+        let result = service.refresh(access_token).await;
+
+        assert_eq!(result.unwrap_err().code, AUTH_TOKEN_ENCODING_CODE);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_all_revokes_access_and_refresh_tokens() {
+        let user_id = Uuid::parse_str(USER_ID).unwrap();
+        let mut repo_mock = MockRefreshTokenRepository::new();
+        repo_mock
+            .expect_revoke_user()
+            .with(mockall::predicate::eq(user_id))
+            .times(1)
+            .returning(|_| Ok(2));
+        let mut store_mock = MockRevocationStore::new();
+        store_mock
+            .expect_revoke_user()
+            .withf(|user_id, _| user_id == USER_ID)
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let auth_service = Arc::new(
+            JwtAuthService::new("secret".to_string()).with_revocation_store(Arc::new(store_mock)),
+        );
+        let service = TokenServiceImpl::new(
+            Arc::new(repo_mock),
+            Arc::new(MockUserRepository::new()),
+            auth_service,
+            5,
+            10,
+        );
+
+        assert!(service.revoke_all(user_id).await.is_ok());
+    }
+}
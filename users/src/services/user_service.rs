@@ -1,65 +1,79 @@
-use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
-use argon2::Argon2;
 use async_trait::async_trait;
-use log::error;
 use mockall::automock;
 use std::sync::Arc;
-use utils::error::CommonError;
+use utils::error::{CommonError, AUTH_TOKEN_ENCODING_CODE};
+use utils::http::middlewares::jwt_auth::Role;
 use uuid::Uuid;
 
-use crate::models::user::User;
+use crate::events;
+use crate::models::user::{hash_password, User};
 use crate::repositories::user_repository::UserRepository;
 
-use super::event_service::EventService;
-
 #[automock]
 #[async_trait]
 pub trait UserService: Send + Sync {
-    async fn create(&self, name: String, password: String) -> Result<User, CommonError>;
+    async fn create(
+        &self,
+        name: String,
+        email: String,
+        password: String,
+    ) -> Result<User, CommonError>;
     async fn list(&self) -> Result<Vec<User>, CommonError>;
     async fn get_by_id(&self, user_id: Uuid) -> Result<User, CommonError>;
     async fn get_by_name(&self, name: String) -> Result<User, CommonError>;
+    /// Renames the user, publishing the `user_updated` event.
     async fn update(&self, user_id: Uuid, name: String) -> Result<User, CommonError>;
+    /// Changes the email of the user, which has to be verified again. The `user_updated`
+    /// event is only published when the email differs.
+    async fn update_email(&self, user_id: Uuid, email: String) -> Result<User, CommonError>;
+    /// Replaces the password once the current one is confirmed.
+    async fn change_password(
+        &self,
+        user_id: Uuid,
+        current_password: String,
+        new_password: String,
+    ) -> Result<User, CommonError>;
+    /// Deletes the user, publishing the `user_deleted` event so the other services
+    /// forget about them.
     async fn delete(&self, user_id: Uuid) -> Result<usize, CommonError>;
+    /// Makes admins of the users with one of the emails. An email counts once it's
+    /// verified, so registering with it isn't enough to become admin.
+    async fn promote_admins(&self, emails: Vec<String>) -> Result<usize, CommonError>;
 }
 
 pub struct UserServiceImpl {
     repo: Arc<dyn UserRepository>,
-    broker: Arc<dyn EventService>,
 }
 
 impl UserServiceImpl {
-    pub fn new(repo: Arc<dyn UserRepository>, broker: Arc<dyn EventService>) -> Self {
-        UserServiceImpl { repo, broker }
+    pub fn new(repo: Arc<dyn UserRepository>) -> Self {
+        UserServiceImpl { repo }
     }
 }
 
 #[async_trait]
 impl UserService for UserServiceImpl {
-    async fn create(&self, name: String, password: String) -> Result<User, CommonError> {
-        let salt = SaltString::generate(&mut OsRng);
-        let hashed_password = Argon2::default()
-            .hash_password(password.as_bytes(), &salt)
-            .expect("Error while hashing password")
-            .to_string();
-
-        let result = self.repo.create(name, hashed_password).await;
-
-        match result {
-            Ok(user) => {
-                let result = self.broker.user_created(user.clone()).await;
-
-                if result.is_err() {
-                    let err: CommonError = result.err().unwrap().into();
-                    error!("failed sending user created event: {}", err);
-                }
-
-                return Ok(user.clone());
-            }
-            Err(error) => {
-                return Err(error.into());
-            }
-        }
+    async fn create(
+        &self,
+        name: String,
+        email: String,
+        password: String,
+    ) -> Result<User, CommonError> {
+        let user = User {
+            id: Uuid::new_v4(),
+            name,
+            password: hash_password(&password),
+            role: Role::User,
+            email: Some(email),
+            email_verified_at: None,
+        };
+        // stored with the user so the event is published even if the broker is down
+        let user_created = events::user_created(&user)?;
+
+        self.repo
+            .create(user, vec![user_created])
+            .await
+            .map_err(|e| -> CommonError { e.into() })
     }
 
     async fn list(&self) -> Result<Vec<User>, CommonError> {
@@ -84,15 +98,76 @@ impl UserService for UserServiceImpl {
     }
 
     async fn update(&self, user_id: Uuid, name: String) -> Result<User, CommonError> {
+        let user = self.get_by_id(user_id).await?;
+        let user_updated = events::user_updated(&User {
+            name: name.clone(),
+            ..user
+        })?;
+
+        self.repo
+            .update(user_id, name, vec![user_updated])
+            .await
+            .map_err(|e| -> CommonError { e.into() })
+    }
+
+    async fn update_email(&self, user_id: Uuid, email: String) -> Result<User, CommonError> {
+        let user = self.get_by_id(user_id).await?;
+        if user.email.as_deref() == Some(email.as_str()) {
+            return Ok(user);
+        }
+        let user_updated = events::user_updated(&User {
+            email: Some(email.clone()),
+            email_verified_at: None,
+            ..user
+        })?;
+
+        self.repo
+            .update_email(user_id, email, vec![user_updated])
+            .await
+            .map_err(|e| -> CommonError { e.into() })
+    }
+
+    async fn change_password(
+        &self,
+        user_id: Uuid,
+        current_password: String,
+        new_password: String,
+    ) -> Result<User, CommonError> {
+        let user = self.get_by_id(user_id).await?;
+        if !user.verify_password(&current_password) {
+            return Err(CommonError {
+                message: "invalid password".to_string(),
+                code: AUTH_TOKEN_ENCODING_CODE,
+            });
+        }
+
+        let password_changed = events::user_password_changed(user_id)?;
         self.repo
-            .update(user_id, name)
+            .update_password(
+                user_id,
+                hash_password(&new_password),
+                vec![password_changed],
+            )
             .await
             .map_err(|e| -> CommonError { e.into() })
     }
 
     async fn delete(&self, user_id: Uuid) -> Result<usize, CommonError> {
+        let user_deleted = events::user_deleted(user_id)?;
+
         self.repo
-            .delete(user_id)
+            .delete(user_id, vec![user_deleted])
+            .await
+            .map_err(|e| -> CommonError { e.into() })
+    }
+
+    async fn promote_admins(&self, emails: Vec<String>) -> Result<usize, CommonError> {
+        if emails.is_empty() {
+            return Ok(0);
+        }
+
+        self.repo
+            .promote_admins(emails)
             .await
             .map_err(|e| -> CommonError { e.into() })
     }
@@ -100,23 +175,19 @@ impl UserService for UserServiceImpl {
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        models::user::User, repositories::user_repository::MockUserRepository,
-        services::event_service::MockEventService,
-    };
+    use crate::{models::user::User, repositories::user_repository::MockUserRepository};
 
     use super::*;
     use mockall::predicate::eq;
     use rstest::*;
     use std::str::FromStr;
-    use utils::error::{BrokerError, DatabaseError};
+    use utils::error::DatabaseError;
     use uuid::Uuid;
 
     struct CreateUserTestCase {
         user: User,
         expected_result: Result<User, CommonError>,
         service_result: Result<User, DatabaseError>,
-        broker_result: Result<(), BrokerError>,
     }
 
     #[rstest]
@@ -126,20 +197,28 @@ mod tests {
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         },
         service_result: Ok(User {
             id:
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
         expected_result: Ok(User {
             id:
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
-        broker_result: Ok(()),
     })]
     #[case::error_db(CreateUserTestCase{
         user: User {
@@ -147,10 +226,12 @@ mod tests {
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         },
         service_result: Err(DatabaseError { message: "db is down".to_owned() }),
         expected_result: Err(CommonError { message: "db is down".to_owned(), code:1}),
-        broker_result: Ok(()),
     })]
     #[case::success(CreateUserTestCase{
         user: User {
@@ -158,61 +239,56 @@ mod tests {
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         },
         service_result: Ok(User {
             id:
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
         expected_result: Ok(User {
             id:
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
-        broker_result: Ok(()),
-    })]
-    #[case::error_broker(CreateUserTestCase{
-        user: User {
-            id:
-            Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
-            name: "John Doe".to_string(),
-            password: "1234".to_string(),
-        },
-        service_result: Ok(User {
-            id:
-            Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
-            name: "John Doe".to_string(),
-            password: "1234".to_string(),
-        }),
-        expected_result: Ok(User {
-            id:
-            Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
-            name: "John Doe".to_string(),
-            password: "1234".to_string(),
-        }),
-        broker_result: Err(BrokerError { message: "server is down".to_string() }),
     })]
     #[tokio::test]
     async fn test_create_user(#[case] case: CreateUserTestCase) {
-        // Create mocks for UserRepository and EventService
+        // Create mocks for UserRepository
         let mut repo_mock = MockUserRepository::new();
-        let mut event_mock = MockEventService::new();
-
+        let name = case.user.name.clone();
         repo_mock
             .expect_create()
+            .withf(move |user, events| {
+                user.name == name
+                    && user.email == Some("john@example.com".to_string())
+                    && user.email_verified_at.is_none()
+                    && events.len() == 1
+                    && events[0].topic == events::USER_CREATED_EVENT
+                    && events[0].key == user.id.to_string()
+            })
             .returning(move |_, _| case.service_result.clone());
-        event_mock
-            .expect_user_created()
-            .returning(move |_| case.broker_result.clone());
 
         // Create the UserServiceImpl with the mocks
-        let service = UserServiceImpl::new(Arc::new(repo_mock), Arc::new(event_mock));
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
 
         // Call the create method and check the result
         let result = service
-            .create(case.user.name.to_string(), case.user.password.to_string())
+            .create(
+                case.user.name.to_string(),
+                "john@example.com".to_string(),
+                case.user.password.to_string(),
+            )
             .await;
         assert_eq!(result, case.expected_result);
     }
@@ -229,11 +305,17 @@ mod tests {
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }]),
         service_result: Ok(vec![User {
             id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }]),
     })]
     #[case::error(ListUsersTestCase{
@@ -242,9 +324,8 @@ mod tests {
     })]
     #[tokio::test]
     async fn test_list_users(#[case] case: ListUsersTestCase) {
-        // Create mocks for UserRepository and EventService
+        // Create mocks for UserRepository
         let mut repo_mock = MockUserRepository::new();
-        let event_mock = MockEventService::new();
 
         // Set up expected behavior for the mocks
 
@@ -254,7 +335,7 @@ mod tests {
             .returning(move || service_result.clone());
 
         // Create the UserServiceImpl with the mocks
-        let service = UserServiceImpl::new(Arc::new(repo_mock), Arc::new(event_mock));
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
 
         // Call the create method and check the result
         let result = service.list().await;
@@ -275,11 +356,17 @@ mod tests {
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
         service_result: Ok(User {
             id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
     })]
     #[case::error(GetUserByIdTestCase{
@@ -289,9 +376,8 @@ mod tests {
     })]
     #[tokio::test]
     async fn test_get_user_by_id(#[case] case: GetUserByIdTestCase) {
-        // Create mocks for UserRepository and EventService
+        // Create mocks for UserRepository
         let mut repo_mock = MockUserRepository::new();
-        let event_mock = MockEventService::new();
 
         // Set up expected behavior for the mocks
 
@@ -302,7 +388,7 @@ mod tests {
             .returning(move |_| service_result.clone());
 
         // Create the UserServiceImpl with the mocks
-        let service = UserServiceImpl::new(Arc::new(repo_mock), Arc::new(event_mock));
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
 
         // Call the create method and check the result
         let result = service.get_by_id(case.id).await;
@@ -323,11 +409,17 @@ mod tests {
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
         service_result: Ok(User {
             id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
     })]
     #[case::error(GetUserByNameTestCase{
@@ -337,9 +429,8 @@ mod tests {
     })]
     #[tokio::test]
     async fn test_get_user_by_name(#[case] case: GetUserByNameTestCase) {
-        // Create mocks for UserRepository and EventService
+        // Create mocks for UserRepository
         let mut repo_mock = MockUserRepository::new();
-        let event_mock = MockEventService::new();
 
         // Set up expected behavior for the mocks
 
@@ -350,7 +441,7 @@ mod tests {
             .returning(move |_| service_result.clone());
 
         // Create the UserServiceImpl with the mocks
-        let service = UserServiceImpl::new(Arc::new(repo_mock), Arc::new(event_mock));
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
 
         // Call the create method and check the result
         let result = service.get_by_name(case.name).await;
@@ -373,11 +464,17 @@ mod tests {
             Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
         service_result: Ok(User {
             id: Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap(),
             name: "John Doe".to_string(),
             password: "1234".to_string(),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
         }),
     })]
     #[case::error(UpdateUserTestCase{
@@ -388,26 +485,173 @@ mod tests {
     })]
     #[tokio::test]
     async fn test_update_user(#[case] case: UpdateUserTestCase) {
-        // Create mocks for UserRepository and EventService
+        // Create mocks for UserRepository
         let mut repo_mock = MockUserRepository::new();
-        let event_mock = MockEventService::new();
 
         // Set up expected behavior for the mocks
 
+        repo_mock
+            .expect_get_by_id()
+            .with(eq(case.id))
+            .returning(|id| {
+                Ok(User {
+                    id,
+                    name: "John Doe".to_string(),
+                    password: "1234".to_string(),
+                    role: Role::User,
+                    email: None,
+                    email_verified_at: None,
+                })
+            });
         let service_result = case.service_result.clone();
+        let name = case.update.clone();
         repo_mock
             .expect_update()
-            .with(eq(case.id), eq(case.update.clone()))
-            .returning(move |_, _| service_result.clone());
+            .withf(move |id, update, events| {
+                *id == case.id
+                    && *update == name
+                    && events.len() == 1
+                    && events[0].topic == events::USER_UPDATED_EVENT
+                    && events[0].key == id.to_string()
+            })
+            .returning(move |_, _, _| service_result.clone());
 
         // Create the UserServiceImpl with the mocks
-        let service = UserServiceImpl::new(Arc::new(repo_mock), Arc::new(event_mock));
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
 
         // Call the create method and check the result
         let result = service.update(case.id, case.update).await;
         assert_eq!(result, case.expected_result);
     }
 
+    struct UpdateEmailTestCase {
+        current_email: Option<&'static str>,
+        expect_update: bool,
+        expected_error: Option<CommonError>,
+    }
+
+    #[rstest]
+    #[case::changed(UpdateEmailTestCase {
+        current_email: None,
+        expect_update: true,
+        expected_error: Some(CommonError { message: "db is down".to_owned(), code: 1 }),
+    })]
+    #[case::unchanged(UpdateEmailTestCase {
+        current_email: Some("john@example.com"),
+        expect_update: false,
+        expected_error: None,
+    })]
+    #[tokio::test]
+    async fn test_update_email(#[case] case: UpdateEmailTestCase) {
+        let id = Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap();
+        let mut repo_mock = MockUserRepository::new();
+        let current_email = case.current_email.map(str::to_string);
+        repo_mock
+            .expect_get_by_id()
+            .with(eq(id))
+            .returning(move |id| {
+                Ok(User {
+                    id,
+                    name: "John Doe".to_string(),
+                    password: "1234".to_string(),
+                    role: Role::User,
+                    email: current_email.clone(),
+                    email_verified_at: None,
+                })
+            });
+        repo_mock
+            .expect_update_email()
+            .withf(move |user_id, email, events| {
+                *user_id == id
+                    && email == "john@example.com"
+                    && events.len() == 1
+                    && events[0].topic == events::USER_UPDATED_EVENT
+            })
+            .times(case.expect_update as usize)
+            .returning(|_, _, _| {
+                Err(DatabaseError {
+                    message: "db is down".to_owned(),
+                })
+            });
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
+
+        let result = service
+            .update_email(id, "john@example.com".to_string())
+            .await;
+
+        assert_eq!(result.err(), case.expected_error);
+    }
+
+    struct ChangePasswordTestCase {
+        current_password: &'static str,
+        expect_update: bool,
+        expected_code: Option<u32>,
+    }
+
+    #[rstest]
+    #[case::changed(ChangePasswordTestCase {
+        current_password: "1234567",
+        expect_update: true,
+        expected_code: None,
+    })]
+    #[case::wrong_password(ChangePasswordTestCase {
+        current_password: "7654321",
+        expect_update: false,
+        expected_code: Some(AUTH_TOKEN_ENCODING_CODE),
+    })]
+    #[tokio::test]
+    async fn test_change_password(#[case] case: ChangePasswordTestCase) {
+        let id = Uuid::from_str("b73ccd26-1832-4d10-9251-271ce453cee3").unwrap();
+        let user = User {
+            id,
+            name: "John Doe".to_string(),
+            password: hash_password("1234567"),
+            role: Role::User,
+            email: None,
+            email_verified_at: None,
+        };
+        let mut repo_mock = MockUserRepository::new();
+        let current = user.clone();
+        repo_mock
+            .expect_get_by_id()
+            .with(eq(id))
+            .returning(move |_| Ok(current.clone()));
+        repo_mock
+            .expect_update_password()
+            .withf(move |user_id, password, events| {
+                let changed = User {
+                    password: password.clone(),
+                    ..user.clone()
+                };
+                *user_id == id
+                    && changed.verify_password("new password")
+                    && events.len() == 1
+                    && events[0].topic == events::USER_PASSWORD_CHANGED_EVENT
+            })
+            .times(case.expect_update as usize)
+            .returning(|user_id, password, _| {
+                Ok(User {
+                    id: user_id,
+                    name: "John Doe".to_string(),
+                    password,
+                    role: Role::User,
+                    email: None,
+                    email_verified_at: None,
+                })
+            });
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
+
+        let result = service
+            .change_password(
+                id,
+                case.current_password.to_string(),
+                "new password".to_string(),
+            )
+            .await;
+
+        assert_eq!(result.err().map(|err| err.code), case.expected_code);
+    }
+
     struct DeleteUserTestCase {
         id: uuid::Uuid,
         expected_result: Result<usize, CommonError>,
@@ -427,23 +671,47 @@ mod tests {
     })]
     #[tokio::test]
     async fn test_delete_user(#[case] case: DeleteUserTestCase) {
-        // Create mocks for UserRepository and EventService
+        // Create mocks for UserRepository
         let mut repo_mock = MockUserRepository::new();
-        let event_mock = MockEventService::new();
 
         // Set up expected behavior for the mocks
 
         let service_result = case.service_result.clone();
         repo_mock
             .expect_delete()
-            .with(eq(case.id))
-            .returning(move |_| service_result.clone());
+            .withf(move |id, events| {
+                *id == case.id
+                    && events.len() == 1
+                    && events[0].topic == events::USER_DELETED_EVENT
+                    && events[0].key == id.to_string()
+            })
+            .returning(move |_, _| service_result.clone());
 
         // Create the UserServiceImpl with the mocks
-        let service = UserServiceImpl::new(Arc::new(repo_mock), Arc::new(event_mock));
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
 
         // Call the create method and check the result
         let result = service.delete(case.id).await;
         assert_eq!(result, case.expected_result);
     }
+
+    #[tokio::test]
+    async fn test_promote_admins() {
+        let mut repo_mock = MockUserRepository::new();
+        repo_mock
+            .expect_promote_admins()
+            .with(eq(vec!["admin@example.com".to_string()]))
+            .times(1)
+            .returning(|_| Ok(1));
+        let service = UserServiceImpl::new(Arc::new(repo_mock));
+
+        assert_eq!(
+            service
+                .promote_admins(vec!["admin@example.com".to_string()])
+                .await,
+            Ok(1)
+        );
+        // the repository isn't called without admins
+        assert_eq!(service.promote_admins(vec![]).await, Ok(0));
+    }
 }
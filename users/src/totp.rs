@@ -0,0 +1,147 @@
+//! Time based one time passwords (RFC 6238) with the parameters every authenticator app
+//! supports: HMAC-SHA1, 6 digits and 30 seconds steps.
+
+use chrono::{DateTime, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::RngCore;
+use sha1::Sha1;
+
+const DIGITS: u32 = 6;
+const STEP_SECONDS: i64 = 30;
+/// 160 bits, the size of a SHA1 digest.
+const SECRET_BYTES: usize = 20;
+
+/// Random secret shared with the authenticator app, base32 encoded.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    BASE32_NOPAD.encode(&secret)
+}
+
+/// Step of the codes valid at the time.
+pub fn step(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(STEP_SECONDS)
+}
+
+/// Code of the step, none if the secret isn't valid base32.
+pub fn code(secret: &str, step: i64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    Some(hotp(&key, step as u64))
+}
+
+/// Step of the code when it is valid within `skew` steps of `now`, to allow for clocks
+/// drifting apart.
+pub fn verify(secret: &str, code: &str, now: DateTime<Utc>, skew: i64) -> Option<i64> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let current = step(now);
+
+    (current - skew..=current + skew).find(|step| {
+        let expected = hotp(&key, *step as u64);
+        // compared in constant time so timing doesn't reveal how many digits matched
+        expected.len() == code.len()
+            && expected
+                .bytes()
+                .zip(code.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    })
+}
+
+/// Uri of the secret the authenticator apps read from a QR code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = utf8_percent_encode(issuer, NON_ALPHANUMERIC);
+    let account = utf8_percent_encode(account, NON_ALPHANUMERIC);
+
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer, account, secret, issuer, DIGITS, STEP_SECONDS
+    )
+}
+
+/// HOTP (RFC 4226) of the counter.
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac takes keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Secret of the RFC 6238 test vectors, "12345678901234567890".
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_code_matches_rfc_vectors() {
+        // last 6 digits of the 8 digits codes of the RFC
+        let cases = vec![
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ];
+
+        for (time, expected) in cases {
+            let time = Utc.timestamp_opt(time, 0).unwrap();
+            assert_eq!(code(SECRET, step(time)).unwrap(), expected, "{}", time);
+        }
+    }
+
+    #[test]
+    fn test_verify_allows_skew() {
+        let now = Utc.timestamp_opt(1111111109, 0).unwrap();
+        let current = step(now);
+        let cases = vec![
+            (current, Some(current)),
+            (current - 1, Some(current - 1)),
+            (current + 1, Some(current + 1)),
+            (current - 2, None),
+            (current + 2, None),
+        ];
+
+        for (step, expected) in cases {
+            let code = code(SECRET, step).unwrap();
+            assert_eq!(verify(SECRET, &code, now, 1), expected, "step {}", step);
+        }
+        assert_eq!(verify(SECRET, "000000", now, 1), None);
+        assert_eq!(verify(SECRET, "", now, 1), None);
+        assert_eq!(verify("not base32!", "081804", now, 1), None);
+    }
+
+    #[test]
+    fn test_generate_secret() {
+        let secret = generate_secret();
+
+        assert_eq!(BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(), 20);
+        assert_ne!(secret, generate_secret());
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        assert_eq!(
+            otpauth_uri("rust xp", "alice", "ABC"),
+            "otpauth://totp/rust%20xp:alice?secret=ABC&issuer=rust%20xp&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,677 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::Actor;
+use actix_web::http::header::{self, HeaderName};
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde::Deserialize;
+use users::app::setup_app;
+use users::config::Config;
+use users::handlers::auth::{
+    ChangePasswordPayload, ForgotPasswordPayload, LoginPayload, MfaCodePayload, RefreshPayload,
+    ResetPasswordPayload, VerifyEmailPayload, VerifyMfaPayload,
+};
+use users::handlers::message::MessagePayload;
+use users::handlers::user::CreateUserPayload;
+use users::totp;
+use utils::http::websockets::ws_server::WebsocketServer;
+use utils::mail::memory::MemoryMailer;
+use utils::mail::Email;
+
+#[derive(Debug, Deserialize)]
+struct UserResponse {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokensResponse {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaEnrollmentResponse {
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaRequiredResponse {
+    pub status: String,
+    pub mfa_token: String,
+}
+
+/// Token of the last link mailed to the address.
+fn mailed_token(mailer: &MemoryMailer, to: &str) -> String {
+    link_token(&mailer.last_sent_to(to).unwrap())
+}
+
+/// Token of the nth password reset mailed to the address, they are sent in the background.
+async fn mailed_reset_token(mailer: &MemoryMailer, to: &str, nth: usize) -> String {
+    let resets = || -> Vec<Email> {
+        mailer
+            .sent()
+            .into_iter()
+            .filter(|email| email.to == to && email.subject == "Reset your password")
+            .collect()
+    };
+    actix_rt::time::timeout(Duration::from_secs(5), async {
+        while resets().len() < nth {
+            actix_rt::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("password reset not mailed");
+
+    link_token(&resets()[nth - 1])
+}
+
+fn link_token(email: &Email) -> String {
+    email
+        .body
+        .split("?token=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap()
+        .to_string()
+}
+
+#[actix_rt::test]
+async fn auth_refresh() {
+    let _database = crate::DATABASE.lock().await;
+    let config = Config::init();
+
+    let ws_server = WebsocketServer::new().start();
+
+    let app = setup_app(&config, ws_server.clone(), Arc::new(MemoryMailer::new()));
+
+    let app_server = test::init_service(app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users")
+        .set_json(CreateUserPayload {
+            name: Some("Bob".to_owned()),
+            email: Some("bob@example.com".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let user: UserResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    let req = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(LoginPayload {
+            name: Some("Bob".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let login: TokensResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    // the refresh token is not an access token
+    let req = test::TestRequest::get()
+        .uri("/auth/me")
+        .insert_header(("Authorization", format!("Bearer {}", login.refresh_token)))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let refresh = |refresh_token: &str| {
+        test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(RefreshPayload {
+                refresh_token: Some(refresh_token.to_owned()),
+            })
+            .to_request()
+    };
+
+    let resp = test::call_service(&app_server, refresh(&login.refresh_token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let refreshed: TokensResponse = test::read_body_json(resp).await;
+    assert_ne!(refreshed.refresh_token, login.refresh_token);
+
+    let req = test::TestRequest::get()
+        .uri("/auth/me")
+        .insert_header(("Authorization", format!("Bearer {}", refreshed.token)))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // reusing the first refresh token revokes the one rotated from it
+    let resp = test::call_service(&app_server, refresh(&login.refresh_token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app_server, refresh(&refreshed.refresh_token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::delete()
+        .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn auth_revoke() {
+    let _database = crate::DATABASE.lock().await;
+    let config = Config::init();
+
+    let ws_server = WebsocketServer::new().start();
+
+    let app = setup_app(&config, ws_server.clone(), Arc::new(MemoryMailer::new()));
+
+    let app_server = test::init_service(app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users")
+        .set_json(CreateUserPayload {
+            name: Some("Carol".to_owned()),
+            email: Some("carol@example.com".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let user: UserResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    let login = || {
+        test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(LoginPayload {
+                name: Some("Carol".to_owned()),
+                password: Some("1234567".to_owned()),
+            })
+            .to_request()
+    };
+    let me = |token: &str| {
+        test::TestRequest::get()
+            .uri("/auth/me")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+    let refresh = |refresh_token: &str| {
+        test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(RefreshPayload {
+                refresh_token: Some(refresh_token.to_owned()),
+            })
+            .to_request()
+    };
+
+    let first: TokensResponse = test::call_and_read_body_json(&app_server, login()).await;
+    let second: TokensResponse = test::call_and_read_body_json(&app_server, login()).await;
+
+    // revoking the current token leaves the other logins alone
+    let req = test::TestRequest::post()
+        .uri("/auth/revoke")
+        .insert_header(("Authorization", format!("Bearer {}", first.token)))
+        .set_json(RefreshPayload {
+            refresh_token: Some(first.refresh_token.clone()),
+        })
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app_server, me(&first.token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app_server, refresh(&first.refresh_token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app_server, me(&second.token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // revoking every token logs out the other sessions too
+    let req = test::TestRequest::post()
+        .uri("/auth/revoke-all")
+        .insert_header(("Authorization", format!("Bearer {}", second.token)))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app_server, me(&second.token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app_server, refresh(&second.refresh_token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::delete()
+        .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn auth_verify_email() {
+    let _database = crate::DATABASE.lock().await;
+    let config = Config::init();
+
+    let ws_server = WebsocketServer::new().start();
+    let mailer = Arc::new(MemoryMailer::new());
+
+    let app = setup_app(&config, ws_server.clone(), mailer.clone());
+
+    let app_server = test::init_service(app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users")
+        .set_json(CreateUserPayload {
+            name: Some("Dave".to_owned()),
+            email: Some("dave@example.com".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let user: UserResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    let login = || {
+        test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(LoginPayload {
+                name: Some("Dave".to_owned()),
+                password: Some("1234567".to_owned()),
+            })
+            .to_request()
+    };
+    let message = |token: &str| {
+        test::TestRequest::post()
+            .uri("/messages")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(MessagePayload {
+                message: Some("hello".to_owned()),
+                user_id: Some(user.id.clone()),
+            })
+            .to_request()
+    };
+    let verify = |token: &str| {
+        test::TestRequest::post()
+            .uri("/auth/verify-email")
+            .set_json(VerifyEmailPayload {
+                token: Some(token.to_owned()),
+            })
+            .to_request()
+    };
+
+    // unverified users can't send messages
+    let unverified: TokensResponse = test::call_and_read_body_json(&app_server, login()).await;
+    let resp = test::call_service(&app_server, message(&unverified.token)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = test::call_service(&app_server, verify("unknown")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let token = mailed_token(&mailer, "dave@example.com");
+
+    let resp = test::call_service(&app_server, verify(&token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    // the token can only be used once
+    let resp = test::call_service(&app_server, verify(&token)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let verified: TokensResponse = test::call_and_read_body_json(&app_server, login()).await;
+    let resp = test::call_service(&app_server, message(&verified.token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::delete()
+        .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn auth_password() {
+    let _database = crate::DATABASE.lock().await;
+    let mut config = Config::init();
+    // the failed logins below are expected, they shouldn't delay the next ones
+    config.login_delay = Duration::ZERO;
+
+    let ws_server = WebsocketServer::new().start();
+    let mailer = Arc::new(MemoryMailer::new());
+
+    let app = setup_app(&config, ws_server.clone(), mailer.clone());
+
+    let app_server = test::init_service(app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users")
+        .set_json(CreateUserPayload {
+            name: Some("Erin".to_owned()),
+            email: Some("erin@example.com".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let user: UserResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    // only verified emails can reset passwords
+    let req = test::TestRequest::post()
+        .uri("/auth/verify-email")
+        .set_json(VerifyEmailPayload {
+            token: Some(mailed_token(&mailer, "erin@example.com")),
+        })
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let login = |password: &str| {
+        test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(LoginPayload {
+                name: Some("Erin".to_owned()),
+                password: Some(password.to_owned()),
+            })
+            .to_request()
+    };
+    let me = |token: &str| {
+        test::TestRequest::get()
+            .uri("/auth/me")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+    let change = |token: &str, current_password: &str| {
+        test::TestRequest::put()
+            .uri("/auth/password")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(ChangePasswordPayload {
+                current_password: Some(current_password.to_owned()),
+                new_password: Some("7654321".to_owned()),
+            })
+            .to_request()
+    };
+    let forgot = |email: &str| {
+        test::TestRequest::post()
+            .uri("/auth/password/forgot")
+            .set_json(ForgotPasswordPayload {
+                email: Some(email.to_owned()),
+            })
+            .to_request()
+    };
+    let reset = |token: &str| {
+        test::TestRequest::post()
+            .uri("/auth/password/reset")
+            .set_json(ResetPasswordPayload {
+                token: Some(token.to_owned()),
+                password: Some("abcdefg".to_owned()),
+            })
+            .to_request()
+    };
+
+    let before: TokensResponse = test::call_and_read_body_json(&app_server, login("1234567")).await;
+
+    let resp = test::call_service(&app_server, change(&before.token, "wrong password")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app_server, change(&before.token, "1234567")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // the tokens issued before the change are revoked
+    let resp = test::call_service(&app_server, me(&before.token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::post()
+        .uri("/auth/refresh")
+        .set_json(RefreshPayload {
+            refresh_token: Some(before.refresh_token.clone()),
+        })
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app_server, login("1234567")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // tokens issued in the second of the revocation are revoked too
+    actix_rt::time::sleep(Duration::from_secs(1)).await;
+    let changed: TokensResponse =
+        test::call_and_read_body_json(&app_server, login("7654321")).await;
+    let resp = test::call_service(&app_server, me(&changed.token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // unknown emails look the same as registered ones
+    let resp = test::call_service(&app_server, forgot("nobody@example.com")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(mailer.last_sent_to("nobody@example.com").is_none());
+
+    let resp = test::call_service(&app_server, forgot("erin@example.com")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let first_token = mailed_reset_token(&mailer, "erin@example.com", 1).await;
+    let resp = test::call_service(&app_server, forgot("erin@example.com")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let token = mailed_reset_token(&mailer, "erin@example.com", 2).await;
+
+    let resp = test::call_service(&app_server, reset(&token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    // the token can only be used once
+    let resp = test::call_service(&app_server, reset(&token)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    // and the other tokens of the user can't be used anymore
+    let resp = test::call_service(&app_server, reset(&first_token)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app_server, me(&changed.token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app_server, login("7654321")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app_server, login("abcdefg")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::delete()
+        .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn auth_lockout() {
+    let _database = crate::DATABASE.lock().await;
+    let mut config = Config::init();
+    config.login_max_failures = 3;
+    config.login_delay = Duration::ZERO;
+
+    let ws_server = WebsocketServer::new().start();
+
+    let app = setup_app(&config, ws_server.clone(), Arc::new(MemoryMailer::new()));
+
+    let app_server = test::init_service(app).await;
+
+    let create = |name: &str| {
+        test::TestRequest::post()
+            .uri("/users")
+            .set_json(CreateUserPayload {
+                name: Some(name.to_owned()),
+                email: Some(format!("{}@example.com", name.to_lowercase())),
+                password: Some("1234567".to_owned()),
+            })
+            .to_request()
+    };
+    let login = |name: &str, password: &str| {
+        test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(LoginPayload {
+                name: Some(name.to_owned()),
+                password: Some(password.to_owned()),
+            })
+            .to_request()
+    };
+    let unlock = |user_id: &str, authorization: (HeaderName, String)| {
+        test::TestRequest::post()
+            .uri(format!("/users/{}/unlock", user_id).as_str())
+            .insert_header(authorization)
+            .to_request()
+    };
+
+    let user: UserResponse = test::call_and_read_body_json(&app_server, create("Frank")).await;
+    let other: UserResponse = test::call_and_read_body_json(&app_server, create("Grace")).await;
+
+    // unknown users and wrong passwords fail the same way
+    let unknown_name = format!("nobody-{}", uuid::Uuid::new_v4());
+    let resp = test::call_service(&app_server, login(&unknown_name, "1234567")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let unknown = test::read_body(resp).await;
+    let resp = test::call_service(&app_server, login("Frank", "wrong password")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(test::read_body(resp).await, unknown);
+
+    for _ in 0..2 {
+        let resp = test::call_service(&app_server, login("Frank", "wrong password")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // the account is locked, even for the right password
+    let resp = test::call_service(&app_server, login("Frank", "1234567")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = resp
+        .headers()
+        .get(header::RETRY_AFTER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0);
+    // other accounts can still log in
+    let grace: TokensResponse =
+        test::call_and_read_body_json(&app_server, login("Grace", "1234567")).await;
+
+    // only admins unlock accounts
+    let authorization = (header::AUTHORIZATION, format!("Bearer {}", grace.token));
+    let resp = test::call_service(&app_server, unlock(&user.id, authorization)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = test::call_service(
+        &app_server,
+        unlock(&user.id, crate::admin_authorization(&config)),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app_server, login("Frank", "1234567")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    for user_id in [&user.id, &other.id] {
+        let req = test::TestRequest::delete()
+            .uri(format!("/users/{}", user_id).as_str())
+            .insert_header(crate::admin_authorization(&config))
+            .to_request();
+        let resp = test::call_service(&app_server, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
+
+#[actix_rt::test]
+async fn auth_mfa() {
+    let _database = crate::DATABASE.lock().await;
+    let mut config = Config::init();
+    config.login_delay = Duration::ZERO;
+
+    let ws_server = WebsocketServer::new().start();
+
+    let app = setup_app(&config, ws_server.clone(), Arc::new(MemoryMailer::new()));
+
+    let app_server = test::init_service(app).await;
+
+    let login = || {
+        test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(LoginPayload {
+                name: Some("Heidi".to_owned()),
+                password: Some("1234567".to_owned()),
+            })
+            .to_request()
+    };
+    let verify = |mfa_token: &str, code: &str| {
+        test::TestRequest::post()
+            .uri("/auth/mfa/verify")
+            .set_json(VerifyMfaPayload {
+                mfa_token: Some(mfa_token.to_owned()),
+                code: Some(code.to_owned()),
+            })
+            .to_request()
+    };
+    let code = |secret: &str, steps: i64| {
+        totp::code(secret, totp::step(chrono::Utc::now()) + steps).unwrap()
+    };
+
+    let req = test::TestRequest::post()
+        .uri("/users")
+        .set_json(CreateUserPayload {
+            name: Some("Heidi".to_owned()),
+            email: Some("heidi@example.com".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let user: UserResponse = test::call_and_read_body_json(&app_server, req).await;
+    let tokens: TokensResponse = test::call_and_read_body_json(&app_server, login()).await;
+    let authorization = (header::AUTHORIZATION, format!("Bearer {}", tokens.token));
+
+    let req = test::TestRequest::post()
+        .uri("/auth/mfa/enroll")
+        .insert_header(authorization.clone())
+        .to_request();
+    let enrollment: MfaEnrollmentResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    // logging in doesn't need a code until the enrollment is confirmed
+    let resp = test::call_service(&app_server, login()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let confirm = |code: String| {
+        test::TestRequest::post()
+            .uri("/auth/mfa/confirm")
+            .insert_header(authorization.clone())
+            .set_json(MfaCodePayload { code: Some(code) })
+            .to_request()
+    };
+    let resp = test::call_service(&app_server, confirm(code(&enrollment.secret, -5))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let confirmed_code = code(&enrollment.secret, 0);
+    let recovery: RecoveryCodesResponse =
+        test::call_and_read_body_json(&app_server, confirm(confirmed_code.clone())).await;
+    assert_eq!(recovery.recovery_codes.len(), 10);
+
+    let req = test::TestRequest::post()
+        .uri("/auth/mfa/enroll")
+        .insert_header(authorization.clone())
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let pending: MfaRequiredResponse = test::call_and_read_body_json(&app_server, login()).await;
+    assert_eq!(pending.status, "mfa_required");
+
+    // the password alone doesn't authenticate requests
+    let req = test::TestRequest::get()
+        .uri("/auth/me")
+        .insert_header(("Authorization", format!("Bearer {}", pending.mfa_token)))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&app_server, verify(&tokens.token, &confirmed_code)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    // codes can't be replayed
+    let resp = test::call_service(&app_server, verify(&pending.mfa_token, &confirmed_code)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let next_code = code(&enrollment.secret, 1);
+    let verified: TokensResponse =
+        test::call_and_read_body_json(&app_server, verify(&pending.mfa_token, &next_code)).await;
+    let req = test::TestRequest::get()
+        .uri("/auth/me")
+        .insert_header(("Authorization", format!("Bearer {}", verified.token)))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // recovery codes work once
+    let pending: MfaRequiredResponse = test::call_and_read_body_json(&app_server, login()).await;
+    let recovery_code = &recovery.recovery_codes[0];
+    let resp = test::call_service(&app_server, verify(&pending.mfa_token, recovery_code)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app_server, verify(&pending.mfa_token, recovery_code)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::delete()
+        .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
@@ -1 +1,5 @@
+mod auth_test;
+mod oidc_test;
+mod personal_token_test;
+mod preferences_test;
 mod user_test;
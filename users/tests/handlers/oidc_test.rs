@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use actix::Actor;
+use actix_web::cookie::Cookie;
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use mockito::{Server, ServerGuard};
+use serde::Deserialize;
+use serde_json::json;
+use users::app::setup_app;
+use users::config::Config;
+use users::oidc::code_challenge;
+use utils::http::websockets::ws_server::WebsocketServer;
+use utils::mail::memory::MemoryMailer;
+
+const CLIENT_ID: &str = "users";
+const SIGNING_KEY: &[u8] = include_bytes!("../fixtures/oidc_key.pem");
+const JWKS: &str = include_str!("../fixtures/oidc_jwks.json");
+
+#[derive(Debug, Deserialize)]
+struct TokensResponse {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MeResponse {
+    pub id: String,
+    pub name: String,
+    pub email: Option<String>,
+    pub email_verified_at: Option<String>,
+}
+
+/// What the mock provider puts in the next ID token, and the challenge the code has to
+/// be sent with.
+#[derive(Debug, Clone, Default)]
+struct NextLogin {
+    code_challenge: String,
+    nonce: String,
+    subject: String,
+    email: String,
+}
+
+/// Identity provider serving the discovery document, its keys and a token endpoint that
+/// checks the PKCE verifier like a real one.
+async fn mock_provider(next_login: Arc<Mutex<NextLogin>>) -> ServerGuard {
+    let mut server = Server::new_async().await;
+    let issuer = server.url();
+
+    server
+        .mock("GET", "/.well-known/openid-configuration")
+        .with_body(
+            json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "jwks_uri": format!("{}/jwks", issuer),
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/jwks")
+        .with_body(JWKS)
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/token")
+        .with_body_from_request(move |request| {
+            let body = String::from_utf8(request.body().unwrap().clone()).unwrap();
+            let form: HashMap<&str, &str> = body
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .collect();
+            let login = next_login.lock().unwrap().clone();
+            if form
+                .get("code_verifier")
+                .map(|verifier| code_challenge(verifier))
+                != Some(login.code_challenge)
+            {
+                return json!({"error": "invalid_grant"}).to_string().into();
+            }
+
+            let now = chrono::Utc::now().timestamp();
+            let claims = json!({
+                "iss": issuer,
+                "sub": login.subject,
+                "aud": CLIENT_ID,
+                "iat": now,
+                "exp": now + 300,
+                "nonce": login.nonce,
+                "email": login.email,
+                "email_verified": true,
+                "preferred_username": "ivan",
+            });
+            let mut header = Header::new(Algorithm::RS256);
+            header.kid = Some("test-key".to_string());
+            let id_token = encode(
+                &header,
+                &claims,
+                &EncodingKey::from_rsa_pem(SIGNING_KEY).unwrap(),
+            )
+            .unwrap();
+
+            json!({"access_token": "access", "token_type": "Bearer", "id_token": id_token})
+                .to_string()
+                .into()
+        })
+        .create_async()
+        .await;
+
+    server
+}
+
+/// Query parameter of the url.
+fn query_param(url: &str, name: &str) -> String {
+    url.split_once('?')
+        .unwrap()
+        .1
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+        .unwrap()
+}
+
+#[actix_rt::test]
+async fn auth_oidc() {
+    let _database = crate::DATABASE.lock().await;
+    let next_login = Arc::new(Mutex::new(NextLogin {
+        subject: uuid::Uuid::new_v4().to_string(),
+        email: format!("ivan-{}@example.com", uuid::Uuid::new_v4()),
+        ..Default::default()
+    }));
+    let provider = mock_provider(next_login.clone()).await;
+    let mut config = Config::init();
+    config.oidc_issuer = provider.url();
+    config.oidc_client_id = CLIENT_ID.to_string();
+
+    let ws_server = WebsocketServer::new().start();
+
+    let app = setup_app(&config, ws_server.clone(), Arc::new(MemoryMailer::new()));
+
+    let app_server = test::init_service(app).await;
+
+    // starts a login at the provider, returning the state and its cookie
+    let start = |nonce: Option<&str>| {
+        let app_server = &app_server;
+        let next_login = next_login.clone();
+        let nonce = nonce.map(str::to_string);
+        async move {
+            let req = test::TestRequest::get()
+                .uri("/auth/oidc/login")
+                .to_request();
+            let resp = test::call_service(app_server, req).await;
+            assert_eq!(resp.status(), StatusCode::FOUND);
+            let location = resp
+                .headers()
+                .get(header::LOCATION)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            let cookie = resp
+                .response()
+                .cookies()
+                .find(|cookie| cookie.name() == "oidc_state")
+                .unwrap()
+                .into_owned();
+
+            let mut login = next_login.lock().unwrap();
+            login.code_challenge = query_param(&location, "code_challenge");
+            login.nonce = nonce.unwrap_or_else(|| query_param(&location, "nonce"));
+
+            (query_param(&location, "state"), cookie)
+        }
+    };
+    let callback = |state: &str, cookie: Option<Cookie<'static>>| {
+        let req =
+            test::TestRequest::get().uri(&format!("/auth/oidc/callback?code=code&state={}", state));
+        match cookie {
+            Some(cookie) => req.cookie(cookie).to_request(),
+            None => req.to_request(),
+        }
+    };
+    let me = |token: &str| {
+        test::TestRequest::get()
+            .uri("/auth/me")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request()
+    };
+
+    // the first login provisions the user, with the email verified by the provider
+    let (state, cookie) = start(None).await;
+    let tokens: TokensResponse =
+        test::call_and_read_body_json(&app_server, callback(&state, Some(cookie.clone()))).await;
+    let user: MeResponse = test::call_and_read_body_json(&app_server, me(&tokens.token)).await;
+    assert_eq!(user.name, "ivan");
+    assert_eq!(user.email, Some(next_login.lock().unwrap().email.clone()));
+    assert!(user.email_verified_at.is_some());
+
+    // the state completes a single login
+    let resp = test::call_service(&app_server, callback(&state, Some(cookie))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // logins started in another browser are rejected
+    let (state, _) = start(None).await;
+    let resp = test::call_service(&app_server, callback(&state, None)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // ID tokens of other logins are rejected
+    let (state, cookie) = start(Some("other nonce")).await;
+    let resp = test::call_service(&app_server, callback(&state, Some(cookie))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // failed logins at the provider
+    let req = test::TestRequest::get()
+        .uri("/auth/oidc/callback?error=access_denied&state=state")
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // the next logins find the linked user
+    let (state, cookie) = start(None).await;
+    let tokens: TokensResponse =
+        test::call_and_read_body_json(&app_server, callback(&state, Some(cookie))).await;
+    let again: MeResponse = test::call_and_read_body_json(&app_server, me(&tokens.token)).await;
+    assert_eq!(again.id, user.id);
+
+    let req = test::TestRequest::delete()
+        .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::Actor;
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde::Deserialize;
+use users::app::setup_app;
+use users::config::Config;
+use users::handlers::auth::LoginPayload;
+use users::handlers::personal_token::CreatePersonalTokenPayload;
+use users::handlers::user::CreateUserPayload;
+use utils::http::middlewares::jwt_auth::Scope;
+use utils::http::websockets::ws_server::WebsocketServer;
+use utils::mail::memory::MemoryMailer;
+
+#[derive(Debug, Deserialize)]
+struct UserResponse {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokensResponse {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PersonalTokenResponse {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatedPersonalTokenResponse {
+    pub token: String,
+    pub details: PersonalTokenResponse,
+}
+
+#[actix_rt::test]
+async fn personal_tokens() {
+    let _database = crate::DATABASE.lock().await;
+    let mut config = Config::init();
+    // revocations are noticed right away
+    config.revocation_cache_ttl = Duration::ZERO;
+
+    let ws_server = WebsocketServer::new().start();
+
+    let app = setup_app(&config, ws_server.clone(), Arc::new(MemoryMailer::new()));
+
+    let app_server = test::init_service(app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users")
+        .set_json(CreateUserPayload {
+            name: Some("Pat".to_owned()),
+            email: Some("pat@example.com".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let user: UserResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    let req = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(LoginPayload {
+            name: Some("Pat".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let login: TokensResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    let bearer = |token: &str| (header::AUTHORIZATION, format!("Bearer {}", token));
+    let create = |token: &str, payload: CreatePersonalTokenPayload| {
+        test::TestRequest::post()
+            .uri("/users/me/tokens")
+            .insert_header(bearer(token))
+            .set_json(payload)
+            .to_request()
+    };
+    let me = |token: &str| {
+        test::TestRequest::get()
+            .uri("/auth/me")
+            .insert_header(bearer(token))
+            .to_request()
+    };
+
+    let resp = test::call_service(
+        &app_server,
+        create(
+            &login.token,
+            CreatePersonalTokenPayload {
+                name: Some("backup".to_owned()),
+                scopes: vec![],
+                expires_in: None,
+            },
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let unrestricted: CreatedPersonalTokenResponse = test::read_body_json(resp).await;
+    assert_eq!(unrestricted.details.name, "backup");
+    assert!(unrestricted.details.expires_at.is_none());
+
+    let resp = test::call_service(
+        &app_server,
+        create(
+            &login.token,
+            CreatePersonalTokenPayload {
+                name: Some("reader".to_owned()),
+                scopes: vec![Scope::NewsRead],
+                expires_in: Some(30),
+            },
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let reader: CreatedPersonalTokenResponse = test::read_body_json(resp).await;
+    assert_eq!(reader.details.scopes, vec!["news:read"]);
+    assert!(reader.details.expires_at.is_some());
+
+    // tokens without scopes do whatever the user can, the others only what they allow
+    let resp = test::call_service(&app_server, me(&unrestricted.token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app_server, me(&reader.token)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // personal tokens don't manage the account, its credentials nor personal tokens
+    let resp = test::call_service(
+        &app_server,
+        create(
+            &unrestricted.token,
+            CreatePersonalTokenPayload {
+                name: Some("another".to_owned()),
+                scopes: vec![],
+                expires_in: None,
+            },
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let account_requests = vec![
+        test::TestRequest::post().uri("/auth/mfa/enroll"),
+        test::TestRequest::post().uri("/auth/mfa/confirm"),
+        test::TestRequest::post().uri("/auth/revoke-all"),
+        test::TestRequest::put().uri("/auth/password"),
+        test::TestRequest::put().uri(&format!("/users/{}", user.id)),
+        test::TestRequest::delete().uri(&format!("/users/{}", user.id)),
+    ];
+    for req in account_requests {
+        let req = req.insert_header(bearer(&unrestricted.token)).to_request();
+        let resp = test::call_service(&app_server, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    let resp = test::call_service(
+        &app_server,
+        create(
+            &login.token,
+            CreatePersonalTokenPayload {
+                name: None,
+                scopes: vec![],
+                expires_in: Some(0),
+            },
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // the tokens themselves are never listed
+    let req = test::TestRequest::get()
+        .uri("/users/me/tokens")
+        .insert_header(bearer(&login.token))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = test::read_body(resp).await;
+    assert!(!String::from_utf8_lossy(&body).contains(&unrestricted.token));
+    let tokens: Vec<PersonalTokenResponse> = serde_json::from_slice(&body).unwrap();
+    let mut names: Vec<String> = tokens.into_iter().map(|token| token.name).collect();
+    names.sort();
+    assert_eq!(names, vec!["backup", "reader"]);
+
+    let revoke = |id: &str| {
+        test::TestRequest::delete()
+            .uri(&format!("/users/me/tokens/{}", id))
+            .insert_header(bearer(&login.token))
+            .to_request()
+    };
+    let resp = test::call_service(&app_server, revoke(&unrestricted.details.id)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app_server, revoke(&unrestricted.details.id)).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp = test::call_service(&app_server, me(&unrestricted.token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::delete()
+        .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use actix::Actor;
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde::Deserialize;
+use users::app::setup_app;
+use users::config::Config;
+use users::handlers::auth::LoginPayload;
+use users::handlers::preferences::UpdatePreferencesPayload;
+use users::handlers::user::CreateUserPayload;
+use users::models::preferences::Notifications;
+use utils::http::websockets::ws_server::WebsocketServer;
+use utils::mail::memory::MemoryMailer;
+
+#[derive(Debug, Deserialize)]
+struct UserResponse {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokensResponse {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PreferencesResponse {
+    pub user_id: String,
+    pub display_name: Option<String>,
+    pub timezone: String,
+    pub locale: String,
+    pub notifications: Notifications,
+    pub digest_hour: i16,
+}
+
+#[actix_rt::test]
+async fn user_preferences() {
+    let _database = crate::DATABASE.lock().await;
+    let config = Config::init();
+
+    let ws_server = WebsocketServer::new().start();
+
+    let app = setup_app(&config, ws_server.clone(), Arc::new(MemoryMailer::new()));
+
+    let app_server = test::init_service(app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users")
+        .set_json(CreateUserPayload {
+            name: Some("Prefs".to_owned()),
+            email: Some("prefs@example.com".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let user: UserResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    let req = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(LoginPayload {
+            name: Some("Prefs".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let login: TokensResponse = test::call_and_read_body_json(&app_server, req).await;
+
+    let authorization = (header::AUTHORIZATION, format!("Bearer {}", login.token));
+    let get = || {
+        test::TestRequest::get()
+            .uri("/users/me/preferences")
+            .insert_header(authorization.clone())
+            .to_request()
+    };
+    let update = |payload: UpdatePreferencesPayload| {
+        test::TestRequest::patch()
+            .uri("/users/me/preferences")
+            .insert_header(authorization.clone())
+            .set_json(payload)
+            .to_request()
+    };
+
+    // the defaults until the user changes anything
+    let preferences: PreferencesResponse = test::call_and_read_body_json(&app_server, get()).await;
+    assert_eq!(preferences.user_id, user.id);
+    assert_eq!(preferences.timezone, "UTC");
+    assert_eq!(preferences.notifications, Notifications::Realtime);
+
+    let invalid = vec![
+        UpdatePreferencesPayload {
+            timezone: Some("Mars/Olympus".to_owned()),
+            ..Default::default()
+        },
+        UpdatePreferencesPayload {
+            locale: Some("english".to_owned()),
+            ..Default::default()
+        },
+        UpdatePreferencesPayload {
+            digest_hour: Some(24),
+            ..Default::default()
+        },
+    ];
+    for payload in invalid {
+        let resp = test::call_service(&app_server, update(payload)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    let resp = test::call_service(
+        &app_server,
+        update(UpdatePreferencesPayload {
+            display_name: Some("Prefs Person".to_owned()),
+            timezone: Some("Europe/Paris".to_owned()),
+            notifications: Some(Notifications::Digest),
+            digest_hour: Some(18),
+            ..Default::default()
+        }),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // the other preferences are kept
+    let resp = test::call_service(
+        &app_server,
+        update(UpdatePreferencesPayload {
+            locale: Some("fr-FR".to_owned()),
+            ..Default::default()
+        }),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let preferences: PreferencesResponse = test::call_and_read_body_json(&app_server, get()).await;
+    assert_eq!(preferences.display_name.as_deref(), Some("Prefs Person"));
+    assert_eq!(preferences.timezone, "Europe/Paris");
+    assert_eq!(preferences.locale, "fr-FR");
+    assert_eq!(preferences.notifications, Notifications::Digest);
+    assert_eq!(preferences.digest_hour, 18);
+
+    let req = test::TestRequest::delete()
+        .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
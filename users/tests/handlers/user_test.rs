@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use actix::Actor;
 use actix_web::http::StatusCode;
 use actix_web::test;
@@ -5,8 +7,10 @@ use serde::Deserialize;
 use serde_json::from_slice;
 use users::app::setup_app;
 use users::config::Config;
+use users::handlers::auth::LoginPayload;
 use users::handlers::user::CreateUserPayload;
 use utils::http::websockets::ws_server::WebsocketServer;
+use utils::mail::memory::MemoryMailer;
 
 #[derive(Debug, Deserialize, PartialEq, Clone)]
 struct UserResponse {
@@ -16,13 +20,19 @@ struct UserResponse {
 
 type UsersListResponse = Vec<UserResponse>;
 
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    pub token: String,
+}
+
 #[actix_rt::test]
 async fn user_create() {
+    let _database = crate::DATABASE.lock().await;
     let config = Config::init();
 
     let ws_server = WebsocketServer::new().start();
 
-    let app = setup_app(&config, ws_server.clone());
+    let app = setup_app(&config, ws_server.clone(), Arc::new(MemoryMailer::new()));
 
     let app_server = test::init_service(app).await;
 
@@ -32,6 +42,7 @@ async fn user_create() {
         .set_payload(
             serde_json::to_vec(&CreateUserPayload {
                 name: Some("Alice".to_owned()),
+                email: Some("alice@example.com".to_owned()),
                 password: Some("1234567".to_owned()),
             })
             .unwrap(),
@@ -56,6 +67,7 @@ async fn user_create() {
         .set_payload(
             serde_json::to_vec(&CreateUserPayload {
                 name: Some("Alice".to_owned()),
+                email: Some("alice@example.com".to_owned()),
                 password: Some("1234567".to_owned()),
             })
             .unwrap(),
@@ -68,7 +80,10 @@ async fn user_create() {
     assert_eq!(body, "");
 
     // get list of users
-    let req = test::TestRequest::get().uri("/users").to_request();
+    let req = test::TestRequest::get()
+        .uri("/users")
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
     let resp = test::call_service(&app_server, req).await;
 
     assert_eq!(resp.status(), StatusCode::OK);
@@ -76,9 +91,26 @@ async fn user_create() {
     let result: UsersListResponse = from_slice(&body).unwrap();
     assert_eq!(result, vec![user.clone()]);
 
+    // users can't list every user
+    let req = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(LoginPayload {
+            name: Some("Alice".to_owned()),
+            password: Some("1234567".to_owned()),
+        })
+        .to_request();
+    let login: TokenResponse = test::call_and_read_body_json(&app_server, req).await;
+    let req = test::TestRequest::get()
+        .uri("/users")
+        .insert_header(("Authorization", format!("Bearer {}", login.token)))
+        .to_request();
+    let resp = test::call_service(&app_server, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
     // delete user
     let req = test::TestRequest::delete()
         .uri(format!("/users/{}", user.id).as_str())
+        .insert_header(crate::admin_authorization(&config))
         .to_request();
     let resp = test::call_service(&app_server, req).await;
 
@@ -87,7 +119,10 @@ async fn user_create() {
     assert_eq!(body, "");
 
     // get empty list of users
-    let req = test::TestRequest::get().uri("/users").to_request();
+    let req = test::TestRequest::get()
+        .uri("/users")
+        .insert_header(crate::admin_authorization(&config))
+        .to_request();
     let resp = test::call_service(&app_server, req).await;
 
     assert_eq!(resp.status(), StatusCode::OK);
@@ -1 +1,3 @@
+mod outbox_repository_test;
+mod refresh_token_repository_test;
 mod user_repository_test;
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use users::config::Config;
+use utils::db;
+use utils::outbox::{
+    models::NewOutboxMessage,
+    repository::{OutboxDieselRepository, OutboxRepository},
+    schema::outbox,
+};
+use uuid::Uuid;
+
+fn new_message(topic: &str, payload: &str) -> NewOutboxMessage {
+    NewOutboxMessage {
+        topic: topic.to_string(),
+        key: "feed".to_string(),
+        payload: payload.to_string(),
+        headers: "{}".to_string(),
+    }
+}
+
+#[actix_rt::test]
+async fn concurrent_claims_keep_the_order_of_a_key() {
+    let _database = crate::DATABASE.lock().await;
+    let config = Config::init();
+    let pool = Arc::new(db::connect_db(config.database_url.clone()));
+    let repo = OutboxDieselRepository::new(pool.clone());
+
+    let mut conn = pool.get().unwrap();
+    // the messages of the other tests would be claimed first
+    conn.batch_execute("update outbox set sent_at = now() where sent_at is null;")
+        .unwrap();
+
+    for _ in 0..20 {
+        let topic = Uuid::new_v4().to_string();
+        let ids: Vec<i64> = diesel::insert_into(outbox::table)
+            .values(vec![
+                new_message(&topic, "first"),
+                new_message(&topic, "second"),
+            ])
+            .returning(outbox::id)
+            .get_results(&mut conn)
+            .unwrap();
+
+        // each claim takes a single message, the second one must wait for the first
+        let lock_for = Duration::from_secs(30);
+        let (first_claim, second_claim) =
+            tokio::join!(repo.claim(1, lock_for), repo.claim(1, lock_for));
+        // messages the other tests add meanwhile may be claimed too
+        let claimed: Vec<i64> = first_claim
+            .unwrap()
+            .into_iter()
+            .chain(second_claim.unwrap())
+            .filter(|message| message.topic == topic)
+            .map(|message| message.id)
+            .collect();
+
+        assert!(
+            !claimed.contains(&ids[1]),
+            "the second message was claimed with the first one locked"
+        );
+        repo.mark_sent(&ids).await.unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use users::{
+    config::Config,
+    models::{refresh_token::RefreshToken, user::User},
+    repositories::{
+        refresh_token_repository::{RefreshTokenDieselRepository, RefreshTokenRepository},
+        user_repository::{UserDieselRepository, UserRepository},
+    },
+};
+use utils::db;
+use utils::http::middlewares::jwt_auth::Role;
+use uuid::Uuid;
+
+fn refresh_token(user_id: Uuid, family_id: Uuid, token_hash: &str) -> RefreshToken {
+    RefreshToken {
+        id: Uuid::new_v4(),
+        user_id,
+        family_id,
+        token_hash: token_hash.to_string(),
+        expires_at: Utc::now() + Duration::minutes(10),
+        created_at: Utc::now(),
+        used_at: None,
+        revoked_at: None,
+    }
+}
+
+#[actix_rt::test]
+async fn refresh_token_repo() {
+    let _database = crate::DATABASE.lock().await;
+    let config = Config::init();
+    let db_connection = Arc::new(db::connect_db(config.database_url.clone()));
+
+    let user_repo = UserDieselRepository::new(db_connection.clone());
+    let user = user_repo
+        .create(
+            User {
+                id: Uuid::new_v4(),
+                name: Uuid::new_v4().to_string(),
+                password: String::from("123456"),
+                role: Role::User,
+                email: None,
+                email_verified_at: None,
+            },
+            vec![],
+        )
+        .await
+        .unwrap();
+
+    let repo = RefreshTokenDieselRepository::new(db_connection);
+    let family_id = Uuid::new_v4();
+    let first = refresh_token(user.id, family_id, &Uuid::new_v4().to_string());
+
+    let result = repo.create(first.clone()).await;
+    assert!(result.is_ok());
+
+    let result = repo.find_by_hash(first.token_hash.clone()).await;
+    assert_eq!(result.unwrap().map(|token| token.id), Some(first.id));
+    let result = repo.find_by_hash(String::from("unknown")).await;
+    assert_eq!(result.unwrap(), None);
+
+    let second = refresh_token(user.id, family_id, &Uuid::new_v4().to_string());
+    let result = repo.rotate(first.id, second.clone()).await;
+    assert!(result.unwrap());
+    let used = repo.find_by_hash(first.token_hash.clone()).await.unwrap();
+    assert!(used.unwrap().used_at.is_some());
+
+    // a used token can't be rotated again
+    let third = refresh_token(user.id, family_id, &Uuid::new_v4().to_string());
+    let result = repo.rotate(first.id, third.clone()).await;
+    assert!(!result.unwrap());
+    let result = repo.find_by_hash(third.token_hash.clone()).await;
+    assert_eq!(result.unwrap(), None);
+
+    let result = repo.revoke_family(family_id).await;
+    assert_eq!(result.unwrap(), 2);
+    let revoked = repo.find_by_hash(second.token_hash.clone()).await.unwrap();
+    assert!(revoked.unwrap().revoked_at.is_some());
+
+    user_repo.delete(user.id, vec![]).await.unwrap();
+}
@@ -3,13 +3,16 @@ use std::sync::Arc;
 use diesel::connection::SimpleConnection;
 use users::{
     config::Config,
+    models::user::User,
     repositories::user_repository::{UserDieselRepository, UserRepository},
 };
 use utils::db;
+use utils::http::middlewares::jwt_auth::Role;
 use uuid::Uuid;
 
 #[actix_rt::test]
 async fn user_repo() {
+    let _database = crate::DATABASE.lock().await;
     let config = Config::init();
     let db_connection = db::connect_db(config.database_url.clone());
 
@@ -21,9 +24,15 @@ async fn user_repo() {
 
     let user_repo = UserDieselRepository::new(Arc::new(db_connection));
 
-    let result = user_repo
-        .create(String::from("John"), String::from("123456"))
-        .await;
+    let user = User {
+        id: Uuid::new_v4(),
+        name: String::from("John"),
+        password: String::from("123456"),
+        role: Role::User,
+        email: None,
+        email_verified_at: None,
+    };
+    let result = user_repo.create(user, vec![]).await;
     assert!(result.is_ok());
     let inserted_user = result.unwrap();
     assert_eq!(inserted_user.name, String::from("John"));
@@ -54,7 +63,7 @@ async fn user_repo() {
         assert_eq!(user, inserted_user.clone());
     }
 
-    let result = user_repo.delete(inserted_user.id).await;
+    let result = user_repo.delete(inserted_user.id, vec![]).await;
     assert!(result.is_ok());
     if let Ok(rows_affected) = result {
         assert_eq!(rows_affected, 1);
@@ -66,3 +75,50 @@ async fn user_repo() {
         assert_eq!(users, vec![])
     }
 }
+
+#[actix_rt::test]
+async fn promote_admins() {
+    let _database = crate::DATABASE.lock().await;
+    let config = Config::init();
+    let db_connection = db::connect_db(config.database_url.clone());
+
+    let pool = db_connection.get();
+    if let Ok(mut pool) = pool {
+        let result = pool.batch_execute("delete from users;");
+        assert!(result.is_ok());
+    }
+
+    let user_repo = UserDieselRepository::new(Arc::new(db_connection));
+
+    let user = |name: &str, verified: bool| User {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        password: String::from("123456"),
+        role: Role::User,
+        email: Some(format!("{}@example.com", name)),
+        email_verified_at: verified.then(chrono::Utc::now),
+    };
+    let verified = user_repo.create(user("alice", true), vec![]).await.unwrap();
+    let unverified = user_repo.create(user("bob", false), vec![]).await.unwrap();
+    let other = user_repo.create(user("carol", true), vec![]).await.unwrap();
+
+    let emails = vec![
+        "alice@example.com".to_string(),
+        "bob@example.com".to_string(),
+    ];
+    let result = user_repo.promote_admins(emails.clone()).await;
+    assert_eq!(result.unwrap(), 1);
+
+    for (user, role) in [
+        (verified, Role::Admin),
+        (unverified, Role::User),
+        (other, Role::User),
+    ] {
+        let stored = user_repo.get_by_id(user.id).await.unwrap();
+        assert_eq!(stored.role, role, "{}", user.name);
+    }
+
+    // admins already are left alone
+    let result = user_repo.promote_admins(emails).await;
+    assert_eq!(result.unwrap(), 0);
+}
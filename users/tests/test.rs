@@ -1,2 +1,23 @@
 mod handlers;
 mod repositories;
+
+use actix_web::http::header::{self, HeaderName};
+use tokio::sync::Mutex;
+use users::config::Config;
+use utils::http::middlewares::jwt_auth::{Role, TokenSubject};
+use utils::http::services::auth_service::{AuthService, JwtAuthService};
+
+/// The tests share the database, so the ones asserting on every user run one at a time.
+pub static DATABASE: Mutex<()> = Mutex::const_new(());
+
+/// Authorization header of an admin allowed to manage every user.
+pub fn admin_authorization(config: &Config) -> (HeaderName, String) {
+    let token = JwtAuthService::new(config.jwt_secret.clone())
+        .encode_token(
+            TokenSubject::new(&uuid::Uuid::new_v4().to_string(), Role::Admin, true),
+            10,
+        )
+        .unwrap();
+
+    (header::AUTHORIZATION, format!("Bearer {}", token))
+}
@@ -0,0 +1,3 @@
+DROP TABLE queue_deliveries;
+DROP TABLE queue_subscriptions;
+DROP TABLE queue_messages;
@@ -0,0 +1,29 @@
+-- the tables used to be created by the services, IF NOT EXISTS keeps those deployments
+CREATE TABLE IF NOT EXISTS queue_messages (
+    id BIGSERIAL PRIMARY KEY,
+    topic TEXT NOT NULL,
+    key TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    headers TEXT NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS queue_messages_topic_idx ON queue_messages (topic, id);
+CREATE INDEX IF NOT EXISTS queue_messages_created_at_idx ON queue_messages (created_at);
+
+CREATE TABLE IF NOT EXISTS queue_subscriptions (
+    group_id TEXT NOT NULL,
+    topic TEXT NOT NULL,
+    PRIMARY KEY (group_id, topic)
+);
+
+CREATE TABLE IF NOT EXISTS queue_deliveries (
+    group_id TEXT NOT NULL,
+    message_id BIGINT NOT NULL REFERENCES queue_messages (id) ON DELETE CASCADE,
+    topic TEXT NOT NULL,
+    locked_by TEXT,
+    locked_until TIMESTAMPTZ,
+    PRIMARY KEY (group_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS queue_deliveries_message_idx ON queue_deliveries (message_id);
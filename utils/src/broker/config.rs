@@ -0,0 +1,438 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::BrokerError;
+
+const SECURITY_PROTOCOLS: [&str; 4] = ["plaintext", "ssl", "sasl_plaintext", "sasl_ssl"];
+const COMPRESSION_TYPES: [&str; 5] = ["none", "gzip", "snappy", "lz4", "zstd"];
+const ACKS: [&str; 4] = ["0", "1", "all", "-1"];
+
+/// Environment variables mapped to the librdkafka property they set.
+const ENV_PROPERTIES: [(&str, &str); 12] = [
+    ("KAFKA_URL", "bootstrap.servers"),
+    ("KAFKA_GROUP_ID", "group.id"),
+    ("KAFKA_CLIENT_ID", "client.id"),
+    ("KAFKA_SECURITY_PROTOCOL", "security.protocol"),
+    ("KAFKA_SASL_MECHANISM", "sasl.mechanism"),
+    ("KAFKA_SASL_USERNAME", "sasl.username"),
+    ("KAFKA_SASL_PASSWORD", "sasl.password"),
+    ("KAFKA_SSL_CA_LOCATION", "ssl.ca.location"),
+    ("KAFKA_ENABLE_IDEMPOTENCE", "enable.idempotence"),
+    ("KAFKA_COMPRESSION_TYPE", "compression.type"),
+    ("KAFKA_ACKS", "acks"),
+    ("KAFKA_TOPIC_REPLICATION_FACTOR", TOPIC_REPLICATION_FACTOR),
+];
+/// Replication factor of the topics created at startup, not passed to librdkafka.
+const TOPIC_REPLICATION_FACTOR: &str = "topic.replication.factor";
+/// Prefix of the environment variables passed through to librdkafka,
+/// `KAFKA_PROPERTY_FETCH_MIN_BYTES` sets `fetch.min.bytes`.
+const ENV_PASSTHROUGH_PREFIX: &str = "KAFKA_PROPERTY_";
+/// Properties file read before the environment variables.
+const ENV_CONFIG_FILE: &str = "KAFKA_CONFIG_FILE";
+
+/// Kafka client settings shared by the producers and consumers of a service.
+#[derive(Clone, PartialEq)]
+pub struct BrokerConfig {
+    pub bootstrap_servers: String,
+    pub group_id: String,
+    pub client_id: String,
+    pub security_protocol: String,
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    pub ssl_ca_location: Option<String>,
+    pub enable_idempotence: bool,
+    pub compression_type: String,
+    pub acks: String,
+    pub topic_replication_factor: i32,
+    /// Extra librdkafka properties, they override the settings above.
+    pub properties: BTreeMap<String, String>,
+}
+
+impl BrokerConfig {
+    pub fn new(bootstrap_servers: &str, group_id: &str, client_id: &str) -> Self {
+        BrokerConfig {
+            bootstrap_servers: bootstrap_servers.to_string(),
+            group_id: group_id.to_string(),
+            client_id: client_id.to_string(),
+            security_protocol: "plaintext".to_string(),
+            sasl_mechanism: None,
+            sasl_username: None,
+            sasl_password: None,
+            ssl_ca_location: None,
+            enable_idempotence: true,
+            compression_type: "none".to_string(),
+            acks: "all".to_string(),
+            topic_replication_factor: 1,
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the configuration from the properties file named by `KAFKA_CONFIG_FILE`, if any,
+    /// and the `KAFKA_*` environment variables, which take precedence.
+    ///
+    /// `service` is the default group and client id.
+    pub fn from_env(service: &str) -> Result<Self, BrokerError> {
+        let mut properties = BTreeMap::new();
+
+        if let Ok(path) = std::env::var(ENV_CONFIG_FILE) {
+            let content = std::fs::read_to_string(&path).map_err(|err| BrokerError {
+                message: format!("failed reading kafka config file {}: {}", path, err),
+            })?;
+            properties.extend(parse_properties(&content)?);
+        }
+        properties.extend(env_properties(std::env::vars()));
+
+        Self::from_properties(service, properties)
+    }
+
+    /// Builds the configuration from librdkafka properties, the properties this struct
+    /// has no field for are kept as passthrough properties.
+    pub fn from_properties(
+        service: &str,
+        mut properties: BTreeMap<String, String>,
+    ) -> Result<Self, BrokerError> {
+        let bootstrap_servers = properties
+            .remove("bootstrap.servers")
+            .ok_or_else(|| BrokerError::new("KAFKA_URL must be set"))?;
+
+        let mut config = BrokerConfig::new(&bootstrap_servers, service, service);
+        if let Some(group_id) = properties.remove("group.id") {
+            config.group_id = group_id;
+        }
+        if let Some(client_id) = properties.remove("client.id") {
+            config.client_id = client_id;
+        }
+        if let Some(security_protocol) = properties.remove("security.protocol") {
+            config.security_protocol = security_protocol.to_lowercase();
+        }
+        // librdkafka accepts both names
+        config.sasl_mechanism = properties
+            .remove("sasl.mechanism")
+            .or_else(|| properties.remove("sasl.mechanisms"));
+        config.sasl_username = properties.remove("sasl.username");
+        config.sasl_password = properties.remove("sasl.password");
+        config.ssl_ca_location = properties.remove("ssl.ca.location");
+        if let Some(enable_idempotence) = properties.remove("enable.idempotence") {
+            config.enable_idempotence = enable_idempotence.parse().map_err(|_| BrokerError {
+                message: format!("invalid enable.idempotence: {}", enable_idempotence),
+            })?;
+        }
+        if let Some(compression_type) = properties.remove("compression.type") {
+            config.compression_type = compression_type.to_lowercase();
+        }
+        if let Some(acks) = properties.remove("acks") {
+            config.acks = acks.to_lowercase();
+        }
+        if let Some(replication_factor) = properties.remove(TOPIC_REPLICATION_FACTOR) {
+            config.topic_replication_factor =
+                replication_factor.parse().map_err(|_| BrokerError {
+                    message: format!(
+                        "invalid {}: {}",
+                        TOPIC_REPLICATION_FACTOR, replication_factor
+                    ),
+                })?;
+        }
+        config.properties = properties;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks the settings librdkafka would only reject when connecting.
+    pub fn validate(&self) -> Result<(), BrokerError> {
+        if self.bootstrap_servers.trim().is_empty() {
+            return Err(BrokerError::new("bootstrap servers must not be empty"));
+        }
+        if self.group_id.trim().is_empty() {
+            return Err(BrokerError::new("group id must not be empty"));
+        }
+        if self.topic_replication_factor < 1 {
+            return Err(BrokerError::new(
+                "topic replication factor must be positive",
+            ));
+        }
+        if !SECURITY_PROTOCOLS.contains(&self.security_protocol.as_str()) {
+            return Err(BrokerError {
+                message: format!("invalid security protocol: {}", self.security_protocol),
+            });
+        }
+        if !COMPRESSION_TYPES.contains(&self.compression_type.as_str()) {
+            return Err(BrokerError {
+                message: format!("invalid compression type: {}", self.compression_type),
+            });
+        }
+        if !ACKS.contains(&self.acks.as_str()) {
+            return Err(BrokerError {
+                message: format!("invalid acks: {}", self.acks),
+            });
+        }
+        if self.enable_idempotence && !matches!(self.acks.as_str(), "all" | "-1") {
+            return Err(BrokerError::new("idempotent producer requires acks=all"));
+        }
+
+        if self.security_protocol.starts_with("sasl") {
+            let mechanism = self
+                .sasl_mechanism
+                .as_deref()
+                .ok_or_else(|| BrokerError::new("SASL requires a sasl mechanism"))?;
+            let needs_credentials = matches!(
+                mechanism.to_uppercase().as_str(),
+                "PLAIN" | "SCRAM-SHA-256" | "SCRAM-SHA-512"
+            );
+            if needs_credentials && (self.sasl_username.is_none() || self.sasl_password.is_none()) {
+                return Err(BrokerError {
+                    message: format!("SASL {} requires a username and a password", mechanism),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Properties shared by producers and consumers.
+    pub(crate) fn client_properties(&self) -> BTreeMap<String, String> {
+        let mut properties = BTreeMap::from([
+            (
+                "bootstrap.servers".to_string(),
+                self.bootstrap_servers.clone(),
+            ),
+            ("client.id".to_string(), self.client_id.clone()),
+            (
+                "security.protocol".to_string(),
+                self.security_protocol.clone(),
+            ),
+        ]);
+        let optional = [
+            ("sasl.mechanism", &self.sasl_mechanism),
+            ("sasl.username", &self.sasl_username),
+            ("sasl.password", &self.sasl_password),
+            ("ssl.ca.location", &self.ssl_ca_location),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                properties.insert(name.to_string(), value.clone());
+            }
+        }
+        properties
+    }
+
+    pub(crate) fn producer_properties(&self) -> BTreeMap<String, String> {
+        let mut properties = self.client_properties();
+        properties.extend([
+            ("message.timeout.ms".to_string(), "5000".to_string()),
+            (
+                "enable.idempotence".to_string(),
+                self.enable_idempotence.to_string(),
+            ),
+            (
+                "compression.type".to_string(),
+                self.compression_type.clone(),
+            ),
+            ("acks".to_string(), self.acks.clone()),
+        ]);
+        properties.extend(self.properties.clone());
+        properties
+    }
+
+    pub(crate) fn admin_properties(&self) -> BTreeMap<String, String> {
+        let mut properties = self.client_properties();
+        properties.extend(self.properties.clone());
+        properties
+    }
+
+    pub(crate) fn consumer_properties(&self) -> BTreeMap<String, String> {
+        let mut properties = self.client_properties();
+        properties.extend([
+            ("group.id".to_string(), self.group_id.clone()),
+            ("auto.offset.reset".to_string(), "earliest".to_string()),
+            // offsets are committed by the data pipeline once messages are processed
+            ("enable.auto.commit".to_string(), "false".to_string()),
+        ]);
+        properties.extend(self.properties.clone());
+        properties
+    }
+}
+
+impl fmt::Debug for BrokerConfig {
+    // keeps the password and passthrough secrets out of the logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BrokerConfig")
+            .field("bootstrap_servers", &self.bootstrap_servers)
+            .field("group_id", &self.group_id)
+            .field("client_id", &self.client_id)
+            .field("security_protocol", &self.security_protocol)
+            .field("sasl_mechanism", &self.sasl_mechanism)
+            .field("sasl_username", &self.sasl_username)
+            .field("sasl_password", &self.sasl_password.as_ref().map(|_| "***"))
+            .field("ssl_ca_location", &self.ssl_ca_location)
+            .field("enable_idempotence", &self.enable_idempotence)
+            .field("compression_type", &self.compression_type)
+            .field("acks", &self.acks)
+            .field("topic_replication_factor", &self.topic_replication_factor)
+            .field("properties", &self.properties.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Parses `name=value` lines, blank lines and lines starting with `#` are skipped.
+pub fn parse_properties(content: &str) -> Result<BTreeMap<String, String>, BrokerError> {
+    let mut properties = BTreeMap::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, value) = line.split_once('=').ok_or_else(|| BrokerError {
+            message: format!("invalid kafka property at line {}: {}", index + 1, line),
+        })?;
+        properties.insert(name.trim().to_string(), value.trim().to_string());
+    }
+
+    Ok(properties)
+}
+
+/// Maps the `KAFKA_*` environment variables to librdkafka properties.
+pub fn env_properties(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> BTreeMap<String, String> {
+    let mut properties = BTreeMap::new();
+
+    for (name, value) in vars {
+        if let Some(property) = name.strip_prefix(ENV_PASSTHROUGH_PREFIX) {
+            properties.insert(property.to_lowercase().replace('_', "."), value);
+        } else if let Some((_, property)) = ENV_PROPERTIES.iter().find(|(var, _)| *var == name) {
+            properties.insert(property.to_string(), value);
+        }
+    }
+
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_properties_defaults() {
+        let properties = env_properties(vars(&[("KAFKA_URL", "kafka:29092")]));
+
+        let config = BrokerConfig::from_properties("news", properties).unwrap();
+
+        assert_eq!(config, BrokerConfig::new("kafka:29092", "news", "news"));
+        assert_eq!(config.consumer_properties()["group.id"], "news");
+        assert_eq!(config.producer_properties()["acks"], "all");
+    }
+
+    #[test]
+    fn test_from_properties_sasl_ssl() {
+        let properties = env_properties(vars(&[
+            ("KAFKA_URL", "broker-1:9093,broker-2:9093"),
+            ("KAFKA_GROUP_ID", "news-consumers"),
+            ("KAFKA_SECURITY_PROTOCOL", "SASL_SSL"),
+            ("KAFKA_SASL_MECHANISM", "SCRAM-SHA-512"),
+            ("KAFKA_SASL_USERNAME", "news"),
+            ("KAFKA_SASL_PASSWORD", "secret"),
+            ("KAFKA_COMPRESSION_TYPE", "zstd"),
+            ("KAFKA_PROPERTY_FETCH_MIN_BYTES", "1024"),
+            ("KAFKA_TOPIC_REPLICATION_FACTOR", "3"),
+            ("DATABASE_URL", "postgres://localhost"),
+        ]));
+
+        let config = BrokerConfig::from_properties("news", properties).unwrap();
+        let consumer = config.consumer_properties();
+
+        assert_eq!(config.group_id, "news-consumers");
+        assert_eq!(config.client_id, "news");
+        assert_eq!(consumer["security.protocol"], "sasl_ssl");
+        assert_eq!(consumer["sasl.password"], "secret");
+        assert_eq!(consumer["fetch.min.bytes"], "1024");
+        assert_eq!(config.producer_properties()["compression.type"], "zstd");
+        assert_eq!(config.topic_replication_factor, 3);
+        assert!(!consumer.contains_key(TOPIC_REPLICATION_FACTOR));
+        assert!(!format!("{:?}", config).contains("secret"));
+    }
+
+    #[test]
+    fn test_parse_properties_file() {
+        let content = "
+            # managed cluster
+            bootstrap.servers = broker-1:9093
+            security.protocol=SSL
+            ssl.ca.location=/etc/kafka/ca.pem
+            socket.keepalive.enable=true
+        ";
+
+        let config =
+            BrokerConfig::from_properties("users", parse_properties(content).unwrap()).unwrap();
+
+        assert_eq!(config.security_protocol, "ssl");
+        assert_eq!(config.ssl_ca_location.as_deref(), Some("/etc/kafka/ca.pem"));
+        assert_eq!(config.properties["socket.keepalive.enable"], "true");
+    }
+
+    #[test]
+    fn test_parse_properties_invalid_line() {
+        let err = parse_properties("bootstrap.servers").unwrap_err();
+
+        assert_eq!(
+            err.message,
+            "invalid kafka property at line 1: bootstrap.servers"
+        );
+    }
+
+    #[test]
+    fn test_from_properties_invalid() {
+        let cases: [(&[(&str, &str)], &str); 6] = [
+            (&[], "KAFKA_URL must be set"),
+            (
+                &[
+                    ("KAFKA_URL", "kafka:9092"),
+                    ("KAFKA_SECURITY_PROTOCOL", "tls"),
+                ],
+                "invalid security protocol: tls",
+            ),
+            (
+                &[
+                    ("KAFKA_URL", "kafka:9092"),
+                    ("KAFKA_COMPRESSION_TYPE", "brotli"),
+                ],
+                "invalid compression type: brotli",
+            ),
+            (
+                &[("KAFKA_URL", "kafka:9092"), ("KAFKA_ACKS", "1")],
+                "idempotent producer requires acks=all",
+            ),
+            (
+                &[
+                    ("KAFKA_URL", "kafka:9092"),
+                    ("KAFKA_SECURITY_PROTOCOL", "sasl_ssl"),
+                ],
+                "SASL requires a sasl mechanism",
+            ),
+            (
+                &[
+                    ("KAFKA_URL", "kafka:9092"),
+                    ("KAFKA_SECURITY_PROTOCOL", "sasl_ssl"),
+                    ("KAFKA_SASL_MECHANISM", "PLAIN"),
+                ],
+                "SASL PLAIN requires a username and a password",
+            ),
+        ];
+
+        for (env, expected) in cases {
+            let err = BrokerConfig::from_properties("news", env_properties(vars(env))).unwrap_err();
+
+            assert_eq!(err.message, expected);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use rdkafka::consumer::StreamConsumer;
+use rdkafka::producer::Producer;
+use rdkafka::{producer::FutureProducer, ClientConfig};
+
+use crate::error::BrokerError;
+
+pub mod config;
+pub mod topics;
+
+pub use config::BrokerConfig;
+
+pub type KafkaProducer = FutureProducer;
+
+pub fn create_producer(config: &BrokerConfig) -> Result<KafkaProducer, BrokerError> {
+    client_config(config.producer_properties())
+        .create()
+        .map_err(|err| BrokerError {
+            message: format!("failed creating producer: {}", err),
+        })
+}
+
+/// Waits until every message queued in the producer is delivered or the timeout expires.
+pub fn flush_producer(producer: &KafkaProducer, timeout: Duration) -> Result<(), BrokerError> {
+    producer.flush(timeout).map_err(|err| BrokerError {
+        message: format!("failed flushing producer: {}", err),
+    })
+}
+
+pub fn create_consumer(config: &BrokerConfig) -> Result<StreamConsumer, BrokerError> {
+    client_config(config.consumer_properties())
+        .create()
+        .map_err(|err| BrokerError {
+            message: format!("failed creating consumer: {}", err),
+        })
+}
+
+fn client_config(properties: BTreeMap<String, String>) -> ClientConfig {
+    let mut client_config = ClientConfig::new();
+    for (name, value) in properties {
+        client_config.set(name, value);
+    }
+    client_config
+}
@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use log::{info, warn};
+use rdkafka::admin::{
+    AdminClient, AdminOptions, NewPartitions, NewTopic, ResourceSpecifier, TopicReplication,
+};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::types::RDKafkaErrorCode;
+
+use super::{client_config, BrokerConfig};
+use crate::error::BrokerError;
+
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(30);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupPolicy {
+    Delete,
+    Compact,
+}
+
+impl CleanupPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CleanupPolicy::Delete => "delete",
+            CleanupPolicy::Compact => "compact",
+        }
+    }
+}
+
+/// Expected layout of a topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicSpec {
+    pub name: String,
+    pub partitions: i32,
+    /// Defaults to the replication factor of the broker config.
+    pub replication: Option<i32>,
+    /// Defaults to the broker retention.
+    pub retention: Option<Duration>,
+    pub cleanup_policy: CleanupPolicy,
+}
+
+impl TopicSpec {
+    pub fn new(name: &str, partitions: i32) -> Self {
+        TopicSpec {
+            name: name.to_string(),
+            partitions,
+            replication: None,
+            retention: None,
+            cleanup_policy: CleanupPolicy::Delete,
+        }
+    }
+
+    pub fn with_replication(mut self, replication: i32) -> Self {
+        self.replication = Some(replication);
+        self
+    }
+
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    pub fn with_cleanup_policy(mut self, cleanup_policy: CleanupPolicy) -> Self {
+        self.cleanup_policy = cleanup_policy;
+        self
+    }
+
+    fn replication_factor(&self, config: &BrokerConfig) -> i32 {
+        self.replication.unwrap_or(config.topic_replication_factor)
+    }
+
+    fn configs(&self) -> Vec<(&'static str, String)> {
+        let mut configs = vec![("cleanup.policy", self.cleanup_policy.as_str().to_string())];
+        if let Some(retention) = self.retention {
+            configs.push(("retention.ms", retention.as_millis().to_string()));
+        }
+        configs
+    }
+}
+
+/// Layout of a topic as reported by the cluster.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExistingTopic {
+    pub partitions: i32,
+    pub replication: i32,
+    pub configs: HashMap<String, String>,
+}
+
+/// Declared topics of the platform, topics are named after the event type they carry.
+#[derive(Debug, Clone, Default)]
+pub struct TopicRegistry {
+    topics: Vec<TopicSpec>,
+}
+
+impl TopicRegistry {
+    pub fn new() -> Self {
+        TopicRegistry::default()
+    }
+
+    pub fn register(mut self, spec: TopicSpec) -> Self {
+        self.topics.retain(|topic| topic.name != spec.name);
+        self.topics.push(spec);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TopicSpec> {
+        self.topics.iter().find(|topic| topic.name == name)
+    }
+
+    /// Specs of the given topics, an unknown topic is an error.
+    pub fn topics(&self, names: &[&str]) -> Result<Vec<TopicSpec>, BrokerError> {
+        names
+            .iter()
+            .map(|name| {
+                self.get(name).cloned().ok_or_else(|| BrokerError {
+                    message: format!("topic {} is not registered", name),
+                })
+            })
+            .collect()
+    }
+}
+
+pub fn registry() -> TopicRegistry {
+    TopicRegistry::new()
+        .register(TopicSpec::new("news_created", 6).with_retention(7 * DAY))
+        .register(TopicSpec::new("user_created", 3).with_retention(30 * DAY))
+        .register(TopicSpec::new("user_updated", 3).with_retention(30 * DAY))
+        .register(TopicSpec::new("user_deleted", 3).with_retention(30 * DAY))
+        .register(TopicSpec::new("user_password_changed", 3).with_retention(30 * DAY))
+        .register(TopicSpec::new("user_preferences_updated", 3).with_retention(30 * DAY))
+}
+
+/// Creates the missing topics and checks the existing ones match their spec.
+///
+/// Partitions are added to the topics with fewer than their spec, every other mismatch is
+/// reported in the returned error and left for an operator to fix.
+pub async fn ensure_topics(config: &BrokerConfig, topics: &[TopicSpec]) -> Result<(), BrokerError> {
+    let admin: AdminClient<DefaultClientContext> = client_config(config.admin_properties())
+        .create()
+        .map_err(|err| BrokerError {
+            message: format!("failed creating admin client: {}", err),
+        })?;
+    let options = AdminOptions::new().request_timeout(Some(ADMIN_TIMEOUT));
+
+    let existing = existing_topics(&admin, &options, topics).await?;
+
+    let mut errors = vec![];
+    let mut missing = vec![];
+    let mut grown = vec![];
+    for spec in topics {
+        match existing.get(&spec.name) {
+            Some(topic) => {
+                errors.extend(check_topic(spec, spec.replication_factor(config), topic));
+                if topic.partitions < spec.partitions {
+                    grown.push(spec);
+                }
+            }
+            None => missing.push(spec),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(BrokerError {
+            message: format!("misconfigured topics: {}", errors.join("; ")),
+        });
+    }
+
+    if !missing.is_empty() {
+        create_topics(&admin, &options, config, &missing).await?;
+    }
+    if !grown.is_empty() {
+        add_partitions(&admin, &options, &grown).await?;
+    }
+
+    Ok(())
+}
+
+/// Ensures the registered topics with the given names, see [`ensure_topics`].
+pub async fn ensure_registered_topics(
+    config: &BrokerConfig,
+    names: &[&str],
+) -> Result<(), BrokerError> {
+    ensure_topics(config, &registry().topics(names)?).await
+}
+
+/// Differences between a spec and the topic in the cluster.
+pub fn check_topic(spec: &TopicSpec, replication: i32, topic: &ExistingTopic) -> Vec<String> {
+    let mut errors = vec![];
+
+    // missing partitions are added, extra ones can't be removed
+    if topic.partitions > spec.partitions {
+        errors.push(format!(
+            "{} has {} partitions, expected {}",
+            spec.name, topic.partitions, spec.partitions
+        ));
+    }
+    if topic.replication != replication {
+        errors.push(format!(
+            "{} has replication factor {}, expected {}",
+            spec.name, topic.replication, replication
+        ));
+    }
+    for (name, expected) in spec.configs() {
+        let actual = topic.configs.get(name).map(String::as_str).unwrap_or("");
+        if actual != expected {
+            errors.push(format!(
+                "{} has {}={}, expected {}",
+                spec.name, name, actual, expected
+            ));
+        }
+    }
+
+    errors
+}
+
+async fn existing_topics(
+    admin: &AdminClient<DefaultClientContext>,
+    options: &AdminOptions,
+    topics: &[TopicSpec],
+) -> Result<HashMap<String, ExistingTopic>, BrokerError> {
+    // asking for a single topic would create it when auto creation is enabled
+    let metadata = admin
+        .inner()
+        .fetch_metadata(None, ADMIN_TIMEOUT)
+        .map_err(|err| BrokerError {
+            message: format!("failed fetching topics metadata: {}", err),
+        })?;
+
+    let mut existing: HashMap<String, ExistingTopic> = metadata
+        .topics()
+        .iter()
+        .filter(|topic| topics.iter().any(|spec| spec.name == topic.name()))
+        .map(|topic| {
+            let partitions = topic.partitions();
+            let replication = partitions
+                .first()
+                .map_or(0, |partition| partition.replicas().len() as i32);
+            (
+                topic.name().to_string(),
+                ExistingTopic {
+                    partitions: partitions.len() as i32,
+                    replication,
+                    configs: HashMap::new(),
+                },
+            )
+        })
+        .collect();
+
+    if existing.is_empty() {
+        return Ok(existing);
+    }
+
+    let names: Vec<String> = existing.keys().cloned().collect();
+    let resources: Vec<ResourceSpecifier> = names
+        .iter()
+        .map(|name| ResourceSpecifier::Topic(name))
+        .collect();
+    let results = admin
+        .describe_configs(&resources, options)
+        .await
+        .map_err(|err| BrokerError {
+            message: format!("failed describing topics: {}", err),
+        })?;
+
+    for (name, result) in names.iter().zip(results) {
+        let resource = result.map_err(|code| BrokerError {
+            message: format!("failed describing topic {}: {}", name, code),
+        })?;
+        if let Some(topic) = existing.get_mut(name) {
+            topic.configs = resource
+                .entries
+                .into_iter()
+                .filter_map(|entry| Some((entry.name, entry.value?)))
+                .collect();
+        }
+    }
+
+    Ok(existing)
+}
+
+async fn create_topics(
+    admin: &AdminClient<DefaultClientContext>,
+    options: &AdminOptions,
+    config: &BrokerConfig,
+    topics: &[&TopicSpec],
+) -> Result<(), BrokerError> {
+    let configs: Vec<Vec<(&str, String)>> = topics.iter().map(|spec| spec.configs()).collect();
+    let new_topics: Vec<NewTopic> = topics
+        .iter()
+        .zip(&configs)
+        .map(|(spec, configs)| {
+            configs.iter().fold(
+                NewTopic::new(
+                    &spec.name,
+                    spec.partitions,
+                    TopicReplication::Fixed(spec.replication_factor(config)),
+                ),
+                |topic, (name, value)| topic.set(name, value),
+            )
+        })
+        .collect();
+
+    let results = admin
+        .create_topics(&new_topics, options)
+        .await
+        .map_err(|err| BrokerError {
+            message: format!("failed creating topics: {}", err),
+        })?;
+
+    for result in results {
+        match result {
+            Ok(name) => info!("created topic {}", name),
+            // another instance created it in the meantime
+            Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {}
+            Err((name, code)) => {
+                return Err(BrokerError {
+                    message: format!("failed creating topic {}: {}", name, code),
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Grows the topics to the partitions of their spec. The keys written before then may
+/// hash to other partitions, so their order is only kept for the new messages.
+async fn add_partitions(
+    admin: &AdminClient<DefaultClientContext>,
+    options: &AdminOptions,
+    topics: &[&TopicSpec],
+) -> Result<(), BrokerError> {
+    let new_partitions: Vec<NewPartitions> = topics
+        .iter()
+        .map(|spec| NewPartitions::new(&spec.name, spec.partitions as usize))
+        .collect();
+
+    let results = admin
+        .create_partitions(&new_partitions, options)
+        .await
+        .map_err(|err| BrokerError {
+            message: format!("failed adding partitions: {}", err),
+        })?;
+
+    for result in results {
+        match result {
+            Ok(name) => warn!("added partitions to topic {}", name),
+            // another instance added them in the meantime
+            Err((_, RDKafkaErrorCode::InvalidPartitions)) => {}
+            Err((name, code)) => {
+                return Err(BrokerError {
+                    message: format!("failed adding partitions to topic {}: {}", name, code),
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn existing(partitions: i32, replication: i32, configs: &[(&str, &str)]) -> ExistingTopic {
+        ExistingTopic {
+            partitions,
+            replication,
+            configs: configs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_topic_matching() {
+        let spec = TopicSpec::new("news_created", 6).with_retention(DAY);
+        let topic = existing(
+            6,
+            3,
+            &[("cleanup.policy", "delete"), ("retention.ms", "86400000")],
+        );
+
+        assert!(check_topic(&spec, 3, &topic).is_empty());
+    }
+
+    #[test]
+    fn test_check_topic_mismatch() {
+        let spec = TopicSpec::new("news_created", 6)
+            .with_retention(DAY)
+            .with_cleanup_policy(CleanupPolicy::Compact);
+        let topic = existing(
+            12,
+            1,
+            &[("cleanup.policy", "delete"), ("retention.ms", "604800000")],
+        );
+
+        assert_eq!(
+            check_topic(&spec, 3, &topic),
+            vec![
+                "news_created has 12 partitions, expected 6",
+                "news_created has replication factor 1, expected 3",
+                "news_created has cleanup.policy=delete, expected compact",
+                "news_created has retention.ms=604800000, expected 86400000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_topic_missing_partitions() {
+        let spec = TopicSpec::new("news_created", 6).with_retention(DAY);
+        let topic = existing(
+            1,
+            3,
+            &[("cleanup.policy", "delete"), ("retention.ms", "86400000")],
+        );
+
+        // they are added rather than reported
+        assert!(check_topic(&spec, 3, &topic).is_empty());
+    }
+
+    #[test]
+    fn test_registry_topics() {
+        let registry = registry().register(TopicSpec::new("news_created", 12));
+
+        let topics = registry.topics(&["news_created"]).unwrap();
+        assert_eq!(topics[0].partitions, 12);
+
+        let err = registry.topics(&["unknown"]).unwrap_err();
+        assert_eq!(err.message, "topic unknown is not registered");
+    }
+}
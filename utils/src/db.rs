@@ -1,6 +1,8 @@
 use diesel::pg::PgConnection;
 use diesel::r2d2::{ConnectionManager, Pool};
 
+use crate::error::DatabaseError;
+
 pub type PgPool = Pool<ConnectionManager<PgConnection>>;
 
 pub fn connect_db(database_url: String) -> PgPool {
@@ -9,3 +11,17 @@ pub fn connect_db(database_url: String) -> PgPool {
         .build(manager)
         .expect("Failed to create pool")
 }
+
+/// Runs the diesel query on the blocking pool, so the other tasks of the thread go on while
+/// it waits for the database.
+pub async fn run<T, F>(query: F) -> Result<T, DatabaseError>
+where
+    F: FnOnce() -> Result<T, DatabaseError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(query)
+        .await
+        .map_err(|err| DatabaseError {
+            message: err.to_string(),
+        })?
+}
@@ -8,6 +8,7 @@ pub const AUTH_TOKEN_ENCODING_CODE: u32 = 4;
 pub const WS_ERROR_CODE: u32 = 5;
 pub const SERIALIZATION_ERROR_CODE: u32 = 6;
 pub const ASYNC_OPERATIONS_ERROR_CODE: u32 = 7;
+pub const MAIL_ERROR_CODE: u32 = 8;
 
 #[derive(Debug, Serialize, Clone, PartialEq)]
 pub struct CommonError {
@@ -126,3 +127,25 @@ impl From<SerializationError> for CommonError {
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct MailError {
+    pub message: String,
+}
+
+impl MailError {
+    pub fn new(message: &str) -> Self {
+        MailError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<MailError> for CommonError {
+    fn from(val: MailError) -> Self {
+        CommonError {
+            message: val.message,
+            code: MAIL_ERROR_CODE,
+        }
+    }
+}
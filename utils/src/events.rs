@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::SerializationError;
+
+pub const SPEC_VERSION: &str = "1.0";
+/// Kafka header carrying the event id, following the CloudEvents Kafka binding.
+pub const EVENT_ID_HEADER: &str = "ce_id";
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Envelope every event is published in, modelled on CloudEvents.
+///
+/// `dataversion` is the schema version of `data`, consumers dispatch on it together with
+/// `event_type` so the payload can evolve without breaking older consumers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub id: Uuid,
+    pub specversion: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub source: String,
+    pub time: DateTime<Utc>,
+    pub dataversion: u32,
+    pub datacontenttype: String,
+    pub data: serde_json::Value,
+}
+
+impl Event {
+    pub fn new<T: Serialize>(
+        event_type: &str,
+        source: &str,
+        dataversion: u32,
+        data: &T,
+    ) -> Result<Self, SerializationError> {
+        let data = serde_json::to_value(data).map_err(|err| {
+            SerializationError::new(format!("failed to serialize event data: {}", err).as_str())
+        })?;
+
+        Ok(Event {
+            id: Uuid::new_v4(),
+            specversion: SPEC_VERSION.to_string(),
+            event_type: event_type.to_string(),
+            source: source.to_string(),
+            time: Utc::now(),
+            dataversion,
+            datacontenttype: JSON_CONTENT_TYPE.to_string(),
+            data,
+        })
+    }
+
+    pub fn from_json(payload: &str) -> Result<Self, SerializationError> {
+        serde_json::from_str(payload).map_err(|err| {
+            SerializationError::new(format!("failed to convert JSON string: {}", err).as_str())
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, SerializationError> {
+        serde_json::to_string(self).map_err(|err| {
+            SerializationError::new(format!("failed to serialize event: {}", err).as_str())
+        })
+    }
+
+    pub fn data<T: DeserializeOwned>(&self) -> Result<T, SerializationError> {
+        serde_json::from_value(self.data.clone()).map_err(|err| {
+            SerializationError::new(
+                format!(
+                    "failed to deserialize {} v{} data: {}",
+                    self.event_type, self.dataversion, err
+                )
+                .as_str(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Data {
+        name: String,
+    }
+
+    #[test]
+    fn test_event_round_trip() {
+        let data = Data {
+            name: "news".to_string(),
+        };
+        let event = Event::new("news_created", "news-scrapper", 1, &data).unwrap();
+
+        let decoded = Event::from_json(&event.to_json().unwrap()).unwrap();
+
+        assert_eq!(decoded, event);
+        assert_eq!(decoded.data::<Data>().unwrap(), data);
+    }
+
+    #[test]
+    fn test_event_serializes_type_attribute() {
+        let event = Event::new("user_created", "users", 1, &"data").unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&event.to_json().unwrap()).unwrap();
+
+        assert_eq!(json["type"], "user_created");
+        assert_eq!(json["specversion"], SPEC_VERSION);
+        assert_eq!(json["dataversion"], 1);
+    }
+}
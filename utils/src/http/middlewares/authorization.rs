@@ -0,0 +1,308 @@
+use actix_web::error::{Error as ActixError, ErrorForbidden};
+use actix_web::{dev::Payload, Error as ActixWebError};
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use uuid::Uuid;
+
+use crate::http::middlewares::jwt_auth::{ErrorResponse, JwtMiddleware, Role, TokenType};
+
+fn forbidden() -> ActixError {
+    forbidden_with("You are not allowed to perform this action")
+}
+
+fn forbidden_with(message: &str) -> ActixError {
+    ErrorForbidden(ErrorResponse {
+        status: "fail".to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// Authenticated user of the request and what they are allowed to do.
+pub struct Authorization {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub email_verified: bool,
+}
+
+impl Authorization {
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// Admins act on every user, other users only on themselves.
+    pub fn can_act_on(&self, user_id: &Uuid) -> bool {
+        self.is_admin() || self.user_id == *user_id
+    }
+
+    /// Fails with forbidden when the user can't act on the given one.
+    pub fn authorize(&self, user_id: &Uuid) -> Result<(), ActixError> {
+        if self.can_act_on(user_id) {
+            Ok(())
+        } else {
+            Err(forbidden())
+        }
+    }
+}
+
+impl From<JwtMiddleware> for Authorization {
+    fn from(auth: JwtMiddleware) -> Self {
+        Authorization {
+            user_id: auth.user_id,
+            role: auth.role,
+            email_verified: auth.claims.email_verified,
+        }
+    }
+}
+
+impl FromRequest for Authorization {
+    type Error = ActixWebError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let auth = JwtMiddleware::from_request(req, payload);
+
+        Box::pin(async move { auth.await.map(Authorization::from) })
+    }
+}
+
+/// Authenticated admin, requests of other users are forbidden.
+pub struct AdminOnly(pub Authorization);
+
+impl FromRequest for AdminOnly {
+    type Error = ActixWebError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let auth = Authorization::from_request(req, payload);
+
+        Box::pin(async move {
+            let auth = auth.await?;
+            if auth.is_admin() {
+                Ok(AdminOnly(auth))
+            } else {
+                Err(forbidden())
+            }
+        })
+    }
+}
+
+/// Authenticated user who verified their email address, or an admin. Unverified users
+/// are forbidden.
+pub struct Verified(pub Authorization);
+
+impl FromRequest for Verified {
+    type Error = ActixWebError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let auth = Authorization::from_request(req, payload);
+
+        Box::pin(async move {
+            let auth = auth.await?;
+            if auth.email_verified || auth.is_admin() {
+                Ok(Verified(auth))
+            } else {
+                Err(forbidden_with("Email address not verified"))
+            }
+        })
+    }
+}
+
+/// User authenticated through a login, personal tokens are forbidden. The account and
+/// its credentials are only managed this way, so a leaked personal token can't take the
+/// account over.
+pub struct LoggedIn(pub Authorization);
+
+impl FromRequest for LoggedIn {
+    type Error = ActixWebError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let auth = JwtMiddleware::from_request(req, payload);
+
+        Box::pin(async move {
+            let auth = auth.await?;
+            if auth.claims.typ == TokenType::Personal {
+                Err(forbidden_with(
+                    "Personal tokens can't manage the account, log in instead",
+                ))
+            } else {
+                Ok(LoggedIn(Authorization::from(auth)))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::http::StatusCode;
+    use actix_web::{get, test, web, App, HttpResponse};
+
+    use super::*;
+    use crate::http::middlewares::jwt_auth::TokenSubject;
+    use crate::http::services::auth_service::{AuthService, JwtAuthService};
+    use crate::http::services::personal_token_store::{MockPersonalTokenStore, PersonalToken};
+    use crate::http::test_utils::get_subject_authorization_header;
+
+    const ALICE: &str = "b73ccd26-1832-4d10-9251-271ce453cee3";
+    const BOB: &str = "fdd0a6f3-af61-4760-a789-5b6dd16eb7dc";
+
+    #[get("/users/{id}")]
+    async fn get_user(
+        auth: Authorization,
+        id: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ActixError> {
+        auth.authorize(&id)?;
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    #[get("/admin")]
+    async fn get_admin(_: AdminOnly) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[get("/verified")]
+    async fn get_verified(_: Verified) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[get("/account")]
+    async fn get_account(_: LoggedIn) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn test_authorization() {
+        let cases = vec![
+            (
+                "/users/".to_owned() + ALICE,
+                Some((ALICE, Role::User)),
+                StatusCode::OK,
+            ),
+            (
+                "/users/".to_owned() + BOB,
+                Some((ALICE, Role::User)),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                "/users/".to_owned() + BOB,
+                Some((ALICE, Role::Admin)),
+                StatusCode::OK,
+            ),
+            ("/users/".to_owned() + BOB, None, StatusCode::UNAUTHORIZED),
+            (
+                "/admin".to_owned(),
+                Some((ALICE, Role::User)),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                "/admin".to_owned(),
+                Some((ALICE, Role::Admin)),
+                StatusCode::OK,
+            ),
+            ("/admin".to_owned(), None, StatusCode::UNAUTHORIZED),
+        ];
+
+        let auth_service: Arc<dyn AuthService> =
+            Arc::new(JwtAuthService::new("secret123".to_owned()));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(auth_service.clone()))
+                .service(get_user)
+                .service(get_admin)
+                .service(get_verified),
+        )
+        .await;
+
+        for (uri, user, expected_status) in cases {
+            let user = user.map(|(user_id, role)| TokenSubject::new(user_id, role, true));
+            let mut req = test::TestRequest::get().uri(&uri);
+            if let Some(subject) = user.clone() {
+                req = req.append_header(get_subject_authorization_header(
+                    auth_service.clone(),
+                    subject,
+                ));
+            }
+
+            let resp = test::call_service(&app, req.to_request()).await;
+
+            assert_eq!(resp.status(), expected_status, "{} as {:?}", uri, user);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_verified() {
+        let cases = vec![
+            (TokenSubject::new(ALICE, Role::User, true), StatusCode::OK),
+            (
+                TokenSubject::new(ALICE, Role::User, false),
+                StatusCode::FORBIDDEN,
+            ),
+            (TokenSubject::new(ALICE, Role::Admin, false), StatusCode::OK),
+        ];
+
+        let auth_service: Arc<dyn AuthService> =
+            Arc::new(JwtAuthService::new("secret123".to_owned()));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(auth_service.clone()))
+                .service(get_verified),
+        )
+        .await;
+
+        for (subject, expected_status) in cases {
+            let req = test::TestRequest::get()
+                .uri("/verified")
+                .append_header(get_subject_authorization_header(
+                    auth_service.clone(),
+                    subject.clone(),
+                ))
+                .to_request();
+
+            let resp = test::call_service(&app, req).await;
+
+            assert_eq!(resp.status(), expected_status, "{:?}", subject);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_logged_in() {
+        let mut store = MockPersonalTokenStore::new();
+        store.expect_find().returning(|_| {
+            Ok(Some(PersonalToken {
+                id: "token".to_string(),
+                user_id: ALICE.to_string(),
+                role: Role::User,
+                email_verified: true,
+                scopes: None,
+                expires_at: None,
+                created_at: chrono::Utc::now(),
+            }))
+        });
+        let auth_service: Arc<dyn AuthService> = Arc::new(
+            JwtAuthService::new("secret123".to_owned()).with_personal_token_store(Arc::new(store)),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(auth_service.clone()))
+                .service(get_account),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/account")
+            .append_header(get_subject_authorization_header(
+                auth_service.clone(),
+                TokenSubject::new(ALICE, Role::User, true),
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // even an unrestricted personal token
+        let req = test::TestRequest::get()
+            .uri("/account")
+            .append_header((actix_web::http::header::AUTHORIZATION, "Bearer pat_token"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -1,24 +1,201 @@
 use core::fmt;
-use std::future::{ready, Ready};
 
-use actix_web::error::{Error as ActixError, ErrorUnauthorized};
+use actix_web::error::{Error as ActixError, ErrorForbidden, ErrorUnauthorized};
 use actix_web::{dev::Payload, Error as ActixWebError};
 use actix_web::{http, web, FromRequest, HttpMessage, HttpRequest};
+use futures::future::LocalBoxFuture;
 use serde::{Deserialize, Serialize};
 
-use crate::http::services::auth_service::AuthService;
+use crate::http::services::auth_service::{AuthService, PERSONAL_TOKEN_PREFIX};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Access tokens authenticate requests, refresh tokens are only exchanged for new tokens.
+/// Mfa tokens prove the password was checked, they are exchanged for the other tokens
+/// along with a second factor. Personal tokens are created by users for their scripts,
+/// they aren't JWTs.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    #[default]
+    Access,
+    Refresh,
+    Mfa,
+    Personal,
+}
+
+/// What a user is allowed to do, admins manage every user.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "database",
+    derive(diesel::AsExpression, diesel::FromSqlRow),
+    diesel(sql_type = diesel::sql_types::Text)
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    #[default]
+    User,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::User => "user",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "admin" => Ok(Role::Admin),
+            "user" => Ok(Role::User),
+            role => Err(format!("unknown role {}", role)),
+        }
+    }
+}
+
+/// What a personal token restricted to it is allowed to do.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Scope {
+    #[serde(rename = "news:read")]
+    NewsRead,
+    #[serde(rename = "subscriptions:manage")]
+    SubscriptionsManage,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::NewsRead => "news:read",
+            Scope::SubscriptionsManage => "subscriptions:manage",
+        }
+    }
+
+    /// Whether the scope covers the request.
+    pub fn allows(&self, method: &http::Method, path: &str) -> bool {
+        let under = |prefix: &str| {
+            path.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        };
+
+        match self {
+            Scope::NewsRead => method == http::Method::GET && under("/news"),
+            Scope::SubscriptionsManage => under("/subscriptions"),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = String;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "news:read" => Ok(Scope::NewsRead),
+            "subscriptions:manage" => Ok(Scope::SubscriptionsManage),
+            scope => Err(format!("unknown scope {}", scope)),
+        }
+    }
+}
+
+#[cfg(feature = "database")]
+impl diesel::serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg> for Role {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, diesel::pg::Pg>,
+    ) -> diesel::serialize::Result {
+        <str as diesel::serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg>>::to_sql(
+            self.as_str(),
+            out,
+        )
+    }
+}
+
+#[cfg(feature = "database")]
+impl diesel::deserialize::FromSql<diesel::sql_types::Text, diesel::pg::Pg> for Role {
+    fn from_sql(bytes: diesel::pg::PgValue<'_>) -> diesel::deserialize::Result<Self> {
+        let role = <String as diesel::deserialize::FromSql<
+            diesel::sql_types::Text,
+            diesel::pg::Pg,
+        >>::from_sql(bytes)?;
+        Ok(role.parse()?)
+    }
+}
+
+/// User an access token is issued to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenSubject {
+    pub user_id: String,
+    pub role: Role,
+    pub email_verified: bool,
+}
+
+impl TokenSubject {
+    pub fn new(user_id: &str, role: Role, email_verified: bool) -> Self {
+        TokenSubject {
+            user_id: user_id.to_string(),
+            role,
+            email_verified,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenClaims {
     pub sub: String,
     pub iat: usize,
     pub exp: usize,
+    /// Tokens issued before refresh tokens existed are access tokens.
+    #[serde(default)]
+    pub typ: TokenType,
+    /// Identifies the token in the revocation store, missing from tokens issued before
+    /// revocation existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Refresh tokens rotated from the same login share a family, revoked as a whole
+    /// when one of them is reused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fam: Option<String>,
+    /// Role of the user when the token was issued, tokens issued before roles existed
+    /// have the least privileged one.
+    #[serde(default)]
+    pub role: Role,
+    /// Users who didn't verify their email address have restricted capabilities.
+    #[serde(default)]
+    pub email_verified: bool,
+    /// Requests the token is restricted to, it can do whatever its user can without.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl TokenClaims {
+    /// Whether the scopes of the token cover the request.
+    pub fn allows(&self, method: &http::Method, path: &str) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.iter().any(|scope| scope.allows(method, path)),
+            None => true,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
-struct ErrorResponse {
-    status: String,
-    message: String,
+pub(crate) struct ErrorResponse {
+    pub(crate) status: String,
+    pub(crate) message: String,
 }
 
 impl fmt::Display for ErrorResponse {
@@ -29,49 +206,219 @@ impl fmt::Display for ErrorResponse {
 
 pub struct JwtMiddleware {
     pub user_id: uuid::Uuid,
+    pub role: Role,
+    pub claims: TokenClaims,
 }
 
 impl FromRequest for JwtMiddleware {
     type Error = ActixWebError;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        let auth_service = req.app_data::<web::Data<dyn AuthService>>();
+        let req = req.clone();
+
+        Box::pin(async move { authenticate(&req).await })
+    }
+}
+
+fn unauthorized(message: &str) -> ActixError {
+    ErrorUnauthorized(ErrorResponse {
+        status: "fail".to_string(),
+        message: message.to_string(),
+    })
+}
+
+async fn authenticate(req: &HttpRequest) -> Result<JwtMiddleware, ActixError> {
+    let auth_service = req
+        .app_data::<web::Data<dyn AuthService>>()
+        .cloned()
+        .ok_or_else(|| unauthorized("invalid configuration"))?;
+
+    let token = match (
+        req.cookie("token"),
+        req.headers().get(http::header::AUTHORIZATION),
+    ) {
+        (Some(cookie), _) => cookie.value().to_string(),
+        (None, Some(header)) => header
+            .to_str()
+            .ok()
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .ok_or_else(|| unauthorized("Invalid authorization header"))?
+            .to_string(),
+        (None, None) => return Err(unauthorized("You are not logged in, please provide token")),
+    };
 
-        let json_error = |message: String| -> ActixError {
-            ErrorUnauthorized(ErrorResponse {
-                status: "fail".to_string(),
-                message,
-            })
+    // the revocation and personal token stores query the database on a cache miss
+    let claims = web::block(move || {
+        if token.starts_with(PERSONAL_TOKEN_PREFIX) {
+            auth_service.decode_personal_token(token)
+        } else {
+            auth_service.decode_token(token)
+        }
+    })
+    .await?
+    .map_err(|_| unauthorized("Invalid token"))?;
+
+    if !claims.allows(req.method(), req.path()) {
+        return Err(ErrorForbidden(ErrorResponse {
+            status: "fail".to_string(),
+            message: "The token's scopes don't allow this request".to_string(),
+        }));
+    }
+    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| unauthorized("Invalid token"))?;
+    req.extensions_mut()
+        .insert::<uuid::Uuid>(user_id.to_owned());
+
+    Ok(JwtMiddleware {
+        user_id,
+        role: claims.role,
+        claims,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::http::{header::HeaderValue, StatusCode};
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{get, App, HttpResponse};
+
+    use super::*;
+    use crate::http::services::auth_service::JwtAuthService;
+
+    #[test]
+    fn test_scopes_allow() {
+        let cases = vec![
+            (None, http::Method::DELETE, "/users/me", true),
+            (Some(vec![]), http::Method::GET, "/news", false),
+            (
+                Some(vec![Scope::NewsRead]),
+                http::Method::GET,
+                "/news",
+                true,
+            ),
+            (
+                Some(vec![Scope::NewsRead]),
+                http::Method::GET,
+                "/news/1",
+                true,
+            ),
+            (
+                Some(vec![Scope::NewsRead]),
+                http::Method::POST,
+                "/news",
+                false,
+            ),
+            (
+                Some(vec![Scope::NewsRead]),
+                http::Method::GET,
+                "/newsletters",
+                false,
+            ),
+            (
+                Some(vec![Scope::NewsRead]),
+                http::Method::GET,
+                "/subscriptions",
+                false,
+            ),
+            (
+                Some(vec![Scope::NewsRead, Scope::SubscriptionsManage]),
+                http::Method::POST,
+                "/subscriptions",
+                true,
+            ),
+            (
+                Some(vec![Scope::SubscriptionsManage]),
+                http::Method::GET,
+                "/auth/me",
+                false,
+            ),
+        ];
+
+        for (scopes, method, path, expected) in cases {
+            let claims = TokenClaims {
+                sub: "user".to_string(),
+                iat: 0,
+                exp: 0,
+                typ: TokenType::Personal,
+                jti: None,
+                fam: None,
+                role: Role::User,
+                email_verified: true,
+                scopes: scopes.clone(),
+            };
+
+            assert_eq!(
+                claims.allows(&method, path),
+                expected,
+                "{:?} {} {}",
+                scopes,
+                method,
+                path
+            );
+        }
+    }
+
+    #[get("/news")]
+    async fn get_news(_: JwtMiddleware) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn test_authenticate_rejects_malformed_headers() {
+        let auth_service: Arc<dyn AuthService> =
+            Arc::new(JwtAuthService::new("secret123".to_owned()));
+        let bearer = |subject: &str| {
+            let token = auth_service
+                .encode_token(TokenSubject::new(subject, Role::User, true), 10)
+                .unwrap();
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap()
         };
+        let cases = vec![
+            (
+                bearer("b73ccd26-1832-4d10-9251-271ce453cee3"),
+                StatusCode::OK,
+            ),
+            (HeaderValue::from_static("Bearer"), StatusCode::UNAUTHORIZED),
+            (
+                HeaderValue::from_static("Basic dXNlcg=="),
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                HeaderValue::from_bytes(b"Bearer \xfa").unwrap(),
+                StatusCode::UNAUTHORIZED,
+            ),
+            (bearer("not a uuid"), StatusCode::UNAUTHORIZED),
+        ];
 
-        if auth_service.is_none() {
-            return ready(Err(json_error("invalid configuration".to_string())));
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::from(auth_service.clone()))
+                .service(get_news),
+        )
+        .await;
+
+        for (header, expected_status) in cases {
+            let req = TestRequest::get()
+                .uri("/news")
+                .append_header((http::header::AUTHORIZATION, header.clone()))
+                .to_request();
+
+            let resp = call_service(&app, req).await;
+
+            assert_eq!(resp.status(), expected_status, "{:?}", header);
         }
+    }
 
-        let auth_service = auth_service.unwrap();
-
-        let token = req
-            .cookie("token")
-            .map(|c| c.value().to_string())
-            .or_else(|| {
-                req.headers()
-                    .get(http::header::AUTHORIZATION)
-                    .map(|h| h.to_str().unwrap().split_at(7).1.to_string())
-            });
-
-        if let Some(token) = token {
-            if let Ok(claims) = auth_service.decode_token(token) {
-                let user_id = uuid::Uuid::parse_str(&claims.sub).unwrap();
-                req.extensions_mut()
-                    .insert::<uuid::Uuid>(user_id.to_owned());
-                ready(Ok(JwtMiddleware { user_id }))
-            } else {
-                ready(Err(json_error("Invalid token".to_string())))
-            }
-        } else {
-            ready(Err(json_error(
-                "You are not logged in, please provide token".to_string(),
-            )))
+    #[test]
+    fn test_scope_round_trips() {
+        for scope in [Scope::NewsRead, Scope::SubscriptionsManage] {
+            assert_eq!(scope.as_str().parse::<Scope>(), Ok(scope));
+            assert_eq!(
+                serde_json::to_string(&scope).unwrap(),
+                format!("\"{}\"", scope)
+            );
         }
+        assert!("admin".parse::<Scope>().is_err());
     }
 }
@@ -1,2 +1,4 @@
+pub mod authorization;
 pub mod cors;
 pub mod jwt_auth;
+pub mod request_tracing;
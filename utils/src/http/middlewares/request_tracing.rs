@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use futures::future::LocalBoxFuture;
+use tracing::field::Empty;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::telemetry::{extract_context, TRACEPARENT_HEADER, TRACESTATE_HEADER};
+
+/// Wraps every request in a server span that continues the trace of the caller, if any.
+#[derive(Default)]
+pub struct RequestTracing;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTracing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestTracingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTracingMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestTracingMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTracingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // the route pattern keeps ids out of the span name
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        let span = tracing::info_span!(
+            "HTTP request",
+            otel.name = %format!("{} {}", req.method(), route),
+            otel.kind = "server",
+            http.method = %req.method(),
+            http.route = %route,
+            http.status_code = Empty,
+        );
+        span.set_parent(extract_context(&trace_headers(&req)));
+
+        let service = self.service.clone();
+        Box::pin(
+            async move {
+                let res = service.call(req).await?;
+                tracing::Span::current().record("http.status_code", res.status().as_u16());
+                Ok(res)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+fn trace_headers(req: &ServiceRequest) -> HashMap<String, String> {
+    [TRACEPARENT_HEADER, TRACESTATE_HEADER]
+        .into_iter()
+        .filter_map(|name| {
+            let value = req.headers().get(name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App, HttpResponse};
+    use opentelemetry::trace::TracerProvider as _;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::telemetry::{current_trace_id, tracer_provider};
+
+    #[actix_web::test]
+    async fn test_request_continues_caller_trace() {
+        let provider = tracer_provider("test", "").unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let app =
+            test::init_service(App::new().wrap(RequestTracing).route(
+                "/news",
+                web::get().to(|| async {
+                    HttpResponse::Ok().body(current_trace_id().unwrap_or_default())
+                }),
+            ))
+            .await;
+
+        let req = test::TestRequest::get()
+            .uri("/news")
+            .insert_header((
+                TRACEPARENT_HEADER,
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            ))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+
+        assert_eq!(body, "0af7651916cd43dd8448eb211c80319c");
+    }
+}
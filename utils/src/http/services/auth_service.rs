@@ -1,40 +1,92 @@
+use std::sync::Arc;
+
 use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 
 use crate::{
     error::{CommonError, AUTH_TOKEN_ENCODING_CODE},
-    http::middlewares::jwt_auth::TokenClaims,
+    http::middlewares::jwt_auth::{Role, TokenClaims, TokenSubject, TokenType},
 };
 
-pub trait AuthService {
-    fn encode_token(&self, user_id: String, expires_in: i64) -> Result<String, CommonError>;
+use super::personal_token_store::PersonalTokenStore;
+use super::revocation_store::{self, timestamp, RevocationStore};
+
+/// Tells personal tokens apart from JWTs.
+pub const PERSONAL_TOKEN_PREFIX: &str = "pat_";
+/// Expiry of the claims of the personal tokens that never expire, 9999-12-31.
+const NEVER_EXPIRES: usize = 253_402_300_799;
+
+pub trait AuthService: Send + Sync {
+    fn encode_token(&self, subject: TokenSubject, expires_in: i64) -> Result<String, CommonError>;
+    /// Decodes an access token, refresh tokens are rejected.
     fn decode_token(&self, token: String) -> Result<TokenClaims, CommonError>;
+    fn encode_refresh_token(
+        &self,
+        user_id: String,
+        family_id: String,
+        expires_in: i64,
+    ) -> Result<String, CommonError>;
+    /// Decodes a refresh token, access tokens are rejected.
+    fn decode_refresh_token(&self, token: String) -> Result<TokenClaims, CommonError>;
+    /// Encodes the token of a login waiting for its second factor.
+    fn encode_mfa_token(&self, user_id: String, expires_in: i64) -> Result<String, CommonError>;
+    fn decode_mfa_token(&self, token: String) -> Result<TokenClaims, CommonError>;
+    /// Looks up a personal token, rejected once expired or revoked like the access tokens.
+    fn decode_personal_token(&self, token: String) -> Result<TokenClaims, CommonError>;
+    /// Rejects the access token until it expires.
+    fn revoke_token(&self, claims: &TokenClaims) -> Result<(), CommonError>;
+    /// Rejects every access token issued to the user so far.
+    fn revoke_user_tokens(&self, user_id: &str) -> Result<(), CommonError>;
+}
+
+/// Hash stored instead of a token, so a leaked table can't be used to authenticate.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
 
 pub struct JwtAuthService {
     jwt_secret: String,
+    revocation_store: Option<Arc<dyn RevocationStore>>,
+    personal_token_store: Option<Arc<dyn PersonalTokenStore>>,
 }
 
 impl JwtAuthService {
     pub fn new(jwt_secret: String) -> Self {
-        JwtAuthService { jwt_secret }
+        JwtAuthService {
+            jwt_secret,
+            revocation_store: None,
+            personal_token_store: None,
+        }
     }
-}
 
-impl AuthService for JwtAuthService {
-    fn encode_token(&self, user_id: String, expires_in: i64) -> Result<String, CommonError> {
-        let now = Utc::now();
-        let iat = now.timestamp() as usize;
-        let exp = (now + Duration::minutes(expires_in)).timestamp() as usize;
-        let claims: TokenClaims = TokenClaims {
-            sub: user_id.to_string(),
-            exp,
-            iat,
-        };
+    /// Checks the access tokens against the store, without one they are valid until
+    /// they expire.
+    pub fn with_revocation_store(mut self, revocation_store: Arc<dyn RevocationStore>) -> Self {
+        self.revocation_store = Some(revocation_store);
+        self
+    }
 
+    /// Accepts the personal tokens of the store, without one they are all rejected.
+    pub fn with_personal_token_store(
+        mut self,
+        personal_token_store: Arc<dyn PersonalTokenStore>,
+    ) -> Self {
+        self.personal_token_store = Some(personal_token_store);
+        self
+    }
+
+    fn revocation_store(&self) -> Result<&dyn RevocationStore, CommonError> {
+        self.revocation_store
+            .as_deref()
+            .ok_or_else(|| CommonError::new("no revocation store configured"))
+    }
+
+    fn encode_claims(&self, claims: &TokenClaims) -> Result<String, CommonError> {
         encode(
             &Header::default(),
-            &claims,
+            claims,
             &EncodingKey::from_secret(self.jwt_secret.as_ref()),
         )
         .map_err(|err| CommonError {
@@ -43,9 +95,9 @@ impl AuthService for JwtAuthService {
         })
     }
 
-    fn decode_token(&self, token: String) -> Result<TokenClaims, CommonError> {
-        decode::<TokenClaims>(
-            &token,
+    fn decode_claims(&self, token: &str, typ: TokenType) -> Result<TokenClaims, CommonError> {
+        let claims = decode::<TokenClaims>(
+            token,
             &DecodingKey::from_secret(self.jwt_secret.as_ref()),
             &Validation::default(),
         )
@@ -53,6 +105,332 @@ impl AuthService for JwtAuthService {
         .map_err(|err| CommonError {
             message: err.to_string(),
             code: AUTH_TOKEN_ENCODING_CODE,
-        })
+        })?;
+
+        if claims.typ != typ {
+            return Err(CommonError {
+                message: "InvalidTokenType".to_string(),
+                code: AUTH_TOKEN_ENCODING_CODE,
+            });
+        }
+
+        Ok(claims)
+    }
+
+    /// Rejects the revoked tokens, when there is a store.
+    fn check_revocation(&self, claims: &TokenClaims) -> Result<(), CommonError> {
+        if let Some(store) = &self.revocation_store {
+            // fail closed, a revoked token must not slip through while the store is down
+            let revoked = revocation_store::is_revoked(store.as_ref(), claims).map_err(|err| {
+                log::error!("failed checking token revocation: {}", err.message);
+                CommonError::from(err)
+            })?;
+            if revoked {
+                return Err(CommonError {
+                    message: "TokenRevoked".to_string(),
+                    code: AUTH_TOKEN_ENCODING_CODE,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl AuthService for JwtAuthService {
+    fn encode_token(&self, subject: TokenSubject, expires_in: i64) -> Result<String, CommonError> {
+        let now = Utc::now();
+        let iat = now.timestamp() as usize;
+        let exp = (now + Duration::minutes(expires_in)).timestamp() as usize;
+        let claims: TokenClaims = TokenClaims {
+            sub: subject.user_id,
+            exp,
+            iat,
+            typ: TokenType::Access,
+            jti: Some(Uuid::new_v4().to_string()),
+            fam: None,
+            role: subject.role,
+            email_verified: subject.email_verified,
+            scopes: None,
+        };
+
+        self.encode_claims(&claims)
+    }
+
+    fn decode_token(&self, token: String) -> Result<TokenClaims, CommonError> {
+        let claims = self.decode_claims(&token, TokenType::Access)?;
+        self.check_revocation(&claims)?;
+
+        Ok(claims)
+    }
+
+    fn encode_refresh_token(
+        &self,
+        user_id: String,
+        family_id: String,
+        expires_in: i64,
+    ) -> Result<String, CommonError> {
+        let now = Utc::now();
+        let claims = TokenClaims {
+            sub: user_id,
+            iat: now.timestamp() as usize,
+            exp: (now + Duration::minutes(expires_in)).timestamp() as usize,
+            typ: TokenType::Refresh,
+            // tokens of a family issued in the same second must still differ
+            jti: Some(Uuid::new_v4().to_string()),
+            fam: Some(family_id),
+            // the role is read again when the token is exchanged
+            role: Role::default(),
+            email_verified: false,
+            scopes: None,
+        };
+
+        self.encode_claims(&claims)
+    }
+
+    fn decode_refresh_token(&self, token: String) -> Result<TokenClaims, CommonError> {
+        self.decode_claims(&token, TokenType::Refresh)
+    }
+
+    fn encode_mfa_token(&self, user_id: String, expires_in: i64) -> Result<String, CommonError> {
+        let now = Utc::now();
+        let claims = TokenClaims {
+            sub: user_id,
+            iat: now.timestamp() as usize,
+            exp: (now + Duration::minutes(expires_in)).timestamp() as usize,
+            typ: TokenType::Mfa,
+            jti: Some(Uuid::new_v4().to_string()),
+            fam: None,
+            role: Role::default(),
+            email_verified: false,
+            scopes: None,
+        };
+
+        self.encode_claims(&claims)
+    }
+
+    fn decode_mfa_token(&self, token: String) -> Result<TokenClaims, CommonError> {
+        self.decode_claims(&token, TokenType::Mfa)
+    }
+
+    fn decode_personal_token(&self, token: String) -> Result<TokenClaims, CommonError> {
+        let invalid = |message: &str| CommonError {
+            message: message.to_string(),
+            code: AUTH_TOKEN_ENCODING_CODE,
+        };
+        let store = self
+            .personal_token_store
+            .as_deref()
+            .ok_or_else(|| invalid("no personal token store configured"))?;
+
+        let token = store
+            .find(&hash_token(&token))
+            .map_err(|err| {
+                log::error!("failed looking up personal token: {}", err.message);
+                CommonError::from(err)
+            })?
+            .ok_or_else(|| invalid("UnknownToken"))?;
+        if token
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(invalid("ExpiredSignature"));
+        }
+
+        let claims = TokenClaims {
+            sub: token.user_id,
+            iat: token.created_at.timestamp() as usize,
+            exp: token
+                .expires_at
+                .map_or(NEVER_EXPIRES, |expires_at| expires_at.timestamp() as usize),
+            typ: TokenType::Personal,
+            jti: Some(token.id),
+            fam: None,
+            role: token.role,
+            email_verified: token.email_verified,
+            scopes: token.scopes,
+        };
+        self.check_revocation(&claims)?;
+
+        Ok(claims)
+    }
+
+    fn revoke_token(&self, claims: &TokenClaims) -> Result<(), CommonError> {
+        let store = self.revocation_store()?;
+
+        match &claims.jti {
+            Some(jti) => Ok(store.revoke_token(jti, &claims.sub, timestamp(claims.exp))?),
+            // tokens issued before they had an id can only be revoked with the others
+            None => Ok(store.revoke_user(&claims.sub, timestamp(claims.iat))?),
+        }
+    }
+
+    fn revoke_user_tokens(&self, user_id: &str) -> Result<(), CommonError> {
+        Ok(self.revocation_store()?.revoke_user(user_id, Utc::now())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{DatabaseError, DATABASE_ERROR_CODE};
+    use crate::http::middlewares::jwt_auth::Scope;
+    use crate::http::services::personal_token_store::{MockPersonalTokenStore, PersonalToken};
+    use crate::http::services::revocation_store::MockRevocationStore;
+
+    #[test]
+    fn test_token_types_are_not_interchangeable() {
+        let service = JwtAuthService::new("secret".to_string());
+        let access = service
+            .encode_token(TokenSubject::new("user", Role::User, true), 10)
+            .unwrap();
+        let refresh = service
+            .encode_refresh_token("user".to_string(), "family".to_string(), 10)
+            .unwrap();
+
+        assert_eq!(service.decode_token(access.clone()).unwrap().sub, "user");
+        let claims = service.decode_refresh_token(refresh.clone()).unwrap();
+        assert_eq!(claims.fam, Some("family".to_string()));
+        let mfa = service.encode_mfa_token("user".to_string(), 5).unwrap();
+        assert_eq!(service.decode_mfa_token(mfa.clone()).unwrap().sub, "user");
+        assert!(service.decode_token(refresh.clone()).is_err());
+        assert!(service.decode_token(mfa.clone()).is_err());
+        assert!(service.decode_refresh_token(access.clone()).is_err());
+        assert!(service.decode_refresh_token(mfa).is_err());
+        assert!(service.decode_mfa_token(access).is_err());
+        assert!(service.decode_mfa_token(refresh).is_err());
+    }
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("token"),
+            "3c469e9d6c5875d37a43f353d4f88e61fcf812c66eee3457465a40b0da4153e0"
+        );
+    }
+
+    #[test]
+    fn test_decode_token_checks_revocations() {
+        let cases = vec![
+            (Ok(false), Ok("user".to_string())),
+            (Ok(true), Err(AUTH_TOKEN_ENCODING_CODE)),
+            (
+                Err(DatabaseError {
+                    message: "db is down".to_string(),
+                }),
+                Err(DATABASE_ERROR_CODE),
+            ),
+        ];
+
+        for (revoked, expected) in cases {
+            let mut store = MockRevocationStore::new();
+            store
+                .expect_is_token_revoked()
+                .return_once(move |_| revoked);
+            store.expect_revoked_before().returning(|_| Ok(None));
+            let service =
+                JwtAuthService::new("secret".to_string()).with_revocation_store(Arc::new(store));
+            let token = service
+                .encode_token(TokenSubject::new("user", Role::User, true), 10)
+                .unwrap();
+
+            let result = service
+                .decode_token(token)
+                .map(|claims| claims.sub)
+                .map_err(|err| err.code);
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_decode_personal_token() {
+        let token = |expires_at, scopes| PersonalToken {
+            id: "token".to_string(),
+            user_id: "user".to_string(),
+            role: Role::User,
+            email_verified: true,
+            scopes,
+            expires_at,
+            created_at: Utc::now() - Duration::days(1),
+        };
+        let cases = vec![
+            ("unknown", None, Err(AUTH_TOKEN_ENCODING_CODE)),
+            (
+                "expired",
+                Some(token(Some(Utc::now() - Duration::minutes(1)), None)),
+                Err(AUTH_TOKEN_ENCODING_CODE),
+            ),
+            ("unrestricted", Some(token(None, None)), Ok(None)),
+            (
+                "scoped",
+                Some(token(
+                    Some(Utc::now() + Duration::days(1)),
+                    Some(vec![Scope::NewsRead]),
+                )),
+                Ok(Some(vec![Scope::NewsRead])),
+            ),
+            (
+                "unknown scopes only",
+                Some(token(None, Some(vec![]))),
+                Ok(Some(vec![])),
+            ),
+        ];
+
+        for (name, found, expected) in cases {
+            let mut store = MockPersonalTokenStore::new();
+            store
+                .expect_find()
+                .withf(|hash| hash == hash_token("pat_token"))
+                .return_once(move |_| Ok(found));
+            let service = JwtAuthService::new("secret".to_string())
+                .with_personal_token_store(Arc::new(store));
+
+            let result = service
+                .decode_personal_token("pat_token".to_string())
+                .map(|claims| {
+                    assert_eq!(claims.typ, TokenType::Personal, "{}", name);
+                    claims.scopes
+                })
+                .map_err(|err| err.code);
+
+            assert_eq!(result, expected, "{}", name);
+        }
+
+        // personal tokens aren't jwts, nor the other way around
+        let service = JwtAuthService::new("secret".to_string());
+        let access = service
+            .encode_token(TokenSubject::new("user", Role::User, true), 10)
+            .unwrap();
+        assert!(service.decode_personal_token(access).is_err());
+        assert!(service.decode_token("pat_token".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_revoke_token() {
+        let mut store = MockRevocationStore::new();
+        store
+            .expect_revoke_token()
+            .withf(|jti, user_id, _| jti == "token" && user_id == "user")
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let service =
+            JwtAuthService::new("secret".to_string()).with_revocation_store(Arc::new(store));
+        let claims = TokenClaims {
+            sub: "user".to_string(),
+            iat: 0,
+            exp: 60,
+            typ: TokenType::Access,
+            jti: Some("token".to_string()),
+            fam: None,
+            role: Role::User,
+            email_verified: true,
+            scopes: None,
+        };
+
+        assert!(service.revoke_token(&claims).is_ok());
+        assert!(JwtAuthService::new("secret".to_string())
+            .revoke_token(&claims)
+            .is_err());
     }
 }
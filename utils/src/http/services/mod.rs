@@ -1 +1,3 @@
 pub mod auth_service;
+pub mod personal_token_store;
+pub mod revocation_store;
@@ -0,0 +1,62 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use super::{PersonalToken, PersonalTokenStore};
+use crate::error::DatabaseError;
+
+/// Keeps the tokens of another store in memory for a while, revoked tokens are noticed
+/// once the cached token expires.
+pub struct CachedPersonalTokenStore {
+    store: Arc<dyn PersonalTokenStore>,
+    ttl: Duration,
+    tokens: Mutex<LruCache<String, (Option<PersonalToken>, Instant)>>,
+}
+
+impl CachedPersonalTokenStore {
+    pub fn new(store: Arc<dyn PersonalTokenStore>, capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        CachedPersonalTokenStore {
+            store,
+            ttl,
+            tokens: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+impl PersonalTokenStore for CachedPersonalTokenStore {
+    fn find(&self, token_hash: &str) -> Result<Option<PersonalToken>, DatabaseError> {
+        if let Some((token, cached_at)) = self.tokens.lock().unwrap().get(token_hash) {
+            if cached_at.elapsed() < self.ttl {
+                return Ok(token.clone());
+            }
+        }
+
+        let token = self.store.find(token_hash)?;
+        self.tokens
+            .lock()
+            .unwrap()
+            .put(token_hash.to_string(), (token.clone(), Instant::now()));
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::services::personal_token_store::MockPersonalTokenStore;
+
+    #[test]
+    fn test_tokens_are_cached_until_expired() {
+        let mut store = MockPersonalTokenStore::new();
+        store.expect_find().times(2).returning(|_| Ok(None));
+        let cache = CachedPersonalTokenStore::new(Arc::new(store), 10, Duration::from_millis(20));
+
+        assert_eq!(cache.find("hash").unwrap(), None);
+        assert_eq!(cache.find("hash").unwrap(), None);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.find("hash").unwrap(), None);
+    }
+}
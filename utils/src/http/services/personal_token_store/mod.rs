@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use mockall::automock;
+
+use crate::error::DatabaseError;
+use crate::http::middlewares::jwt_auth::{Role, Scope};
+
+pub mod cache;
+#[cfg(feature = "database")]
+pub mod postgres;
+#[cfg(feature = "database")]
+pub mod schema;
+
+/// Personal token of a user, with what the user is allowed to do now rather than when
+/// it was created.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersonalToken {
+    pub id: String,
+    pub user_id: String,
+    pub role: Role,
+    pub email_verified: bool,
+    /// The token can do whatever its user can when `None`, a token restricted to scopes
+    /// this version doesn't know can do nothing.
+    pub scopes: Option<Vec<Scope>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Looks up the personal tokens the users service created, by the hash of the token.
+#[automock]
+pub trait PersonalTokenStore: Send + Sync {
+    /// The token, unless it is unknown or revoked.
+    fn find(&self, token_hash: &str) -> Result<Option<PersonalToken>, DatabaseError>;
+}
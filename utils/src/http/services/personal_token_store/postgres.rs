@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+use super::schema::{personal_access_tokens, users};
+use super::{PersonalToken, PersonalTokenStore};
+use crate::db::PgPool;
+use crate::error::DatabaseError;
+use crate::http::middlewares::jwt_auth::{Role, Scope};
+
+type Row = (
+    uuid::Uuid,
+    uuid::Uuid,
+    Vec<String>,
+    Option<DateTime<Utc>>,
+    DateTime<Utc>,
+    Role,
+    Option<String>,
+    Option<DateTime<Utc>>,
+);
+
+/// Reads the `personal_access_tokens` table of the users service, along with the role of
+/// the users.
+pub struct PostgresPersonalTokenStore {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresPersonalTokenStore {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        PostgresPersonalTokenStore { pool }
+    }
+}
+
+/// Stored scopes of newer versions are dropped, the token stays restricted to the others
+/// rather than becoming unrestricted.
+fn parse_scopes(scopes: &[String]) -> Option<Vec<Scope>> {
+    if scopes.is_empty() {
+        return None;
+    }
+
+    Some(
+        scopes
+            .iter()
+            .filter_map(|scope| scope.parse().ok())
+            .collect(),
+    )
+}
+
+impl PersonalTokenStore for PostgresPersonalTokenStore {
+    fn find(&self, token_hash: &str) -> Result<Option<PersonalToken>, DatabaseError> {
+        let mut conn = self.pool.get().map_err(|err| DatabaseError {
+            message: err.to_string(),
+        })?;
+
+        let row = personal_access_tokens::table
+            .inner_join(users::table)
+            .filter(personal_access_tokens::token_hash.eq(token_hash))
+            .filter(personal_access_tokens::revoked_at.is_null())
+            .select((
+                personal_access_tokens::id,
+                personal_access_tokens::user_id,
+                personal_access_tokens::scopes,
+                personal_access_tokens::expires_at,
+                personal_access_tokens::created_at,
+                users::role,
+                users::email,
+                users::email_verified_at,
+            ))
+            .first::<Row>(&mut conn)
+            .optional()
+            .map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })?;
+
+        Ok(row.map(
+            |(id, user_id, scopes, expires_at, created_at, role, email, email_verified_at)| {
+                PersonalToken {
+                    id: id.to_string(),
+                    user_id: user_id.to_string(),
+                    role,
+                    email_verified: email.is_some() && email_verified_at.is_some(),
+                    scopes: parse_scopes(&scopes),
+                    expires_at,
+                    created_at,
+                }
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scopes() {
+        let cases = vec![
+            (vec![], None),
+            (vec!["news:read"], Some(vec![Scope::NewsRead])),
+            (vec!["news:read", "news:write"], Some(vec![Scope::NewsRead])),
+            (vec!["news:write"], Some(vec![])),
+        ];
+
+        for (scopes, expected) in cases {
+            let scopes: Vec<String> = scopes.into_iter().map(String::from).collect();
+            assert_eq!(parse_scopes(&scopes), expected, "{:?}", scopes);
+        }
+    }
+}
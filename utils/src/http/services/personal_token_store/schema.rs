@@ -0,0 +1,26 @@
+use diesel::table;
+
+table! {
+  personal_access_tokens (id) {
+      id -> Uuid,
+      user_id -> Uuid,
+      token_hash -> Text,
+      scopes -> Array<Text>,
+      expires_at -> Nullable<Timestamptz>,
+      created_at -> Timestamptz,
+      revoked_at -> Nullable<Timestamptz>,
+  }
+}
+
+// the columns of the users table the tokens need
+table! {
+  users (id) {
+      id -> Uuid,
+      role -> Text,
+      email -> Nullable<Text>,
+      email_verified_at -> Nullable<Timestamptz>,
+  }
+}
+
+diesel::joinable!(personal_access_tokens -> users (user_id));
+diesel::allow_tables_to_appear_in_same_query!(personal_access_tokens, users);
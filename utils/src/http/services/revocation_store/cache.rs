@@ -0,0 +1,130 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use lru::LruCache;
+
+use super::RevocationStore;
+use crate::error::DatabaseError;
+
+/// An answer of the store and when it was fetched.
+type Cached<T> = (T, Instant);
+
+/// Keeps the answers of another store in memory for a while.
+///
+/// Revocations made through the cache apply at once, the ones made by other instances
+/// are noticed once the cached answer expires. Revoked tokens stay revoked, so they are
+/// cached until evicted.
+pub struct CachedRevocationStore {
+    store: Arc<dyn RevocationStore>,
+    ttl: Duration,
+    tokens: Mutex<LruCache<String, Cached<bool>>>,
+    users: Mutex<LruCache<String, Cached<Option<DateTime<Utc>>>>>,
+}
+
+impl CachedRevocationStore {
+    pub fn new(store: Arc<dyn RevocationStore>, capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        CachedRevocationStore {
+            store,
+            ttl,
+            tokens: Mutex::new(LruCache::new(capacity)),
+            users: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+impl RevocationStore for CachedRevocationStore {
+    fn is_token_revoked(&self, jti: &str) -> Result<bool, DatabaseError> {
+        if let Some((revoked, cached_at)) = self.tokens.lock().unwrap().get(jti) {
+            if *revoked || cached_at.elapsed() < self.ttl {
+                return Ok(*revoked);
+            }
+        }
+
+        let revoked = self.store.is_token_revoked(jti)?;
+        self.tokens
+            .lock()
+            .unwrap()
+            .put(jti.to_string(), (revoked, Instant::now()));
+        Ok(revoked)
+    }
+
+    fn revoked_before(&self, user_id: &str) -> Result<Option<DateTime<Utc>>, DatabaseError> {
+        if let Some((before, cached_at)) = self.users.lock().unwrap().get(user_id) {
+            if cached_at.elapsed() < self.ttl {
+                return Ok(*before);
+            }
+        }
+
+        let before = self.store.revoked_before(user_id)?;
+        self.users
+            .lock()
+            .unwrap()
+            .put(user_id.to_string(), (before, Instant::now()));
+        Ok(before)
+    }
+
+    fn revoke_token(
+        &self,
+        jti: &str,
+        user_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), DatabaseError> {
+        self.store.revoke_token(jti, user_id, expires_at)?;
+        self.tokens
+            .lock()
+            .unwrap()
+            .put(jti.to_string(), (true, Instant::now()));
+        Ok(())
+    }
+
+    fn revoke_user(&self, user_id: &str, before: DateTime<Utc>) -> Result<(), DatabaseError> {
+        self.store.revoke_user(user_id, before)?;
+        self.users
+            .lock()
+            .unwrap()
+            .put(user_id.to_string(), (Some(before), Instant::now()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::services::revocation_store::MockRevocationStore;
+
+    #[test]
+    fn test_answers_are_cached_until_expired() {
+        let mut store = MockRevocationStore::new();
+        store
+            .expect_is_token_revoked()
+            .times(2)
+            .returning(|_| Ok(false));
+        let cache = CachedRevocationStore::new(Arc::new(store), 10, Duration::from_millis(20));
+
+        assert!(!cache.is_token_revoked("token").unwrap());
+        assert!(!cache.is_token_revoked("token").unwrap());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!cache.is_token_revoked("token").unwrap());
+    }
+
+    #[test]
+    fn test_revocations_apply_at_once() {
+        let mut store = MockRevocationStore::new();
+        store
+            .expect_is_token_revoked()
+            .times(1)
+            .returning(|_| Ok(false));
+        store
+            .expect_revoke_token()
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let cache = CachedRevocationStore::new(Arc::new(store), 10, Duration::from_secs(60));
+
+        assert!(!cache.is_token_revoked("token").unwrap());
+        cache.revoke_token("token", "user", Utc::now()).unwrap();
+        assert!(cache.is_token_revoked("token").unwrap());
+    }
+}
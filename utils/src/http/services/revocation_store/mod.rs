@@ -0,0 +1,100 @@
+use chrono::{DateTime, TimeZone, Utc};
+use mockall::automock;
+
+use crate::error::DatabaseError;
+use crate::http::middlewares::jwt_auth::TokenClaims;
+
+pub mod cache;
+#[cfg(feature = "database")]
+pub mod postgres;
+#[cfg(feature = "database")]
+pub mod schema;
+
+/// Remembers the access tokens revoked before they expire, one by one or every token
+/// issued to a user.
+#[automock]
+pub trait RevocationStore: Send + Sync {
+    fn is_token_revoked(&self, jti: &str) -> Result<bool, DatabaseError>;
+    /// Tokens of the user issued at or before the returned time are revoked.
+    fn revoked_before(&self, user_id: &str) -> Result<Option<DateTime<Utc>>, DatabaseError>;
+    /// Revokes the token until it expires.
+    fn revoke_token(
+        &self,
+        jti: &str,
+        user_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), DatabaseError>;
+    fn revoke_user(&self, user_id: &str, before: DateTime<Utc>) -> Result<(), DatabaseError>;
+}
+
+/// Checks both the token id and the user revocations.
+pub fn is_revoked(
+    store: &dyn RevocationStore,
+    claims: &TokenClaims,
+) -> Result<bool, DatabaseError> {
+    if let Some(jti) = &claims.jti {
+        if store.is_token_revoked(jti)? {
+            return Ok(true);
+        }
+    }
+
+    // iat has a precision of seconds, a token issued in the second the user revoked
+    // every token is revoked too
+    Ok(store
+        .revoked_before(&claims.sub)?
+        .is_some_and(|before| timestamp(claims.iat) <= before))
+}
+
+pub(crate) fn timestamp(seconds: usize) -> DateTime<Utc> {
+    Utc.timestamp_opt(seconds as i64, 0)
+        .single()
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::http::middlewares::jwt_auth::{Role, TokenType};
+
+    fn claims(jti: Option<&str>, iat: DateTime<Utc>) -> TokenClaims {
+        TokenClaims {
+            sub: "user".to_string(),
+            iat: iat.timestamp() as usize,
+            exp: (iat + Duration::minutes(10)).timestamp() as usize,
+            typ: TokenType::Access,
+            jti: jti.map(str::to_string),
+            fam: None,
+            role: Role::User,
+            email_verified: true,
+            scopes: None,
+        }
+    }
+
+    #[test]
+    fn test_is_revoked() {
+        let revoked_at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let cases = vec![
+            (Some("revoked"), revoked_at + Duration::seconds(10), true),
+            (Some("valid"), revoked_at + Duration::seconds(10), false),
+            (Some("valid"), revoked_at, true),
+            (None, revoked_at - Duration::seconds(10), true),
+            (None, revoked_at + Duration::seconds(1), false),
+        ];
+
+        for (jti, iat, expected) in cases {
+            let mut store = MockRevocationStore::new();
+            store
+                .expect_is_token_revoked()
+                .returning(|jti| Ok(jti == "revoked"));
+            store
+                .expect_revoked_before()
+                .returning(move |_| Ok(Some(revoked_at)));
+
+            let revoked = is_revoked(&store, &claims(jti, iat)).unwrap();
+
+            assert_eq!(revoked, expected, "{:?} issued at {}", jti, iat);
+        }
+    }
+}
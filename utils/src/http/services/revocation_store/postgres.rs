@@ -0,0 +1,135 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+
+use super::schema::{revoked_tokens, revoked_users};
+use super::RevocationStore;
+use crate::db::PgPool;
+use crate::error::DatabaseError;
+
+/// How often the revocations of expired tokens are deleted.
+const PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Keeps the revocations in the `revoked_tokens` and `revoked_users` tables, shared by
+/// every service validating the tokens.
+pub struct PostgresRevocationStore {
+    pool: Arc<PgPool>,
+    last_purge: Mutex<Option<Instant>>,
+}
+
+impl PostgresRevocationStore {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        PostgresRevocationStore {
+            pool,
+            last_purge: Mutex::new(None),
+        }
+    }
+
+    /// Deletes the revocations of the tokens that expired anyway.
+    pub fn purge_expired(&self) -> Result<usize, DatabaseError> {
+        let mut conn = self.connection()?;
+
+        diesel::delete(revoked_tokens::table.filter(revoked_tokens::expires_at.lt(Utc::now())))
+            .execute(&mut conn)
+            .map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })
+    }
+
+    fn purge_if_due(&self) {
+        {
+            let mut last_purge = self.last_purge.lock().unwrap();
+            if last_purge.is_some_and(|last_purge| last_purge.elapsed() < PURGE_INTERVAL) {
+                return;
+            }
+            *last_purge = Some(Instant::now());
+        }
+
+        if let Err(err) = self.purge_expired() {
+            log::error!("failed purging revoked tokens: {}", err.message);
+        }
+    }
+
+    fn connection(
+        &self,
+    ) -> Result<PooledConnection<ConnectionManager<PgConnection>>, DatabaseError> {
+        self.pool.get().map_err(|err| DatabaseError {
+            message: err.to_string(),
+        })
+    }
+}
+
+impl RevocationStore for PostgresRevocationStore {
+    fn is_token_revoked(&self, jti: &str) -> Result<bool, DatabaseError> {
+        let mut conn = self.connection()?;
+
+        diesel::select(exists(
+            revoked_tokens::table.filter(revoked_tokens::jti.eq(jti)),
+        ))
+        .get_result(&mut conn)
+        .map_err(|err| DatabaseError {
+            message: err.to_string(),
+        })
+    }
+
+    fn revoked_before(&self, user_id: &str) -> Result<Option<DateTime<Utc>>, DatabaseError> {
+        let mut conn = self.connection()?;
+
+        revoked_users::table
+            .find(user_id)
+            .select(revoked_users::revoked_before)
+            .first(&mut conn)
+            .optional()
+            .map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })
+    }
+
+    fn revoke_token(
+        &self,
+        jti: &str,
+        user_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), DatabaseError> {
+        let mut conn = self.connection()?;
+
+        diesel::insert_into(revoked_tokens::table)
+            .values((
+                revoked_tokens::jti.eq(jti),
+                revoked_tokens::user_id.eq(user_id),
+                revoked_tokens::expires_at.eq(expires_at),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })?;
+
+        self.purge_if_due();
+
+        Ok(())
+    }
+
+    fn revoke_user(&self, user_id: &str, before: DateTime<Utc>) -> Result<(), DatabaseError> {
+        let mut conn = self.connection()?;
+
+        diesel::insert_into(revoked_users::table)
+            .values((
+                revoked_users::user_id.eq(user_id),
+                revoked_users::revoked_before.eq(before),
+            ))
+            .on_conflict(revoked_users::user_id)
+            .do_update()
+            .set(revoked_users::revoked_before.eq(before))
+            .execute(&mut conn)
+            .map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+use diesel::table;
+
+table! {
+  revoked_tokens (jti) {
+      jti -> Text,
+      user_id -> Text,
+      expires_at -> Timestamptz,
+  }
+}
+
+table! {
+  revoked_users (user_id) {
+      user_id -> Text,
+      revoked_before -> Timestamptz,
+  }
+}
@@ -9,12 +9,31 @@ use actix_web::{
     Error,
 };
 
+use crate::http::middlewares::jwt_auth::{Role, TokenSubject};
 use crate::http::services::auth_service::AuthService;
 
 pub fn get_authorization_header(auth_service: Arc<dyn AuthService>) -> impl TryIntoHeaderPair {
-    let token = auth_service
-        .encode_token("b73ccd26-1832-4d10-9251-271ce453cee3".to_owned(), 10)
-        .unwrap();
+    get_user_authorization_header(
+        auth_service,
+        "b73ccd26-1832-4d10-9251-271ce453cee3",
+        Role::User,
+    )
+}
+
+/// Authorization header of a user who verified their email address.
+pub fn get_user_authorization_header(
+    auth_service: Arc<dyn AuthService>,
+    user_id: &str,
+    role: Role,
+) -> impl TryIntoHeaderPair {
+    get_subject_authorization_header(auth_service, TokenSubject::new(user_id, role, true))
+}
+
+pub fn get_subject_authorization_header(
+    auth_service: Arc<dyn AuthService>,
+    subject: TokenSubject,
+) -> impl TryIntoHeaderPair {
+    let token = auth_service.encode_token(subject, 10).unwrap();
 
     (http::header::AUTHORIZATION, format!("Bearer {}", token))
 }
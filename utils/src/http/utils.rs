@@ -1,15 +1,34 @@
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
 use actix_web::middleware::Logger;
+use std::sync::OnceLock;
+
 use actix_web::{error::Error, App};
-use actix_web_prom::PrometheusMetricsBuilder;
+use actix_web_prom::{PrometheusMetrics, PrometheusMetricsBuilder};
 
 use super::middlewares::cors::cors;
+use super::middlewares::request_tracing::RequestTracing;
+
+static PROMETHEUS: OnceLock<PrometheusMetrics> = OnceLock::new();
 
 pub fn logger() -> Logger {
     Logger::default()
 }
 
+/// Serves the metrics of the default registry, so the ones registered outside of the
+/// http server are exported too. Created once since every server worker builds an app.
+pub fn prometheus() -> PrometheusMetrics {
+    PROMETHEUS
+        .get_or_init(|| {
+            PrometheusMetricsBuilder::new("api")
+                .registry(prometheus::default_registry().clone())
+                .endpoint("/metrics")
+                .build()
+                .unwrap()
+        })
+        .clone()
+}
+
 pub fn build_server(
     cors_origin: String,
 ) -> App<
@@ -21,13 +40,9 @@ pub fn build_server(
         Error = Error,
     >,
 > {
-    let prometheus = PrometheusMetricsBuilder::new("api")
-        .endpoint("/metrics")
-        .build()
-        .unwrap();
-
     App::new()
         .wrap(cors(cors_origin.clone()))
         .wrap(logger())
-        .wrap(prometheus.clone())
+        .wrap(prometheus())
+        .wrap(RequestTracing)
 }
@@ -1,13 +1,15 @@
 use actix::{Addr, MailboxError};
 use async_trait::async_trait;
 use mockall::automock;
+use tracing::instrument;
 
-use super::ws_server::{SessionMessage, WebsocketServer};
+use super::ws_server::{CloseSession, SessionMessage, WebsocketServer};
 
 #[automock]
 #[async_trait]
 pub trait WebsocketServerSender: Sync + Send {
     async fn do_send(&self, m: SessionMessage) -> Result<(), MailboxError>;
+    async fn close(&self, m: CloseSession) -> Result<(), MailboxError>;
 }
 
 pub struct WsSenderWrapper {
@@ -22,7 +24,13 @@ impl WsSenderWrapper {
 
 #[async_trait]
 impl WebsocketServerSender for WsSenderWrapper {
+    #[instrument(skip_all, name = "websocket.send", fields(session = %message.id))]
     async fn do_send(&self, message: SessionMessage) -> Result<(), MailboxError> {
         self.websocket_server.send(message).await
     }
+
+    #[instrument(skip_all, name = "websocket.close", fields(session = %message.id))]
+    async fn close(&self, message: CloseSession) -> Result<(), MailboxError> {
+        self.websocket_server.send(message).await
+    }
 }
@@ -10,11 +10,17 @@ use rand::{rngs::ThreadRng, Rng};
 #[rtype(result = "()")]
 pub struct Message(pub String);
 
+/// Asks a session to close its websocket connection.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Close;
+
 #[derive(Message)]
 #[rtype(result = "String")]
 pub struct Connect {
     pub id: String,
     pub addr: Recipient<Message>,
+    pub close: Recipient<Close>,
 }
 
 #[derive(Message)]
@@ -37,9 +43,27 @@ pub struct SessionMessage {
     pub message: String,
 }
 
+/// Closes the session of a user, used when the user is deleted.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseSession {
+    pub id: String,
+}
+
+/// Closes every connected session, used when the server is shutting down.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseAll;
+
+#[derive(Debug)]
+struct Session {
+    addr: Recipient<Message>,
+    close: Recipient<Close>,
+}
+
 #[derive(Debug)]
 pub struct WebsocketServer {
-    sessions: HashMap<String, Recipient<Message>>,
+    sessions: HashMap<String, Session>,
     rng: ThreadRng,
 }
 
@@ -72,7 +96,13 @@ impl Handler<Connect> for WebsocketServer {
 
         debug!("Someone joined: {}", id);
 
-        self.sessions.insert(id.clone(), msg.addr);
+        self.sessions.insert(
+            id.clone(),
+            Session {
+                addr: msg.addr,
+                close: msg.close,
+            },
+        );
 
         id
     }
@@ -85,7 +115,7 @@ impl Handler<SessionMessage> for WebsocketServer {
         let session_result = self.sessions.get(&msg.id);
 
         if let Some(session_result) = session_result {
-            session_result.do_send(Message(msg.message));
+            session_result.addr.do_send(Message(msg.message));
         } else {
             warn!("server tried to send message to unknown session {}", msg.id);
         }
@@ -123,3 +153,26 @@ impl Handler<Swap> for WebsocketServer {
         }
     }
 }
+
+impl Handler<CloseSession> for WebsocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseSession, _: &mut Context<Self>) {
+        if let Some(session) = self.sessions.remove(&msg.id) {
+            debug!("closing websocket session {}", msg.id);
+            session.close.do_send(Close);
+        }
+    }
+}
+
+impl Handler<CloseAll> for WebsocketServer {
+    type Result = ();
+
+    fn handle(&mut self, _: CloseAll, _: &mut Context<Self>) {
+        debug!("closing {} websocket sessions", self.sessions.len());
+
+        for (_, session) in self.sessions.drain() {
+            session.close.do_send(Close);
+        }
+    }
+}
@@ -3,12 +3,13 @@ use std::time::{Duration, Instant};
 
 use actix::prelude::*;
 use actix::{Actor, AsyncContext, StreamHandler};
+use actix_web::web;
 use actix_web_actors::ws;
-use log::warn;
+use log::{error, warn};
 
 use crate::http::services::auth_service::AuthService;
 
-use super::ws_server::{Connect, Disconnect, Message, Swap, WebsocketServer};
+use super::ws_server::{Close, Connect, Disconnect, Message, Swap, WebsocketServer};
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
@@ -71,7 +72,8 @@ impl Actor for WebsocketSession {
         self.addr
             .send(Connect {
                 id: self.id.clone(),
-                addr: addr.recipient(),
+                addr: addr.clone().recipient(),
+                close: addr.recipient(),
             })
             .into_actor(self)
             .then(|res, act, ctx| {
@@ -101,6 +103,15 @@ impl Handler<Message> for WebsocketSession {
     }
 }
 
+impl Handler<Close> for WebsocketSession {
+    type Result = ();
+
+    fn handle(&mut self, _: Close, ctx: &mut Self::Context) {
+        ctx.close(Some(ws::CloseCode::Away.into()));
+        ctx.stop();
+    }
+}
+
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
@@ -115,20 +126,26 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketSession
                     // if there is a token after the command /login check whether the token is valid
                     // if token is valid set the session as authenticated and set the session id with the user id
                     if let Some(token) = msg.split_once(' ').map(|x| x.1) {
-                        match self.auth_service.decode_token(token.to_owned()) {
-                            Ok(c) => {
-                                let new_id = c.sub.to_string();
-                                self.addr.do_send(Swap {
-                                    id: self.id.clone(),
-                                    new_id: new_id.clone(),
-                                });
-                                self.id = new_id;
-                                self.authenticated = true
-                            }
-                            Err(_) => {
-                                warn!("invalid authentication token {}", token);
+                        let auth_service = self.auth_service.clone();
+                        let token = token.to_owned();
+                        // the revocation store queries the database on a cache miss
+                        let decoded = web::block(move || auth_service.decode_token(token));
+
+                        ctx.spawn(fut::wrap_future::<_, Self>(decoded).map(|result, act, _| {
+                            match result {
+                                Ok(Ok(c)) => {
+                                    let new_id = c.sub.to_string();
+                                    act.addr.do_send(Swap {
+                                        id: act.id.clone(),
+                                        new_id: new_id.clone(),
+                                    });
+                                    act.id = new_id;
+                                    act.authenticated = true
+                                }
+                                Ok(Err(_)) => warn!("invalid authentication token"),
+                                Err(err) => error!("failed checking authentication token: {}", err),
                             }
-                        };
+                        }));
                     }
                 } else if self.authenticated {
                     self.hb = Instant::now();
@@ -4,12 +4,25 @@ pub mod broker;
 pub mod db;
 
 pub mod error;
+pub mod events;
 pub mod http;
 pub mod logger;
 pub mod serializer;
+pub mod shutdown;
+pub mod telemetry;
+
+#[cfg(feature = "mail")]
+pub mod mail;
 
 #[cfg(feature = "news")]
 pub mod news;
 
-#[cfg(feature = "broker")]
+#[cfg(feature = "database")]
+pub mod outbox;
+
+#[cfg(any(
+    feature = "broker",
+    feature = "memory-broker",
+    feature = "postgres-broker"
+))]
 pub mod pipeline;
@@ -4,6 +4,8 @@ use std::io::Write;
 use chrono::Local;
 use env_logger::Env;
 
+use crate::telemetry::current_trace_id;
+
 pub fn init_logger(logs_path: String) {
     let mut target = env_logger::Target::Stdout;
 
@@ -16,11 +18,16 @@ pub fn init_logger(logs_path: String) {
     env_logger::Builder::from_env(Env::default().default_filter_or("info"))
         .target(target)
         .format(|buf, record| {
+            // lines logged within a span carry its trace id
+            let trace = current_trace_id()
+                .map(|trace_id| format!(" trace_id={}", trace_id))
+                .unwrap_or_default();
             writeln!(
                 buf,
-                "{} {} {}:{} {}",
+                "{} {}{} {}:{} {}",
                 Local::now().format("%b %d %H:%M:%S"),
                 record.level(),
+                trace,
                 record.file_static().unwrap_or("unknown"),
                 record.line().unwrap_or(0),
                 record.args()
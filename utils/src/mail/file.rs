@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{Email, Mailer};
+use crate::error::MailError;
+
+/// Writes every email to a file of the directory instead of sending it, so local runs
+/// can follow the links they contain.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: &str) -> Self {
+        FileMailer {
+            dir: PathBuf::from(dir),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let now = Utc::now();
+        let path = self.dir.join(format!(
+            "{}-{}.eml",
+            now.format("%Y%m%d%H%M%S"),
+            Uuid::new_v4()
+        ));
+        let content = format!(
+            "Date: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            now.to_rfc2822(),
+            email.to,
+            email.subject,
+            email.body
+        );
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|err| MailError {
+                message: format!("failed creating {}: {}", self.dir.display(), err),
+            })?;
+        tokio::fs::write(&path, content)
+            .await
+            .map_err(|err| MailError {
+                message: format!("failed writing {}: {}", path.display(), err),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_writes_email_to_dir() {
+        let dir = std::env::temp_dir().join(format!("mails-{}", Uuid::new_v4()));
+        let mailer = FileMailer::new(dir.to_str().unwrap());
+
+        let result = mailer
+            .send(Email::new("alice@example.com", "Hello", "Hi Alice"))
+            .await;
+
+        assert!(result.is_ok());
+        let mut files = std::fs::read_dir(&dir).unwrap();
+        let content = std::fs::read_to_string(files.next().unwrap().unwrap().path()).unwrap();
+        assert!(content.contains("To: alice@example.com\nSubject: Hello\n\nHi Alice\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use mockall::automock;
+
+use crate::error::MailError;
+
+pub mod file;
+pub mod memory;
+pub mod smtp;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    /// Plain text body.
+    pub body: String,
+}
+
+impl Email {
+    pub fn new(to: &str, subject: &str, body: &str) -> Self {
+        Email {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+        }
+    }
+}
+
+/// Sends emails to the users.
+#[automock]
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+/// Builds the mailer selected by `MAILER`:
+/// - `smtp` sends through the server of `SMTP_URL`, from `MAIL_FROM`.
+/// - `file`, the default, writes the emails to `MAIL_DIR` for local runs.
+/// - `memory` keeps them in the process, for tests.
+pub fn from_env() -> Result<Arc<dyn Mailer>, MailError> {
+    match std::env::var("MAILER")
+        .unwrap_or_else(|_| String::from("file"))
+        .as_str()
+    {
+        "smtp" => {
+            let url =
+                std::env::var("SMTP_URL").map_err(|_| MailError::new("SMTP_URL must be set"))?;
+            let from =
+                std::env::var("MAIL_FROM").map_err(|_| MailError::new("MAIL_FROM must be set"))?;
+            Ok(Arc::new(smtp::SmtpMailer::new(&url, &from)?))
+        }
+        "file" => {
+            let dir = std::env::var("MAIL_DIR").unwrap_or_else(|_| String::from("mails"));
+            Ok(Arc::new(file::FileMailer::new(&dir)))
+        }
+        "memory" => Ok(Arc::new(memory::MemoryMailer::new())),
+        mailer => Err(MailError {
+            message: format!("unsupported mailer: {}", mailer),
+        }),
+    }
+}
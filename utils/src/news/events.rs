@@ -1 +1,53 @@
+use crate::error::SerializationError;
+use crate::events::Event;
+use crate::news::models::news::News;
+use crate::outbox::models::NewOutboxMessage;
+
 pub const NEWS_CREATED_EVENT: &str = "news_created";
+pub const NEWS_CREATED_VERSION: u32 = 1;
+
+/// Source of the events about news.
+pub const NEWS_EVENTS_SOURCE: &str = "news-scrapper";
+
+/// Builds the `news_created` outbox message, keyed by feed so the news of a feed are
+/// consumed in order.
+pub fn news_created(news: &News) -> Result<NewOutboxMessage, SerializationError> {
+    let event = Event::new(
+        NEWS_CREATED_EVENT,
+        NEWS_EVENTS_SOURCE,
+        NEWS_CREATED_VERSION,
+        news,
+    )?;
+
+    NewOutboxMessage::from_event(
+        NEWS_CREATED_EVENT,
+        news.feed_id.to_string().as_str(),
+        &event,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_news_created_is_keyed_by_feed_in_envelope() {
+        let news = News {
+            id: uuid::Uuid::new_v4(),
+            author: "author".to_string(),
+            url: "".to_string(),
+            title: "news".to_string(),
+            publish_date: None,
+            feed_id: uuid::Uuid::new_v4(),
+        };
+
+        let message = news_created(&news).unwrap();
+        let event = Event::from_json(&message.payload).unwrap();
+
+        assert_eq!(message.topic, NEWS_CREATED_EVENT);
+        assert_eq!(message.key, news.feed_id.to_string());
+        assert_eq!(event.event_type, NEWS_CREATED_EVENT);
+        assert_eq!(event.dataversion, NEWS_CREATED_VERSION);
+        assert_eq!(event.data::<News>().unwrap(), news);
+    }
+}
@@ -4,6 +4,7 @@ use crate::db::PgPool;
 use crate::error::DatabaseError;
 use diesel::prelude::*;
 use mockall::automock;
+use tracing::instrument;
 use uuid::Uuid;
 
 use crate::news::models::feed::Feed;
@@ -29,6 +30,7 @@ impl FeedDieselRepository {
 }
 
 impl FeedRepository for FeedDieselRepository {
+    #[instrument(skip_all, name = "feed_repository.create")]
     fn create(&self, rss_feed: &Feed) -> Result<Feed, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -40,6 +42,7 @@ impl FeedRepository for FeedDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "feed_repository.find_by_id")]
     fn find_by_id(&self, feed_id: Uuid) -> Result<Option<Feed>, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -52,6 +55,7 @@ impl FeedRepository for FeedDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "feed_repository.list")]
     fn list(&self) -> Result<Vec<Feed>, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -62,6 +66,7 @@ impl FeedRepository for FeedDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "feed_repository.delete")]
     fn delete(&self, feed_id: Uuid) -> Result<usize, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
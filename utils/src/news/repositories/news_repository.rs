@@ -4,10 +4,12 @@ use crate::db::PgPool;
 use crate::error::DatabaseError;
 use diesel::prelude::*;
 use mockall::automock;
+use tracing::instrument;
 use uuid::Uuid;
 
 use crate::news::models::news::News;
 use crate::news::schema::{feeds, news, subscriptions};
+use crate::outbox::{models::NewOutboxMessage, repository::enqueue};
 
 #[automock]
 pub trait NewsRepository: Send + Sync {
@@ -19,7 +21,8 @@ pub trait NewsRepository: Send + Sync {
         title: Option<String>,
         feed_id: Option<Uuid>,
     ) -> Result<Option<News>, DatabaseError>;
-    fn create(&self, news: &News) -> Result<News, DatabaseError>;
+    /// Inserts the news and stores the events in the outbox in the same transaction.
+    fn create(&self, news: &News, events: &[NewOutboxMessage]) -> Result<News, DatabaseError>;
     fn delete(&self, news_id: Uuid) -> Result<usize, DatabaseError>;
 }
 
@@ -34,17 +37,23 @@ impl NewsDieselRepository {
 }
 
 impl NewsRepository for NewsDieselRepository {
-    fn create(&self, news: &News) -> Result<News, DatabaseError> {
+    #[instrument(skip_all, name = "news_repository.create")]
+    fn create(&self, news: &News, events: &[NewOutboxMessage]) -> Result<News, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
-        diesel::insert_into(news::table)
-            .values(news)
-            .get_result(&mut conn)
-            .map_err(|err| DatabaseError {
-                message: err.to_string(),
-            })
+        conn.transaction(|conn| {
+            let news = diesel::insert_into(news::table)
+                .values(news)
+                .get_result(conn)?;
+            enqueue(conn, events)?;
+            Ok(news)
+        })
+        .map_err(|err: diesel::result::Error| DatabaseError {
+            message: err.to_string(),
+        })
     }
 
+    #[instrument(skip_all, name = "news_repository.find_by_id")]
     fn find_by_id(&self, news_id: Uuid) -> Result<Option<News>, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -57,6 +66,7 @@ impl NewsRepository for NewsDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "news_repository.find_by_fields")]
     fn find_by_fields(
         &self,
         title: Option<String>,
@@ -82,6 +92,7 @@ impl NewsRepository for NewsDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "news_repository.list")]
     fn list(&self) -> Result<Vec<News>, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -92,6 +103,7 @@ impl NewsRepository for NewsDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "news_repository.find_by_user_id")]
     fn find_by_user_id(&self, user_id: Uuid) -> Result<Vec<News>, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -106,6 +118,7 @@ impl NewsRepository for NewsDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "news_repository.delete")]
     fn delete(&self, news_id: Uuid) -> Result<usize, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -4,6 +4,7 @@ use crate::db::PgPool;
 use crate::error::DatabaseError;
 use diesel::prelude::*;
 use mockall::automock;
+use tracing::instrument;
 use uuid::Uuid;
 
 use crate::news::models::subscription::Subscription;
@@ -20,6 +21,8 @@ pub trait SubscriptionRepository: Send + Sync {
     ) -> Result<Option<Subscription>, DatabaseError>;
     fn create(&self, subscription: &Subscription) -> Result<Subscription, DatabaseError>;
     fn delete(&self, feed_id: Uuid, user_id: Uuid) -> Result<usize, DatabaseError>;
+    /// Deletes every subscription of the user.
+    fn delete_by_user(&self, user_id: Uuid) -> Result<usize, DatabaseError>;
 }
 
 pub struct SubscriptionsDieselRepository {
@@ -33,6 +36,7 @@ impl SubscriptionsDieselRepository {
 }
 
 impl SubscriptionRepository for SubscriptionsDieselRepository {
+    #[instrument(skip_all, name = "subscription_repository.create")]
     fn create(&self, subscription: &Subscription) -> Result<Subscription, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -44,6 +48,7 @@ impl SubscriptionRepository for SubscriptionsDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "subscription_repository.find_by_id")]
     fn find_by_id(
         &self,
         feed_id: Uuid,
@@ -64,6 +69,7 @@ impl SubscriptionRepository for SubscriptionsDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "subscription_repository.list_by_user")]
     fn list_by_user(&self, user_id: Uuid) -> Result<Vec<Subscription>, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -75,6 +81,7 @@ impl SubscriptionRepository for SubscriptionsDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "subscription_repository.list_by_feed")]
     fn list_by_feed(&self, feed_id: Uuid) -> Result<Vec<Subscription>, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -86,6 +93,7 @@ impl SubscriptionRepository for SubscriptionsDieselRepository {
             })
     }
 
+    #[instrument(skip_all, name = "subscription_repository.delete")]
     fn delete(&self, feed_id: Uuid, user_id: Uuid) -> Result<usize, DatabaseError> {
         let mut conn = self.pool.get().unwrap();
 
@@ -101,4 +109,15 @@ impl SubscriptionRepository for SubscriptionsDieselRepository {
             message: err.to_string(),
         })
     }
+
+    #[instrument(skip_all, name = "subscription_repository.delete_by_user")]
+    fn delete_by_user(&self, user_id: Uuid) -> Result<usize, DatabaseError> {
+        let mut conn = self.pool.get().unwrap();
+
+        diesel::delete(subscriptions::table.filter(subscriptions::user_id.eq(user_id)))
+            .execute(&mut conn)
+            .map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })
+    }
 }
@@ -1,2 +1 @@
-pub mod events_service;
 pub mod news_service;
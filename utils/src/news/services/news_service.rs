@@ -5,6 +5,7 @@ use mockall::automock;
 use std::sync::Arc;
 
 use crate::news::{
+    events,
     models::{feed::Feed, news::News},
     repositories::{
         feed_repository::FeedRepository, news_repository::NewsRepository,
@@ -12,8 +13,6 @@ use crate::news::{
     },
 };
 
-use super::events_service::EventService;
-
 #[automock]
 #[async_trait]
 pub trait NewsService: Send + Sync {
@@ -25,7 +24,6 @@ pub struct Service {
     pub feed_repo: Arc<dyn FeedRepository>,
     pub news_repo: Arc<dyn NewsRepository>,
     pub subscriptions_repo: Arc<dyn SubscriptionRepository>,
-    pub events_service: Arc<dyn EventService>,
 }
 
 impl Service {
@@ -33,13 +31,11 @@ impl Service {
         feed_repo: Arc<dyn FeedRepository>,
         news_repo: Arc<dyn NewsRepository>,
         subscriptions_repo: Arc<dyn SubscriptionRepository>,
-        events_service: Arc<dyn EventService>,
     ) -> Self {
         Service {
             feed_repo,
             news_repo,
             subscriptions_repo,
-            events_service,
         }
     }
 }
@@ -53,12 +49,13 @@ impl NewsService for Service {
 
         return match db_news {
             None => {
-                let news = self.news_repo.create(news)?;
+                // stored with the news so the event is published even if the broker is down
+                let news_created = events::news_created(news)?;
+                let news = self.news_repo.create(news, &[news_created])?;
                 info!(
                     "News with title {} of feed {} inserted!",
                     news.title, news.feed_id
                 );
-                self.events_service.news_created(&news).await?;
                 Ok(news)
             }
             Some(news) => {
@@ -83,7 +80,6 @@ mod tests {
                 feed_repository::MockFeedRepository, news_repository::MockNewsRepository,
                 subscription_repository::MockSubscriptionRepository,
             },
-            services::events_service::MockEventService,
         },
     };
 
@@ -95,7 +91,6 @@ mod tests {
     async fn test_insert_news_success() {
         // Arrange
         let mut news_repo = MockNewsRepository::new();
-        let mut events_service = MockEventService::new();
         let feeds_repo = MockFeedRepository::new();
         let subscriptions_repo = MockSubscriptionRepository::new();
 
@@ -115,24 +110,21 @@ mod tests {
             .times(1)
             .returning(move |_, _| Ok(None));
 
+        let expected_news = news.clone();
         news_repo
             .expect_create()
-            .with(eq(news.clone()))
-            .times(1)
-            .return_once(move |_| Ok(inserted_news.clone()));
-
-        let inserted_news = news.clone();
-        events_service
-            .expect_news_created()
-            .with(eq(inserted_news.clone()))
+            .withf(move |news, events| {
+                news == &expected_news
+                    && events.len() == 1
+                    && events[0].topic == events::NEWS_CREATED_EVENT
+            })
             .times(1)
-            .returning(|_| Ok(()));
+            .return_once(move |_, _| Ok(inserted_news.clone()));
 
         let service = Service::new(
             Arc::new(feeds_repo),
             Arc::new(news_repo),
             Arc::new(subscriptions_repo),
-            Arc::new(events_service),
         );
 
         // Act
@@ -147,7 +139,6 @@ mod tests {
     async fn test_insert_news_already_exists() {
         // Arrange
         let mut news_repo = MockNewsRepository::new();
-        let events_service = MockEventService::new();
         let feeds_repo = MockFeedRepository::new();
         let subscriptions_repo = MockSubscriptionRepository::new();
 
@@ -170,7 +161,6 @@ mod tests {
             Arc::new(feeds_repo),
             Arc::new(news_repo),
             Arc::new(subscriptions_repo),
-            Arc::new(events_service),
         );
 
         // Act
@@ -208,7 +198,6 @@ mod tests {
             Arc::new(feed_repo),
             Arc::new(MockNewsRepository::new()),
             Arc::new(MockSubscriptionRepository::new()),
-            Arc::new(MockEventService::new()),
         );
 
         // Act
@@ -236,7 +225,6 @@ mod tests {
             Arc::new(feed_repo),
             Arc::new(MockNewsRepository::new()),
             Arc::new(MockSubscriptionRepository::new()),
-            Arc::new(MockEventService::new()),
         );
 
         // Act
@@ -0,0 +1,9 @@
+pub mod models;
+#[cfg(any(
+    feature = "broker",
+    feature = "memory-broker",
+    feature = "postgres-broker"
+))]
+pub mod relay;
+pub mod repository;
+pub mod schema;
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+use crate::error::SerializationError;
+use crate::events::{Event, EVENT_ID_HEADER};
+use crate::outbox::schema::outbox;
+use crate::telemetry::inject_context;
+
+/// Event waiting in the outbox table to be published.
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable)]
+#[diesel(table_name = outbox)]
+pub struct OutboxMessage {
+    pub id: i64,
+    pub topic: String,
+    pub key: String,
+    pub payload: String,
+    pub headers: String,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// Set once the relay gave up on the message, it's kept but never published.
+    pub failed_at: Option<DateTime<Utc>>,
+}
+
+impl OutboxMessage {
+    pub fn headers(&self) -> Result<HashMap<String, String>, SerializationError> {
+        serde_json::from_str(&self.headers).map_err(|err| {
+            SerializationError::new(
+                format!("failed to deserialize outbox message headers: {}", err).as_str(),
+            )
+        })
+    }
+}
+
+/// Event to store in the outbox in the same transaction as the change it describes.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[diesel(table_name = outbox)]
+pub struct NewOutboxMessage {
+    pub topic: String,
+    pub key: String,
+    pub payload: String,
+    pub headers: String,
+}
+
+impl NewOutboxMessage {
+    pub fn from_event(topic: &str, key: &str, event: &Event) -> Result<Self, SerializationError> {
+        let mut headers = HashMap::from([(EVENT_ID_HEADER.to_string(), event.id.to_string())]);
+        // links the message, published later by the relay, to the trace that produced it
+        inject_context(&mut headers);
+
+        Ok(NewOutboxMessage {
+            topic: topic.to_string(),
+            key: key.to_string(),
+            payload: event.to_json()?,
+            headers: serde_json::to_string(&headers).map_err(|err| {
+                SerializationError::new(
+                    format!("failed to serialize outbox message headers: {}", err).as_str(),
+                )
+            })?,
+        })
+    }
+}
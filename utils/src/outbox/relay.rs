@@ -0,0 +1,413 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use log::{error, info};
+use tokio_util::sync::CancellationToken;
+
+use crate::outbox::models::OutboxMessage;
+use crate::outbox::repository::OutboxRepository;
+use crate::pipeline::producer::{Producer, ProducerMessage};
+
+const DEFAULT_BATCH_SIZE: i64 = 100;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long claimed messages stay locked, after that another relay may publish them again.
+const DEFAULT_LOCK_DURATION: Duration = Duration::from_secs(30);
+/// How long sent messages are kept, to look into what was published.
+const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Publications of a message before it's given up on, counting the first one.
+const DEFAULT_MAX_ATTEMPTS: i32 = 10;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Publishes the messages stored in the outbox and marks them as sent.
+///
+/// Messages are published oldest first. A message that fails is retried later, doubling
+/// the backoff each time, and the messages of its key wait for it so they keep their
+/// order, the other keys go on. After the max attempts it's given up on and left in the
+/// outbox as failed. Delivery is at least once. Sent messages are deleted once they are
+/// older than the retention.
+pub struct OutboxRelay {
+    repo: Arc<dyn OutboxRepository>,
+    producer: Arc<dyn Producer>,
+    batch_size: i64,
+    poll_interval: Duration,
+    lock_duration: Duration,
+    retention: Duration,
+    max_attempts: i32,
+    retry_backoff: Duration,
+}
+
+impl OutboxRelay {
+    pub fn new(repo: Arc<dyn OutboxRepository>, producer: Arc<dyn Producer>) -> Self {
+        OutboxRelay {
+            repo,
+            producer,
+            batch_size: DEFAULT_BATCH_SIZE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            lock_duration: DEFAULT_LOCK_DURATION,
+            retention: DEFAULT_RETENTION,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// `max_attempts` counts the first publication.
+    pub fn with_max_attempts(mut self, max_attempts: i32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Relays messages until `shutdown` is cancelled, the batch in progress is finished first.
+    pub async fn start(&self, shutdown: CancellationToken) {
+        let mut purged_at: Option<Instant> = None;
+
+        while !shutdown.is_cancelled() {
+            let purge_due = match purged_at {
+                Some(purged_at) => purged_at.elapsed() >= PURGE_INTERVAL,
+                None => true,
+            };
+            if purge_due {
+                self.purge().await;
+                purged_at = Some(Instant::now());
+            }
+
+            // keep draining while there is a backlog
+            if self.relay_batch().await == self.batch_size as usize {
+                continue;
+            }
+
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(self.poll_interval) => {}
+            }
+        }
+
+        info!("outbox relay stopped");
+    }
+
+    /// Publishes one batch of pending messages and returns how many were sent.
+    pub async fn relay_batch(&self) -> usize {
+        let messages = match self.repo.claim(self.batch_size, self.lock_duration).await {
+            Ok(messages) => messages,
+            Err(err) => {
+                error!("failed claiming outbox messages: {}", err.message);
+                return 0;
+            }
+        };
+
+        let mut sent = vec![];
+        let mut held_back = vec![];
+        let mut failed_keys = HashSet::new();
+        for message in &messages {
+            let key = (message.topic.as_str(), message.key.as_str());
+            if failed_keys.contains(&key) {
+                held_back.push(message.id);
+                continue;
+            }
+
+            match self.publish(message).await {
+                Ok(()) => sent.push(message.id),
+                Err(err) => {
+                    error!("failed relaying outbox message {}: {}", message.id, err);
+                    self.record_failure(message, &err).await;
+                    failed_keys.insert(key);
+                }
+            }
+        }
+
+        if !held_back.is_empty() {
+            if let Err(err) = self.repo.release(&held_back).await {
+                // they are claimed again once the lock expires
+                error!("failed releasing outbox messages: {}", err.message);
+            }
+        }
+
+        if !sent.is_empty() {
+            if let Err(err) = self.repo.mark_sent(&sent).await {
+                // they are published again once the lock expires
+                error!("failed marking outbox messages as sent: {}", err.message);
+            }
+        }
+
+        sent.len()
+    }
+
+    /// Deletes the messages sent before the retention and returns how many were deleted.
+    pub async fn purge(&self) -> usize {
+        let sent_before = match chrono::Duration::from_std(self.retention)
+            .ok()
+            .and_then(|retention| Utc::now().checked_sub_signed(retention))
+        {
+            Some(sent_before) => sent_before,
+            None => return 0,
+        };
+
+        match self.repo.purge_sent(sent_before).await {
+            Ok(purged) => {
+                if purged > 0 {
+                    info!("purged {} sent outbox messages", purged);
+                }
+                purged
+            }
+            Err(err) => {
+                error!("failed purging sent outbox messages: {}", err.message);
+                0
+            }
+        }
+    }
+
+    /// Schedules the retry of the message, or gives up on it after the max attempts.
+    async fn record_failure(&self, message: &OutboxMessage, error: &str) {
+        let attempts = message.attempts + 1;
+        let result = if attempts >= self.max_attempts {
+            error!(
+                "giving up on outbox message {} after {} attempts",
+                message.id, attempts
+            );
+            self.repo.fail(message.id, error).await
+        } else {
+            let retry_at = chrono::Duration::from_std(self.backoff(attempts))
+                .ok()
+                .and_then(|backoff| Utc::now().checked_add_signed(backoff))
+                .unwrap_or_else(Utc::now);
+            self.repo.retry(message.id, error, retry_at).await
+        };
+
+        if let Err(err) = result {
+            // it's published again once the lock expires
+            error!(
+                "failed recording the failure of outbox message {}: {}",
+                message.id, err.message
+            );
+        }
+    }
+
+    /// Backoff before the next attempt, doubled by every failed one.
+    fn backoff(&self, attempts: i32) -> Duration {
+        // far beyond the max backoff already
+        let doublings = attempts.clamp(1, 21) - 1;
+
+        self.retry_backoff
+            .saturating_mul(1 << doublings)
+            .min(MAX_RETRY_BACKOFF)
+    }
+
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), String> {
+        let headers = message.headers().map_err(|err| err.message)?;
+
+        self.producer
+            .send(ProducerMessage {
+                topic: message.topic.clone(),
+                key: message.key.clone(),
+                payload: message.payload.clone(),
+                headers,
+            })
+            .await
+            .map_err(|err| err.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use mockall::predicate::eq;
+
+    use super::*;
+    use crate::error::{BrokerError, DatabaseError};
+    use crate::outbox::repository::MockOutboxRepository;
+    use crate::pipeline::producer::MockProducer;
+
+    fn outbox_message(id: i64) -> OutboxMessage {
+        keyed_outbox_message(id, "feed")
+    }
+
+    fn keyed_outbox_message(id: i64, key: &str) -> OutboxMessage {
+        OutboxMessage {
+            id,
+            topic: "news_created".to_string(),
+            key: key.to_string(),
+            payload: format!("news {}", id),
+            headers: "{}".to_string(),
+            created_at: Utc::now(),
+            sent_at: None,
+            attempts: 0,
+            locked_until: None,
+            last_error: None,
+            failed_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relay_batch_marks_published_messages_as_sent() {
+        let mut repo = MockOutboxRepository::new();
+        let mut producer = MockProducer::new();
+
+        repo.expect_claim()
+            .returning(|_, _| Ok(vec![outbox_message(1), outbox_message(2)]));
+        producer.expect_send().times(2).returning(|_| Ok(()));
+        repo.expect_mark_sent()
+            .withf(|ids| ids == [1, 2])
+            .times(1)
+            .returning(|_| Ok(2));
+        repo.expect_release().times(0);
+
+        let relay = OutboxRelay::new(Arc::new(repo), Arc::new(producer));
+
+        assert_eq!(relay.relay_batch().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_relay_batch_holds_back_the_key_of_a_failure() {
+        let mut repo = MockOutboxRepository::new();
+        let mut producer = MockProducer::new();
+
+        repo.expect_claim().returning(|_, _| {
+            Ok(vec![
+                keyed_outbox_message(1, "a"),
+                keyed_outbox_message(2, "a"),
+                keyed_outbox_message(3, "b"),
+                keyed_outbox_message(4, "a"),
+            ])
+        });
+        producer
+            .expect_send()
+            .withf(|message| message.payload == "news 2")
+            .times(1)
+            .returning(|_| Err(BrokerError::new("broker is down")));
+        producer
+            .expect_send()
+            .withf(|message| message.payload == "news 1" || message.payload == "news 3")
+            .times(2)
+            .returning(|_| Ok(()));
+        repo.expect_retry()
+            .withf(|id, error, retry_at| {
+                let backoff = *retry_at - Utc::now();
+                *id == 2
+                    && error == "broker is down"
+                    && backoff > chrono::Duration::zero()
+                    && backoff <= chrono::Duration::seconds(1)
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(1));
+        repo.expect_release()
+            .withf(|ids| ids == [4])
+            .times(1)
+            .returning(|_| Ok(1));
+        repo.expect_mark_sent()
+            .withf(|ids| ids == [1, 3])
+            .times(1)
+            .returning(|_| Ok(2));
+        repo.expect_fail().times(0);
+
+        let relay = OutboxRelay::new(Arc::new(repo), Arc::new(producer));
+
+        assert_eq!(relay.relay_batch().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_relay_batch_gives_up_after_max_attempts() {
+        let mut repo = MockOutboxRepository::new();
+        let mut producer = MockProducer::new();
+
+        repo.expect_claim().returning(|_, _| {
+            let mut message = outbox_message(1);
+            message.attempts = 2;
+            Ok(vec![message])
+        });
+        producer
+            .expect_send()
+            .returning(|_| Err(BrokerError::new("message too large")));
+        repo.expect_fail()
+            .with(eq(1), eq("message too large"))
+            .times(1)
+            .returning(|_, _| Ok(1));
+        repo.expect_retry().times(0);
+
+        let relay = OutboxRelay::new(Arc::new(repo), Arc::new(producer)).with_max_attempts(3);
+
+        assert_eq!(relay.relay_batch().await, 0);
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_max() {
+        let relay = OutboxRelay::new(
+            Arc::new(MockOutboxRepository::new()),
+            Arc::new(MockProducer::new()),
+        )
+        .with_retry_backoff(Duration::from_secs(2));
+
+        assert_eq!(relay.backoff(1), Duration::from_secs(2));
+        assert_eq!(relay.backoff(2), Duration::from_secs(4));
+        assert_eq!(relay.backoff(4), Duration::from_secs(16));
+        assert_eq!(relay.backoff(20), MAX_RETRY_BACKOFF);
+        assert_eq!(relay.backoff(i32::MAX), MAX_RETRY_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_relay_batch_claim_error() {
+        let mut repo = MockOutboxRepository::new();
+
+        repo.expect_claim()
+            .with(eq(10), eq(DEFAULT_LOCK_DURATION))
+            .returning(|_, _| Err(DatabaseError::new("db is down")));
+
+        let relay =
+            OutboxRelay::new(Arc::new(repo), Arc::new(MockProducer::new())).with_batch_size(10);
+
+        assert_eq!(relay.relay_batch().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_purge_deletes_messages_sent_before_retention() {
+        let mut repo = MockOutboxRepository::new();
+        let retention = Duration::from_secs(60);
+
+        repo.expect_purge_sent()
+            .withf(move |sent_before| {
+                let age = Utc::now() - *sent_before;
+                age >= chrono::Duration::seconds(60) && age < chrono::Duration::seconds(70)
+            })
+            .times(1)
+            .returning(|_| Ok(3));
+
+        let relay = OutboxRelay::new(Arc::new(repo), Arc::new(MockProducer::new()))
+            .with_retention(retention);
+
+        assert_eq!(relay.purge().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_purge_error() {
+        let mut repo = MockOutboxRepository::new();
+
+        repo.expect_purge_sent()
+            .returning(|_| Err(DatabaseError::new("db is down")));
+
+        let relay = OutboxRelay::new(Arc::new(repo), Arc::new(MockProducer::new()));
+
+        assert_eq!(relay.purge().await, 0);
+    }
+}
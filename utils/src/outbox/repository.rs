@@ -0,0 +1,268 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::dsl::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::sql_types::{Array, BigInt, Bool, Integer, Timestamptz};
+use mockall::automock;
+
+use crate::db::{run, PgPool};
+use crate::error::DatabaseError;
+use crate::outbox::models::{NewOutboxMessage, OutboxMessage};
+use crate::outbox::schema::outbox;
+
+/// Stores events in the outbox, to be called inside the transaction that changes the data
+/// the events describe.
+pub fn enqueue(
+    conn: &mut PgConnection,
+    messages: &[NewOutboxMessage],
+) -> Result<usize, diesel::result::Error> {
+    diesel::insert_into(outbox::table)
+        .values(messages)
+        .execute(conn)
+}
+
+/// Locks the keys of the next pending messages for the transaction, in the same order in
+/// every relay so concurrent claims can't deadlock.
+const LOCK_PENDING_KEYS: &str = "SELECT keys.lock_key FROM ( \
+    SELECT DISTINCT hashtext(topic || '/' || key) AS lock_key FROM ( \
+        SELECT topic, key FROM outbox \
+        WHERE sent_at IS NULL AND failed_at IS NULL \
+        AND (locked_until IS NULL OR locked_until < $1) \
+        ORDER BY id LIMIT $2 \
+    ) pending \
+    ORDER BY lock_key \
+) keys \
+CROSS JOIN LATERAL (SELECT pg_advisory_xact_lock(keys.lock_key)) locked";
+
+#[derive(QueryableByName)]
+struct LockedKey {
+    #[diesel(sql_type = Integer)]
+    lock_key: i32,
+}
+
+#[automock]
+#[async_trait]
+pub trait OutboxRepository: Send + Sync {
+    /// Locks up to `limit` pending messages for `lock_for`, oldest first. Messages locked by
+    /// another relay or waiting for a retry are skipped, and so are the messages of their key
+    /// that come after them. Concurrent claims of the same keys run one after the other.
+    async fn claim(
+        &self,
+        limit: i64,
+        lock_for: Duration,
+    ) -> Result<Vec<OutboxMessage>, DatabaseError>;
+    async fn mark_sent(&self, ids: &[i64]) -> Result<usize, DatabaseError>;
+    /// Unlocks messages that were claimed but not published, they can be claimed again.
+    async fn release(&self, ids: &[i64]) -> Result<usize, DatabaseError>;
+    /// Records a failed publication, the message is claimed again after `retry_at`.
+    async fn retry(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: DateTime<Utc>,
+    ) -> Result<usize, DatabaseError>;
+    /// Records a failed publication and gives up on the message, it's never published.
+    async fn fail(&self, id: i64, error: &str) -> Result<usize, DatabaseError>;
+    /// Deletes the messages sent before `sent_before`.
+    async fn purge_sent(&self, sent_before: DateTime<Utc>) -> Result<usize, DatabaseError>;
+}
+
+pub struct OutboxDieselRepository {
+    pool: Arc<PgPool>,
+}
+
+impl OutboxDieselRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        OutboxDieselRepository { pool }
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for OutboxDieselRepository {
+    async fn claim(
+        &self,
+        limit: i64,
+        lock_for: Duration,
+    ) -> Result<Vec<OutboxMessage>, DatabaseError> {
+        let pool = self.pool.clone();
+        let now = Utc::now();
+        let locked_until = now
+            + chrono::Duration::from_std(lock_for).map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })?;
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            let mut messages = conn
+                .transaction(|conn| {
+                    // waits for the claims of the same keys to commit, so the messages
+                    // they locked hold back the later ones of their key
+                    let keys: Vec<i32> = diesel::sql_query(LOCK_PENDING_KEYS)
+                        .bind::<Timestamptz, _>(now)
+                        .bind::<BigInt, _>(limit)
+                        .load::<LockedKey>(conn)?
+                        .into_iter()
+                        .map(|key| key.lock_key)
+                        .collect();
+
+                    let ids: Vec<i64> = outbox::table
+                        .select(outbox::id)
+                        .filter(outbox::sent_at.is_null())
+                        .filter(outbox::failed_at.is_null())
+                        .filter(
+                            outbox::locked_until
+                                .is_null()
+                                .or(outbox::locked_until.lt(now)),
+                        )
+                        // the messages of a key are published in order, none of them may go
+                        // ahead of an earlier one that is locked
+                        .filter(
+                            sql::<Bool>(
+                                "NOT EXISTS (SELECT 1 FROM outbox earlier \
+                                WHERE earlier.topic = outbox.topic AND earlier.key = outbox.key \
+                                AND earlier.id < outbox.id AND earlier.sent_at IS NULL \
+                                AND earlier.failed_at IS NULL AND earlier.locked_until >= ",
+                            )
+                            .bind::<Timestamptz, _>(now)
+                            .sql(")"),
+                        )
+                        .filter(
+                            sql::<Bool>("hashtext(outbox.topic || '/' || outbox.key) = ANY(")
+                                .bind::<Array<Integer>, _>(keys)
+                                .sql(")"),
+                        )
+                        .order(outbox::id.asc())
+                        .limit(limit)
+                        .for_update()
+                        .skip_locked()
+                        .load(conn)?;
+
+                    diesel::update(outbox::table.filter(outbox::id.eq_any(ids)))
+                        .set(outbox::locked_until.eq(locked_until))
+                        .get_results::<OutboxMessage>(conn)
+                })
+                .map_err(|err: diesel::result::Error| DatabaseError {
+                    message: err.to_string(),
+                })?;
+
+            messages.sort_by_key(|message| message.id);
+
+            Ok(messages)
+        })
+        .await
+    }
+
+    async fn mark_sent(&self, ids: &[i64]) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+        let ids = ids.to_vec();
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            diesel::update(outbox::table.filter(outbox::id.eq_any(ids)))
+                .set((
+                    outbox::sent_at.eq(Utc::now()),
+                    outbox::locked_until.eq(None::<DateTime<Utc>>),
+                ))
+                .execute(&mut conn)
+                .map_err(|err| DatabaseError {
+                    message: err.to_string(),
+                })
+        })
+        .await
+    }
+
+    async fn release(&self, ids: &[i64]) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+        let ids = ids.to_vec();
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            diesel::update(outbox::table.filter(outbox::id.eq_any(ids)))
+                .set(outbox::locked_until.eq(None::<DateTime<Utc>>))
+                .execute(&mut conn)
+                .map_err(|err| DatabaseError {
+                    message: err.to_string(),
+                })
+        })
+        .await
+    }
+
+    async fn retry(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: DateTime<Utc>,
+    ) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+        let error = error.to_string();
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            diesel::update(outbox::table.find(id))
+                .set((
+                    outbox::attempts.eq(outbox::attempts + 1),
+                    outbox::locked_until.eq(retry_at),
+                    outbox::last_error.eq(error),
+                ))
+                .execute(&mut conn)
+                .map_err(|err| DatabaseError {
+                    message: err.to_string(),
+                })
+        })
+        .await
+    }
+
+    async fn fail(&self, id: i64, error: &str) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+        let error = error.to_string();
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            diesel::update(outbox::table.find(id))
+                .set((
+                    outbox::attempts.eq(outbox::attempts + 1),
+                    outbox::locked_until.eq(None::<DateTime<Utc>>),
+                    outbox::last_error.eq(error),
+                    outbox::failed_at.eq(Utc::now()),
+                ))
+                .execute(&mut conn)
+                .map_err(|err| DatabaseError {
+                    message: err.to_string(),
+                })
+        })
+        .await
+    }
+
+    async fn purge_sent(&self, sent_before: DateTime<Utc>) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            diesel::delete(outbox::table.filter(outbox::sent_at.lt(sent_before)))
+                .execute(&mut conn)
+                .map_err(|err| DatabaseError {
+                    message: err.to_string(),
+                })
+        })
+        .await
+    }
+}
+
+fn connection(
+    pool: &PgPool,
+) -> Result<PooledConnection<ConnectionManager<PgConnection>>, DatabaseError> {
+    pool.get().map_err(|err| DatabaseError {
+        message: err.to_string(),
+    })
+}
@@ -0,0 +1,17 @@
+use diesel::table;
+
+table! {
+  outbox (id) {
+      id -> Int8,
+      topic -> Text,
+      key -> Text,
+      payload -> Text,
+      headers -> Text,
+      created_at -> Timestamptz,
+      sent_at -> Nullable<Timestamptz>,
+      attempts -> Int4,
+      locked_until -> Nullable<Timestamptz>,
+      last_error -> Nullable<Text>,
+      failed_at -> Nullable<Timestamptz>,
+  }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+#[cfg(feature = "broker")]
+use crate::broker::{self, BrokerConfig};
+use crate::error::BrokerError;
+use crate::pipeline::{consumer::Consumer, producer::Producer};
+#[cfg(feature = "broker")]
+use crate::pipeline::{consumer::KafkaConsumer, producer::KafkaProducer};
+#[cfg(feature = "postgres-broker")]
+use crate::{
+    db::connect_db,
+    pipeline::postgres_broker::{
+        self, consumer::PostgresConsumer, producer::PostgresProducer, PostgresBrokerConfig,
+    },
+};
+
+/// Broker the services publish and consume events through, selected with `BROKER_BACKEND`.
+#[derive(Debug, Clone)]
+pub enum BrokerBackend {
+    #[cfg(feature = "broker")]
+    Kafka(BrokerConfig),
+    #[cfg(feature = "postgres-broker")]
+    Postgres(PostgresBrokerConfig),
+}
+
+impl BrokerBackend {
+    /// Reads `BROKER_BACKEND`, `kafka` by default or `postgres`, and the settings of that backend.
+    pub fn from_env(service: &str) -> Result<Self, BrokerError> {
+        let backend = std::env::var("BROKER_BACKEND").unwrap_or_else(|_| String::from("kafka"));
+
+        match backend.as_str() {
+            #[cfg(feature = "broker")]
+            "kafka" => Ok(BrokerBackend::Kafka(BrokerConfig::from_env(service)?)),
+            #[cfg(feature = "postgres-broker")]
+            "postgres" => Ok(BrokerBackend::Postgres(PostgresBrokerConfig::from_env(
+                service,
+            )?)),
+            backend => Err(BrokerError {
+                message: format!("unsupported broker backend: {}", backend),
+            }),
+        }
+    }
+
+    pub fn group_id(&self) -> &str {
+        match self {
+            #[cfg(feature = "broker")]
+            BrokerBackend::Kafka(config) => &config.group_id,
+            #[cfg(feature = "postgres-broker")]
+            BrokerBackend::Postgres(config) => &config.group_id,
+        }
+    }
+
+    /// Creates the registered topics on Kafka, or checks the queue tables on Postgres.
+    #[cfg_attr(not(feature = "broker"), allow(unused_variables))]
+    pub async fn ensure_topics(&self, topics: &[&str]) -> Result<(), BrokerError> {
+        match self {
+            #[cfg(feature = "broker")]
+            BrokerBackend::Kafka(config) => {
+                broker::topics::ensure_registered_topics(config, topics).await
+            }
+            #[cfg(feature = "postgres-broker")]
+            BrokerBackend::Postgres(config) => {
+                postgres_broker::check_schema(&connect_db(config.database_url.clone()))
+            }
+        }
+    }
+
+    pub fn create_producer(&self) -> Result<Arc<dyn Producer>, BrokerError> {
+        match self {
+            #[cfg(feature = "broker")]
+            BrokerBackend::Kafka(config) => Ok(Arc::new(KafkaProducer::new(
+                broker::create_producer(config)?,
+            ))),
+            #[cfg(feature = "postgres-broker")]
+            BrokerBackend::Postgres(config) => Ok(Arc::new(PostgresProducer::new(Arc::new(
+                connect_db(config.database_url.clone()),
+            )))),
+        }
+    }
+
+    pub async fn create_consumer(&self) -> Result<Box<dyn Consumer>, BrokerError> {
+        match self {
+            #[cfg(feature = "broker")]
+            BrokerBackend::Kafka(config) => Ok(Box::new(KafkaConsumer::new(
+                broker::create_consumer(config)?,
+            ))),
+            #[cfg(feature = "postgres-broker")]
+            BrokerBackend::Postgres(config) => Ok(Box::new(
+                PostgresConsumer::connect(
+                    Arc::new(connect_db(config.database_url.clone())),
+                    config,
+                )
+                .await?,
+            )),
+        }
+    }
+}
@@ -1,43 +1,261 @@
+use std::collections::HashMap;
+#[cfg(feature = "broker")]
+use std::sync::Arc;
+#[cfg(feature = "broker")]
+use std::time::Duration;
+
 use async_trait::async_trait;
-use rdkafka::consumer::StreamConsumer;
-use rdkafka::Message;
+#[cfg(feature = "broker")]
+use rdkafka::consumer::{CommitMode, Consumer as KafkaConsumerTrait, StreamConsumer};
+#[cfg(feature = "broker")]
+use rdkafka::message::Headers;
+#[cfg(feature = "broker")]
+use rdkafka::{Message, Offset, TopicPartitionList};
+
+#[cfg(feature = "broker")]
+use crate::error::BrokerError;
+use crate::error::CommonError;
+
+#[cfg(feature = "broker")]
+const WATERMARKS_TIMEOUT: Duration = Duration::from_secs(5);
 
-use crate::error::{BrokerError, CommonError};
+/// A message read from the broker together with its position in the topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumedMessage {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<String>,
+    pub payload: String,
+    pub headers: HashMap<String, String>,
+}
+
+/// Position to resume consuming a topic partition from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicPartitionOffset {
+    pub topic: String,
+    pub partition: i32,
+    /// Offset of the next message to consume.
+    pub offset: i64,
+}
+
+/// Messages of a topic partition the consumer group has not committed yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: i32,
+    pub lag: i64,
+}
+
+impl PartitionLag {
+    /// Lag from the committed offset to the high watermark. Partitions the group never
+    /// committed lag from the oldest message still in the partition.
+    pub fn new(
+        topic: &str,
+        partition: i32,
+        committed: Option<i64>,
+        low_watermark: i64,
+        high_watermark: i64,
+    ) -> Self {
+        let committed = committed.unwrap_or(low_watermark).max(low_watermark);
+        PartitionLag {
+            topic: topic.to_string(),
+            partition,
+            lag: (high_watermark - committed).max(0),
+        }
+    }
+}
 
 #[async_trait]
 pub trait Consumer: Send + Sync {
-    async fn consume(&self) -> Result<String, CommonError>;
+    async fn subscribe(&self, topics: &[String]) -> Result<(), CommonError>;
+    async fn consume(&self) -> Result<ConsumedMessage, CommonError>;
+    /// Commits the given offsets so consuming resumes from them after a restart.
+    async fn commit(&self, offsets: &[TopicPartitionOffset]) -> Result<(), CommonError>;
+    /// Lag of the partitions assigned to the consumer, empty when the broker can't tell.
+    async fn lag(&self) -> Result<Vec<PartitionLag>, CommonError> {
+        Ok(vec![])
+    }
 }
 
+#[cfg(feature = "broker")]
 pub struct KafkaConsumer {
-    consumer: StreamConsumer,
+    consumer: Arc<StreamConsumer>,
 }
 
+#[cfg(feature = "broker")]
 impl KafkaConsumer {
     pub fn new(consumer: StreamConsumer) -> Self {
-        KafkaConsumer { consumer }
+        KafkaConsumer {
+            consumer: Arc::new(consumer),
+        }
     }
 }
 
+#[cfg(feature = "broker")]
 #[async_trait]
 impl Consumer for KafkaConsumer {
-    async fn consume(&self) -> Result<String, CommonError> {
-        return match self.consumer.recv().await {
-            Ok(message) => match message.payload_view::<str>() {
-                Some(Ok(payload)) => Ok(payload.to_string()),
-                Some(Err(err)) => Err(BrokerError {
+    async fn subscribe(&self, topics: &[String]) -> Result<(), CommonError> {
+        let topics: Vec<&str> = topics.iter().map(String::as_str).collect();
+        self.consumer.subscribe(&topics).map_err(|err| {
+            BrokerError {
+                message: format!("Error subscribing to topics: {}", err),
+            }
+            .into()
+        })
+    }
+
+    async fn consume(&self) -> Result<ConsumedMessage, CommonError> {
+        let message = match self.consumer.recv().await {
+            Ok(message) => message,
+            Err(_) => {
+                return Err(BrokerError {
+                    message: "Error deserializing message payload".to_string(),
+                }
+                .into())
+            }
+        };
+
+        let payload = match message.payload_view::<str>() {
+            Some(Ok(payload)) => payload.to_string(),
+            Some(Err(err)) => {
+                return Err(BrokerError {
                     message: format!("Error deserializing message payload: {}", err),
                 }
-                .into()),
-                None => Err(BrokerError {
+                .into())
+            }
+            None => {
+                return Err(BrokerError {
                     message: "Empty message payload".to_string(),
                 }
-                .into()),
-            },
-            Err(_) => Err(BrokerError {
-                message: "Error deserializing message payload".to_string(),
+                .into())
             }
-            .into()),
         };
+
+        let key = match message.key_view::<str>() {
+            Some(Ok(key)) => Some(key.to_string()),
+            _ => None,
+        };
+
+        let mut headers = HashMap::new();
+        if let Some(message_headers) = message.headers() {
+            for header in message_headers.iter() {
+                if let Some(Ok(value)) = header.value.map(std::str::from_utf8) {
+                    headers.insert(header.key.to_string(), value.to_string());
+                }
+            }
+        }
+
+        Ok(ConsumedMessage {
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+            key,
+            payload,
+            headers,
+        })
+    }
+
+    /// Commits on the blocking pool as the commit waits for the broker.
+    async fn commit(&self, offsets: &[TopicPartitionOffset]) -> Result<(), CommonError> {
+        if offsets.is_empty() {
+            return Ok(());
+        }
+
+        let mut partitions = TopicPartitionList::new();
+        for offset in offsets {
+            partitions
+                .add_partition_offset(
+                    &offset.topic,
+                    offset.partition,
+                    Offset::Offset(offset.offset),
+                )
+                .map_err(|err| BrokerError {
+                    message: format!("Error committing offsets: {}", err),
+                })?;
+        }
+
+        let consumer = self.consumer.clone();
+        tokio::task::spawn_blocking(move || consumer.commit(&partitions, CommitMode::Sync))
+            .await
+            .map_err(|err| BrokerError {
+                message: format!("Error committing offsets: {}", err),
+            })?
+            .map_err(|err| {
+                BrokerError {
+                    message: format!("Error committing offsets: {}", err),
+                }
+                .into()
+            })
+    }
+
+    /// Asks the broker for the committed offsets and the watermarks of every partition,
+    /// on the blocking pool as each request waits for the broker.
+    async fn lag(&self) -> Result<Vec<PartitionLag>, CommonError> {
+        let consumer = self.consumer.clone();
+
+        tokio::task::spawn_blocking(move || kafka_lag(&consumer))
+            .await
+            .map_err(|err| BrokerError {
+                message: format!("Error fetching consumer lag: {}", err),
+            })?
+    }
+}
+
+#[cfg(feature = "broker")]
+fn kafka_lag(consumer: &StreamConsumer) -> Result<Vec<PartitionLag>, CommonError> {
+    let assignment = consumer.assignment().map_err(|err| BrokerError {
+        message: format!("Error fetching assignment: {}", err),
+    })?;
+    let committed = consumer
+        .committed_offsets(assignment, WATERMARKS_TIMEOUT)
+        .map_err(|err| BrokerError {
+            message: format!("Error fetching committed offsets: {}", err),
+        })?;
+
+    committed
+        .elements()
+        .iter()
+        .map(|element| {
+            let (low, high) = consumer
+                .fetch_watermarks(element.topic(), element.partition(), WATERMARKS_TIMEOUT)
+                .map_err(|err| BrokerError {
+                    message: format!("Error fetching watermarks: {}", err),
+                })?;
+            let committed = match element.offset() {
+                Offset::Offset(offset) => Some(offset),
+                _ => None,
+            };
+
+            Ok(PartitionLag::new(
+                element.topic(),
+                element.partition(),
+                committed,
+                low,
+                high,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_lag() {
+        let cases = vec![
+            (Some(7), 0, 10, 3),
+            (Some(10), 0, 10, 0),
+            (None, 4, 10, 6),
+            // committed offset deleted by retention
+            (Some(2), 4, 10, 6),
+            (None, 0, 0, 0),
+        ];
+
+        for (committed, low, high, expected) in cases {
+            let lag = PartitionLag::new("news_created", 0, committed, low, high);
+            assert_eq!(lag.lag, expected, "committed {:?}", committed);
+        }
     }
 }
@@ -1,33 +1,728 @@
-use log::error;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use super::consumer::Consumer;
-use super::processor::Processor;
+use futures::future::join_all;
+use log::{debug, error, info, warn};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use super::consumer::{ConsumedMessage, Consumer};
+use super::idempotency::{message_id, IdempotencyStore};
+use super::metrics::{PipelineMetrics, ProcessingStatus};
+use super::offset_tracker::OffsetTracker;
+use super::processor::MessageHandler;
+use crate::error::CommonError;
+use crate::telemetry::extract_context;
+
+/// How long the messages being processed when shutdown is requested are given to finish.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_WORKERS: usize = 1;
+const DEFAULT_MAX_IN_FLIGHT: usize = 100;
+const DEFAULT_COMMIT_INTERVAL: Duration = Duration::from_secs(5);
+
+type Dispatched = (ConsumedMessage, OwnedSemaphorePermit);
 
 pub struct DataPipeline<'a> {
     consumer: &'a dyn Consumer,
-    processor: &'a dyn Processor,
+    handler: &'a dyn MessageHandler,
+    idempotency_store: Option<&'a dyn IdempotencyStore>,
+    metrics: Option<&'a PipelineMetrics>,
+    shutdown_timeout: Duration,
+    workers: usize,
+    max_in_flight: usize,
+    commit_interval: Duration,
 }
 
 impl<'a> DataPipeline<'a> {
-    pub fn new(consumer: &'a dyn Consumer, processor: &'a dyn Processor) -> Self {
+    pub fn new(consumer: &'a dyn Consumer, handler: &'a dyn MessageHandler) -> Self {
         DataPipeline {
             consumer,
-            processor,
+            handler,
+            idempotency_store: None,
+            metrics: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            workers: DEFAULT_WORKERS,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            commit_interval: DEFAULT_COMMIT_INTERVAL,
         }
     }
+
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// Number of messages processed in parallel. Messages with the same key are always
+    /// processed by the same worker, in the order they were consumed.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Maximum number of messages consumed but not processed yet. Consuming pauses while
+    /// the limit is reached.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    pub fn with_commit_interval(mut self, commit_interval: Duration) -> Self {
+        self.commit_interval = commit_interval;
+        self
+    }
+
+    /// Skips the messages the store knows were processed, so redelivered messages don't
+    /// reach the handler twice.
+    pub fn with_idempotency_store(mut self, store: &'a dyn IdempotencyStore) -> Self {
+        self.idempotency_store = Some(store);
+        self
+    }
+
+    /// Records consumed and processed messages, and the consumer lag every commit interval.
+    pub fn with_metrics(mut self, metrics: &'a PipelineMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
 }
 
 impl<'a> DataPipeline<'a> {
-    pub async fn start(&self) {
+    /// Subscribes to the topics of the handler, then consumes and processes messages until
+    /// `shutdown` is cancelled.
+    ///
+    /// Processed offsets are committed periodically. Once cancelled the pipeline stops
+    /// consuming, gives the messages being processed up to the shutdown timeout to finish
+    /// and commits the offsets processed so far.
+    pub async fn start(&self, shutdown: CancellationToken) -> Result<(), CommonError> {
+        self.consumer.subscribe(&self.handler.topics()).await?;
+
+        let offsets = Mutex::new(OffsetTracker::default());
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..self.workers)
+            .map(|_| mpsc::channel::<Dispatched>(self.max_in_flight))
+            .unzip();
+
+        let working = join_all(
+            receivers
+                .into_iter()
+                .map(|messages| self.work(messages, &offsets, &shutdown)),
+        );
+        tokio::pin!(working);
+
+        let workers_stopped = tokio::select! {
+            biased;
+            _ = self.dispatch(senders, &offsets, &shutdown) => false,
+            _ = &mut working => true,
+            _ = self.commit_periodically(&offsets) => false,
+            _ = self.record_lag_periodically() => false,
+        };
+
+        if !workers_stopped
+            && tokio::time::timeout(self.shutdown_timeout, working)
+                .await
+                .is_err()
+        {
+            warn!("timed out processing in-flight messages during shutdown");
+        }
+
+        self.commit(&offsets).await;
+        if let Some(metrics) = self.metrics {
+            metrics.stopped();
+        }
+
+        info!("data pipeline stopped");
+
+        Ok(())
+    }
+
+    async fn dispatch(
+        &self,
+        workers: Vec<mpsc::Sender<Dispatched>>,
+        offsets: &Mutex<OffsetTracker>,
+        shutdown: &CancellationToken,
+    ) {
+        let in_flight = Arc::new(Semaphore::new(self.max_in_flight));
+
         loop {
-            match self.consumer.consume().await {
-                Ok(message) => {
-                    if let Err(err) = self.processor.process(&message).await {
-                        error!("failed processing message {}: {}", message, err);
-                    }
+            let permit = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                permit = in_flight.clone().acquire_owned() => {
+                    permit.expect("in-flight semaphore is never closed")
+                }
+            };
+
+            let result = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                result = self.consumer.consume() => result,
+            };
+
+            let message = match result {
+                Ok(message) => message,
+                Err(err) => {
+                    error!("failed consuming message: {}", err);
+                    continue;
+                }
+            };
+
+            offsets.lock().unwrap().consumed(&message);
+            if let Some(metrics) = self.metrics {
+                metrics.consumed(&message.topic);
+            }
+
+            let worker = &workers[self.worker_index(&message)];
+            if worker.send((message, permit)).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    async fn work(
+        &self,
+        mut messages: mpsc::Receiver<Dispatched>,
+        offsets: &Mutex<OffsetTracker>,
+        shutdown: &CancellationToken,
+    ) {
+        loop {
+            let (message, _permit) = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                message = messages.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+            };
+
+            let span = tracing::info_span!(
+                "process",
+                otel.name = %format!("{} process", message.topic),
+                otel.kind = "consumer",
+                messaging.system = "kafka",
+                messaging.source = %message.topic,
+                messaging.kafka.partition = message.partition,
+                messaging.kafka.offset = message.offset,
+            );
+            // continues the trace of the producer
+            span.set_parent(extract_context(&message.headers));
+            let started = Instant::now();
+            let status = self.process(&message).instrument(span).await;
+
+            offsets.lock().unwrap().processed(&message);
+            if let Some(metrics) = self.metrics {
+                metrics.processed(&message.topic, status, started.elapsed());
+                metrics.done();
+            }
+        }
+    }
+
+    async fn process(&self, message: &ConsumedMessage) -> ProcessingStatus {
+        let id = message_id(message);
+
+        if let Some(store) = self.idempotency_store {
+            match store.is_processed(&id).await {
+                Ok(true) => {
+                    debug!("skipping already processed message {}", id);
+                    return ProcessingStatus::Skipped;
+                }
+                Ok(false) => {}
+                // processing a message twice is better than not processing it
+                Err(err) => warn!(
+                    "failed checking message {} was processed: {}",
+                    id, err.message
+                ),
+            }
+        }
+
+        if let Err(err) = self.handler.handle(message).await {
+            error!("failed processing message {}: {}", message.payload, err);
+            return ProcessingStatus::Failed(err.code);
+        }
+
+        if let Some(store) = self.idempotency_store {
+            if let Err(err) = store.mark_processed(&id).await {
+                error!(
+                    "failed marking message {} as processed: {}",
+                    id, err.message
+                );
+            }
+        }
+
+        ProcessingStatus::Succeeded
+    }
+
+    async fn commit_periodically(&self, offsets: &Mutex<OffsetTracker>) {
+        let mut interval = tokio::time::interval(self.commit_interval);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            self.commit(offsets).await;
+        }
+    }
+
+    /// Fetches the lag apart from the commits, the broker may take a while to answer.
+    async fn record_lag_periodically(&self) {
+        let metrics = match self.metrics {
+            Some(metrics) => metrics,
+            None => return std::future::pending().await,
+        };
+
+        let mut interval = tokio::time::interval(self.commit_interval);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            match self.consumer.lag().await {
+                Ok(lag) => metrics.lag(&lag),
+                Err(err) => warn!("failed fetching consumer lag: {}", err),
+            }
+        }
+    }
+
+    async fn commit(&self, offsets: &Mutex<OffsetTracker>) {
+        let committable = offsets.lock().unwrap().committable();
+        if committable.is_empty() {
+            return;
+        }
+
+        match self.consumer.commit(&committable).await {
+            Ok(_) => offsets.lock().unwrap().committed(&committable),
+            Err(err) => error!("failed committing offsets: {}", err),
+        }
+    }
+
+    /// Messages without a key keep the order of their partition.
+    fn worker_index(&self, message: &ConsumedMessage) -> usize {
+        let mut hasher = FnvHasher::default();
+        match &message.key {
+            Some(key) => key.hash(&mut hasher),
+            None => (&message.topic, message.partition).hash(&mut hasher),
+        }
+        (hasher.finish() % self.workers as u64) as usize
+    }
+}
+
+/// FNV-1a, which unlike `DefaultHasher` doesn't change between Rust releases, so a key
+/// is always handled by the same worker.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use prometheus::Registry;
+
+    use super::*;
+    use crate::events::EVENT_ID_HEADER;
+    use crate::pipeline::consumer::{PartitionLag, TopicPartitionOffset};
+    use crate::pipeline::idempotency::memory::MemoryIdempotencyStore;
+
+    struct VecConsumer {
+        topics: Mutex<Vec<String>>,
+        messages: Mutex<Vec<ConsumedMessage>>,
+        commits: Mutex<Vec<Vec<TopicPartitionOffset>>>,
+        /// The broker never answers the lag requests.
+        lag_hangs: bool,
+    }
+
+    impl VecConsumer {
+        fn new(messages: Vec<(&str, &str)>) -> Self {
+            let messages = messages
+                .into_iter()
+                .enumerate()
+                .map(|(offset, (key, payload))| ConsumedMessage {
+                    topic: "news_created".to_string(),
+                    partition: 0,
+                    offset: offset as i64,
+                    key: Some(key.to_string()),
+                    payload: payload.to_string(),
+                    headers: HashMap::new(),
+                })
+                .rev()
+                .collect();
+
+            VecConsumer {
+                topics: Mutex::new(vec![]),
+                messages: Mutex::new(messages),
+                commits: Mutex::new(vec![]),
+                lag_hangs: false,
+            }
+        }
+
+        fn committed_offsets(&self) -> Vec<i64> {
+            self.commits
+                .lock()
+                .unwrap()
+                .iter()
+                .flatten()
+                .map(|offset| offset.offset)
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl Consumer for VecConsumer {
+        async fn subscribe(&self, topics: &[String]) -> Result<(), CommonError> {
+            *self.topics.lock().unwrap() = topics.to_vec();
+            Ok(())
+        }
+
+        async fn consume(&self) -> Result<ConsumedMessage, CommonError> {
+            let message = self.messages.lock().unwrap().pop();
+            match message {
+                Some(message) => Ok(message),
+                None => std::future::pending().await,
+            }
+        }
+
+        async fn commit(&self, offsets: &[TopicPartitionOffset]) -> Result<(), CommonError> {
+            self.commits.lock().unwrap().push(offsets.to_vec());
+            Ok(())
+        }
+
+        async fn lag(&self) -> Result<Vec<PartitionLag>, CommonError> {
+            if self.lag_hangs {
+                std::future::pending().await
+            } else {
+                Ok(vec![])
+            }
+        }
+    }
+
+    struct CancellingHandler {
+        shutdown: CancellationToken,
+        delay: Duration,
+        processed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl MessageHandler for CancellingHandler {
+        fn topics(&self) -> Vec<String> {
+            vec!["news_created".to_string()]
+        }
+
+        async fn handle(&self, message: &ConsumedMessage) -> Result<(), CommonError> {
+            self.shutdown.cancel();
+            tokio::time::sleep(self.delay).await;
+            self.processed.lock().unwrap().push(message.payload.clone());
+            Ok(())
+        }
+    }
+
+    /// Sleeps the number of milliseconds in the message and cancels once `expected`
+    /// messages are processed.
+    struct SleepingHandler {
+        shutdown: CancellationToken,
+        expected: usize,
+        processed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl MessageHandler for SleepingHandler {
+        fn topics(&self) -> Vec<String> {
+            vec!["news_created".to_string()]
+        }
+
+        async fn handle(&self, message: &ConsumedMessage) -> Result<(), CommonError> {
+            let message = &message.payload;
+            let millis = message.rsplit(':').next().unwrap().parse::<u64>().unwrap();
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+
+            let mut processed = self.processed.lock().unwrap();
+            processed.push(message.to_string());
+            if processed.len() == self.expected {
+                self.shutdown.cancel();
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start_finishes_in_flight_message_on_shutdown() {
+        let shutdown = CancellationToken::new();
+        let consumer = VecConsumer::new(vec![("feed", "first"), ("feed", "second")]);
+        let handler = CancellingHandler {
+            shutdown: shutdown.clone(),
+            delay: Duration::from_millis(10),
+            processed: Mutex::new(vec![]),
+        };
+
+        DataPipeline::new(&consumer, &handler)
+            .start(shutdown)
+            .await
+            .unwrap();
+
+        assert_eq!(*consumer.topics.lock().unwrap(), vec!["news_created"]);
+        assert_eq!(*handler.processed.lock().unwrap(), vec!["first"]);
+        assert_eq!(consumer.committed_offsets(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_start_times_out_in_flight_message_on_shutdown() {
+        let shutdown = CancellationToken::new();
+        let consumer = VecConsumer::new(vec![("feed", "first")]);
+        let handler = CancellingHandler {
+            shutdown: shutdown.clone(),
+            delay: Duration::from_secs(60),
+            processed: Mutex::new(vec![]),
+        };
+
+        DataPipeline::new(&consumer, &handler)
+            .with_shutdown_timeout(Duration::from_millis(10))
+            .start(shutdown)
+            .await
+            .unwrap();
+
+        assert!(handler.processed.lock().unwrap().is_empty());
+        assert!(consumer.committed_offsets().is_empty());
+    }
+
+    /// Holds the "slow" messages until every "fast" one is processed, which only
+    /// happens when the keys are handled by different workers.
+    struct GatedHandler {
+        shutdown: CancellationToken,
+        processed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl MessageHandler for GatedHandler {
+        fn topics(&self) -> Vec<String> {
+            vec!["news_created".to_string()]
+        }
+
+        async fn handle(&self, message: &ConsumedMessage) -> Result<(), CommonError> {
+            if message.key.as_deref() == Some("slow") {
+                while self.fast_processed() < 2 {
+                    tokio::task::yield_now().await;
                 }
-                Err(err) => error!("failed consuming message: {}", err),
             }
+
+            let mut processed = self.processed.lock().unwrap();
+            processed.push(message.payload.clone());
+            if processed.len() == 4 {
+                self.shutdown.cancel();
+            }
+            Ok(())
+        }
+    }
+
+    impl GatedHandler {
+        fn fast_processed(&self) -> usize {
+            let processed = self.processed.lock().unwrap();
+            processed
+                .iter()
+                .filter(|payload| payload.starts_with("fast"))
+                .count()
+        }
+    }
+
+    #[test]
+    fn test_worker_index() {
+        let consumer = VecConsumer::new(vec![]);
+        let handler = GatedHandler {
+            shutdown: CancellationToken::new(),
+            processed: Mutex::new(vec![]),
+        };
+        let pipeline = DataPipeline::new(&consumer, &handler).with_workers(4);
+        let without_key = |partition| ConsumedMessage {
+            key: None,
+            partition,
+            ..consumed_message("")
+        };
+
+        assert_eq!(pipeline.worker_index(&consumed_message("slow")), 1);
+        assert_eq!(pipeline.worker_index(&consumed_message("fast")), 0);
+        assert_eq!(pipeline.worker_index(&consumed_message("feed")), 2);
+        assert_eq!(
+            pipeline.worker_index(&ConsumedMessage {
+                offset: 7,
+                ..consumed_message("feed")
+            }),
+            2
+        );
+        assert_eq!(pipeline.worker_index(&without_key(0)), 2);
+        assert_eq!(pipeline.worker_index(&without_key(1)), 3);
+    }
+
+    #[tokio::test]
+    async fn test_start_keeps_order_per_key() {
+        let shutdown = CancellationToken::new();
+        let consumer = VecConsumer::new(vec![
+            ("slow", "slow:1"),
+            ("fast", "fast:1"),
+            ("slow", "slow:2"),
+            ("fast", "fast:2"),
+        ]);
+        let handler = GatedHandler {
+            shutdown: shutdown.clone(),
+            processed: Mutex::new(vec![]),
+        };
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            DataPipeline::new(&consumer, &handler)
+                .with_workers(4)
+                .start(shutdown),
+        )
+        .await
+        .expect("the keys were not handled by different workers")
+        .unwrap();
+
+        let processed = handler.processed.lock().unwrap();
+        assert_eq!(*processed, vec!["fast:1", "fast:2", "slow:1", "slow:2"]);
+        assert_eq!(consumer.committed_offsets(), vec![4]);
+    }
+
+    #[tokio::test]
+    async fn test_start_skips_processed_messages() {
+        let shutdown = CancellationToken::new();
+        let consumer = VecConsumer::new(vec![
+            ("feed", "first:0"),
+            ("feed", "first:0"),
+            ("feed", "second:0"),
+        ]);
+        for message in consumer.messages.lock().unwrap().iter_mut() {
+            message
+                .headers
+                .insert(EVENT_ID_HEADER.to_string(), message.payload.clone());
+        }
+        let handler = SleepingHandler {
+            shutdown: shutdown.clone(),
+            expected: 2,
+            processed: Mutex::new(vec![]),
+        };
+        let store = MemoryIdempotencyStore::new(10);
+
+        DataPipeline::new(&consumer, &handler)
+            .with_idempotency_store(&store)
+            .start(shutdown)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *handler.processed.lock().unwrap(),
+            vec!["first:0", "second:0"]
+        );
+        assert_eq!(consumer.committed_offsets(), vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_start_records_metrics() {
+        let shutdown = CancellationToken::new();
+        let consumer = VecConsumer::new(vec![
+            ("feed", "first:0"),
+            ("feed", "first:0"),
+            ("feed", "second:0"),
+        ]);
+        for message in consumer.messages.lock().unwrap().iter_mut() {
+            message
+                .headers
+                .insert(EVENT_ID_HEADER.to_string(), message.payload.clone());
+        }
+        let handler = SleepingHandler {
+            shutdown: shutdown.clone(),
+            expected: 2,
+            processed: Mutex::new(vec![]),
+        };
+        let store = MemoryIdempotencyStore::new(10);
+        let registry = Registry::new();
+        let metrics = PipelineMetrics::new(&registry).unwrap();
+
+        DataPipeline::new(&consumer, &handler)
+            .with_idempotency_store(&store)
+            .with_metrics(&metrics)
+            .start(shutdown)
+            .await
+            .unwrap();
+
+        let families = registry.gather();
+        let processed: Vec<(String, f64)> = families
+            .iter()
+            .find(|family| family.get_name() == "pipeline_messages_processed_total")
+            .unwrap()
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let status = metric
+                    .get_label()
+                    .iter()
+                    .find(|label| label.get_name() == "status")
+                    .unwrap()
+                    .get_value()
+                    .to_string();
+                (status, metric.get_counter().get_value())
+            })
+            .collect();
+        assert_eq!(
+            processed,
+            vec![("skipped".to_string(), 1.0), ("succeeded".to_string(), 2.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_commits_while_fetching_lag() {
+        let shutdown = CancellationToken::new();
+        let mut consumer = VecConsumer::new(vec![("feed", "first:0")]);
+        consumer.lag_hangs = true;
+        let handler = SleepingHandler {
+            shutdown: CancellationToken::new(),
+            expected: 1,
+            processed: Mutex::new(vec![]),
+        };
+        let metrics = PipelineMetrics::new(&Registry::new()).unwrap();
+        let pipeline = DataPipeline::new(&consumer, &handler)
+            .with_commit_interval(Duration::from_millis(5))
+            .with_metrics(&metrics);
+
+        let committed = async {
+            while consumer.committed_offsets().is_empty() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            shutdown.cancel();
+        };
+        let (started, _) = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join(pipeline.start(shutdown.clone()), committed),
+        )
+        .await
+        .expect("offsets were not committed while the lag was fetched");
+
+        started.unwrap();
+        assert_eq!(consumer.committed_offsets(), vec![1]);
+    }
+
+    fn consumed_message(key: &str) -> ConsumedMessage {
+        ConsumedMessage {
+            topic: "news_created".to_string(),
+            partition: 0,
+            offset: 0,
+            key: Some(key.to_string()),
+            payload: "".to_string(),
+            headers: HashMap::new(),
         }
     }
 }
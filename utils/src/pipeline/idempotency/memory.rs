@@ -0,0 +1,67 @@
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use lru::LruCache;
+
+use super::IdempotencyStore;
+use crate::error::DatabaseError;
+
+/// Keeps the ids of the last processed messages in memory.
+///
+/// Duplicates are only detected within the process and while the id is among the
+/// `capacity` most recent ones.
+pub struct MemoryIdempotencyStore {
+    processed: Mutex<LruCache<String, ()>>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        MemoryIdempotencyStore {
+            processed: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn is_processed(&self, message_id: &str) -> Result<bool, DatabaseError> {
+        Ok(self.processed.lock().unwrap().get(message_id).is_some())
+    }
+
+    async fn mark_processed(&self, message_id: &str) -> Result<(), DatabaseError> {
+        self.processed
+            .lock()
+            .unwrap()
+            .put(message_id.to_string(), ());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_is_processed_after_mark() {
+        let store = MemoryIdempotencyStore::new(10);
+
+        assert!(!store.is_processed("event").await.unwrap());
+        store.mark_processed("event").await.unwrap();
+        assert!(store.is_processed("event").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_id_is_evicted() {
+        let store = MemoryIdempotencyStore::new(2);
+
+        store.mark_processed("first").await.unwrap();
+        store.mark_processed("second").await.unwrap();
+        store.is_processed("first").await.unwrap();
+        store.mark_processed("third").await.unwrap();
+
+        assert!(store.is_processed("first").await.unwrap());
+        assert!(!store.is_processed("second").await.unwrap());
+    }
+}
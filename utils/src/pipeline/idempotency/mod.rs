@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use mockall::automock;
+
+use super::consumer::ConsumedMessage;
+use crate::error::DatabaseError;
+use crate::events::EVENT_ID_HEADER;
+
+pub mod memory;
+#[cfg(feature = "database")]
+pub mod postgres;
+#[cfg(feature = "database")]
+pub mod schema;
+
+/// Remembers the messages a consumer group has processed, so redelivered messages are
+/// skipped instead of being processed twice.
+#[automock]
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    async fn is_processed(&self, message_id: &str) -> Result<bool, DatabaseError>;
+    async fn mark_processed(&self, message_id: &str) -> Result<(), DatabaseError>;
+}
+
+/// Identifies a message across redeliveries.
+///
+/// The event id survives the message being published twice, the position in the topic
+/// is used for messages that don't carry one.
+pub fn message_id(message: &ConsumedMessage) -> String {
+    match message.headers.get(EVENT_ID_HEADER) {
+        Some(event_id) => event_id.clone(),
+        None => format!("{}-{}-{}", message.topic, message.partition, message.offset),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn message(headers: HashMap<String, String>) -> ConsumedMessage {
+        ConsumedMessage {
+            topic: "news_created".to_string(),
+            partition: 2,
+            offset: 10,
+            key: None,
+            payload: "".to_string(),
+            headers,
+        }
+    }
+
+    #[test]
+    fn test_message_id_uses_event_id() {
+        let headers = HashMap::from([(EVENT_ID_HEADER.to_string(), "event".to_string())]);
+
+        assert_eq!(message_id(&message(headers)), "event");
+    }
+
+    #[test]
+    fn test_message_id_falls_back_to_position() {
+        assert_eq!(message_id(&message(HashMap::new())), "news_created-2-10");
+    }
+}
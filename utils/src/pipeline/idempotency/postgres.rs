@@ -0,0 +1,150 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+
+use super::schema::processed_messages;
+use super::IdempotencyStore;
+use crate::db::{run, PgPool};
+use crate::error::DatabaseError;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// How often expired ids are deleted, they are ignored as soon as they expire.
+const PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Keeps the ids of the messages processed by a consumer group in the `processed_messages`
+/// table, so duplicates are detected across restarts and instances.
+///
+/// Ids expire after the TTL, which should be longer than a message can be redelivered.
+pub struct PostgresIdempotencyStore {
+    pool: Arc<PgPool>,
+    group_id: String,
+    ttl: Duration,
+    last_purge: Mutex<Option<Instant>>,
+}
+
+impl PostgresIdempotencyStore {
+    pub fn new(pool: Arc<PgPool>, group_id: &str) -> Self {
+        PostgresIdempotencyStore {
+            pool,
+            group_id: group_id.to_string(),
+            ttl: DEFAULT_TTL,
+            last_purge: Mutex::new(None),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Deletes the ids of the group that expired.
+    pub async fn purge_expired(&self) -> Result<usize, DatabaseError> {
+        let pool = self.pool.clone();
+        let group_id = self.group_id.clone();
+        let expired_before = self.expired_before()?;
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            diesel::delete(
+                processed_messages::table
+                    .filter(processed_messages::group_id.eq(&group_id))
+                    .filter(processed_messages::processed_at.lt(expired_before)),
+            )
+            .execute(&mut conn)
+            .map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })
+        })
+        .await
+    }
+
+    async fn purge_if_due(&self) {
+        {
+            let mut last_purge = self.last_purge.lock().unwrap();
+            if last_purge.is_some_and(|last_purge| last_purge.elapsed() < PURGE_INTERVAL) {
+                return;
+            }
+            *last_purge = Some(Instant::now());
+        }
+
+        if let Err(err) = self.purge_expired().await {
+            log::error!("failed purging processed messages: {}", err.message);
+        }
+    }
+
+    fn expired_before(&self) -> Result<chrono::DateTime<Utc>, DatabaseError> {
+        let ttl = chrono::Duration::from_std(self.ttl).map_err(|err| DatabaseError {
+            message: err.to_string(),
+        })?;
+        Ok(Utc::now() - ttl)
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for PostgresIdempotencyStore {
+    async fn is_processed(&self, message_id: &str) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+        let group_id = self.group_id.clone();
+        let message_id = message_id.to_string();
+        let expired_before = self.expired_before()?;
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            diesel::select(exists(
+                processed_messages::table
+                    .filter(processed_messages::group_id.eq(&group_id))
+                    .filter(processed_messages::message_id.eq(&message_id))
+                    .filter(processed_messages::processed_at.ge(expired_before)),
+            ))
+            .get_result(&mut conn)
+            .map_err(|err| DatabaseError {
+                message: err.to_string(),
+            })
+        })
+        .await
+    }
+
+    async fn mark_processed(&self, message_id: &str) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let group_id = self.group_id.clone();
+        let message_id = message_id.to_string();
+
+        run(move || {
+            let mut conn = connection(&pool)?;
+
+            diesel::insert_into(processed_messages::table)
+                .values((
+                    processed_messages::group_id.eq(&group_id),
+                    processed_messages::message_id.eq(&message_id),
+                    processed_messages::processed_at.eq(Utc::now()),
+                ))
+                .on_conflict((processed_messages::group_id, processed_messages::message_id))
+                .do_update()
+                .set(processed_messages::processed_at.eq(Utc::now()))
+                .execute(&mut conn)
+                .map_err(|err| DatabaseError {
+                    message: err.to_string(),
+                })
+        })
+        .await?;
+
+        self.purge_if_due().await;
+
+        Ok(())
+    }
+}
+
+fn connection(
+    pool: &PgPool,
+) -> Result<PooledConnection<ConnectionManager<PgConnection>>, DatabaseError> {
+    pool.get().map_err(|err| DatabaseError {
+        message: err.to_string(),
+    })
+}
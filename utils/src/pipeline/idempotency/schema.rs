@@ -0,0 +1,9 @@
+use diesel::table;
+
+table! {
+  processed_messages (group_id, message_id) {
+      group_id -> Text,
+      message_id -> Text,
+      processed_at -> Timestamptz,
+  }
+}
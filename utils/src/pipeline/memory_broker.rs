@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use super::consumer::{ConsumedMessage, Consumer, PartitionLag, TopicPartitionOffset};
+use super::producer::{Producer, ProducerMessage};
+use crate::error::{BrokerError, CommonError};
+
+/// Every topic of the in-memory broker has a single partition.
+const PARTITION: i32 = 0;
+
+#[derive(Default)]
+struct GroupOffsets {
+    /// Offset of the next message handed out to the group.
+    position: i64,
+    committed: Option<i64>,
+}
+
+struct StoredMessage {
+    key: String,
+    payload: String,
+    headers: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct State {
+    topics: HashMap<String, Vec<StoredMessage>>,
+    groups: HashMap<(String, String), GroupOffsets>,
+}
+
+/// In-process broker for tests and local development.
+///
+/// Topics are created when first used. Consumers of the same group share the group
+/// position so each message is handed out once per group, and a new consumer starts
+/// from the offset its group last committed.
+#[derive(Default)]
+pub struct MemoryBroker {
+    state: Mutex<State>,
+    published: Notify,
+}
+
+impl MemoryBroker {
+    pub fn new() -> Arc<Self> {
+        Arc::new(MemoryBroker::default())
+    }
+
+    pub fn producer(self: &Arc<Self>) -> MemoryProducer {
+        MemoryProducer {
+            broker: self.clone(),
+        }
+    }
+
+    pub fn consumer(self: &Arc<Self>, group_id: &str) -> MemoryConsumer {
+        MemoryConsumer {
+            broker: self.clone(),
+            group_id: group_id.to_string(),
+            topics: Mutex::new(vec![]),
+        }
+    }
+
+    /// Payloads published to the topic so far.
+    pub fn messages(&self, topic: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .topics
+            .get(topic)
+            .map(|messages| {
+                messages
+                    .iter()
+                    .map(|message| message.payload.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn committed_offset(&self, group_id: &str, topic: &str) -> Option<i64> {
+        let state = self.state.lock().unwrap();
+        state
+            .groups
+            .get(&(group_id.to_string(), topic.to_string()))
+            .and_then(|group| group.committed)
+    }
+
+    fn join(&self, group_id: &str, topics: &[String]) {
+        let mut state = self.state.lock().unwrap();
+        for topic in topics {
+            let group = state
+                .groups
+                .entry((group_id.to_string(), topic.clone()))
+                .or_default();
+            if let Some(committed) = group.committed {
+                group.position = committed;
+            }
+        }
+    }
+
+    fn publish(&self, message: ProducerMessage) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .topics
+            .entry(message.topic)
+            .or_default()
+            .push(StoredMessage {
+                key: message.key,
+                payload: message.payload,
+                headers: message.headers,
+            });
+        self.published.notify_waiters();
+    }
+
+    fn next_message(&self, group_id: &str, topics: &[String]) -> Option<ConsumedMessage> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+
+        for topic in topics {
+            let messages = match state.topics.get(topic) {
+                Some(messages) => messages,
+                None => continue,
+            };
+            let group = state
+                .groups
+                .entry((group_id.to_string(), topic.clone()))
+                .or_default();
+
+            if let Some(message) = messages.get(group.position as usize) {
+                let offset = group.position;
+                group.position += 1;
+                return Some(ConsumedMessage {
+                    topic: topic.clone(),
+                    partition: PARTITION,
+                    offset,
+                    key: Some(message.key.clone()),
+                    payload: message.payload.clone(),
+                    headers: message.headers.clone(),
+                });
+            }
+        }
+
+        None
+    }
+}
+
+pub struct MemoryProducer {
+    broker: Arc<MemoryBroker>,
+}
+
+#[async_trait]
+impl Producer for MemoryProducer {
+    async fn send(&self, message: ProducerMessage) -> Result<(), BrokerError> {
+        self.broker.publish(message);
+        Ok(())
+    }
+}
+
+pub struct MemoryConsumer {
+    broker: Arc<MemoryBroker>,
+    group_id: String,
+    topics: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl Consumer for MemoryConsumer {
+    async fn subscribe(&self, topics: &[String]) -> Result<(), CommonError> {
+        self.broker.join(&self.group_id, topics);
+        *self.topics.lock().unwrap() = topics.to_vec();
+        Ok(())
+    }
+
+    async fn consume(&self) -> Result<ConsumedMessage, CommonError> {
+        loop {
+            // register before looking for messages so a publish in between is not missed
+            let published = self.broker.published.notified();
+            tokio::pin!(published);
+            published.as_mut().enable();
+
+            let topics = self.topics.lock().unwrap().clone();
+            if let Some(message) = self.broker.next_message(&self.group_id, &topics) {
+                return Ok(message);
+            }
+
+            published.await;
+        }
+    }
+
+    async fn commit(&self, offsets: &[TopicPartitionOffset]) -> Result<(), CommonError> {
+        let mut state = self.broker.state.lock().unwrap();
+        for offset in offsets {
+            let group = state
+                .groups
+                .entry((self.group_id.clone(), offset.topic.clone()))
+                .or_default();
+            group.committed = group.committed.max(Some(offset.offset));
+        }
+        Ok(())
+    }
+
+    async fn lag(&self) -> Result<Vec<PartitionLag>, CommonError> {
+        let state = self.broker.state.lock().unwrap();
+        let topics = self.topics.lock().unwrap();
+
+        Ok(topics
+            .iter()
+            .map(|topic| {
+                let high_watermark = state.topics.get(topic).map_or(0, Vec::len) as i64;
+                let committed = state
+                    .groups
+                    .get(&(self.group_id.clone(), topic.clone()))
+                    .and_then(|group| group.committed);
+                PartitionLag::new(topic, PARTITION, committed, 0, high_watermark)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn subscribed(broker: &Arc<MemoryBroker>, group_id: &str) -> MemoryConsumer {
+        let consumer = broker.consumer(group_id);
+        consumer
+            .subscribe(&["news_created".to_string()])
+            .await
+            .unwrap();
+        consumer
+    }
+
+    fn message(payload: &str) -> ProducerMessage {
+        ProducerMessage::new("news_created", "feed", payload)
+    }
+
+    #[tokio::test]
+    async fn test_consumer_groups_receive_every_message() {
+        let broker = MemoryBroker::new();
+        let producer = broker.producer();
+        let first_group = subscribed(&broker, "first").await;
+        let second_group = subscribed(&broker, "second").await;
+
+        producer.send(message("news")).await.unwrap();
+
+        assert_eq!(first_group.consume().await.unwrap().payload, "news");
+        assert_eq!(second_group.consume().await.unwrap().payload, "news");
+    }
+
+    #[tokio::test]
+    async fn test_consumers_of_same_group_share_messages() {
+        let broker = MemoryBroker::new();
+        let producer = broker.producer();
+        let first = subscribed(&broker, "news").await;
+        let second = subscribed(&broker, "news").await;
+
+        producer.send(message("first")).await.unwrap();
+        producer.send(message("second")).await.unwrap();
+
+        assert_eq!(first.consume().await.unwrap().payload, "first");
+        assert_eq!(second.consume().await.unwrap().payload, "second");
+    }
+
+    #[tokio::test]
+    async fn test_consumer_resumes_from_committed_offset() {
+        let broker = MemoryBroker::new();
+        let producer = broker.producer();
+        for payload in ["first", "second", "third"] {
+            producer.send(message(payload)).await.unwrap();
+        }
+
+        let consumer = subscribed(&broker, "news").await;
+        let message = consumer.consume().await.unwrap();
+        consumer.consume().await.unwrap();
+        consumer
+            .commit(&[TopicPartitionOffset {
+                topic: message.topic,
+                partition: message.partition,
+                offset: message.offset + 1,
+            }])
+            .await
+            .unwrap();
+
+        let consumer = subscribed(&broker, "news").await;
+        assert_eq!(consumer.consume().await.unwrap().payload, "second");
+        assert_eq!(broker.committed_offset("news", "news_created"), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_consume_waits_for_published_message() {
+        let broker = MemoryBroker::new();
+        let producer = broker.producer();
+        let consumer = subscribed(&broker, "news").await;
+
+        let (message, _) = tokio::join!(consumer.consume(), async {
+            tokio::task::yield_now().await;
+            producer.send(message("news")).await.unwrap();
+        });
+
+        assert_eq!(message.unwrap().payload, "news");
+    }
+
+    #[tokio::test]
+    async fn test_lag_counts_uncommitted_messages() {
+        let broker = MemoryBroker::new();
+        let producer = broker.producer();
+        for payload in ["first", "second", "third"] {
+            producer.send(message(payload)).await.unwrap();
+        }
+
+        let consumer = subscribed(&broker, "news").await;
+        consumer
+            .commit(&[TopicPartitionOffset {
+                topic: "news_created".to_string(),
+                partition: PARTITION,
+                offset: 1,
+            }])
+            .await
+            .unwrap();
+
+        let lag = consumer.lag().await.unwrap();
+        assert_eq!(
+            lag,
+            vec![PartitionLag {
+                topic: "news_created".to_string(),
+                partition: PARTITION,
+                lag: 2,
+            }]
+        );
+    }
+}
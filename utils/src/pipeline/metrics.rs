@@ -0,0 +1,270 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+};
+
+use super::consumer::PartitionLag;
+use crate::error::CommonError;
+
+const PROCESSING_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Outcome of processing a consumed message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessingStatus {
+    Succeeded,
+    /// The message was processed before, the handler was not called.
+    Skipped,
+    Failed(u32),
+}
+
+/// Prometheus metrics of a `DataPipeline`, exported on the `/metrics` endpoint when
+/// registered on `prometheus::default_registry()`.
+#[derive(Clone)]
+pub struct PipelineMetrics {
+    consumed: IntCounterVec,
+    processed: IntCounterVec,
+    processing_duration: HistogramVec,
+    in_flight: IntGauge,
+    consumer_lag: IntGaugeVec,
+    /// Topic and partition label values of the lag reported last.
+    lag_partitions: Arc<Mutex<HashSet<(String, String)>>>,
+}
+
+impl PipelineMetrics {
+    pub fn new(registry: &Registry) -> Result<Self, CommonError> {
+        let metrics = PipelineMetrics {
+            consumed: IntCounterVec::new(
+                Opts::new(
+                    "pipeline_messages_consumed_total",
+                    "Messages consumed by the pipeline",
+                ),
+                &["topic"],
+            )
+            .map_err(metrics_error)?,
+            processed: IntCounterVec::new(
+                Opts::new(
+                    "pipeline_messages_processed_total",
+                    "Messages processed by the pipeline, by status and error code",
+                ),
+                &["topic", "status", "error_code"],
+            )
+            .map_err(metrics_error)?,
+            processing_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "pipeline_processing_duration_seconds",
+                    "Time spent processing a message",
+                )
+                .buckets(PROCESSING_BUCKETS.to_vec()),
+                &["topic"],
+            )
+            .map_err(metrics_error)?,
+            in_flight: IntGauge::new(
+                "pipeline_messages_in_flight",
+                "Messages consumed and not processed yet",
+            )
+            .map_err(metrics_error)?,
+            consumer_lag: IntGaugeVec::new(
+                Opts::new(
+                    "pipeline_consumer_lag",
+                    "Messages of the partition not committed by the consumer group",
+                ),
+                &["topic", "partition"],
+            )
+            .map_err(metrics_error)?,
+            lag_partitions: Arc::new(Mutex::new(HashSet::new())),
+        };
+
+        registry
+            .register(Box::new(metrics.consumed.clone()))
+            .and_then(|_| registry.register(Box::new(metrics.processed.clone())))
+            .and_then(|_| registry.register(Box::new(metrics.processing_duration.clone())))
+            .and_then(|_| registry.register(Box::new(metrics.in_flight.clone())))
+            .and_then(|_| registry.register(Box::new(metrics.consumer_lag.clone())))
+            .map_err(metrics_error)?;
+
+        Ok(metrics)
+    }
+
+    pub(crate) fn consumed(&self, topic: &str) {
+        self.consumed.with_label_values(&[topic]).inc();
+        self.in_flight.inc();
+    }
+
+    pub(crate) fn processed(&self, topic: &str, status: ProcessingStatus, duration: Duration) {
+        let (status, error_code) = match status {
+            ProcessingStatus::Succeeded => ("succeeded", String::new()),
+            ProcessingStatus::Skipped => ("skipped", String::new()),
+            ProcessingStatus::Failed(code) => ("failed", code.to_string()),
+        };
+
+        self.processed
+            .with_label_values(&[topic, status, &error_code])
+            .inc();
+        self.processing_duration
+            .with_label_values(&[topic])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub(crate) fn done(&self) {
+        self.in_flight.dec();
+    }
+
+    /// Messages left in the worker queues on shutdown are dropped unprocessed.
+    pub(crate) fn stopped(&self) {
+        self.in_flight.set(0);
+    }
+
+    /// Partitions missing from the lag were revoked from the consumer, their lag is
+    /// dropped rather than left at its last value.
+    pub(crate) fn lag(&self, lag: &[PartitionLag]) {
+        let partitions: HashSet<(String, String)> = lag
+            .iter()
+            .map(|partition| (partition.topic.clone(), partition.partition.to_string()))
+            .collect();
+
+        let mut lag_partitions = self.lag_partitions.lock().unwrap();
+        for (topic, partition) in lag_partitions.difference(&partitions) {
+            // only fails when the labels are not there
+            let _ = self.consumer_lag.remove_label_values(&[topic, partition]);
+        }
+        for partition in lag {
+            self.consumer_lag
+                .with_label_values(&[&partition.topic, &partition.partition.to_string()])
+                .set(partition.lag);
+        }
+        *lag_partitions = partitions;
+    }
+}
+
+/// Metrics of the processors wrapped with `WithMetrics`, labelled with the processor name.
+#[derive(Clone)]
+pub struct ProcessorMetrics {
+    calls: IntCounterVec,
+    duration: HistogramVec,
+}
+
+impl ProcessorMetrics {
+    pub fn new(registry: &Registry) -> Result<Self, CommonError> {
+        let metrics = ProcessorMetrics {
+            calls: IntCounterVec::new(
+                Opts::new(
+                    "pipeline_processor_calls_total",
+                    "Events processed by the processor, by status and error code",
+                ),
+                &["processor", "status", "error_code"],
+            )
+            .map_err(metrics_error)?,
+            duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "pipeline_processor_duration_seconds",
+                    "Time spent by the processor on an event",
+                )
+                .buckets(PROCESSING_BUCKETS.to_vec()),
+                &["processor"],
+            )
+            .map_err(metrics_error)?,
+        };
+
+        registry
+            .register(Box::new(metrics.calls.clone()))
+            .and_then(|_| registry.register(Box::new(metrics.duration.clone())))
+            .map_err(metrics_error)?;
+
+        Ok(metrics)
+    }
+
+    pub(crate) fn record(&self, processor: &str, error_code: Option<u32>, duration: Duration) {
+        let (status, error_code) = match error_code {
+            None => ("succeeded", String::new()),
+            Some(code) => ("failed", code.to_string()),
+        };
+
+        self.calls
+            .with_label_values(&[processor, status, &error_code])
+            .inc();
+        self.duration
+            .with_label_values(&[processor])
+            .observe(duration.as_secs_f64());
+    }
+}
+
+fn metrics_error(err: prometheus::Error) -> CommonError {
+    CommonError::new(&format!("failed registering pipeline metrics: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_are_registered_once() {
+        let registry = Registry::new();
+
+        let metrics = PipelineMetrics::new(&registry).unwrap();
+        metrics.consumed("news_created");
+        metrics.processed(
+            "news_created",
+            ProcessingStatus::Failed(2),
+            Duration::from_millis(3),
+        );
+
+        let names: Vec<String> = registry
+            .gather()
+            .iter()
+            .map(|family| family.get_name().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "pipeline_messages_consumed_total",
+                "pipeline_messages_in_flight",
+                "pipeline_messages_processed_total",
+                "pipeline_processing_duration_seconds",
+            ]
+        );
+        assert!(PipelineMetrics::new(&registry).is_err());
+    }
+
+    #[test]
+    fn test_lag_of_revoked_partitions_is_dropped() {
+        let registry = Registry::new();
+        let metrics = PipelineMetrics::new(&registry).unwrap();
+        let lag = |partition, lag| PartitionLag {
+            topic: "news_created".to_string(),
+            partition,
+            lag,
+        };
+        let reported = || -> Vec<(String, i64)> {
+            registry
+                .gather()
+                .iter()
+                .filter(|family| family.get_name() == "pipeline_consumer_lag")
+                .flat_map(|family| family.get_metric().to_vec())
+                .map(|metric| {
+                    let partition = metric
+                        .get_label()
+                        .iter()
+                        .find(|label| label.get_name() == "partition")
+                        .unwrap()
+                        .get_value()
+                        .to_string();
+                    (partition, metric.get_gauge().get_value() as i64)
+                })
+                .collect()
+        };
+
+        metrics.lag(&[lag(0, 3), lag(1, 5)]);
+        assert_eq!(reported(), vec![("0".to_string(), 3), ("1".to_string(), 5)]);
+
+        metrics.lag(&[lag(1, 2)]);
+        assert_eq!(reported(), vec![("1".to_string(), 2)]);
+
+        metrics.lag(&[]);
+        assert_eq!(reported(), vec![]);
+    }
+}
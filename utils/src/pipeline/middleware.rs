@@ -0,0 +1,352 @@
+//! Decorators adding cross-cutting behaviour to any `Processor`.
+//!
+//! Wrappers stack from the inside out, the last one added runs first:
+//!
+//! ```ignore
+//! let processor = NewsWebsocketProcessor::new(&ws_sender, subscription_repo)
+//!     .catch_panic()
+//!     .with_timeout(Duration::from_secs(30))
+//!     .with_retry(3)
+//!     .with_metrics("news_websocket", &metrics);
+//! let router = TopicRouter::new().route(NEWS_CREATED_EVENT, &processor);
+//! ```
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::FutureExt;
+use log::{error, warn};
+use serde::de::DeserializeOwned;
+
+use super::metrics::ProcessorMetrics;
+use super::processor::Processor;
+use crate::error::{CommonError, ASYNC_OPERATIONS_ERROR_CODE};
+
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Fails the processing that takes longer than the timeout.
+pub struct WithTimeout<P> {
+    inner: P,
+    timeout: Duration,
+}
+
+impl<P> WithTimeout<P> {
+    pub fn new(inner: P, timeout: Duration) -> Self {
+        WithTimeout { inner, timeout }
+    }
+}
+
+#[async_trait]
+impl<E, P> Processor<E> for WithTimeout<P>
+where
+    E: DeserializeOwned + Send + Sync,
+    P: Processor<E>,
+{
+    async fn process(&self, event: &E) -> Result<(), CommonError> {
+        match tokio::time::timeout(self.timeout, self.inner.process(event)).await {
+            Ok(result) => result,
+            Err(_) => Err(CommonError {
+                message: format!("processing timed out after {:?}", self.timeout),
+                code: ASYNC_OPERATIONS_ERROR_CODE,
+            }),
+        }
+    }
+}
+
+/// Turns a panic of the processor into an error, so it's handled as any other failure
+/// instead of stopping the pipeline worker.
+pub struct CatchPanic<P> {
+    inner: P,
+}
+
+impl<P> CatchPanic<P> {
+    pub fn new(inner: P) -> Self {
+        CatchPanic { inner }
+    }
+}
+
+#[async_trait]
+impl<E, P> Processor<E> for CatchPanic<P>
+where
+    E: DeserializeOwned + Send + Sync,
+    P: Processor<E>,
+{
+    async fn process(&self, event: &E) -> Result<(), CommonError> {
+        match AssertUnwindSafe(self.inner.process(event))
+            .catch_unwind()
+            .await
+        {
+            Ok(result) => result,
+            Err(panic) => Err(CommonError::new(&format!(
+                "processor panicked: {}",
+                panic_message(panic.as_ref())
+            ))),
+        }
+    }
+}
+
+/// Processes the event again when it fails, doubling the backoff between attempts.
+pub struct WithRetry<P> {
+    inner: P,
+    attempts: usize,
+    backoff: Duration,
+}
+
+impl<P> WithRetry<P> {
+    /// `attempts` counts the first try.
+    pub fn new(inner: P, attempts: usize) -> Self {
+        WithRetry {
+            inner,
+            attempts: attempts.max(1),
+            backoff: DEFAULT_BACKOFF,
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+}
+
+#[async_trait]
+impl<E, P> Processor<E> for WithRetry<P>
+where
+    E: DeserializeOwned + Send + Sync,
+    P: Processor<E>,
+{
+    async fn process(&self, event: &E) -> Result<(), CommonError> {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+
+        loop {
+            match self.inner.process(event).await {
+                Err(err) if attempt < self.attempts => {
+                    warn!(
+                        "processing attempt {} of {} failed, retrying in {:?}: {}",
+                        attempt, self.attempts, backoff, err
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Records the calls, failures and duration of the processor.
+pub struct WithMetrics<P> {
+    inner: P,
+    name: String,
+    metrics: ProcessorMetrics,
+}
+
+impl<P> WithMetrics<P> {
+    pub fn new(inner: P, name: &str, metrics: &ProcessorMetrics) -> Self {
+        WithMetrics {
+            inner,
+            name: name.to_string(),
+            metrics: metrics.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<E, P> Processor<E> for WithMetrics<P>
+where
+    E: DeserializeOwned + Send + Sync,
+    P: Processor<E>,
+{
+    async fn process(&self, event: &E) -> Result<(), CommonError> {
+        let started = Instant::now();
+        let result = self.inner.process(event).await;
+        self.metrics.record(
+            &self.name,
+            result.as_ref().err().map(|err| err.code),
+            started.elapsed(),
+        );
+        result
+    }
+}
+
+/// Logs the failures of the processor with its name and the error code.
+pub struct WithLogging<P> {
+    inner: P,
+    name: String,
+}
+
+impl<P> WithLogging<P> {
+    pub fn new(inner: P, name: &str) -> Self {
+        WithLogging {
+            inner,
+            name: name.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl<E, P> Processor<E> for WithLogging<P>
+where
+    E: DeserializeOwned + Send + Sync,
+    P: Processor<E>,
+{
+    async fn process(&self, event: &E) -> Result<(), CommonError> {
+        let started = Instant::now();
+        let result = self.inner.process(event).await;
+        if let Err(err) = &result {
+            error!(
+                "processor={} code={} elapsed_ms={} failed: {}",
+                self.name,
+                err.code,
+                started.elapsed().as_millis(),
+                err.message
+            );
+        }
+        result
+    }
+}
+
+/// Stacks the middleware around a processor, `E` is the event it processes.
+pub trait ProcessorExt<E>: Processor<E> + Sized
+where
+    E: DeserializeOwned + Send + Sync,
+{
+    fn with_timeout(self, timeout: Duration) -> WithTimeout<Self> {
+        WithTimeout::new(self, timeout)
+    }
+
+    fn catch_panic(self) -> CatchPanic<Self> {
+        CatchPanic::new(self)
+    }
+
+    fn with_retry(self, attempts: usize) -> WithRetry<Self> {
+        WithRetry::new(self, attempts)
+    }
+
+    fn with_metrics(self, name: &str, metrics: &ProcessorMetrics) -> WithMetrics<Self> {
+        WithMetrics::new(self, name, metrics)
+    }
+
+    fn with_logging(self, name: &str) -> WithLogging<Self> {
+        WithLogging::new(self, name)
+    }
+}
+
+impl<E, P> ProcessorExt<E> for P
+where
+    E: DeserializeOwned + Send + Sync,
+    P: Processor<E>,
+{
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use prometheus::Registry;
+
+    use super::*;
+
+    /// Fails the first `failures` calls, panics on negative events and sleeps the event
+    /// milliseconds otherwise.
+    #[derive(Default)]
+    struct FlakyProcessor {
+        failures: usize,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Processor<i64> for FlakyProcessor {
+        async fn process(&self, event: &i64) -> Result<(), CommonError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures {
+                return Err(CommonError::new("flaky"));
+            }
+            if *event < 0 {
+                panic!("negative event {}", event);
+            }
+            tokio::time::sleep(Duration::from_millis(*event as u64)).await;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout() {
+        let processor = FlakyProcessor::default().with_timeout(Duration::from_millis(10));
+
+        assert!(processor.process(&0).await.is_ok());
+        let err = processor.process(&1000).await.unwrap_err();
+        assert_eq!(err.code, ASYNC_OPERATIONS_ERROR_CODE);
+    }
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        let processor = FlakyProcessor::default().catch_panic();
+
+        let err = processor.process(&-1).await.unwrap_err();
+
+        assert_eq!(err.message, "processor panicked: negative event -1");
+    }
+
+    #[tokio::test]
+    async fn test_with_retry() {
+        let cases = vec![(2, 3, true, 3), (3, 3, false, 3), (0, 3, true, 1)];
+
+        for (failures, attempts, succeeds, calls) in cases {
+            let processor = FlakyProcessor {
+                failures,
+                ..Default::default()
+            }
+            .with_retry(attempts)
+            .with_backoff(Duration::from_millis(1));
+
+            let result = processor.process(&0).await;
+
+            assert_eq!(result.is_ok(), succeeds, "{} failures", failures);
+            assert_eq!(processor.inner.calls.load(Ordering::SeqCst), calls);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stacked_middleware() {
+        let registry = Registry::new();
+        let metrics = ProcessorMetrics::new(&registry).unwrap();
+        let processor = FlakyProcessor {
+            failures: 1,
+            ..Default::default()
+        }
+        .catch_panic()
+        .with_retry(2)
+        .with_backoff(Duration::from_millis(1))
+        .with_logging("flaky")
+        .with_metrics("flaky", &metrics);
+
+        assert!(processor.process(&0).await.is_ok());
+        assert!(processor.process(&-1).await.is_err());
+
+        let calls = registry
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "pipeline_processor_calls_total")
+            .unwrap();
+        let counts: Vec<f64> = calls
+            .get_metric()
+            .iter()
+            .map(|metric| metric.get_counter().get_value())
+            .collect();
+        // one success, one failure after retrying the panic
+        assert_eq!(counts, vec![1.0, 1.0]);
+    }
+}
@@ -1,3 +1,15 @@
+#[cfg(any(feature = "broker", feature = "postgres-broker"))]
+pub mod backend;
 pub mod consumer;
 pub mod data_pipeline;
+pub mod idempotency;
+#[cfg(feature = "memory-broker")]
+pub mod memory_broker;
+pub mod metrics;
+pub mod middleware;
+mod offset_tracker;
+#[cfg(feature = "postgres-broker")]
+pub mod postgres_broker;
 pub mod processor;
+pub mod producer;
+pub mod router;
@@ -0,0 +1,152 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::consumer::{ConsumedMessage, TopicPartitionOffset};
+
+#[derive(Default)]
+struct PartitionOffsets {
+    /// Lowest offset consumed, nothing can be committed until it is processed.
+    first: Option<i64>,
+    /// Offsets consumed but not processed yet.
+    pending: BTreeSet<i64>,
+    /// Offset following the highest processed message.
+    processed: Option<i64>,
+    committed: Option<i64>,
+}
+
+impl PartitionOffsets {
+    /// Everything below the returned offset has been processed.
+    fn committable(&self) -> Option<i64> {
+        let processed = self.processed?;
+        let offset = match self.pending.first() {
+            Some(first_pending) => processed.min(*first_pending),
+            None => processed,
+        };
+        (Some(offset) > self.first).then_some(offset)
+    }
+}
+
+/// Keeps track of the messages processed out of order so only contiguous ranges of
+/// processed offsets are committed.
+#[derive(Default)]
+pub(crate) struct OffsetTracker {
+    partitions: HashMap<(String, i32), PartitionOffsets>,
+}
+
+impl OffsetTracker {
+    pub fn consumed(&mut self, message: &ConsumedMessage) {
+        let partition = self.partition(message);
+        partition.first = Some(
+            partition
+                .first
+                .map_or(message.offset, |first| first.min(message.offset)),
+        );
+        partition.pending.insert(message.offset);
+    }
+
+    pub fn processed(&mut self, message: &ConsumedMessage) {
+        let partition = self.partition(message);
+        partition.pending.remove(&message.offset);
+        partition.processed = partition.processed.max(Some(message.offset + 1));
+    }
+
+    /// Offsets that moved forward since they were last committed.
+    pub fn committable(&self) -> Vec<TopicPartitionOffset> {
+        self.partitions
+            .iter()
+            .filter_map(|((topic, partition), offsets)| {
+                let offset = offsets.committable()?;
+                if offsets.committed >= Some(offset) {
+                    return None;
+                }
+                Some(TopicPartitionOffset {
+                    topic: topic.clone(),
+                    partition: *partition,
+                    offset,
+                })
+            })
+            .collect()
+    }
+
+    pub fn committed(&mut self, offsets: &[TopicPartitionOffset]) {
+        for offset in offsets {
+            if let Some(partition) = self
+                .partitions
+                .get_mut(&(offset.topic.clone(), offset.partition))
+            {
+                partition.committed = partition.committed.max(Some(offset.offset));
+            }
+        }
+    }
+
+    fn partition(&mut self, message: &ConsumedMessage) -> &mut PartitionOffsets {
+        self.partitions
+            .entry((message.topic.clone(), message.partition))
+            .or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn message(partition: i32, offset: i64) -> ConsumedMessage {
+        ConsumedMessage {
+            topic: "news_created".to_string(),
+            partition,
+            offset,
+            key: None,
+            payload: "".to_string(),
+            headers: HashMap::new(),
+        }
+    }
+
+    fn offset(partition: i32, offset: i64) -> TopicPartitionOffset {
+        TopicPartitionOffset {
+            topic: "news_created".to_string(),
+            partition,
+            offset,
+        }
+    }
+
+    #[test]
+    fn test_committable_waits_for_gaps_to_be_processed() {
+        let mut tracker = OffsetTracker::default();
+        for offset in 0..3 {
+            tracker.consumed(&message(0, offset));
+        }
+
+        tracker.processed(&message(0, 1));
+        tracker.processed(&message(0, 2));
+        assert!(tracker.committable().is_empty());
+
+        tracker.processed(&message(0, 0));
+        assert_eq!(tracker.committable(), vec![offset(0, 3)]);
+    }
+
+    #[test]
+    fn test_committable_stops_at_first_pending_offset() {
+        let mut tracker = OffsetTracker::default();
+        for offset in 0..4 {
+            tracker.consumed(&message(0, offset));
+        }
+
+        tracker.processed(&message(0, 0));
+        tracker.processed(&message(0, 1));
+        tracker.processed(&message(0, 3));
+
+        assert_eq!(tracker.committable(), vec![offset(0, 2)]);
+    }
+
+    #[test]
+    fn test_committable_skips_committed_offsets() {
+        let mut tracker = OffsetTracker::default();
+        tracker.consumed(&message(0, 0));
+        tracker.consumed(&message(1, 0));
+        tracker.processed(&message(0, 0));
+        tracker.committed(&[offset(0, 1)]);
+        tracker.processed(&message(1, 0));
+
+        assert_eq!(tracker.committable(), vec![offset(1, 1)]);
+    }
+}
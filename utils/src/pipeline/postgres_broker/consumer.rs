@@ -0,0 +1,387 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::dsl::{exists, not};
+use diesel::prelude::*;
+use futures::{stream, StreamExt};
+use log::{error, info, warn};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio_postgres::{AsyncMessage, Client, NoTls};
+use uuid::Uuid;
+
+use super::schema::{queue_deliveries, queue_messages, queue_subscriptions};
+use super::{run_blocking, PostgresBrokerConfig, NOTIFY_CHANNEL};
+use crate::db::PgPool;
+use crate::error::{BrokerError, CommonError};
+use crate::pipeline::consumer::{ConsumedMessage, Consumer, PartitionLag, TopicPartitionOffset};
+
+/// The queue has a single partition per topic, offsets are message ids.
+const PARTITION: i32 = 0;
+/// Commits delete the consumed messages at most this often.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct PostgresConsumer {
+    queue: Queue,
+    poll_interval: Duration,
+    purged_at: Mutex<Option<Instant>>,
+    topics: Mutex<Vec<String>>,
+    published: Arc<Notify>,
+    /// Dropping the client closes the listening connection.
+    _listen_client: Client,
+    listener: JoinHandle<()>,
+}
+
+impl PostgresConsumer {
+    /// Connects the listener woken up by new messages.
+    pub async fn connect(
+        pool: Arc<PgPool>,
+        config: &PostgresBrokerConfig,
+    ) -> Result<Self, BrokerError> {
+        let published = Arc::new(Notify::new());
+        let (listen_client, listener) = listen(&config.database_url, published.clone()).await?;
+
+        Ok(PostgresConsumer {
+            queue: Queue {
+                pool,
+                group_id: config.group_id.clone(),
+                consumer_id: Uuid::new_v4().to_string(),
+                lease: config.lease,
+                retention: config.retention,
+            },
+            poll_interval: config.poll_interval,
+            purged_at: Mutex::new(None),
+            topics: Mutex::new(vec![]),
+            published,
+            _listen_client: listen_client,
+            listener,
+        })
+    }
+
+    /// Purges the messages unless it was done recently, a failure only delays it.
+    async fn purge_periodically(&self) {
+        {
+            let mut purged_at = self.purged_at.lock().unwrap();
+            if purged_at.is_some_and(|purged_at| purged_at.elapsed() < PURGE_INTERVAL) {
+                return;
+            }
+            *purged_at = Some(Instant::now());
+        }
+
+        let queue = self.queue.clone();
+        match run_blocking(move || queue.purge()).await {
+            Ok(0) => {}
+            Ok(purged) => info!("purged {} queue messages", purged),
+            Err(err) => error!("{}", err.message),
+        }
+    }
+}
+
+impl Drop for PostgresConsumer {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+#[async_trait]
+impl Consumer for PostgresConsumer {
+    /// Registers the group on the topics. A group subscribing to a topic for the first
+    /// time receives the messages still kept, like Kafka's earliest offset.
+    async fn subscribe(&self, topics: &[String]) -> Result<(), CommonError> {
+        let queue = self.queue.clone();
+        let subscribed = topics.to_vec();
+        run_blocking(move || queue.subscribe(&subscribed)).await?;
+
+        *self.topics.lock().unwrap() = topics.to_vec();
+        Ok(())
+    }
+
+    async fn consume(&self) -> Result<ConsumedMessage, CommonError> {
+        loop {
+            // register before looking for messages so a notification in between is not missed
+            let published = self.published.notified();
+            tokio::pin!(published);
+            published.as_mut().enable();
+
+            let queue = self.queue.clone();
+            let topics = self.topics.lock().unwrap().clone();
+            if let Some(message) = run_blocking(move || queue.claim(&topics)).await? {
+                return Ok(message);
+            }
+
+            // polling also picks up the messages whose lease expired
+            let _ = tokio::time::timeout(self.poll_interval, published).await;
+        }
+    }
+
+    /// Deletes the deliveries of this consumer below the offsets, then the messages no
+    /// group has to consume anymore.
+    async fn commit(&self, offsets: &[TopicPartitionOffset]) -> Result<(), CommonError> {
+        let queue = self.queue.clone();
+        let offsets = offsets.to_vec();
+        run_blocking(move || queue.commit(&offsets)).await?;
+
+        self.purge_periodically().await;
+
+        Ok(())
+    }
+
+    /// Counts the deliveries of the group not committed yet, there's no watermark to compare.
+    async fn lag(&self) -> Result<Vec<PartitionLag>, CommonError> {
+        let queue = self.queue.clone();
+        let topics = self.topics.lock().unwrap().clone();
+
+        Ok(run_blocking(move || queue.lag(topics)).await?)
+    }
+}
+
+/// The queue as seen by a consumer, cloned into the blocking tasks running the queries.
+#[derive(Clone)]
+struct Queue {
+    pool: Arc<PgPool>,
+    group_id: String,
+    /// Marks the deliveries leased by this consumer, so committing doesn't release the
+    /// messages other consumers of the group are processing.
+    consumer_id: String,
+    lease: Duration,
+    retention: Duration,
+}
+
+impl Queue {
+    fn subscribe(&self, topics: &[String]) -> Result<(), BrokerError> {
+        let mut conn = self.pool.get().map_err(|err| BrokerError {
+            message: err.to_string(),
+        })?;
+
+        conn.transaction(|conn| {
+            for topic in topics {
+                let subscribed = diesel::insert_into(queue_subscriptions::table)
+                    .values((
+                        queue_subscriptions::group_id.eq(&self.group_id),
+                        queue_subscriptions::topic.eq(topic),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                if subscribed == 0 {
+                    continue;
+                }
+
+                diesel::insert_into(queue_deliveries::table)
+                    .values(
+                        queue_messages::table
+                            .filter(queue_messages::topic.eq(topic))
+                            .select((
+                                self.group_id.as_str().into_sql::<diesel::sql_types::Text>(),
+                                queue_messages::id,
+                                queue_messages::topic,
+                            )),
+                    )
+                    .into_columns((
+                        queue_deliveries::group_id,
+                        queue_deliveries::message_id,
+                        queue_deliveries::topic,
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+        .map_err(|err: diesel::result::Error| BrokerError {
+            message: format!("Error subscribing to topics: {}", err),
+        })
+    }
+
+    /// Leases the oldest message of the topics that no consumer of the group holds.
+    fn claim(&self, topics: &[String]) -> Result<Option<ConsumedMessage>, BrokerError> {
+        let mut conn = self.pool.get().map_err(|err| BrokerError {
+            message: err.to_string(),
+        })?;
+        let now = Utc::now();
+        let locked_until = now
+            + chrono::Duration::from_std(self.lease).map_err(|err| BrokerError {
+                message: err.to_string(),
+            })?;
+
+        conn.transaction(|conn| {
+            let message_id: Option<i64> = queue_deliveries::table
+                .select(queue_deliveries::message_id)
+                .filter(queue_deliveries::group_id.eq(&self.group_id))
+                .filter(queue_deliveries::topic.eq_any(topics))
+                .filter(
+                    queue_deliveries::locked_until
+                        .is_null()
+                        .or(queue_deliveries::locked_until.lt(now)),
+                )
+                .order(queue_deliveries::message_id.asc())
+                .limit(1)
+                .for_update()
+                .skip_locked()
+                .first(conn)
+                .optional()?;
+
+            let message_id = match message_id {
+                Some(message_id) => message_id,
+                None => return Ok(None),
+            };
+
+            diesel::update(
+                queue_deliveries::table
+                    .filter(queue_deliveries::group_id.eq(&self.group_id))
+                    .filter(queue_deliveries::message_id.eq(message_id)),
+            )
+            .set((
+                queue_deliveries::locked_by.eq(&self.consumer_id),
+                queue_deliveries::locked_until.eq(locked_until),
+            ))
+            .execute(conn)?;
+
+            let (topic, key, payload, headers): (String, String, String, String) =
+                queue_messages::table
+                    .find(message_id)
+                    .select((
+                        queue_messages::topic,
+                        queue_messages::key,
+                        queue_messages::payload,
+                        queue_messages::headers,
+                    ))
+                    .first(conn)?;
+
+            Ok(Some(ConsumedMessage {
+                topic,
+                partition: PARTITION,
+                offset: message_id,
+                key: Some(key),
+                payload,
+                headers: serde_json::from_str(&headers).unwrap_or_default(),
+            }))
+        })
+        .map_err(|err: diesel::result::Error| BrokerError {
+            message: format!("failed consuming message: {}", err),
+        })
+    }
+
+    /// Deletes the messages of the subscribed topics every group committed, along with
+    /// the messages older than the retention whether they were committed or not.
+    fn purge(&self) -> Result<usize, BrokerError> {
+        let mut conn = self.pool.get().map_err(|err| BrokerError {
+            message: err.to_string(),
+        })?;
+        let expired = chrono::Duration::from_std(self.retention)
+            .ok()
+            .and_then(|retention| Utc::now().checked_sub_signed(retention))
+            .unwrap_or(chrono::DateTime::<Utc>::MIN_UTC);
+
+        let subscribed =
+            queue_subscriptions::table.filter(queue_subscriptions::topic.eq(queue_messages::topic));
+        let pending =
+            queue_deliveries::table.filter(queue_deliveries::message_id.eq(queue_messages::id));
+
+        diesel::delete(
+            queue_messages::table.filter(
+                queue_messages::created_at
+                    .lt(expired)
+                    .or(exists(subscribed).and(not(exists(pending)))),
+            ),
+        )
+        .execute(&mut conn)
+        .map_err(|err| BrokerError {
+            message: format!("failed purging messages: {}", err),
+        })
+    }
+
+    fn commit(&self, offsets: &[TopicPartitionOffset]) -> Result<(), BrokerError> {
+        let mut conn = self.pool.get().map_err(|err| BrokerError {
+            message: err.to_string(),
+        })?;
+
+        for offset in offsets {
+            diesel::delete(
+                queue_deliveries::table
+                    .filter(queue_deliveries::group_id.eq(&self.group_id))
+                    .filter(queue_deliveries::topic.eq(&offset.topic))
+                    .filter(queue_deliveries::message_id.lt(offset.offset))
+                    .filter(queue_deliveries::locked_by.eq(&self.consumer_id)),
+            )
+            .execute(&mut conn)
+            .map_err(|err| BrokerError {
+                message: format!("failed committing {}: {}", offset.topic, err),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn lag(&self, topics: Vec<String>) -> Result<Vec<PartitionLag>, BrokerError> {
+        let mut conn = self.pool.get().map_err(|err| BrokerError {
+            message: err.to_string(),
+        })?;
+
+        let pending: Vec<(String, i64)> = queue_deliveries::table
+            .filter(queue_deliveries::group_id.eq(&self.group_id))
+            .filter(queue_deliveries::topic.eq_any(&topics))
+            .group_by(queue_deliveries::topic)
+            .select((queue_deliveries::topic, diesel::dsl::count_star()))
+            .load(&mut conn)
+            .map_err(|err| BrokerError {
+                message: format!("failed counting pending messages: {}", err),
+            })?;
+
+        Ok(topics
+            .into_iter()
+            .map(|topic| {
+                let lag = pending
+                    .iter()
+                    .find(|(pending_topic, _)| *pending_topic == topic)
+                    .map_or(0, |(_, count)| *count);
+                PartitionLag {
+                    topic,
+                    partition: PARTITION,
+                    lag,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Listens to the queue notifications on a dedicated connection and wakes up the consumer.
+async fn listen(
+    database_url: &str,
+    published: Arc<Notify>,
+) -> Result<(Client, JoinHandle<()>), BrokerError> {
+    let (client, mut connection) =
+        tokio_postgres::connect(database_url, NoTls)
+            .await
+            .map_err(|err| BrokerError {
+                message: format!("failed connecting queue listener: {}", err),
+            })?;
+
+    // the connection has to be polled for the LISTEN below to complete
+    let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+    let listener = tokio::spawn(async move {
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(_)) => published.notify_waiters(),
+                Ok(_) => {}
+                Err(err) => {
+                    error!("queue listener failed: {}", err);
+                    break;
+                }
+            }
+        }
+        warn!("queue listener stopped, falling back to polling");
+    });
+
+    if let Err(err) = client
+        .batch_execute(&format!("LISTEN {}", NOTIFY_CHANNEL))
+        .await
+    {
+        listener.abort();
+        return Err(BrokerError {
+            message: format!("failed listening to {}: {}", NOTIFY_CHANNEL, err),
+        });
+    }
+
+    Ok((client, listener))
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use diesel::prelude::*;
+
+use crate::db::PgPool;
+use crate::error::BrokerError;
+
+pub mod consumer;
+pub mod producer;
+pub mod schema;
+
+/// Channel notified with the topic of every published message.
+pub(crate) const NOTIFY_CHANNEL: &str = "queue_messages";
+
+const DEFAULT_LEASE: Duration = Duration::from_secs(60);
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Settings of the Postgres queue, used instead of Kafka by small deployments. The
+/// services share a database migrated with `utils/migrations`.
+///
+/// Every consumer group gets its own copy of the messages published to the topics it
+/// subscribed to. Messages are handed out in the order they were published and a message
+/// not committed before its lease expires is delivered again. The order of the messages
+/// of a key is only kept when a group has a single consumer.
+///
+/// Messages are deleted once every group committed them, or after the retention.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostgresBrokerConfig {
+    pub database_url: String,
+    pub group_id: String,
+    /// How long a consumed message is reserved for the consumer before it's redelivered.
+    pub lease: Duration,
+    /// Consumers look for messages at least this often, in case a notification is missed.
+    pub poll_interval: Duration,
+    /// How long messages are kept when some group doesn't commit them.
+    pub retention: Duration,
+}
+
+impl PostgresBrokerConfig {
+    pub fn new(database_url: &str, group_id: &str) -> Self {
+        PostgresBrokerConfig {
+            database_url: database_url.to_string(),
+            group_id: group_id.to_string(),
+            lease: DEFAULT_LEASE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            retention: DEFAULT_RETENTION,
+        }
+    }
+
+    /// Reads `BROKER_DATABASE_URL`, the database every service shares, and
+    /// `BROKER_GROUP_ID`, which defaults to the service name.
+    pub fn from_env(service: &str) -> Result<Self, BrokerError> {
+        // each service has its own DATABASE_URL, falling back to it would split the queue
+        let database_url = std::env::var("BROKER_DATABASE_URL")
+            .map_err(|_| BrokerError::new("BROKER_DATABASE_URL must be set"))?;
+        let group_id = std::env::var("BROKER_GROUP_ID").unwrap_or_else(|_| service.to_string());
+
+        Ok(PostgresBrokerConfig::new(&database_url, &group_id))
+    }
+}
+
+/// Runs the queries on the blocking pool, diesel would hold up the runtime otherwise.
+async fn run_blocking<T, F>(query: F) -> Result<T, BrokerError>
+where
+    F: FnOnce() -> Result<T, BrokerError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(query)
+        .await
+        .map_err(|err| BrokerError {
+            message: format!("queue query failed: {}", err),
+        })?
+}
+
+/// Checks the queue tables were migrated.
+pub fn check_schema(pool: &PgPool) -> Result<(), BrokerError> {
+    let mut conn = pool.get().map_err(|err| BrokerError {
+        message: err.to_string(),
+    })?;
+
+    schema::queue_deliveries::table
+        .select(schema::queue_deliveries::message_id)
+        .limit(0)
+        .load::<i64>(&mut conn)
+        .map(|_| ())
+        .map_err(|err| BrokerError {
+            message: format!("queue tables missing, run the broker migrations: {}", err),
+        })
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+
+use super::schema::{queue_deliveries, queue_messages, queue_subscriptions};
+use super::{run_blocking, NOTIFY_CHANNEL};
+use crate::db::PgPool;
+use crate::error::BrokerError;
+use crate::pipeline::producer::{Producer, ProducerMessage};
+
+pub struct PostgresProducer {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresProducer {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        PostgresProducer { pool }
+    }
+}
+
+#[async_trait]
+impl Producer for PostgresProducer {
+    /// Stores the message and a delivery for every group subscribed to the topic, then
+    /// wakes up the consumers.
+    async fn send(&self, message: ProducerMessage) -> Result<(), BrokerError> {
+        let pool = self.pool.clone();
+        run_blocking(move || publish(&pool, &message)).await
+    }
+}
+
+fn publish(pool: &PgPool, message: &ProducerMessage) -> Result<(), BrokerError> {
+    let mut conn = pool.get().map_err(|err| BrokerError {
+        message: err.to_string(),
+    })?;
+    let headers = serde_json::to_string(&message.headers).map_err(|err| BrokerError {
+        message: format!("failed serializing headers: {}", err),
+    })?;
+
+    conn.transaction(|conn| {
+        let id: i64 = diesel::insert_into(queue_messages::table)
+            .values((
+                queue_messages::topic.eq(&message.topic),
+                queue_messages::key.eq(&message.key),
+                queue_messages::payload.eq(&message.payload),
+                queue_messages::headers.eq(&headers),
+            ))
+            .returning(queue_messages::id)
+            .get_result(conn)?;
+
+        diesel::insert_into(queue_deliveries::table)
+            .values(
+                queue_subscriptions::table
+                    .filter(queue_subscriptions::topic.eq(&message.topic))
+                    .select((
+                        queue_subscriptions::group_id,
+                        id.into_sql::<diesel::sql_types::Int8>(),
+                        queue_subscriptions::topic,
+                    )),
+            )
+            .into_columns((
+                queue_deliveries::group_id,
+                queue_deliveries::message_id,
+                queue_deliveries::topic,
+            ))
+            .execute(conn)?;
+
+        // delivered to the listeners once the transaction commits
+        diesel::sql_query("SELECT pg_notify($1, $2)")
+            .bind::<Text, _>(NOTIFY_CHANNEL)
+            .bind::<Text, _>(&message.topic)
+            .execute(conn)?;
+
+        Ok(())
+    })
+    .map_err(|err: diesel::result::Error| BrokerError {
+        message: format!("failed publishing to {}: {}", message.topic, err),
+    })
+}
@@ -0,0 +1,37 @@
+use diesel::table;
+
+table! {
+  queue_messages (id) {
+      id -> Int8,
+      topic -> Text,
+      key -> Text,
+      payload -> Text,
+      headers -> Text,
+      created_at -> Timestamptz,
+  }
+}
+
+table! {
+  queue_subscriptions (group_id, topic) {
+      group_id -> Text,
+      topic -> Text,
+  }
+}
+
+table! {
+  queue_deliveries (group_id, message_id) {
+      group_id -> Text,
+      message_id -> Int8,
+      topic -> Text,
+      locked_by -> Nullable<Text>,
+      locked_until -> Nullable<Timestamptz>,
+  }
+}
+
+diesel::joinable!(queue_deliveries -> queue_messages (message_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    queue_messages,
+    queue_subscriptions,
+    queue_deliveries
+);
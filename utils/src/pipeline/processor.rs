@@ -1,8 +1,19 @@
 use async_trait::async_trait;
+use serde::de::DeserializeOwned;
 
+use super::consumer::ConsumedMessage;
 use crate::error::CommonError;
 
+/// Handles the raw messages consumed by the data pipeline.
 #[async_trait]
-pub trait Processor: Send + Sync {
-    async fn process(&self, message: &str) -> Result<(), CommonError>;
+pub trait MessageHandler: Send + Sync {
+    /// Topics the pipeline subscribes to.
+    fn topics(&self) -> Vec<String>;
+    async fn handle(&self, message: &ConsumedMessage) -> Result<(), CommonError>;
+}
+
+/// Processes the events of a topic once decoded, see `TopicRouter`.
+#[async_trait]
+pub trait Processor<E: DeserializeOwned + Send + Sync>: Send + Sync {
+    async fn process(&self, event: &E) -> Result<(), CommonError>;
 }
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use mockall::automock;
+#[cfg(feature = "broker")]
+use rdkafka::message::{Header, OwnedHeaders};
+#[cfg(feature = "broker")]
+use rdkafka::producer::FutureRecord;
+#[cfg(feature = "broker")]
+use tracing::Instrument;
+#[cfg(feature = "broker")]
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::error::BrokerError;
+#[cfg(feature = "broker")]
+use crate::telemetry;
+
+/// Message to publish. Messages with the same key land on the same partition, so they are
+/// consumed in the order they were sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProducerMessage {
+    pub topic: String,
+    pub key: String,
+    pub payload: String,
+    pub headers: HashMap<String, String>,
+}
+
+impl ProducerMessage {
+    pub fn new(topic: &str, key: &str, payload: &str) -> Self {
+        ProducerMessage {
+            topic: topic.to_string(),
+            key: key.to_string(),
+            payload: payload.to_string(),
+            headers: HashMap::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait Producer: Send + Sync {
+    async fn send(&self, message: ProducerMessage) -> Result<(), BrokerError>;
+    /// Waits for the messages still buffered to be delivered, before shutting down.
+    fn flush(&self, _timeout: Duration) -> Result<(), BrokerError> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "broker")]
+pub struct KafkaProducer {
+    producer: crate::broker::KafkaProducer,
+}
+
+#[cfg(feature = "broker")]
+impl KafkaProducer {
+    pub fn new(producer: crate::broker::KafkaProducer) -> Self {
+        KafkaProducer { producer }
+    }
+}
+
+#[cfg(feature = "broker")]
+#[async_trait]
+impl Producer for KafkaProducer {
+    async fn send(&self, message: ProducerMessage) -> Result<(), BrokerError> {
+        let span = tracing::info_span!(
+            "publish",
+            otel.name = %format!("{} publish", message.topic),
+            otel.kind = "producer",
+            messaging.system = "kafka",
+            messaging.destination = %message.topic,
+        );
+        // continues the trace carried by the message, consumers continue this span
+        span.set_parent(telemetry::extract_context(&message.headers));
+        let mut message_headers = message.headers.clone();
+        telemetry::inject_span_context(&span, &mut message_headers);
+
+        let mut headers = OwnedHeaders::new();
+        for (key, value) in message_headers.iter() {
+            headers = headers.insert(Header {
+                key,
+                value: Some(value),
+            });
+        }
+
+        self.producer
+            .send(
+                FutureRecord::to(&message.topic)
+                    .payload(&message.payload)
+                    .key(&message.key)
+                    .headers(headers),
+                Duration::from_secs(120),
+            )
+            .instrument(span)
+            .await
+            .map_err(|(err, _)| BrokerError {
+                message: err.to_string(),
+            })?;
+
+        Ok(())
+    }
+
+    fn flush(&self, timeout: Duration) -> Result<(), BrokerError> {
+        crate::broker::flush_producer(&self.producer, timeout)
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+
+use super::consumer::ConsumedMessage;
+use super::processor::{MessageHandler, Processor};
+use crate::error::{BrokerError, CommonError, SerializationError};
+
+/// Decodes the messages of a topic and hands them to a typed processor.
+struct TypedHandler<'a, E> {
+    processor: &'a dyn Processor<E>,
+    decode: fn(&ConsumedMessage) -> Result<E, SerializationError>,
+}
+
+#[async_trait]
+trait Route: Send + Sync {
+    async fn handle(&self, message: &ConsumedMessage) -> Result<(), CommonError>;
+}
+
+#[async_trait]
+impl<'a, E: DeserializeOwned + Send + Sync> Route for TypedHandler<'a, E> {
+    async fn handle(&self, message: &ConsumedMessage) -> Result<(), CommonError> {
+        let event = (self.decode)(message)?;
+
+        self.processor.process(&event).await
+    }
+}
+
+/// Decodes the payload as JSON, how the messages of the routes are read by default.
+pub fn decode_json<E: DeserializeOwned>(
+    message: &ConsumedMessage,
+) -> Result<E, SerializationError> {
+    serde_json::from_str(&message.payload).map_err(|err| {
+        SerializationError::new(
+            format!("failed to decode {} message: {}", message.topic, err).as_str(),
+        )
+    })
+}
+
+/// Dispatches the messages of each topic to the processor registered for it, so a
+/// single pipeline consumes many topics.
+#[derive(Default)]
+pub struct TopicRouter<'a> {
+    routes: HashMap<String, Box<dyn Route + 'a>>,
+}
+
+impl<'a> TopicRouter<'a> {
+    pub fn new() -> Self {
+        TopicRouter::default()
+    }
+
+    pub fn route<E: DeserializeOwned + Send + Sync + 'a>(
+        self,
+        topic: &str,
+        processor: &'a dyn Processor<E>,
+    ) -> Self {
+        self.route_with(topic, processor, decode_json::<E>)
+    }
+
+    /// Like `route`, for the events that need more than the payload to be decoded.
+    pub fn route_with<E: DeserializeOwned + Send + Sync + 'a>(
+        mut self,
+        topic: &str,
+        processor: &'a dyn Processor<E>,
+        decode: fn(&ConsumedMessage) -> Result<E, SerializationError>,
+    ) -> Self {
+        self.routes.insert(
+            topic.to_string(),
+            Box::new(TypedHandler { processor, decode }),
+        );
+        self
+    }
+}
+
+#[async_trait]
+impl<'a> MessageHandler for TopicRouter<'a> {
+    fn topics(&self) -> Vec<String> {
+        let mut topics: Vec<String> = self.routes.keys().cloned().collect();
+        topics.sort();
+        topics
+    }
+
+    async fn handle(&self, message: &ConsumedMessage) -> Result<(), CommonError> {
+        match self.routes.get(&message.topic) {
+            Some(route) => route.handle(message).await,
+            None => Err(BrokerError::new(
+                format!("no processor for topic {}", message.topic).as_str(),
+            )
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Created {
+        id: u32,
+    }
+
+    #[derive(Default)]
+    struct RecordingProcessor {
+        events: Mutex<Vec<u32>>,
+    }
+
+    #[async_trait]
+    impl Processor<Created> for RecordingProcessor {
+        async fn process(&self, event: &Created) -> Result<(), CommonError> {
+            self.events.lock().unwrap().push(event.id);
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Processor<String> for RecordingProcessor {
+        async fn process(&self, event: &String) -> Result<(), CommonError> {
+            self.events.lock().unwrap().push(event.len() as u32);
+            Ok(())
+        }
+    }
+
+    fn message(topic: &str, payload: &str) -> ConsumedMessage {
+        ConsumedMessage {
+            topic: topic.to_string(),
+            partition: 0,
+            offset: 0,
+            key: None,
+            payload: payload.to_string(),
+            headers: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_dispatches_by_topic() {
+        let created = RecordingProcessor::default();
+        let deleted = RecordingProcessor::default();
+        let router = TopicRouter::new()
+            .route::<Created>("created", &created)
+            .route::<String>("deleted", &deleted);
+
+        router
+            .handle(&message("created", r#"{"id":1}"#))
+            .await
+            .unwrap();
+        router
+            .handle(&message("deleted", r#""abc""#))
+            .await
+            .unwrap();
+
+        assert_eq!(router.topics(), vec!["created", "deleted"]);
+        assert_eq!(*created.events.lock().unwrap(), vec![1]);
+        assert_eq!(*deleted.events.lock().unwrap(), vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_decode_error() {
+        let processor = RecordingProcessor::default();
+        let router = TopicRouter::new().route::<Created>("created", &processor);
+
+        let result = router.handle(&message("created", "invalid json")).await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: failed to decode created message: expected value at line 1 column 1, Code: 6"
+        );
+        assert!(processor.events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_route_with_decoder() {
+        let processor = RecordingProcessor::default();
+        let router = TopicRouter::new().route_with::<Created>("created", &processor, |message| {
+            Ok(Created {
+                id: message.partition as u32 + 7,
+            })
+        });
+
+        // the payload is left to the decoder
+        router
+            .handle(&message("created", "not json"))
+            .await
+            .unwrap();
+
+        assert_eq!(*processor.events.lock().unwrap(), vec![7]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_unknown_topic() {
+        let router = TopicRouter::new();
+
+        let result = router.handle(&message("unknown", "{}")).await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: no processor for topic unknown, Code: 2"
+        );
+    }
+}
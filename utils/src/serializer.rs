@@ -21,10 +21,11 @@ pub mod serde_naive_date {
     pub fn deserialize<'de, D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<Option<NaiveDate>, D::Error> {
-        let time: &str = Deserialize::deserialize(deserializer)?;
+        // owned so it also deserializes from values that cannot be borrowed, like event data
+        let time: String = Deserialize::deserialize(deserializer)?;
         if !time.is_empty() {
             Ok(Some(
-                NaiveDate::parse_from_str(time, "%Y-%m-%d").map_err(D::Error::custom)?,
+                NaiveDate::parse_from_str(&time, "%Y-%m-%d").map_err(D::Error::custom)?,
             ))
         } else {
             Ok(None)
@@ -0,0 +1,44 @@
+use log::info;
+use tokio::signal;
+use tokio_util::sync::CancellationToken;
+
+/// Returns a token that is cancelled once the process receives SIGINT or SIGTERM.
+///
+/// Services pass clones of the token to every long running task (HTTP server,
+/// data pipelines, schedulers) so they can stop consuming work and drain what is in flight.
+pub fn shutdown_token() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("shutdown signal received");
+        cancel.cancel();
+    });
+
+    token
+}
+
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed installing Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed installing SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
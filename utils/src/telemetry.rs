@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{TraceContextExt, TraceError, TracerProvider as _};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Header carrying the W3C trace context.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// Installs a tracing subscriber that records spans with OpenTelemetry.
+///
+/// Spans are exported over OTLP/HTTP when `otlp_endpoint` is set, e.g. `http://collector:4318`,
+/// otherwise they are only used to propagate and log the trace context.
+pub fn init_tracing(service_name: &str, otlp_endpoint: &str) {
+    let provider = tracer_provider(service_name, otlp_endpoint).expect("Can't create tracer");
+    let tracer = provider.tracer(service_name.to_string());
+    global::set_tracer_provider(provider);
+
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber).expect("Can't install tracing subscriber");
+}
+
+/// Exports the spans still buffered, call it before the process exits.
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+pub fn tracer_provider(
+    service_name: &str,
+    otlp_endpoint: &str,
+) -> Result<trace::TracerProvider, TraceError> {
+    let config = trace::config().with_resource(Resource::new([KeyValue::new(
+        "service.name",
+        service_name.to_string(),
+    )]));
+    let mut builder = trace::TracerProvider::builder().with_config(config);
+
+    if !otlp_endpoint.is_empty() {
+        let exporter = opentelemetry_otlp::SpanExporterBuilder::from(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(format!("{}/v1/traces", otlp_endpoint.trim_end_matches('/'))),
+        )
+        .build_span_exporter()?;
+        // the exporter runs on its own thread so flushing from the actix runtime can't block it
+        builder = builder.with_batch_exporter(exporter, runtime::TokioCurrentThread);
+    }
+
+    Ok(builder.build())
+}
+
+/// Writes the trace context of the current span into the headers.
+pub fn inject_context(headers: &mut HashMap<String, String>) {
+    inject_span_context(&Span::current(), headers);
+}
+
+pub fn inject_span_context(span: &Span, headers: &mut HashMap<String, String>) {
+    TraceContextPropagator::new().inject_context(&span.context(), headers);
+}
+
+/// Reads the trace context propagated in the headers.
+pub fn extract_context(headers: &HashMap<String, String>) -> Context {
+    TraceContextPropagator::new().extract(headers)
+}
+
+/// Trace id of the current span, used to correlate log lines.
+pub fn current_trace_id() -> Option<String> {
+    let context = Span::current().context();
+    let span_context = context.span().span_context().clone();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+    use tracing::subscriber::with_default;
+
+    use super::*;
+
+    const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    #[test]
+    fn test_extract_context() {
+        let headers = HashMap::from([(TRACEPARENT_HEADER.to_string(), TRACEPARENT.to_string())]);
+
+        let context = extract_context(&headers);
+        let span_context = context.span().span_context().clone();
+
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+        );
+        assert!(span_context.is_remote());
+    }
+
+    #[test]
+    fn test_inject_context_continues_parent_trace() {
+        let provider = tracer_provider("test", "").unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        with_default(subscriber, || {
+            let parent = Context::new().with_remote_span_context(SpanContext::new(
+                TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap(),
+                SpanId::from_hex("b7ad6b7169203331").unwrap(),
+                TraceFlags::SAMPLED,
+                true,
+                TraceState::default(),
+            ));
+            let span = tracing::info_span!("publish");
+            span.set_parent(parent);
+            let _guard = span.enter();
+
+            let mut headers = HashMap::new();
+            inject_context(&mut headers);
+
+            let traceparent = &headers[TRACEPARENT_HEADER];
+            assert!(traceparent.starts_with("00-0af7651916cd43dd8448eb211c80319c-"));
+            assert_ne!(traceparent, TRACEPARENT);
+            assert_eq!(
+                current_trace_id().as_deref(),
+                Some("0af7651916cd43dd8448eb211c80319c")
+            );
+        });
+    }
+
+    #[test]
+    fn test_inject_context_without_span() {
+        let mut headers = HashMap::new();
+
+        inject_context(&mut headers);
+
+        assert!(headers.is_empty());
+        assert_eq!(current_trace_id(), None);
+    }
+
+    #[tokio::test]
+    async fn test_spans_are_exported_to_collector() {
+        // stands in for the OpenTelemetry collector
+        let mut collector = mockito::Server::new_async().await;
+        let traces = collector
+            .mock("POST", "/v1/traces")
+            .match_header("content-type", "application/x-protobuf")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let provider = tracer_provider("test", &collector.url()).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        with_default(subscriber, || {
+            let _guard = tracing::info_span!("handle").entered();
+        });
+
+        for result in provider.force_flush() {
+            result.unwrap();
+        }
+
+        traces.assert_async().await;
+    }
+}
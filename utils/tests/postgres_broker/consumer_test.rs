@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::prelude::*;
+use utils::db::{connect_db, PgPool};
+use utils::pipeline::{
+    consumer::{Consumer, PartitionLag, TopicPartitionOffset},
+    postgres_broker::{
+        consumer::PostgresConsumer, producer::PostgresProducer, schema::queue_messages,
+        PostgresBrokerConfig,
+    },
+    producer::{Producer, ProducerMessage},
+};
+use uuid::Uuid;
+
+/// Queue of a single test, the topic is unique so the tests don't see each other's messages.
+struct Queue {
+    pool: Arc<PgPool>,
+    config: PostgresBrokerConfig,
+    topic: String,
+}
+
+impl Queue {
+    fn new() -> Self {
+        let mut config = PostgresBrokerConfig::from_env("tests").unwrap();
+        config.group_id = Uuid::new_v4().to_string();
+        config.poll_interval = Duration::from_millis(100);
+
+        Queue {
+            pool: Arc::new(connect_db(config.database_url.clone())),
+            config,
+            topic: format!("news_created_{}", Uuid::new_v4()),
+        }
+    }
+
+    async fn consumer(&self) -> PostgresConsumer {
+        let consumer = PostgresConsumer::connect(self.pool.clone(), &self.config)
+            .await
+            .unwrap();
+        consumer
+            .subscribe(std::slice::from_ref(&self.topic))
+            .await
+            .unwrap();
+        consumer
+    }
+
+    async fn publish(&self, payload: &str) {
+        PostgresProducer::new(self.pool.clone())
+            .send(ProducerMessage::new(&self.topic, "feed", payload))
+            .await
+            .unwrap();
+    }
+
+    fn stored_messages(&self) -> i64 {
+        let mut conn = self.pool.get().unwrap();
+        queue_messages::table
+            .filter(queue_messages::topic.eq(&self.topic))
+            .count()
+            .get_result(&mut conn)
+            .unwrap()
+    }
+}
+
+#[tokio::test]
+async fn test_committed_messages_are_purged() {
+    let queue = Queue::new();
+    let consumer = queue.consumer().await;
+
+    queue.publish("first").await;
+    queue.publish("second").await;
+
+    let message = consumer.consume().await.unwrap();
+    assert_eq!(message.payload, "first");
+    consumer
+        .commit(&[TopicPartitionOffset {
+            topic: message.topic.clone(),
+            partition: message.partition,
+            offset: message.offset + 1,
+        }])
+        .await
+        .unwrap();
+
+    assert_eq!(queue.stored_messages(), 1);
+    assert_eq!(
+        consumer.lag().await.unwrap(),
+        vec![PartitionLag {
+            topic: queue.topic.clone(),
+            partition: 0,
+            lag: 1,
+        }]
+    );
+    assert_eq!(consumer.consume().await.unwrap().payload, "second");
+}
+
+#[tokio::test]
+async fn test_consumers_of_same_group_compete_for_a_message() {
+    let queue = Queue::new();
+    let first = queue.consumer().await;
+    let second = queue.consumer().await;
+
+    queue.publish("news").await;
+
+    let (first_message, second_message) = tokio::join!(
+        tokio::time::timeout(Duration::from_millis(500), first.consume()),
+        tokio::time::timeout(Duration::from_millis(500), second.consume()),
+    );
+
+    let consumed: Vec<String> = [first_message, second_message]
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|message| message.unwrap().payload)
+        .collect();
+    assert_eq!(consumed, vec!["news"]);
+}
+
+#[tokio::test]
+async fn test_message_not_committed_is_redelivered_once_the_lease_expires() {
+    let mut queue = Queue::new();
+    queue.config.lease = Duration::from_millis(200);
+    let first = queue.consumer().await;
+    let second = queue.consumer().await;
+
+    queue.publish("news").await;
+    let message = first.consume().await.unwrap();
+
+    let redelivered = tokio::time::timeout(Duration::from_secs(2), second.consume())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(redelivered.offset, message.offset);
+    assert_eq!(redelivered.payload, "news");
+}
+
+#[tokio::test]
+async fn test_consumer_groups_receive_every_message() {
+    let first_group = Queue::new();
+    let mut second_group = Queue::new();
+    second_group.topic = first_group.topic.clone();
+    let first = first_group.consumer().await;
+    let second = second_group.consumer().await;
+
+    first_group.publish("news").await;
+
+    assert_eq!(first.consume().await.unwrap().payload, "news");
+    assert_eq!(second.consume().await.unwrap().payload, "news");
+}
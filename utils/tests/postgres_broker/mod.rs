@@ -0,0 +1 @@
+mod consumer_test;